@@ -24,6 +24,9 @@ impl Plugin for ConfigPlugins {
 #[derive(Debug, Resource)]
 pub struct Config {
     pub peer_address: Option<SocketAddr>,
+    pub undo_limit: usize,
+    pub camera_sensitivity: f32,
+    pub camera_speed: f32,
     #[cfg(debug_assertions)]
     pub client_id: u64,
 }
@@ -46,6 +49,9 @@ impl Config {
 
         Self {
             peer_address: address,
+            undo_limit: cli_args.undo_limit,
+            camera_sensitivity: cli_args.camera_sensitivity,
+            camera_speed: cli_args.camera_speed,
             #[cfg(debug_assertions)]
             client_id,
         }
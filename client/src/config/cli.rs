@@ -13,6 +13,15 @@ pub struct Cli {
     pub ip: Option<IpAddr>,
     #[arg(short, long, default_value = None)]
     pub port: Option<u16>,
+    /// How many edits the editor can undo.
+    #[arg(long, default_value_t = 100)]
+    pub undo_limit: usize,
+    /// How fast the editor camera turns, in radians per pixel of mouse movement.
+    #[arg(long, default_value_t = 0.003)]
+    pub camera_sensitivity: f32,
+    /// How fast the editor camera flies, in meters per second.
+    #[arg(long, default_value_t = 10.0)]
+    pub camera_speed: f32,
 
     #[cfg(debug_assertions)]
     #[arg(short, long, default_value = None)]
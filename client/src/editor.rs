@@ -1,7 +1,144 @@
 use bevy::prelude::*;
+use common::vehicle::VehicleBlueprint;
+
+use crate::{
+    editor::{
+        camera::EditorCameraPlugin,
+        history::HistoryPlugin,
+        placement::PlacementPlugin,
+        session::{
+            AvailableEditorSessions, CreateEditorSessionEvent, EditorConnection,
+            InviteToEditorSessionEvent, JoinEditorSessionEvent, KickFromEditorSessionEvent,
+            LeaveEditorSessionEvent, ListEditorSessionsEvent, SessionPlugin,
+        },
+        wiring::WiringPlugin,
+    },
+    states::GameState,
+};
+
+mod camera;
+mod history;
+mod placement;
+mod session;
+mod wiring;
 
 pub struct EditorPlugins;
 
 impl Plugin for EditorPlugins {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            EditorCameraPlugin,
+            PlacementPlugin,
+            HistoryPlugin,
+            SessionPlugin,
+            WiringPlugin,
+        ));
+
+        app.add_systems(
+            Update,
+            (toggle_editor_system, session_keys_system)
+                .run_if(in_state(GameState::InGame).or(in_state(GameState::InEditor))),
+        );
+    }
+}
+
+/// The vehicle that is currently edited.
+#[derive(Debug, Component)]
+pub struct EditedVehicle;
+
+impl EditedVehicle {
+    /// The components of an edited vehicle. It is despawned when the editor closes.
+    pub fn bundle(blueprint: VehicleBlueprint) -> impl Bundle {
+        (
+            Name::new("EditedVehicle"),
+            EditedVehicle,
+            blueprint,
+            Transform::default(),
+            Visibility::default(),
+            DespawnOnExit(GameState::InEditor),
+        )
+    }
+}
+
+/// Create a new building session or leave the current one.
+// TODO: Replace with a menu entry once there is a UI.
+fn toggle_editor_system(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyB) {
+        return;
+    }
+
+    match state.get() {
+        GameState::InEditor => commands.trigger(LeaveEditorSessionEvent),
+        _ => commands.trigger(CreateEditorSessionEvent {
+            name: "vehicle".to_string(),
+        }),
+    }
+}
+
+/// The keys that pick an entry of [`AvailableEditorSessions`].
+const NUMBER_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// List the building sessions with L. Outside of the editor the number keys join a listed
+/// session, in the editor they kick one of the other listed members of the own session, or
+/// invite it to build while shift is held.
+// TODO: Replace with a session menu once there is a UI.
+fn session_keys_system(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    available: Res<AvailableEditorSessions>,
+    connection: Res<EditorConnection>,
+) {
+    if keys.just_pressed(KeyCode::KeyL) {
+        commands.trigger(ListEditorSessionsEvent);
+    }
+
+    let Some(index) = NUMBER_KEYS.iter().position(|key| keys.just_pressed(*key)) else {
+        return;
+    };
+
+    match state.get() {
+        GameState::InEditor => {
+            // The owner is the only one who can kick and invite, so it is never a target.
+            let member = connection
+                .session()
+                .and_then(|id| available.0.iter().find(|session| session.id == id))
+                .and_then(|session| {
+                    session
+                        .members
+                        .iter()
+                        .filter(|member| **member != session.owner)
+                        .nth(index)
+                });
+            let invite = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+            match member {
+                Some(client) if invite => {
+                    commands.trigger(InviteToEditorSessionEvent { client: *client });
+                }
+                Some(client) => commands.trigger(KickFromEditorSessionEvent { client: *client }),
+                None => {}
+            }
+        }
+        _ => {
+            if let Some(session) = available.0.get(index) {
+                commands.trigger(JoinEditorSessionEvent {
+                    session: session.id,
+                });
+            }
+        }
+    }
 }
@@ -0,0 +1,242 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{input::mouse::AccumulatedMouseMotion, prelude::*};
+
+use crate::{config::Config, states::GameState};
+
+/// The pitch stops a little before looking straight up or down, where the yaw flips.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+#[derive(Debug)]
+pub struct EditorCameraPlugin;
+
+impl Plugin for EditorCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_settings)
+            .add_systems(OnEnter(GameState::InEditor), setup)
+            .add_systems(OnExit(GameState::InEditor), activate_other_cameras)
+            .add_systems(
+                Update,
+                (look_system, move_system)
+                    .chain()
+                    .run_if(in_state(GameState::InEditor)),
+            );
+    }
+}
+
+/// How the editor camera reacts to the mouse and keyboard.
+#[derive(Debug, Clone, PartialEq, Resource)]
+pub struct EditorCameraSettings {
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Meters per second.
+    pub speed: f32,
+    /// Speed multiplier while shift is held.
+    pub fast_multiplier: f32,
+    /// Speed multiplier while ctrl is held.
+    pub slow_multiplier: f32,
+}
+
+impl Default for EditorCameraSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.003,
+            speed: 10.0,
+            fast_multiplier: 4.0,
+            slow_multiplier: 0.25,
+        }
+    }
+}
+
+impl EditorCameraSettings {
+    /// The speed in meters per second with the speed modifiers applied.
+    pub fn speed(&self, fast: bool, slow: bool) -> f32 {
+        let mut speed = self.speed;
+        if fast {
+            speed *= self.fast_multiplier;
+        }
+        if slow {
+            speed *= self.slow_multiplier;
+        }
+
+        speed
+    }
+}
+
+/// The free flying camera of the editor. Looks around while the middle mouse button is held.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct EditorCamera {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl EditorCamera {
+    pub fn from_rotation(rotation: Quat) -> Self {
+        let (yaw, pitch, _) = rotation.to_euler(EulerRot::YXZ);
+
+        Self {
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+        }
+    }
+
+    /// Turn by a mouse movement of `delta` pixels.
+    pub fn look(&mut self, delta: Vec2, sensitivity: f32) {
+        self.yaw = (self.yaw - delta.x * sensitivity).rem_euclid(std::f32::consts::TAU);
+        self.pitch = (self.pitch - delta.y * sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0)
+    }
+}
+
+/// The movement of the camera in world space.
+///
+/// `input` is in camera space, x is right and z is backwards. Its y axis moves along the
+/// world up axis regardless of where the camera looks.
+pub fn camera_translation(rotation: Quat, input: Vec3, speed: f32, delta_secs: f32) -> Vec3 {
+    let direction = rotation * Vec3::new(input.x, 0.0, input.z) + Vec3::Y * input.y;
+
+    direction.normalize_or_zero() * speed * delta_secs
+}
+
+/// The camera space movement input of WASD, Q and E.
+fn movement_input(keys: &ButtonInput<KeyCode>) -> Vec3 {
+    let axis =
+        |negative, positive| (keys.pressed(positive) as i32 - keys.pressed(negative) as i32) as f32;
+
+    Vec3::new(
+        axis(KeyCode::KeyA, KeyCode::KeyD),
+        axis(KeyCode::KeyQ, KeyCode::KeyE),
+        axis(KeyCode::KeyW, KeyCode::KeyS),
+    )
+}
+
+fn setup_settings(mut commands: Commands, config: Res<Config>) {
+    commands.insert_resource(EditorCameraSettings {
+        sensitivity: config.camera_sensitivity,
+        speed: config.camera_speed,
+        ..default()
+    });
+}
+
+/// Spawn the editor camera and deactivate every other one until the editor closes.
+fn setup(mut commands: Commands, mut cameras: Query<&mut Camera>) {
+    for mut camera in cameras.iter_mut() {
+        camera.is_active = false;
+    }
+
+    let transform = Transform::from_xyz(8.0, 6.0, 8.0).looking_at(Vec3::ZERO, Vec3::Y);
+    commands.spawn((
+        Name::new("EditorCamera"),
+        EditorCamera::from_rotation(transform.rotation),
+        Camera3d::default(),
+        transform,
+        DespawnOnExit(GameState::InEditor),
+    ));
+}
+
+fn activate_other_cameras(mut cameras: Query<&mut Camera, Without<EditorCamera>>) {
+    for mut camera in cameras.iter_mut() {
+        camera.is_active = true;
+    }
+}
+
+fn look_system(
+    mouse: Res<ButtonInput<MouseButton>>,
+    motion: Res<AccumulatedMouseMotion>,
+    settings: Res<EditorCameraSettings>,
+    mut cameras: Query<(&mut EditorCamera, &mut Transform)>,
+) {
+    if !mouse.pressed(MouseButton::Middle) || motion.delta == Vec2::ZERO {
+        return;
+    }
+
+    for (mut camera, mut transform) in cameras.iter_mut() {
+        camera.look(motion.delta, settings.sensitivity);
+        transform.rotation = camera.rotation();
+    }
+}
+
+/// Fly with WASD, down with Q and up with E. Shift is faster and ctrl slower.
+fn move_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    settings: Res<EditorCameraSettings>,
+    mut cameras: Query<&mut Transform, With<EditorCamera>>,
+) {
+    let input = movement_input(&keys);
+    if input == Vec3::ZERO {
+        return;
+    }
+
+    let speed = settings.speed(
+        keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+        keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+    );
+    for mut transform in cameras.iter_mut() {
+        let translation = camera_translation(transform.rotation, input, speed, time.delta_secs());
+        transform.translation += translation;
+    }
+}
+
+#[cfg(test)]
+mod camera_test {
+    use std::f32::consts::FRAC_PI_2;
+
+    use bevy::prelude::*;
+
+    use crate::editor::camera::{EditorCamera, EditorCameraSettings, camera_translation};
+
+    #[test]
+    fn translation_test() {
+        let rotation = Quat::from_rotation_y(FRAC_PI_2);
+
+        // Forward follows the camera, up stays world up.
+        let forward = camera_translation(rotation, Vec3::NEG_Z, 2.0, 0.5);
+        assert!(forward.abs_diff_eq(Vec3::NEG_X, 1e-5));
+        let up = camera_translation(Quat::from_rotation_x(1.0), Vec3::Y, 1.0, 1.0);
+        assert!(up.abs_diff_eq(Vec3::Y, 1e-5));
+
+        // Diagonals are not faster.
+        let diagonal = camera_translation(Quat::IDENTITY, Vec3::new(1.0, 1.0, -1.0), 1.0, 1.0);
+        assert!((diagonal.length() - 1.0).abs() < 1e-5);
+
+        assert_eq!(
+            camera_translation(rotation, Vec3::ZERO, 1.0, 1.0),
+            Vec3::ZERO
+        );
+    }
+
+    #[test]
+    fn speed_test() {
+        let settings = EditorCameraSettings {
+            speed: 10.0,
+            fast_multiplier: 4.0,
+            slow_multiplier: 0.5,
+            ..default()
+        };
+
+        assert_eq!(settings.speed(false, false), 10.0);
+        assert_eq!(settings.speed(true, false), 40.0);
+        assert_eq!(settings.speed(false, true), 5.0);
+        assert_eq!(settings.speed(true, true), 20.0);
+    }
+
+    #[test]
+    fn look_test() {
+        let transform = Transform::from_xyz(1.0, 1.0, 1.0).looking_at(Vec3::ZERO, Vec3::Y);
+        let mut camera = EditorCamera::from_rotation(transform.rotation);
+        assert!(camera.rotation().abs_diff_eq(transform.rotation, 1e-5));
+
+        // The pitch is clamped before the camera flips over.
+        camera.look(Vec2::new(0.0, -10_000.0), 0.01);
+        assert!(camera.pitch < FRAC_PI_2);
+        assert!((camera.rotation() * Vec3::NEG_Z).y > 0.99);
+
+        let yaw = camera.yaw;
+        camera.look(Vec2::new(100.0, 0.0), 0.01);
+        assert!((camera.yaw - (yaw - 1.0).rem_euclid(std::f32::consts::TAU)).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use common::vehicle::{
+    Blueprint,
+    edit::{AppliedEdits, EditKind, EditOperation, apply_all},
+};
+
+use crate::{config::Config, editor::session::EditRequested, states::GameState};
+
+#[derive(Debug)]
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(OnEnter(GameState::InEditor), clear_history)
+            .add_systems(
+                Update,
+                undo_redo_system.run_if(in_state(GameState::InEditor)),
+            );
+    }
+}
+
+/// The undo and redo stacks of the editor.
+///
+/// Every entry is a group of inverse operations that is undone as a whole. Operations
+/// that can no longer be applied, because somebody else changed the same block in the
+/// meantime, are skipped instead of failing the whole group.
+///
+/// In a building session the server answers every request before it is recorded. An undo
+/// or redo group is kept until then, so a rejected one can be taken again.
+#[derive(Debug, Default, Resource)]
+pub struct EditHistory {
+    undo: VecDeque<Vec<EditOperation>>,
+    redo: Vec<Vec<EditOperation>>,
+    /// The group that is currently recorded into.
+    group: Option<Vec<EditOperation>>,
+    /// Close the group once every pending edit was answered.
+    closing: bool,
+    /// New edits that were sent to the server and not answered yet.
+    pending: usize,
+    /// The undo or redo group that was sent to the server and not answered yet.
+    in_flight: Option<(EditKind, Vec<EditOperation>)>,
+    /// The maximum number of undo groups, unbounded if [`None`].
+    limit: Option<usize>,
+}
+
+impl EditHistory {
+    /// Keep at most `limit` undo groups and drop the oldest ones.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..default()
+        }
+    }
+
+    /// Apply `operations` to `blueprint` and record their inverses as `kind`.
+    ///
+    /// Operations that can not be applied are skipped.
+    pub fn apply(
+        &mut self,
+        kind: EditKind,
+        operations: Vec<EditOperation>,
+        blueprint: &mut Blueprint,
+    ) -> AppliedEdits {
+        let edits = apply_all(operations, blueprint);
+        self.record(kind, edits.inverse.clone());
+
+        edits
+    }
+
+    /// Record the inverses of operations that were already applied, in the order they
+    /// have to be applied.
+    pub fn record(&mut self, kind: EditKind, inverse: Vec<EditOperation>) {
+        if inverse.is_empty() {
+            return;
+        }
+
+        match kind {
+            EditKind::Do => {
+                self.redo.clear();
+                match &mut self.group {
+                    // Later edits have to be reverted first.
+                    Some(group) => {
+                        group.splice(0..0, inverse);
+                    }
+                    None => self.push_undo(inverse),
+                }
+            }
+            EditKind::Undo => self.redo.push(inverse),
+            EditKind::Redo => self.push_undo(inverse),
+        }
+    }
+
+    /// Collect every following edit into one undo step until [`Self::end_group`].
+    ///
+    /// Edits of the previous group that the server did not answer yet end up in the new one.
+    pub fn begin_group(&mut self) {
+        self.close_group();
+        self.group = Some(Vec::new());
+    }
+
+    /// Stop collecting edits once the server answered the pending ones.
+    pub fn end_group(&mut self) {
+        if self.pending > 0 {
+            self.closing = true;
+        } else {
+            self.close_group();
+        }
+    }
+
+    /// Take the operations that revert the last group. Returns [`None`] if there is
+    /// nothing to undo or the server did not answer the last undo or redo yet.
+    ///
+    /// Apply them with [`EditKind::Undo`] so they can be redone.
+    pub fn take_undo(&mut self) -> Option<Vec<EditOperation>> {
+        if self.in_flight.is_some() {
+            return None;
+        }
+        self.close_group();
+        self.undo.pop_back()
+    }
+
+    /// Take the operations that apply the last undone group again. Returns [`None`] if
+    /// there is nothing to redo or the server did not answer the last undo or redo yet.
+    ///
+    /// Apply them with [`EditKind::Redo`] so they can be undone again.
+    pub fn take_redo(&mut self) -> Option<Vec<EditOperation>> {
+        if self.in_flight.is_some() {
+            return None;
+        }
+        self.close_group();
+        self.redo.pop()
+    }
+
+    /// Remember that `operations` were sent to the server instead of being applied.
+    pub fn sent(&mut self, kind: EditKind, operations: &[EditOperation]) {
+        match kind {
+            EditKind::Do => self.pending += 1,
+            EditKind::Undo | EditKind::Redo => {
+                self.in_flight = Some((kind, operations.to_vec()));
+            }
+        }
+    }
+
+    /// The server applied our oldest request of `kind`. Record it with [`Self::apply`]
+    /// first.
+    pub fn acknowledged(&mut self, kind: EditKind) {
+        match kind {
+            EditKind::Do => self.answered(),
+            EditKind::Undo | EditKind::Redo => self.in_flight = None,
+        }
+    }
+
+    /// The server rejected our oldest request of `kind`. A rejected undo or redo group is
+    /// put back, so it can be taken again.
+    pub fn rejected(&mut self, kind: EditKind) {
+        match (kind, self.in_flight.take()) {
+            (EditKind::Do, _) => self.answered(),
+            (EditKind::Undo, Some((EditKind::Undo, group))) => self.push_undo(group),
+            (EditKind::Redo, Some((EditKind::Redo, group))) => self.redo.push(group),
+            (_, in_flight) => self.in_flight = in_flight,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.group = None;
+        self.closing = false;
+        self.pending = 0;
+        self.in_flight = None;
+    }
+
+    fn answered(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+        if self.pending == 0 && self.closing {
+            self.close_group();
+        }
+    }
+
+    fn close_group(&mut self) {
+        self.closing = false;
+        if let Some(group) = self.group.take()
+            && !group.is_empty()
+        {
+            self.push_undo(group);
+        }
+    }
+
+    fn push_undo(&mut self, group: Vec<EditOperation>) {
+        self.undo.push_back(group);
+        if let Some(limit) = self.limit {
+            while self.undo.len() > limit {
+                self.undo.pop_front();
+            }
+        }
+    }
+}
+
+fn setup(mut commands: Commands, config: Res<Config>) {
+    commands.insert_resource(EditHistory::with_limit(config.undo_limit));
+}
+
+fn clear_history(mut history: ResMut<EditHistory>) {
+    history.clear();
+}
+
+/// Undo with ctrl + Z and redo with ctrl + Y or ctrl + shift + Z.
+fn undo_redo_system(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut history: ResMut<EditHistory>,
+) {
+    let control = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !control {
+        return;
+    }
+
+    let request = if keys.just_pressed(KeyCode::KeyZ) && !shift {
+        history.take_undo().map(|operations| EditRequested {
+            kind: EditKind::Undo,
+            operations,
+        })
+    } else if keys.just_pressed(KeyCode::KeyY) || (keys.just_pressed(KeyCode::KeyZ) && shift) {
+        history.take_redo().map(|operations| EditRequested {
+            kind: EditKind::Redo,
+            operations,
+        })
+    } else {
+        None
+    };
+
+    if let Some(request) = request {
+        commands.trigger(request);
+    }
+}
+
+#[cfg(test)]
+mod history_test {
+    use bevy::prelude::*;
+    use common::vehicle::{
+        Block, BlockRotation, Blueprint, Face, PropertyValue,
+        edit::{AppliedEdits, EditKind, EditOperation},
+    };
+
+    use crate::editor::history::EditHistory;
+
+    fn undo(history: &mut EditHistory, blueprint: &mut Blueprint) -> Option<AppliedEdits> {
+        let operations = history.take_undo()?;
+        Some(history.apply(EditKind::Undo, operations, blueprint))
+    }
+
+    fn redo(history: &mut EditHistory, blueprint: &mut Blueprint) -> Option<AppliedEdits> {
+        let operations = history.take_redo()?;
+        Some(history.apply(EditKind::Redo, operations, blueprint))
+    }
+
+    fn apply(history: &mut EditHistory, operation: EditOperation, blueprint: &mut Blueprint) {
+        let edits = history.apply(EditKind::Do, vec![operation], blueprint);
+        assert_eq!(edits.applied.len(), 1);
+    }
+
+    fn blueprint() -> Blueprint {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+
+        blueprint
+    }
+
+    /// A deterministic mix of every kind of operation.
+    fn operations() -> Vec<EditOperation> {
+        let mut operations = Vec::new();
+        for i in 1..20 {
+            let position = IVec3::new(i, i % 3, 0);
+            operations.push(EditOperation::Place(Block::new(position, "cube")));
+            match i % 4 {
+                0 => operations.push(EditOperation::Remove(position)),
+                1 => operations.push(EditOperation::Rotate {
+                    position,
+                    rotation: BlockRotation::new(Face::ALL[i as usize % 6], 2),
+                }),
+                2 => operations.push(EditOperation::Paint {
+                    position: IVec3::ZERO,
+                    color: Some(format!("#0000{i:02}")),
+                }),
+                _ => operations.push(EditOperation::SetProperty {
+                    position,
+                    key: "power".to_string(),
+                    value: Some(PropertyValue::Number(i as f64)),
+                }),
+            }
+        }
+        operations.push(EditOperation::Remove(IVec3::ZERO));
+
+        operations
+    }
+
+    #[test]
+    fn undo_all_test() {
+        let original = blueprint();
+        let mut blueprint = original.clone();
+        let mut history = EditHistory::default();
+
+        for operation in operations() {
+            apply(&mut history, operation, &mut blueprint);
+        }
+        let edited = blueprint.clone();
+
+        while undo(&mut history, &mut blueprint).is_some() {}
+        assert_eq!(blueprint, original);
+
+        while redo(&mut history, &mut blueprint).is_some() {}
+        assert_eq!(blueprint, edited);
+    }
+
+    #[test]
+    fn group_test() {
+        let original = blueprint();
+        let mut blueprint = original.clone();
+        let mut history = EditHistory::default();
+
+        history.begin_group();
+        for x in 1..5 {
+            apply(
+                &mut history,
+                EditOperation::Place(Block::new(IVec3::new(x, 0, 0), "cube")),
+                &mut blueprint,
+            );
+        }
+        history.end_group();
+        apply(
+            &mut history,
+            EditOperation::Remove(IVec3::ZERO),
+            &mut blueprint,
+        );
+
+        undo(&mut history, &mut blueprint).unwrap();
+        assert_eq!(blueprint.len(), 5);
+
+        let changes = undo(&mut history, &mut blueprint).unwrap();
+        assert_eq!(changes.removed.len(), 4);
+        assert_eq!(blueprint, original);
+        assert!(undo(&mut history, &mut blueprint).is_none());
+    }
+
+    #[test]
+    fn redo_cleared_test() {
+        let mut blueprint = blueprint();
+        let mut history = EditHistory::default();
+
+        apply(
+            &mut history,
+            EditOperation::Remove(IVec3::ZERO),
+            &mut blueprint,
+        );
+        undo(&mut history, &mut blueprint);
+
+        apply(
+            &mut history,
+            EditOperation::Place(Block::new(IVec3::X, "cube")),
+            &mut blueprint,
+        );
+        assert!(redo(&mut history, &mut blueprint).is_none());
+        assert_eq!(blueprint.len(), 2);
+    }
+
+    #[test]
+    fn limit_test() {
+        let mut blueprint = Blueprint::new("test");
+        let mut history = EditHistory::with_limit(2);
+
+        for x in 0..5 {
+            apply(
+                &mut history,
+                EditOperation::Place(Block::new(IVec3::new(x, 0, 0), "cube")),
+                &mut blueprint,
+            );
+        }
+
+        while undo(&mut history, &mut blueprint).is_some() {}
+        assert_eq!(blueprint.len(), 3);
+    }
+
+    #[test]
+    fn conflict_test() {
+        let mut blueprint = blueprint();
+        let mut history = EditHistory::default();
+
+        apply(
+            &mut history,
+            EditOperation::Place(Block::new(IVec3::X, "cube")),
+            &mut blueprint,
+        );
+        // Another player removes the block before it is undone.
+        blueprint.remove(IVec3::X);
+
+        let changes = undo(&mut history, &mut blueprint).unwrap();
+        assert!(changes.applied.is_empty());
+        assert_eq!(blueprint, self::blueprint());
+    }
+
+    #[test]
+    fn rejected_undo_test() {
+        let mut blueprint = blueprint();
+        let mut history = EditHistory::default();
+        apply(
+            &mut history,
+            EditOperation::Remove(IVec3::ZERO),
+            &mut blueprint,
+        );
+
+        // Nothing else is taken while the server did not answer.
+        let operations = history.take_undo().unwrap();
+        history.sent(EditKind::Undo, &operations);
+        assert!(history.take_undo().is_none());
+        assert!(history.take_redo().is_none());
+
+        // A rejected undo can be taken again.
+        history.rejected(EditKind::Undo);
+        assert_eq!(history.take_undo(), Some(operations.clone()));
+
+        history.sent(EditKind::Undo, &operations);
+        history.apply(EditKind::Undo, operations, &mut blueprint);
+        history.acknowledged(EditKind::Undo);
+        assert_eq!(blueprint, self::blueprint());
+        assert!(redo(&mut history, &mut blueprint).is_some());
+    }
+
+    #[test]
+    fn pending_group_test() {
+        let mut blueprint = Blueprint::new("test");
+        let mut history = EditHistory::default();
+        let place = |x| {
+            vec![EditOperation::Place(Block::new(
+                IVec3::new(x, 0, 0),
+                "cube",
+            ))]
+        };
+        let answer = |history: &mut EditHistory, blueprint: &mut Blueprint, x| {
+            history.apply(EditKind::Do, place(x), blueprint);
+            history.acknowledged(EditKind::Do);
+        };
+
+        // The server answers the edits of the group after it ended.
+        history.begin_group();
+        for x in 0..3 {
+            history.sent(EditKind::Do, &place(x));
+        }
+        history.end_group();
+        answer(&mut history, &mut blueprint, 0);
+        answer(&mut history, &mut blueprint, 1);
+        history.rejected(EditKind::Do);
+
+        // Later edits are not part of the group.
+        history.sent(EditKind::Do, &place(5));
+        answer(&mut history, &mut blueprint, 5);
+
+        assert_eq!(undo(&mut history, &mut blueprint).unwrap().removed.len(), 1);
+        assert_eq!(undo(&mut history, &mut blueprint).unwrap().removed.len(), 2);
+        assert!(blueprint.is_empty());
+        assert!(undo(&mut history, &mut blueprint).is_none());
+    }
+}
@@ -0,0 +1,664 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use common::{
+    part_registry::PartRegistry,
+    vehicle::{
+        BLOCK_SIZE, Block, BlockRotation, Blueprint, BlueprintError, Face, PartId,
+        VehicleBlocksChanged, VehicleBlueprint,
+        edit::{EditKind, EditOperation},
+        occupancy::Occupancy,
+        raycast::{BlockHit, raycast_blocks},
+    },
+};
+
+use crate::{
+    editor::{EditedVehicle, camera::EditorCamera, history::EditHistory, session::EditRequested},
+    states::GameState,
+};
+
+/// How far away from the camera blocks can be edited in meters.
+const MAX_EDIT_DISTANCE: f32 = 100.0;
+/// The mouse buttons that place and remove blocks.
+const EDIT_BUTTONS: [MouseButton; 2] = [MouseButton::Left, MouseButton::Right];
+
+#[derive(Debug)]
+pub struct PlacementPlugin;
+
+impl Plugin for PlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorTarget>()
+            .add_systems(OnEnter(GameState::InEditor), setup)
+            .add_systems(
+                Update,
+                (
+                    select_part_system,
+                    update_target_system,
+                    drag_group_system,
+                    edit_system,
+                    update_ghost_system,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InEditor)),
+            )
+            .add_observer(insert_occupancy_observer)
+            .add_observer(update_occupancy_observer);
+    }
+}
+
+/// The part that is placed next and its rotation.
+#[derive(Debug, Clone, PartialEq, Resource)]
+pub struct EditorSelection {
+    pub part: PartId,
+    pub rotation: BlockRotation,
+}
+
+impl EditorSelection {
+    /// Select the part after the current one in `registry`, wrapping around at the end.
+    fn next_part(&mut self, registry: &PartRegistry) {
+        let parts: Vec<_> = registry.iter().map(|part| &part.id).collect();
+        let next = parts
+            .iter()
+            .position(|id| **id == self.part)
+            .and_then(|index| parts.get(index + 1))
+            .or(parts.first());
+
+        if let Some(next) = next {
+            self.part = (*next).clone();
+        }
+    }
+}
+
+/// What the cursor points at.
+#[derive(Debug, Clone, Default, PartialEq, Resource)]
+pub struct EditorTarget {
+    /// The block cell under the cursor.
+    pub hit: Option<BlockHit>,
+    /// The block that would be placed on click, [`None`] if it does not fit.
+    pub placement: Option<Block>,
+}
+
+/// The layer of blocks a held mouse button edits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct EditorDrag {
+    face: Face,
+    /// The coordinate of the hit cells along the normal of `face`.
+    layer: i32,
+    /// The block that was edited last.
+    last: IVec3,
+}
+
+impl EditorDrag {
+    fn new(hit: &BlockHit, position: IVec3) -> Self {
+        Self {
+            face: hit.face,
+            layer: hit.cell[hit.face.axis()],
+            last: position,
+        }
+    }
+
+    /// Move on to the block at `position` if `hit` lies on the layer and the block was not
+    /// edited yet. Blocks placed in front of the layer are never hit on it, so a drag does
+    /// not grow towards the camera.
+    fn advance(&mut self, hit: &BlockHit, position: IVec3) -> bool {
+        if hit.face != self.face
+            || hit.cell[self.face.axis()] != self.layer
+            || position == self.last
+        {
+            return false;
+        }
+        self.last = position;
+
+        true
+    }
+}
+
+impl EditorTarget {
+    /// Find the target of a ray in the local space of the vehicle.
+    ///
+    /// The first block of an empty vehicle is always placed at the origin.
+    pub fn find(
+        blueprint: &Blueprint,
+        occupancy: &Occupancy,
+        registry: &PartRegistry,
+        selection: &EditorSelection,
+        ray: Ray3d,
+    ) -> Result<Self, BlueprintError> {
+        let Some(bounds) = occupancy.bounds() else {
+            return Ok(Self {
+                hit: None,
+                placement: Some(
+                    Block::new(IVec3::ZERO, selection.part.clone())
+                        .with_rotation(selection.rotation),
+                ),
+            });
+        };
+
+        let Some(hit) = raycast_blocks(
+            ray.origin,
+            ray.direction,
+            MAX_EDIT_DISTANCE,
+            bounds,
+            |cell| occupancy.is_occupied(cell),
+        ) else {
+            return Ok(Self::default());
+        };
+
+        let block =
+            Block::new(hit.adjacent(), selection.part.clone()).with_rotation(selection.rotation);
+        let placement = occupancy
+            .can_attach(blueprint, &hit, &block, registry)?
+            .then_some(block);
+
+        Ok(Self {
+            hit: Some(hit),
+            placement,
+        })
+    }
+
+    /// The position of the block that would be removed on click.
+    pub fn removal(&self, occupancy: &Occupancy) -> Option<IVec3> {
+        self.hit.and_then(|hit| occupancy.owner(hit.cell))
+    }
+
+    /// Turn the targeted block once around its up face.
+    ///
+    /// Returns [`None`] if nothing is targeted or the rotated block does not fit.
+    pub fn rotation(
+        &self,
+        blueprint: &Blueprint,
+        occupancy: &Occupancy,
+        registry: &PartRegistry,
+    ) -> Result<Option<EditOperation>, BlueprintError> {
+        let Some(block) = self
+            .removal(occupancy)
+            .and_then(|position| blueprint.get(position))
+        else {
+            return Ok(None);
+        };
+
+        let rotated = block.clone().with_rotation(block.rotation.next_turn());
+        let mut occupancy = occupancy.clone();
+        occupancy.remove(block, registry)?;
+
+        Ok(occupancy
+            .can_place(&rotated, registry)?
+            .then_some(EditOperation::Rotate {
+                position: rotated.position,
+                rotation: rotated.rotation,
+            }))
+    }
+}
+
+/// The cells of the edited vehicle, kept up to date with its blueprint.
+#[derive(Debug, Component, Deref)]
+pub struct VehicleOccupancy(Occupancy);
+
+/// The translucent preview of the part that would be placed.
+#[derive(Debug, Component)]
+struct EditorGhost;
+
+/// The local transform that covers every cell of `block`.
+fn ghost_transform(block: &Block, registry: &PartRegistry) -> Option<Transform> {
+    let part = registry.get(&block.part)?;
+    let (min, max) = part
+        .cells(block.rotation)
+        .fold((IVec3::MAX, IVec3::MIN), |(min, max), cell| {
+            (min.min(cell), max.max(cell))
+        });
+
+    let center = block.position.as_vec3() + (min + max).as_vec3() * 0.5;
+    // Slightly larger, so the ghost does not flicker on faces of neighbouring blocks.
+    let size = (max - min + IVec3::ONE).as_vec3() * 1.02;
+
+    Some(Transform::from_translation(center * BLOCK_SIZE).with_scale(size * BLOCK_SIZE))
+}
+
+fn setup(
+    mut commands: Commands,
+    vehicles: Query<Entity, With<EditedVehicle>>,
+    registry: Res<PartRegistry>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Some(part) = registry.iter().next() else {
+        error!("Can not edit vehicles without parts");
+        return;
+    };
+    commands.insert_resource(EditorSelection {
+        part: part.id.clone(),
+        rotation: BlockRotation::IDENTITY,
+    });
+
+    let vehicle = match vehicles.single() {
+        Ok(vehicle) => vehicle,
+        Err(_) => commands
+            .spawn(EditedVehicle::bundle(VehicleBlueprint(Blueprint::new(
+                "vehicle",
+            ))))
+            .id(),
+    };
+
+    commands.spawn((
+        Name::new("EditorGhost"),
+        EditorGhost,
+        Mesh3d(meshes.add(Cuboid::from_length(1.0))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgba(0.3, 0.6, 1.0, 0.4),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        })),
+        Visibility::Hidden,
+        ChildOf(vehicle),
+        DespawnOnExit(GameState::InEditor),
+    ));
+}
+
+fn insert_occupancy_observer(
+    trigger: On<Insert, VehicleBlueprint>,
+    mut commands: Commands,
+    vehicles: Query<&VehicleBlueprint, With<EditedVehicle>>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(blueprint) = vehicles.get(entity) else {
+        return;
+    };
+
+    match Occupancy::from_blueprint(blueprint, &registry) {
+        Ok(occupancy) => {
+            commands.entity(entity).insert(VehicleOccupancy(occupancy));
+        }
+        Err(e) => error!("Failed to edit vehicle `{}`: {}", blueprint.name(), e),
+    }
+}
+
+fn update_occupancy_observer(
+    trigger: On<VehicleBlocksChanged>,
+    mut vehicles: Query<&mut VehicleOccupancy>,
+    registry: Res<PartRegistry>,
+) {
+    let event = trigger.event();
+    let Ok(mut occupancy) = vehicles.get_mut(event.entity) else {
+        return;
+    };
+
+    let result = event
+        .removed
+        .iter()
+        .try_for_each(|block| occupancy.0.remove(block, &registry))
+        .and_then(|_| {
+            event
+                .added
+                .iter()
+                .try_for_each(|block| occupancy.0.add(block, &registry))
+        });
+    if let Err(e) = result {
+        error!("Failed to update the edited vehicle: {}", e);
+    }
+}
+
+/// Cycle through the parts with tab and rotate the selection with R and F.
+fn select_part_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut selection: ResMut<EditorSelection>,
+    registry: Res<PartRegistry>,
+) {
+    if keys.just_pressed(KeyCode::Tab) {
+        selection.next_part(&registry);
+        info!("Selected part `{}`", selection.part);
+    }
+    if keys.just_pressed(KeyCode::KeyR) && !is_shift_pressed(&keys) {
+        selection.rotation = selection.rotation.next_turn();
+    }
+    if keys.just_pressed(KeyCode::KeyF) {
+        selection.rotation = selection.rotation.next_up();
+    }
+}
+
+fn update_target_system(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<EditorCamera>>,
+    vehicles: Query<(&VehicleBlueprint, &VehicleOccupancy, &GlobalTransform), With<EditedVehicle>>,
+    selection: Res<EditorSelection>,
+    registry: Res<PartRegistry>,
+    mut target: ResMut<EditorTarget>,
+) {
+    let ray = windows
+        .single()
+        .ok()
+        .and_then(Window::cursor_position)
+        .zip(cameras.single().ok())
+        .and_then(|(cursor, (camera, camera_transform))| {
+            camera.viewport_to_world(camera_transform, cursor).ok()
+        });
+    let (Some(ray), Ok((blueprint, occupancy, transform))) = (ray, vehicles.single()) else {
+        *target = EditorTarget::default();
+        return;
+    };
+
+    let inverse = transform.affine().inverse();
+    let Ok(direction) = Dir3::new(inverse.transform_vector3(*ray.direction)) else {
+        return;
+    };
+    let local_ray = Ray3d::new(inverse.transform_point3(ray.origin), direction);
+
+    match EditorTarget::find(blueprint, occupancy, &registry, &selection, local_ray) {
+        Ok(new_target) => target.set_if_neq(new_target),
+        Err(e) => {
+            error!("Failed to find the editor target: {}", e);
+            target.set_if_neq(EditorTarget::default())
+        }
+    };
+}
+
+/// Collect the edits of a drag into one undo group.
+fn drag_group_system(mouse: Res<ButtonInput<MouseButton>>, mut history: ResMut<EditHistory>) {
+    if mouse.any_just_released(EDIT_BUTTONS) {
+        history.end_group();
+    }
+    if mouse.any_just_pressed(EDIT_BUTTONS) {
+        history.begin_group();
+    }
+}
+
+/// Place with the left mouse button, remove with the right one and rotate the targeted
+/// block with shift + R.
+///
+/// Moving the cursor while a button is held places or removes blocks on the layer the
+/// drag started on. A drag is undone as a whole.
+fn edit_system(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    target: Res<EditorTarget>,
+    registry: Res<PartRegistry>,
+    mut drag: Local<Option<EditorDrag>>,
+    vehicles: Query<(&VehicleBlueprint, &VehicleOccupancy), With<EditedVehicle>>,
+) {
+    let Ok((blueprint, occupancy)) = vehicles.single() else {
+        return;
+    };
+
+    if mouse.any_just_released(EDIT_BUTTONS) {
+        *drag = None;
+    }
+
+    let operation = if mouse.just_pressed(MouseButton::Left) {
+        let placement = target.placement.clone();
+        *drag = target
+            .hit
+            .zip(placement.as_ref())
+            .map(|(hit, block)| EditorDrag::new(&hit, block.position));
+        placement.map(EditOperation::Place)
+    } else if mouse.pressed(MouseButton::Left) {
+        target
+            .placement
+            .clone()
+            .filter(|block| dragged_to(&mut drag, &target, block.position))
+            .map(EditOperation::Place)
+    } else if mouse.just_pressed(MouseButton::Right) {
+        let removal = target.removal(occupancy);
+        *drag = target
+            .hit
+            .zip(removal)
+            .map(|(hit, position)| EditorDrag::new(&hit, position));
+        removal.map(EditOperation::Remove)
+    } else if mouse.pressed(MouseButton::Right) {
+        target
+            .removal(occupancy)
+            .filter(|position| dragged_to(&mut drag, &target, *position))
+            .map(EditOperation::Remove)
+    } else if keys.just_pressed(KeyCode::KeyR) && is_shift_pressed(&keys) {
+        match target.rotation(blueprint, occupancy, &registry) {
+            Ok(operation) => operation,
+            Err(e) => {
+                error!("Failed to rotate block: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let Some(operation) = operation else {
+        return;
+    };
+
+    commands.trigger(EditRequested {
+        kind: EditKind::Do,
+        operations: vec![operation],
+    });
+}
+
+/// Returns true if the cursor moved on to `position` on the layer of the drag.
+fn dragged_to(drag: &mut Option<EditorDrag>, target: &EditorTarget, position: IVec3) -> bool {
+    drag.as_mut()
+        .zip(target.hit)
+        .is_some_and(|(drag, hit)| drag.advance(&hit, position))
+}
+
+fn is_shift_pressed(keys: &ButtonInput<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+}
+
+fn update_ghost_system(
+    target: Res<EditorTarget>,
+    registry: Res<PartRegistry>,
+    mut ghosts: Query<(&mut Transform, &mut Visibility), With<EditorGhost>>,
+) {
+    let Ok((mut transform, mut visibility)) = ghosts.single_mut() else {
+        return;
+    };
+
+    match target
+        .placement
+        .as_ref()
+        .and_then(|block| ghost_transform(block, &registry))
+    {
+        Some(ghost) => {
+            *transform = ghost;
+            *visibility = Visibility::Inherited;
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
+
+#[cfg(test)]
+mod placement_test {
+    use bevy::prelude::*;
+    use common::{
+        part_registry::PartRegistry,
+        vehicle::{
+            BLOCK_SIZE, Block, BlockRotation, Blueprint, Face, occupancy::Occupancy,
+            raycast::BlockHit,
+        },
+    };
+
+    use crate::editor::placement::{EditorDrag, EditorSelection, EditorTarget, ghost_transform};
+    use common::vehicle::edit::EditOperation;
+
+    fn registry() -> PartRegistry {
+        PartRegistry::from_sources([(
+            "test",
+            r#"
+            [[material]]
+            id = "a"
+
+            [[part]]
+            id = "beam"
+            name = "Beam"
+            category = "Structure"
+            size = [1, 3, 1]
+            mass = 1.0
+            material = "a"
+
+            [[part]]
+            id = "cube"
+            name = "Cube"
+            category = "Structure"
+            mass = 1.0
+            material = "a"
+
+            [[part]]
+            id = "plank"
+            name = "Plank"
+            category = "Structure"
+            size = [2, 1, 1]
+            mass = 1.0
+            material = "a"
+            "#,
+        )])
+        .unwrap()
+    }
+
+    fn selection(part: &str) -> EditorSelection {
+        EditorSelection {
+            part: part.to_string(),
+            rotation: BlockRotation::IDENTITY,
+        }
+    }
+
+    /// A ray from above looking straight down on `x`, `z`.
+    fn ray_down(x: f32, z: f32) -> Ray3d {
+        Ray3d::new(Vec3::new(x, 10.0, z) * BLOCK_SIZE, Dir3::NEG_Y)
+    }
+
+    fn find(blueprint: &Blueprint, selection: &EditorSelection, ray: Ray3d) -> EditorTarget {
+        let registry = registry();
+        let occupancy = Occupancy::from_blueprint(blueprint, &registry).unwrap();
+
+        EditorTarget::find(blueprint, &occupancy, &registry, selection, ray).unwrap()
+    }
+
+    #[test]
+    fn first_block_test() {
+        let target = find(
+            &Blueprint::new("test"),
+            &selection("cube"),
+            ray_down(5.0, 5.0),
+        );
+
+        assert!(target.hit.is_none());
+        assert_eq!(target.placement, Some(Block::new(IVec3::ZERO, "cube")));
+    }
+
+    #[test]
+    fn place_on_face_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+
+        let target = find(&blueprint, &selection("cube"), ray_down(0.0, 0.0));
+        assert_eq!(target.hit.unwrap().face, Face::PosY);
+        assert_eq!(target.placement, Some(Block::new(IVec3::Y, "cube")));
+
+        // Missing the vehicle places nothing.
+        let target = find(&blueprint, &selection("cube"), ray_down(1.5, 0.0));
+        assert_eq!(target, EditorTarget::default());
+    }
+
+    #[test]
+    fn blocked_placement_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(0, 2, -1), "cube"))
+            .unwrap();
+
+        // An upright beam in front would run into the second cube.
+        let ray = Ray3d::new(Vec3::new(0.0, 0.0, -5.0) * BLOCK_SIZE, Dir3::Z);
+        let target = find(&blueprint, &selection("beam"), ray);
+        assert!(target.hit.is_some());
+        assert!(target.placement.is_none());
+
+        let mut rotated = selection("beam");
+        rotated.rotation = BlockRotation::new(Face::NegZ, 0);
+        let target = find(&blueprint, &rotated, ray);
+        assert_eq!(
+            target.placement.map(|block| block.position),
+            Some(IVec3::NEG_Z)
+        );
+    }
+
+    #[test]
+    fn drag_test() {
+        let hit = |cell: IVec3, face: Face| BlockHit {
+            cell,
+            face,
+            distance: 0.0,
+        };
+        // Dragging over the top of a row of blocks.
+        let mut drag = EditorDrag::new(&hit(IVec3::ZERO, Face::PosY), IVec3::Y);
+
+        assert!(!drag.advance(&hit(IVec3::ZERO, Face::PosY), IVec3::Y));
+        assert!(drag.advance(&hit(IVec3::X, Face::PosY), IVec3::new(1, 1, 0)));
+        // The placed blocks and the sides of the row are not on the layer.
+        assert!(!drag.advance(&hit(IVec3::Y, Face::PosY), IVec3::new(0, 2, 0)));
+        assert!(!drag.advance(&hit(IVec3::new(2, 0, 0), Face::PosX), IVec3::new(3, 0, 0)));
+        assert!(drag.advance(&hit(IVec3::ZERO, Face::PosY), IVec3::Y));
+    }
+
+    #[test]
+    fn removal_test() {
+        let registry = registry();
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "beam")).unwrap();
+        let occupancy = Occupancy::from_blueprint(&blueprint, &registry).unwrap();
+
+        // Hitting the top cell of the beam removes the whole beam.
+        let ray = Ray3d::new(Vec3::new(-1.0, 2.0, 0.0) * BLOCK_SIZE, Dir3::X);
+        let target =
+            EditorTarget::find(&blueprint, &occupancy, &registry, &selection("cube"), ray).unwrap();
+        assert_eq!(target.hit.unwrap().cell, IVec3::new(0, 2, 0));
+        assert_eq!(target.removal(&occupancy), Some(IVec3::ZERO));
+    }
+
+    #[test]
+    fn rotation_test() {
+        let registry = registry();
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "plank")).unwrap();
+        let occupancy = Occupancy::from_blueprint(&blueprint, &registry).unwrap();
+        let ray = Ray3d::new(Vec3::new(0.0, 10.0, 0.0) * BLOCK_SIZE, Dir3::NEG_Y);
+        let target =
+            EditorTarget::find(&blueprint, &occupancy, &registry, &selection("cube"), ray).unwrap();
+
+        assert_eq!(
+            target.rotation(&blueprint, &occupancy, &registry).unwrap(),
+            Some(EditOperation::Rotate {
+                position: IVec3::ZERO,
+                rotation: BlockRotation::IDENTITY.next_turn(),
+            })
+        );
+
+        // Block the cells the plank would turn into.
+        blueprint.add(Block::new(IVec3::Z, "cube")).unwrap();
+        blueprint.add(Block::new(IVec3::NEG_Z, "cube")).unwrap();
+        let occupancy = Occupancy::from_blueprint(&blueprint, &registry).unwrap();
+
+        assert_eq!(
+            target.rotation(&blueprint, &occupancy, &registry).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn next_part_test() {
+        let registry = registry();
+        let mut selection = selection("beam");
+
+        selection.next_part(&registry);
+        assert_eq!(selection.part, "cube");
+        selection.next_part(&registry);
+        assert_eq!(selection.part, "plank");
+        selection.next_part(&registry);
+        assert_eq!(selection.part, "beam");
+    }
+
+    #[test]
+    fn ghost_transform_test() {
+        let registry = registry();
+        let transform = ghost_transform(&Block::new(IVec3::X, "beam"), &registry).unwrap();
+
+        assert_eq!(transform.translation, Vec3::new(1.0, 1.0, 0.0) * BLOCK_SIZE);
+        assert!(transform.scale.y > transform.scale.x * 2.9);
+    }
+}
@@ -0,0 +1,491 @@
+use bevy::prelude::*;
+use common::vehicle::{
+    Blueprint, VehicleBlocksChanged, VehicleBlueprint,
+    edit::{AppliedEdits, EditKind, EditOperation, apply_all},
+};
+use lightyear::prelude::{Connected, MessageReceiver, MessageSender, PeerId};
+use log::{info, warn};
+use protocol::{
+    channels::ReliableChannel,
+    messages::{
+        CreateEditorSession, EditApplied, EditRejected, EditRequest, EditorSessionInfo,
+        EditorSessionList, EditorSessionRejected, EditorSnapshot, InviteToEditorSession,
+        JoinEditorSession, KickFromEditorSession, KickedFromEditorSession, LeaveEditorSession,
+        ListEditorSessions, SessionId,
+    },
+};
+
+use crate::{
+    editor::{EditedVehicle, history::EditHistory},
+    network::LocalClient,
+    states::{AppState, GameState},
+};
+
+#[derive(Debug)]
+pub struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorConnection>()
+            .init_resource::<AvailableEditorSessions>()
+            .add_systems(OnExit(GameState::InEditor), leave_session)
+            .add_systems(
+                Update,
+                (
+                    session_list_system,
+                    session_edits_system,
+                    edit_rejected_system,
+                    kicked_from_session_system,
+                    session_rejected_system,
+                )
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_observer(list_sessions_observer)
+            .add_observer(create_session_observer)
+            .add_observer(join_session_observer)
+            .add_observer(leave_session_observer)
+            .add_observer(kick_member_observer)
+            .add_observer(invite_member_observer)
+            .add_observer(edit_requested_observer);
+    }
+}
+
+/// Ask the server for its building sessions, they end up in [`AvailableEditorSessions`].
+#[derive(Debug, Event)]
+pub struct ListEditorSessionsEvent;
+
+/// Create a building session and open the editor once the server created it.
+#[derive(Debug, Event)]
+pub struct CreateEditorSessionEvent {
+    pub name: String,
+}
+
+/// Join a building session and open the editor once the server sent the vehicle.
+#[derive(Debug, Event)]
+pub struct JoinEditorSessionEvent {
+    pub session: SessionId,
+}
+
+/// Leave the building session and close the editor.
+#[derive(Debug, Event)]
+pub struct LeaveEditorSessionEvent;
+
+/// Remove a member from the building session until it is invited. Only works for the owner.
+#[derive(Debug, Event)]
+pub struct KickFromEditorSessionEvent {
+    pub client: PeerId,
+}
+
+/// Let a player build in the building session. Only works for the owner.
+#[derive(Debug, Event)]
+pub struct InviteToEditorSessionEvent {
+    pub client: PeerId,
+}
+
+/// Trigger to edit the [`EditedVehicle`].
+///
+/// In a building session the edit is sent to the server and applied once it comes back,
+/// otherwise it is applied right away.
+#[derive(Debug, Event)]
+pub struct EditRequested {
+    pub kind: EditKind,
+    pub operations: Vec<EditOperation>,
+}
+
+/// The building sessions of the server, as of the last [`ListEditorSessionsEvent`].
+#[derive(Debug, Default, Resource)]
+pub struct AvailableEditorSessions(pub Vec<EditorSessionInfo>);
+
+/// The building session the editor is connected to.
+#[derive(Debug, Default, Resource)]
+pub struct EditorConnection {
+    /// [`None`] if the vehicle is edited offline.
+    session: Option<SessionId>,
+    /// The sequence number of the last applied [`EditApplied`].
+    sequence: u64,
+}
+
+impl EditorConnection {
+    pub fn session(&self) -> Option<SessionId> {
+        self.session
+    }
+}
+
+fn list_sessions_observer(
+    _: On<ListEditorSessionsEvent>,
+    mut client: Query<&mut MessageSender<ListEditorSessions>, (With<LocalClient>, With<Connected>)>,
+) {
+    if let Ok(mut sender) = client.single_mut() {
+        sender.send::<ReliableChannel>(ListEditorSessions);
+    }
+}
+
+fn create_session_observer(
+    trigger: On<CreateEditorSessionEvent>,
+    mut client: Query<
+        &mut MessageSender<CreateEditorSession>,
+        (With<LocalClient>, With<Connected>),
+    >,
+) {
+    if let Ok(mut sender) = client.single_mut() {
+        sender.send::<ReliableChannel>(CreateEditorSession {
+            name: trigger.event().name.clone(),
+        });
+    }
+}
+
+fn join_session_observer(
+    trigger: On<JoinEditorSessionEvent>,
+    mut client: Query<&mut MessageSender<JoinEditorSession>, (With<LocalClient>, With<Connected>)>,
+) {
+    if let Ok(mut sender) = client.single_mut() {
+        sender.send::<ReliableChannel>(JoinEditorSession {
+            session: trigger.event().session,
+        });
+    }
+}
+
+fn leave_session_observer(
+    _: On<LeaveEditorSessionEvent>,
+    client: Query<&mut MessageSender<LeaveEditorSession>, (With<LocalClient>, With<Connected>)>,
+    connection: ResMut<EditorConnection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    leave_session(client, connection);
+    next_state.set(GameState::InGame);
+}
+
+fn kick_member_observer(
+    trigger: On<KickFromEditorSessionEvent>,
+    mut client: Query<
+        &mut MessageSender<KickFromEditorSession>,
+        (With<LocalClient>, With<Connected>),
+    >,
+    connection: Res<EditorConnection>,
+) {
+    if let Some(session) = connection.session
+        && let Ok(mut sender) = client.single_mut()
+    {
+        sender.send::<ReliableChannel>(KickFromEditorSession {
+            session,
+            client: trigger.event().client,
+        });
+    }
+}
+
+fn invite_member_observer(
+    trigger: On<InviteToEditorSessionEvent>,
+    mut client: Query<
+        &mut MessageSender<InviteToEditorSession>,
+        (With<LocalClient>, With<Connected>),
+    >,
+    connection: Res<EditorConnection>,
+) {
+    if let Some(session) = connection.session
+        && let Ok(mut sender) = client.single_mut()
+    {
+        sender.send::<ReliableChannel>(InviteToEditorSession {
+            session,
+            client: trigger.event().client,
+        });
+    }
+}
+
+fn leave_session(
+    mut client: Query<&mut MessageSender<LeaveEditorSession>, (With<LocalClient>, With<Connected>)>,
+    mut connection: ResMut<EditorConnection>,
+) {
+    if let Some(session) = connection.session.take()
+        && let Ok(mut sender) = client.single_mut()
+    {
+        sender.send::<ReliableChannel>(LeaveEditorSession { session });
+    }
+}
+
+fn edit_requested_observer(
+    trigger: On<EditRequested>,
+    mut commands: Commands,
+    mut client: Query<&mut MessageSender<EditRequest>, (With<LocalClient>, With<Connected>)>,
+    connection: Res<EditorConnection>,
+    mut history: ResMut<EditHistory>,
+    mut vehicles: Query<(Entity, &mut VehicleBlueprint), With<EditedVehicle>>,
+) {
+    let EditRequested { kind, operations } = trigger.event();
+
+    if let Some(session) = connection.session {
+        history.sent(*kind, operations);
+        match client.single_mut() {
+            Ok(mut sender) => sender.send::<ReliableChannel>(EditRequest {
+                session,
+                kind: *kind,
+                operations: operations.clone(),
+            }),
+            Err(_) => {
+                warn!("Lost the connection to building session {}", session);
+                history.rejected(*kind);
+            }
+        }
+        return;
+    }
+
+    let Ok((entity, mut blueprint)) = vehicles.single_mut() else {
+        return;
+    };
+    let edits = history.apply(*kind, operations.clone(), &mut blueprint);
+    trigger_blocks_changed(&mut commands, entity, edits);
+}
+
+fn session_list_system(
+    mut client: Query<&mut MessageReceiver<EditorSessionList>, With<LocalClient>>,
+    mut available: ResMut<AvailableEditorSessions>,
+) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    if let Some(EditorSessionList { sessions }) = receiver.receive().last() {
+        if sessions.is_empty() {
+            info!("There are no building sessions");
+        }
+        for (index, session) in sessions.iter().enumerate() {
+            let members: Vec<_> = session
+                .members
+                .iter()
+                .filter(|member| **member != session.owner)
+                .collect();
+            info!(
+                "{}: building session {} `{}` of {:?}, other members {:?}, builders {:?}",
+                index + 1,
+                session.id,
+                session.name,
+                session.owner,
+                members,
+                session.builders
+            );
+        }
+        available.0 = sessions;
+    }
+}
+
+/// Open the editor with the vehicle of the session after creating or joining it, and apply
+/// the edits of every member, including our own, in the order of the server.
+///
+/// Edits that arrive in the same frame as the snapshot are applied to it before the editor
+/// opens.
+fn session_edits_system(
+    mut commands: Commands,
+    mut client: Query<
+        (
+            &mut MessageReceiver<EditorSnapshot>,
+            &mut MessageReceiver<EditApplied>,
+        ),
+        With<LocalClient>,
+    >,
+    mut connection: ResMut<EditorConnection>,
+    mut history: ResMut<EditHistory>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut vehicles: Query<(Entity, &mut VehicleBlueprint), With<EditedVehicle>>,
+) {
+    let Ok((mut snapshots, mut edits)) = client.single_mut() else {
+        return;
+    };
+
+    if let Some(snapshot) = snapshots.receive().last() {
+        let mut blueprint = apply_snapshot(snapshot, &mut connection, &mut history);
+        for applied in edits.receive() {
+            apply_edit(applied, &mut connection, &mut history, &mut blueprint);
+        }
+
+        let blueprint = VehicleBlueprint(blueprint);
+        match vehicles.single() {
+            Ok((entity, _)) => {
+                commands.entity(entity).insert(blueprint);
+            }
+            Err(_) => {
+                commands.spawn(EditedVehicle::bundle(blueprint));
+            }
+        }
+        next_state.set(GameState::InEditor);
+        return;
+    }
+
+    let Ok((entity, mut blueprint)) = vehicles.single_mut() else {
+        return;
+    };
+    for applied in edits.receive() {
+        if let Some(changes) = apply_edit(applied, &mut connection, &mut history, &mut blueprint.0)
+        {
+            trigger_blocks_changed(&mut commands, entity, changes);
+        }
+    }
+}
+
+/// Connect to the session of `snapshot` and return its blueprint.
+fn apply_snapshot(
+    snapshot: EditorSnapshot,
+    connection: &mut EditorConnection,
+    history: &mut EditHistory,
+) -> Blueprint {
+    info!(
+        "Joined building session {} at {}",
+        snapshot.session, snapshot.sequence
+    );
+    *connection = EditorConnection {
+        session: Some(snapshot.session),
+        sequence: snapshot.sequence,
+    };
+    history.clear();
+
+    snapshot.blueprint
+}
+
+/// Apply `applied` to `blueprint` if it is a new edit of the connected session.
+fn apply_edit(
+    applied: EditApplied,
+    connection: &mut EditorConnection,
+    history: &mut EditHistory,
+    blueprint: &mut Blueprint,
+) -> Option<AppliedEdits> {
+    // Edits older than the snapshot are already part of it.
+    if connection.session != Some(applied.session) || applied.sequence <= connection.sequence {
+        return None;
+    }
+    connection.sequence = applied.sequence;
+
+    let edits = if applied.own {
+        let edits = history.apply(applied.kind, applied.operations, blueprint);
+        history.acknowledged(applied.kind);
+        edits
+    } else {
+        apply_all(applied.operations, blueprint)
+    };
+    Some(edits)
+}
+
+fn kicked_from_session_system(
+    mut client: Query<&mut MessageReceiver<KickedFromEditorSession>, With<LocalClient>>,
+    mut connection: ResMut<EditorConnection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    for KickedFromEditorSession { session } in receiver.receive() {
+        if connection.session == Some(session) {
+            warn!("Kicked from building session {}", session);
+            *connection = EditorConnection::default();
+            next_state.set(GameState::InGame);
+        }
+    }
+}
+
+fn session_rejected_system(
+    mut client: Query<&mut MessageReceiver<EditorSessionRejected>, With<LocalClient>>,
+) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    for EditorSessionRejected { reason } in receiver.receive() {
+        warn!("Building session request rejected: {}", reason);
+    }
+}
+
+/// Put rejected undo and redo groups back into the history.
+fn edit_rejected_system(
+    mut client: Query<&mut MessageReceiver<EditRejected>, With<LocalClient>>,
+    connection: Res<EditorConnection>,
+    mut history: ResMut<EditHistory>,
+) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    for EditRejected {
+        session,
+        kind,
+        reason,
+    } in receiver.receive()
+    {
+        warn!("Building session {} rejected the edit: {}", session, reason);
+        if connection.session == Some(session) {
+            history.rejected(kind);
+        }
+    }
+}
+
+fn trigger_blocks_changed(commands: &mut Commands, entity: Entity, edits: AppliedEdits) {
+    if edits.added.is_empty() && edits.removed.is_empty() {
+        return;
+    }
+
+    commands.trigger(VehicleBlocksChanged {
+        entity,
+        added: edits.added,
+        removed: edits.removed,
+    });
+}
+
+#[cfg(test)]
+mod session_test {
+    use bevy::prelude::*;
+    use common::vehicle::{
+        Block, Blueprint,
+        edit::{EditKind, EditOperation},
+    };
+    use protocol::messages::{EditApplied, EditorSnapshot};
+
+    use crate::editor::{
+        history::EditHistory,
+        session::{EditorConnection, apply_edit, apply_snapshot},
+    };
+
+    fn place(sequence: u64, x: i32) -> EditApplied {
+        EditApplied {
+            session: 7,
+            sequence,
+            kind: EditKind::Do,
+            own: false,
+            operations: vec![EditOperation::Place(Block::new(
+                IVec3::new(x, 0, 0),
+                "cube",
+            ))],
+        }
+    }
+
+    #[test]
+    fn snapshot_and_edit_test() {
+        let mut connection = EditorConnection::default();
+        let mut history = EditHistory::default();
+
+        let mut snapshot = Blueprint::new("test");
+        snapshot.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        snapshot.add(Block::new(IVec3::X, "cube")).unwrap();
+
+        // The server sent the snapshot and the next edit in the same tick.
+        let mut blueprint = apply_snapshot(
+            EditorSnapshot {
+                session: 7,
+                sequence: 3,
+                blueprint: snapshot,
+            },
+            &mut connection,
+            &mut history,
+        );
+        assert!(apply_edit(place(3, 1), &mut connection, &mut history, &mut blueprint).is_none());
+        let edits = apply_edit(place(4, 2), &mut connection, &mut history, &mut blueprint).unwrap();
+
+        assert_eq!(edits.added.len(), 1);
+        assert_eq!(edits.added[0].position, IVec3::new(2, 0, 0));
+        assert_eq!(blueprint.len(), 3);
+        assert_eq!(connection.session(), Some(7));
+        assert_eq!(connection.sequence, 4);
+
+        // Edits of other sessions are ignored.
+        let mut other = place(5, 3);
+        other.session = 8;
+        assert!(apply_edit(other, &mut connection, &mut history, &mut blueprint).is_none());
+        assert_eq!(blueprint.len(), 3);
+    }
+}
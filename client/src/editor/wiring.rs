@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use common::{
+    part_registry::PartRegistry,
+    vehicle::{
+        Blueprint, VehicleBlueprint,
+        edit::{EditKind, EditOperation},
+        signal::{Wire, block_function},
+    },
+};
+
+use crate::{
+    editor::{
+        EditedVehicle,
+        placement::{EditorTarget, VehicleOccupancy},
+        session::EditRequested,
+    },
+    states::GameState,
+};
+
+#[derive(Debug)]
+pub struct WiringPlugin;
+
+impl Plugin for WiringPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WiringSource>()
+            .add_systems(OnEnter(GameState::InEditor), clear_source)
+            .add_systems(Update, wiring_system.run_if(in_state(GameState::InEditor)));
+    }
+}
+
+/// The block whose output is connected by the next wiring click.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Resource)]
+struct WiringSource(Option<IVec3>);
+
+/// The wire from the first output of `from` to the first free input of `to` with the same
+/// type, [`None`] if the blocks can not be connected.
+fn first_wire(
+    blueprint: &Blueprint,
+    registry: &PartRegistry,
+    from: IVec3,
+    to: IVec3,
+) -> Option<Wire> {
+    let outputs = block_function(blueprint, registry, from)?.outputs();
+    let inputs = block_function(blueprint, registry, to)?.inputs();
+
+    outputs.iter().find_map(|output| {
+        inputs
+            .iter()
+            .filter(|input| input.signal_type == output.signal_type)
+            .find(|input| {
+                !blueprint
+                    .wires()
+                    .any(|wire| wire.to.block == to && wire.to.port == input.name)
+            })
+            .map(|input| Wire::new(from, output.name, to, input.name))
+    })
+}
+
+/// The operations removing every wire into `to`.
+fn disconnect_all(blueprint: &Blueprint, to: IVec3) -> Vec<EditOperation> {
+    blueprint
+        .wires()
+        .filter(|wire| wire.to.block == to)
+        .cloned()
+        .map(EditOperation::Disconnect)
+        .collect()
+}
+
+fn clear_source(mut source: ResMut<WiringSource>) {
+    *source = WiringSource::default();
+}
+
+/// Pick the targeted block as the source with C and connect it to the next targeted block
+/// with C again. Shift + C removes the wires into the targeted block.
+// TODO: Let the player choose the ports once there is a UI.
+fn wiring_system(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    target: Res<EditorTarget>,
+    registry: Res<PartRegistry>,
+    mut source: ResMut<WiringSource>,
+    vehicles: Query<(&VehicleBlueprint, &VehicleOccupancy), With<EditedVehicle>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyC) {
+        return;
+    }
+    let Ok((blueprint, occupancy)) = vehicles.single() else {
+        return;
+    };
+    let Some(position) = target.removal(occupancy) else {
+        *source = WiringSource::default();
+        return;
+    };
+
+    let operations = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        disconnect_all(blueprint, position)
+    } else {
+        let Some(from) = source.0.take() else {
+            info!("Wiring from the block at {}", position);
+            source.0 = Some(position);
+            return;
+        };
+
+        match first_wire(blueprint, &registry, from, position) {
+            Some(wire) => vec![EditOperation::Connect(wire)],
+            None => {
+                warn!("The block at {} can not be wired to {}", from, position);
+                return;
+            }
+        }
+    };
+    if operations.is_empty() {
+        return;
+    }
+
+    commands.trigger(EditRequested {
+        kind: EditKind::Do,
+        operations,
+    });
+}
+
+#[cfg(test)]
+mod wiring_test {
+    use bevy::prelude::*;
+    use common::{
+        part_registry::PartRegistry,
+        vehicle::{Block, Blueprint, edit::EditOperation, signal::Wire},
+    };
+
+    use crate::editor::wiring::{disconnect_all, first_wire};
+
+    fn blueprint() -> Blueprint {
+        let mut blueprint = Blueprint::new("test");
+        for (x, part) in ["switch", "dial", "and_gate", "cube"]
+            .into_iter()
+            .enumerate()
+        {
+            blueprint
+                .add(Block::new(IVec3::new(x as i32, 0, 0), part))
+                .unwrap();
+        }
+
+        blueprint
+    }
+
+    #[test]
+    fn first_wire_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = blueprint();
+        let (switch, dial, gate, cube) = (
+            IVec3::ZERO,
+            IVec3::X,
+            IVec3::new(2, 0, 0),
+            IVec3::new(3, 0, 0),
+        );
+
+        let wire = first_wire(&blueprint, &registry, switch, gate).unwrap();
+        assert_eq!(wire, Wire::new(switch, "out", gate, "a"));
+
+        // The next wire takes the next free input.
+        blueprint.connect(wire).unwrap();
+        assert_eq!(
+            first_wire(&blueprint, &registry, switch, gate),
+            Some(Wire::new(switch, "out", gate, "b"))
+        );
+
+        assert_eq!(first_wire(&blueprint, &registry, dial, gate), None);
+        assert_eq!(first_wire(&blueprint, &registry, switch, cube), None);
+        assert_eq!(first_wire(&blueprint, &registry, gate, switch), None);
+    }
+
+    #[test]
+    fn disconnect_all_test() {
+        let mut blueprint = blueprint();
+        let (switch, gate) = (IVec3::ZERO, IVec3::new(2, 0, 0));
+        let wire = Wire::new(switch, "out", gate, "a");
+        blueprint.connect(wire.clone()).unwrap();
+
+        assert_eq!(
+            disconnect_all(&blueprint, gate),
+            vec![EditOperation::Disconnect(wire)]
+        );
+        assert!(disconnect_all(&blueprint, switch).is_empty());
+    }
+}
@@ -1,13 +1,24 @@
 use bevy::prelude::*;
 
-use crate::game::world::WorldPlugin;
+use crate::game::{
+    player::PlayerPlugin,
+    vehicle::{VehicleMeshPlugin, spawn::VehicleSpawnPlugin},
+    world::WorldPlugin,
+};
 
+mod player;
+mod vehicle;
 mod world;
 
 pub struct GamePlugins;
 
 impl Plugin for GamePlugins {
     fn build(&self, app: &mut App) {
-        app.add_plugins(WorldPlugin);
+        app.add_plugins((
+            WorldPlugin,
+            VehicleMeshPlugin,
+            VehicleSpawnPlugin,
+            PlayerPlugin,
+        ));
     }
 }
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use common::character::{CharacterController, CharacterInput, CharacterState};
+use lightyear::prelude::{
+    Controlled, Predicted,
+    client::input::InputSystems,
+    input::native::{ActionState, InputMarker},
+};
+
+use crate::{game::player::seat::SeatPlugin, states::GameState};
+
+mod seat;
+
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(SeatPlugin)
+            .add_systems(
+                FixedPreUpdate,
+                write_input_system.in_set(InputSystems::WriteClientInputs),
+            )
+            .add_systems(Update, (mark_local_character_system, character_mesh_system));
+    }
+}
+
+/// The predicted copy of the character this client controls.
+type LocalCharacter = (With<CharacterController>, With<Predicted>, With<Controlled>);
+
+/// The character this client controls is predicted and gets the inputs.
+fn mark_local_character_system(
+    mut commands: Commands,
+    characters: Query<Entity, (LocalCharacter, Without<InputMarker<CharacterInput>>)>,
+) {
+    for entity in characters.iter() {
+        commands
+            .entity(entity)
+            .insert(InputMarker::<CharacterInput>::default());
+    }
+}
+
+/// Walk with WASD, jump with space, run with shift, crouch with ctrl and go prone with alt.
+///
+/// Forward is where the camera looks. There is no input outside of [`GameState::InGame`].
+fn write_input_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    state: Option<Res<State<GameState>>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut characters: Query<&mut ActionState<CharacterInput>, With<InputMarker<CharacterInput>>>,
+) {
+    let playing = state.is_some_and(|state| *state.get() == GameState::InGame);
+    let input = if playing {
+        let axis = |negative, positive| {
+            (keys.pressed(positive) as i32 - keys.pressed(negative) as i32) as f32
+        };
+        let forward = cameras
+            .iter()
+            .find(|(camera, _)| camera.is_active)
+            .map(|(_, transform)| transform.forward())
+            .unwrap_or(Dir3::NEG_Z);
+
+        CharacterInput {
+            movement: Vec2::new(
+                axis(KeyCode::KeyA, KeyCode::KeyD),
+                axis(KeyCode::KeyS, KeyCode::KeyW),
+            ),
+            yaw: (-forward.x).atan2(-forward.z),
+            jump: keys.pressed(KeyCode::Space),
+            run: keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+            crouch: keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+            prone: keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]),
+        }
+    } else {
+        CharacterInput::default()
+    };
+
+    for mut action_state in characters.iter_mut() {
+        action_state.0 = input.clone();
+    }
+}
+
+/// Show characters as capsules that follow their stance.
+fn character_mesh_system(
+    mut commands: Commands,
+    characters: Query<(Entity, &CharacterController, &CharacterState), Changed<CharacterState>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut material: Local<Option<Handle<StandardMaterial>>>,
+) {
+    for (entity, controller, state) in characters.iter() {
+        let material = material
+            .get_or_insert_with(|| materials.add(Color::srgb(0.8, 0.5, 0.2)))
+            .clone();
+        let height = controller.height(state.stance);
+
+        commands.entity(entity).insert((
+            Mesh3d(meshes.add(Capsule3d::new(
+                controller.radius,
+                height - controller.radius * 2.0,
+            ))),
+            MeshMaterial3d(material),
+        ));
+    }
+}
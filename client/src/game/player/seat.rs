@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use common::{
+    character::CharacterInput,
+    part_registry::PartRegistry,
+    vehicle::{
+        VehicleBlueprint,
+        seat::{Seated, seat_at},
+    },
+};
+use lightyear::prelude::{Connected, MessageSender, input::native::InputMarker};
+use protocol::{
+    channels::ReliableChannel,
+    messages::{EnterSeat, ExitSeat},
+};
+
+use crate::{network::LocalClient, states::GameState};
+
+/// The height of the eyes above the center of a seated character.
+const EYE_HEIGHT: f32 = 0.4;
+
+pub struct SeatPlugin;
+
+impl Plugin for SeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (seat_key_system, seat_camera_system).run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// The camera at the eyes of our seated character, a child of the vehicle.
+#[derive(Debug, Clone, PartialEq, Component)]
+struct SeatCamera {
+    vehicle: Entity,
+    seat: IVec3,
+}
+
+/// Sit down on the closest seat or get up with F.
+fn seat_key_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    characters: Query<Has<Seated>, With<InputMarker<CharacterInput>>>,
+    mut enter_senders: Query<&mut MessageSender<EnterSeat>, (With<LocalClient>, With<Connected>)>,
+    mut exit_senders: Query<&mut MessageSender<ExitSeat>, (With<LocalClient>, With<Connected>)>,
+) {
+    if !keys.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    let Ok(seated) = characters.single() else {
+        return;
+    };
+
+    if seated {
+        if let Ok(mut sender) = exit_senders.single_mut() {
+            sender.send::<ReliableChannel>(ExitSeat);
+        }
+    } else if let Ok(mut sender) = enter_senders.single_mut() {
+        sender.send::<ReliableChannel>(EnterSeat);
+    }
+}
+
+/// Look through the eyes of our character while it is seated and go back to the other
+/// cameras once it gets up.
+fn seat_camera_system(
+    mut commands: Commands,
+    characters: Query<&Seated, With<InputMarker<CharacterInput>>>,
+    seat_cameras: Query<(Entity, &SeatCamera)>,
+    mut cameras: Query<&mut Camera, Without<SeatCamera>>,
+    vehicles: Query<&VehicleBlueprint>,
+    registry: Res<PartRegistry>,
+) {
+    let wanted = characters.single().ok().map(|seated| SeatCamera {
+        vehicle: seated.vehicle,
+        seat: seated.seat,
+    });
+
+    let mut current = None;
+    for (entity, camera) in seat_cameras.iter() {
+        if Some(camera) == wanted.as_ref() {
+            current = Some(entity);
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let Some(wanted) = wanted else {
+        if !seat_cameras.is_empty() {
+            for mut camera in cameras.iter_mut() {
+                camera.is_active = true;
+            }
+        }
+        return;
+    };
+
+    if current.is_none() {
+        let Some(seat) = vehicles
+            .get(wanted.vehicle)
+            .ok()
+            .and_then(|blueprint| seat_at(blueprint, wanted.seat, &registry))
+        else {
+            return;
+        };
+
+        commands.spawn((
+            Name::new("SeatCamera"),
+            Camera3d::default(),
+            seat.mul_transform(Transform::from_xyz(0.0, EYE_HEIGHT, 0.0)),
+            ChildOf(wanted.vehicle),
+            wanted,
+        ));
+    }
+
+    // Other cameras can be turned back on, e.g. by leaving the editor.
+    for mut camera in cameras.iter_mut() {
+        if camera.is_active {
+            camera.is_active = false;
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use common::{
+    part_registry::PartRegistry,
+    vehicle::{
+        BLOCK_SIZE, Blueprint, VehicleBlueprint,
+        joint::{VehicleBodies, VehicleSubBody},
+        mesh::build_vehicle_mesh,
+    },
+};
+
+use crate::editor::EditedVehicle;
+
+pub mod spawn;
+
+pub struct VehicleMeshPlugin;
+
+impl Plugin for VehicleMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VehicleMaterials>().add_systems(
+            PostUpdate,
+            (update_vehicle_mesh_system, sub_body_mesh_system),
+        );
+    }
+}
+
+/// A child entity holding a part of the mesh of a vehicle.
+#[derive(Debug, Component)]
+pub struct VehicleMeshPart;
+
+/// One material per [`common::part_registry::MaterialDefinition`], shared by all vehicles.
+#[derive(Debug, Default, Resource)]
+struct VehicleMaterials(HashMap<String, Handle<StandardMaterial>>);
+
+impl VehicleMaterials {
+    fn get_or_create(
+        &mut self,
+        id: &str,
+        registry: &PartRegistry,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.0
+            .entry(id.to_string())
+            .or_insert_with(|| {
+                let material = match registry.material(id) {
+                    Some(definition) => StandardMaterial {
+                        base_color: Color::linear_rgba(
+                            definition.color[0],
+                            definition.color[1],
+                            definition.color[2],
+                            definition.color[3],
+                        ),
+                        metallic: definition.metallic,
+                        perceptual_roughness: definition.roughness,
+                        ..default()
+                    },
+                    None => StandardMaterial::default(),
+                };
+
+                materials.add(material)
+            })
+            .clone()
+    }
+}
+
+type VehicleMeshChanged = Or<(Changed<VehicleBlueprint>, Changed<VehicleBodies>)>;
+type VehicleMeshData = (
+    Entity,
+    &'static VehicleBlueprint,
+    Option<&'static VehicleBodies>,
+    Option<&'static Children>,
+    Has<EditedVehicle>,
+);
+
+#[derive(SystemParam)]
+struct VehicleAssets<'w> {
+    asset_server: Res<'w, AssetServer>,
+    vehicle_materials: ResMut<'w, VehicleMaterials>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+}
+
+/// Rebuild the mesh of every vehicle whose blueprint changed.
+///
+/// Spawned vehicles only show their first body, the others are shown by their
+/// [`VehicleSubBody`]. The edited vehicle is not simulated and shows every block.
+fn update_vehicle_mesh_system(
+    mut commands: Commands,
+    vehicles: Query<VehicleMeshData, VehicleMeshChanged>,
+    mesh_parts: Query<(), With<VehicleMeshPart>>,
+    registry: Res<PartRegistry>,
+    mut assets: VehicleAssets,
+) {
+    for (entity, blueprint, bodies, children, is_edited) in &vehicles {
+        for child in children.into_iter().flatten() {
+            if mesh_parts.contains(*child) {
+                commands.entity(*child).despawn();
+            }
+        }
+
+        let blueprint = match bodies {
+            Some(bodies) if !is_edited => bodies.root_blueprint(blueprint),
+            _ => Cow::Borrowed(&blueprint.0),
+        };
+        spawn_mesh_parts(&mut commands, entity, &blueprint, &registry, &mut assets);
+    }
+}
+
+/// Build the mesh of sub-bodies once their vehicle is known.
+fn sub_body_mesh_system(
+    mut commands: Commands,
+    sub_bodies: Query<(Entity, &VehicleSubBody), Without<Children>>,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+    mut assets: VehicleAssets,
+) {
+    for (entity, sub_body) in &sub_bodies {
+        let Ok((blueprint, bodies)) = vehicles.get(sub_body.vehicle) else {
+            continue;
+        };
+
+        commands.entity(entity).insert_if_new(Visibility::default());
+        let blueprint = bodies.body_blueprint(blueprint, sub_body.body);
+        spawn_mesh_parts(&mut commands, entity, &blueprint, &registry, &mut assets);
+    }
+}
+
+fn spawn_mesh_parts(
+    commands: &mut Commands,
+    entity: Entity,
+    blueprint: &Blueprint,
+    registry: &PartRegistry,
+    assets: &mut VehicleAssets,
+) {
+    let vehicle_mesh = match build_vehicle_mesh(blueprint, registry) {
+        Ok(vehicle_mesh) => vehicle_mesh,
+        Err(e) => {
+            error!(
+                "Failed to build the mesh of vehicle `{}`: {}",
+                blueprint.name(),
+                e
+            );
+            return;
+        }
+    };
+
+    for group in vehicle_mesh.groups {
+        if group.quad_count() == 0 {
+            continue;
+        }
+
+        commands.spawn((
+            VehicleMeshPart,
+            Mesh3d(assets.meshes.add(group.to_mesh())),
+            MeshMaterial3d(assets.vehicle_materials.get_or_create(
+                &group.material,
+                registry,
+                &mut assets.materials,
+            )),
+            ChildOf(entity),
+        ));
+    }
+
+    for custom in vehicle_mesh.custom {
+        commands.spawn((
+            VehicleMeshPart,
+            SceneRoot(
+                assets
+                    .asset_server
+                    .load(GltfAssetLabel::Scene(0).from_asset(custom.mesh)),
+            ),
+            Transform::from_translation(custom.position.as_vec3() * BLOCK_SIZE)
+                .with_rotation(custom.rotation.to_quat()),
+            ChildOf(entity),
+        ));
+    }
+}
@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+use common::vehicle::Blueprint;
+use lightyear::prelude::{Connected, Controlled, MessageReceiver, MessageSender};
+use log::{error, info, warn};
+use protocol::{
+    channels::ReliableChannel,
+    components::SpawnedVehicle,
+    messages::{DespawnVehicle, RespawnVehicle, SpawnVehicle, VehicleId, VehicleRejected},
+};
+
+use crate::{network::LocalClient, states::GameState};
+
+#[derive(Debug)]
+pub struct VehicleSpawnPlugin;
+
+impl Plugin for VehicleSpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                vehicle_rejected_system,
+                vehicle_keys_system.run_if(in_state(GameState::InGame)),
+            ),
+        )
+        .add_observer(spawn_vehicle_observer)
+        .add_observer(despawn_vehicle_observer)
+        .add_observer(respawn_vehicle_observer)
+        .add_observer(show_vehicle_observer);
+    }
+}
+
+/// Ask the server to spawn the saved vehicle called `name`.
+#[derive(Debug, Event)]
+pub struct SpawnVehicleEvent {
+    pub name: String,
+}
+
+/// Ask the server to remove one of our vehicles.
+#[derive(Debug, Event)]
+pub struct DespawnVehicleEvent {
+    pub vehicle: VehicleId,
+}
+
+/// Ask the server to put one of our vehicles back onto a spawn point.
+#[derive(Debug, Event)]
+pub struct RespawnVehicleEvent {
+    pub vehicle: VehicleId,
+}
+
+fn spawn_vehicle_observer(
+    trigger: On<SpawnVehicleEvent>,
+    mut client: Query<&mut MessageSender<SpawnVehicle>, (With<LocalClient>, With<Connected>)>,
+) {
+    let name = &trigger.event().name;
+    let blueprint = match Blueprint::load(name.as_str()) {
+        Ok(blueprint) => blueprint,
+        Err(e) => {
+            error!("Failed to load vehicle `{}`: {}", name, e);
+            return;
+        }
+    };
+
+    if let Ok(mut sender) = client.single_mut() {
+        info!("Spawning vehicle `{}`", name);
+        sender.send::<ReliableChannel>(SpawnVehicle { blueprint });
+    }
+}
+
+fn despawn_vehicle_observer(
+    trigger: On<DespawnVehicleEvent>,
+    mut client: Query<&mut MessageSender<DespawnVehicle>, (With<LocalClient>, With<Connected>)>,
+) {
+    if let Ok(mut sender) = client.single_mut() {
+        sender.send::<ReliableChannel>(DespawnVehicle {
+            vehicle: trigger.event().vehicle,
+        });
+    }
+}
+
+fn respawn_vehicle_observer(
+    trigger: On<RespawnVehicleEvent>,
+    mut client: Query<&mut MessageSender<RespawnVehicle>, (With<LocalClient>, With<Connected>)>,
+) {
+    if let Ok(mut sender) = client.single_mut() {
+        sender.send::<ReliableChannel>(RespawnVehicle {
+            vehicle: trigger.event().vehicle,
+        });
+    }
+}
+
+/// Replicated vehicles only get their mesh as children, which need a visible parent.
+fn show_vehicle_observer(trigger: On<Add, SpawnedVehicle>, mut commands: Commands) {
+    commands
+        .entity(trigger.event().entity)
+        .insert_if_new(Visibility::default());
+}
+
+fn vehicle_rejected_system(
+    mut client: Query<&mut MessageReceiver<VehicleRejected>, With<LocalClient>>,
+) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    for VehicleRejected { reason } in receiver.receive() {
+        warn!("Vehicle request rejected: {}", reason);
+    }
+}
+
+/// Spawn the saved vehicle with V, respawn the newest own vehicle with R and despawn it
+/// with X.
+// TODO: Replace with a vehicle menu once there is a UI.
+fn vehicle_keys_system(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    vehicles: Query<&SpawnedVehicle, With<Controlled>>,
+) {
+    if keys.just_pressed(KeyCode::KeyV) {
+        commands.trigger(SpawnVehicleEvent {
+            name: "vehicle".to_string(),
+        });
+    }
+
+    let Some(vehicle) = vehicles.iter().map(|vehicle| vehicle.id).max() else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::KeyR) {
+        commands.trigger(RespawnVehicleEvent { vehicle });
+    }
+    if keys.just_pressed(KeyCode::KeyX) {
+        commands.trigger(DespawnVehicleEvent { vehicle });
+    }
+}
@@ -1,35 +1,74 @@
 use bevy::prelude::*;
 
+use common::world::{terrain::Heightmap, water::Water};
+
 use crate::states::AppState;
 
+/// The edge length of the water plane in meters.
+const WATER_SIZE: f32 = 2000.0;
+
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnExit(AppState::MainMenu), setup);
+        app.add_systems(OnExit(AppState::MainMenu), setup)
+            .add_observer(terrain_mesh_observer);
     }
 }
 
-fn setup(mut commands: Commands, asset_server: ResMut<AssetServer>) {
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
     log::info!("Setting up scene");
 
-    let scene: Handle<Scene> =
-        asset_server.load(GltfAssetLabel::Scene(0).from_asset("empty_plane_50x50m.glb"));
+    // The client does not simulate water, it only shows where the server has it.
+    commands.spawn((
+        Name::new("Water"),
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(WATER_SIZE, WATER_SIZE))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgba(0.1, 0.3, 0.5, 0.7),
+            alpha_mode: AlphaMode::Blend,
+            perceptual_roughness: 0.1,
+            ..default()
+        })),
+        Transform::from_xyz(0.0, Water::default().level, 0.0),
+    ));
+    commands.spawn((
+        Name::new("Sun"),
+        DirectionalLight {
+            illuminance: light_consts::lux::AMBIENT_DAYLIGHT,
+            shadows_enabled: true,
+            ..default()
+        },
+        Transform::from_xyz(0.0, 0.0, 0.0).looking_at(Vec3::new(-0.5, -1.0, -0.3), Vec3::Y),
+    ));
     commands.spawn((
-        Name::new("TestScene"),
-        SceneRoot(scene),
-        Transform::from_xyz(0.0, 0.0, 0.0).with_scale(Vec3::splat(1.0)),
-        children![(
-            Camera3d::default(),
-            Transform::from_xyz(0.0, 20.0, 50.0).looking_at(Vec3::splat(0.0), Vec3::Y),
-            children![(
-                PointLight {
-                    intensity: 800000.0,
-                    shadows_enabled: true,
-                    ..Default::default()
-                },
-                Transform::from_xyz(0.0, 0.0, 0.0).looking_at(Vec3::splat(0.0), Vec3::Y),
-            )]
-        ),],
+        Camera3d::default(),
+        Transform::from_xyz(0.0, 20.0, 50.0).looking_at(Vec3::splat(0.0), Vec3::Y),
+    ));
+}
+
+/// Show the terrain once it was generated from the seed sent by the server.
+fn terrain_mesh_observer(
+    trigger: On<Add, Heightmap>,
+    mut commands: Commands,
+    heightmaps: Query<&Heightmap>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(heightmap) = heightmaps.get(entity) else {
+        return;
+    };
+
+    // The colors of the mesh are multiplied with the white base color.
+    commands.entity(entity).insert((
+        Mesh3d(meshes.add(heightmap.mesh())),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            perceptual_roughness: 0.9,
+            ..default()
+        })),
     ));
 }
@@ -1,12 +1,10 @@
+use avian3d::prelude::*;
 use bevy::prelude::*;
 use common::CommonPlugins;
 use lightyear::prelude::client::*;
 
 use crate::{
-    config::ConfigPlugins,
-    editor::EditorPlugins,
-    game::GamePlugins,
-    network::{LocalClient, NetworkPlugins},
+    config::ConfigPlugins, editor::EditorPlugins, game::GamePlugins, network::NetworkPlugins,
     states::StatesPlugins,
 };
 
@@ -20,7 +18,7 @@ fn main() {
     let mut app = App::new();
 
     // Bevy plugins and config.
-    app.add_plugins(DefaultPlugins);
+    app.add_plugins((DefaultPlugins, PhysicsPlugins::default()));
 
     // Lightyear plugins and config.
     app.add_plugins(ClientPlugins {
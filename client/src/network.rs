@@ -1,16 +1,21 @@
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 use bevy::prelude::*;
+use common::part_registry::PartRegistry;
 use lightyear::{
     link::Link,
     netcode::{ConnectToken, Key, NetcodeClient},
     prelude::{
-        Authentication, Client, Connect, Disconnect, LocalAddr, PeerAddr, ReplicationReceiver,
-        UdpIo, client::NetcodeConfig,
+        Authentication, Client, Connect, Connected, Disconnect, LocalAddr, MessageReceiver,
+        MessageSender, PeerAddr, ReplicationReceiver, UdpIo, client::NetcodeConfig,
     },
 };
-use log::info;
-use protocol::PROTOCOL_ID;
+use log::{info, warn};
+use protocol::{
+    PROTOCOL_ID,
+    channels::ReliableChannel,
+    messages::{ChatMessage, Kicked, PartRegistryChecksum, ServerShutdown},
+};
 
 use crate::{config::Config, states::AppState};
 
@@ -20,9 +25,11 @@ pub struct NetworkPlugins;
 impl Plugin for NetworkPlugins {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup);
+        app.add_systems(Update, (kicked_system, server_shutdown_system, chat_system));
 
         app.add_observer(join_game_observer)
-            .add_observer(leave_game_observer);
+            .add_observer(leave_game_observer)
+            .add_observer(send_part_registry_checksum_observer);
     }
 }
 
@@ -31,9 +38,11 @@ pub struct LocalClient;
 
 #[derive(Event)]
 pub enum JoinGameEvent {
+    // TODO: Remove the allow once the token exchange is implemented.
+    #[allow(dead_code)]
     Token {
         address: SocketAddr,
-        token: ConnectToken,
+        token: Box<ConnectToken>,
     },
     #[cfg(debug_assertions)]
     Manual { address: SocketAddr, key: Key },
@@ -61,7 +70,9 @@ fn join_game_observer(
     mut config: ResMut<Config>,
 ) {
     let (address, auth) = match trigger.event() {
-        JoinGameEvent::Token { address, token } => (address, Authentication::Token(token.clone())),
+        JoinGameEvent::Token { address, token } => {
+            (address, Authentication::Token(token.as_ref().clone()))
+        }
         #[cfg(debug_assertions)]
         JoinGameEvent::Manual { address, key } => (
             address,
@@ -114,3 +125,53 @@ fn leave_game_observer(
 
     info!("Disconnected from server");
 }
+
+/// Let the server verify that both use the same part definitions.
+fn send_part_registry_checksum_observer(
+    trigger: On<Add, Connected>,
+    mut client: Query<&mut MessageSender<PartRegistryChecksum>, With<LocalClient>>,
+    registry: Res<PartRegistry>,
+) {
+    if let Ok(mut sender) = client.get_mut(trigger.event().entity) {
+        sender.send::<ReliableChannel>(PartRegistryChecksum(registry.checksum()));
+    }
+}
+
+fn kicked_system(
+    mut commands: Commands,
+    mut client: Query<&mut MessageReceiver<Kicked>, With<LocalClient>>,
+) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    if let Some(Kicked { reason }) = receiver.receive().last() {
+        warn!("Kicked from server: {}", reason);
+        commands.trigger(LeaveGameEvent);
+    }
+}
+
+fn server_shutdown_system(
+    mut client: Query<&mut MessageReceiver<ServerShutdown>, With<LocalClient>>,
+) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    for ServerShutdown { seconds } in receiver.receive() {
+        warn!("The server shuts down in {} seconds", seconds);
+    }
+}
+
+fn chat_system(mut client: Query<&mut MessageReceiver<ChatMessage>, With<LocalClient>>) {
+    let Ok(mut receiver) = client.single_mut() else {
+        return;
+    };
+
+    for ChatMessage { sender, text } in receiver.receive() {
+        match sender {
+            Some(sender) => info!("<{:?}> {}", sender, text),
+            None => info!("[Server] {}", text),
+        }
+    }
+}
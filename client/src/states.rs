@@ -26,7 +26,6 @@ pub enum GameState {
     #[default]
     InGame,
     InEditor,
-    Pause,
 }
 
 /// Despawns a list of entities.
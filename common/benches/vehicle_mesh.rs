@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+use common::{
+    part_registry::PartRegistry,
+    vehicle::{Block, Blueprint, mesh::build_vehicle_mesh},
+};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+/// A 48x48x48 block of two materials with holes, a bit over 100k blocks.
+fn blueprint() -> Blueprint {
+    let mut blueprint = Blueprint::new("bench");
+    for x in 0..48 {
+        for y in 0..48 {
+            for z in 0..48 {
+                if (x * 7 + y * 13 + z * 31) % 11 == 0 {
+                    continue;
+                }
+
+                let part = if (x / 8 + y / 8 + z / 8) % 2 == 0 {
+                    "cube"
+                } else {
+                    "wood_cube"
+                };
+                blueprint
+                    .add(Block::new(IVec3::new(x, y, z), part))
+                    .unwrap();
+            }
+        }
+    }
+
+    blueprint
+}
+
+fn vehicle_mesh(c: &mut Criterion) {
+    let registry = PartRegistry::builtin();
+    let blueprint = blueprint();
+    assert!(blueprint.len() >= 100_000);
+
+    c.bench_function("vehicle_mesh_100k", |b| {
+        b.iter(|| build_vehicle_mesh(&blueprint, &registry).unwrap())
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = vehicle_mesh
+}
+criterion_main!(benches);
@@ -0,0 +1,661 @@
+use avian3d::prelude::*;
+use bevy::{ecs::entity::MapEntities, prelude::*};
+use lightyear::prelude::input::native::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::vehicle::seat::Seated;
+
+/// The gap that is kept between the capsule and everything it touches, so casts do not
+/// start inside of colliders.
+const SKIN: f32 = 0.01;
+/// How often a movement is deflected by surfaces before the rest is dropped.
+const MAX_SLIDES: usize = 4;
+/// How far below the capsule the ground is still detected.
+const GROUND_DISTANCE: f32 = 0.05;
+
+#[derive(Debug)]
+pub struct CharacterPlugin;
+
+impl Plugin for CharacterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, character_movement_system);
+    }
+}
+
+/// The input of an on-foot character for one tick.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct CharacterInput {
+    /// x is right and y is forward, at most one long.
+    pub movement: Vec2,
+    /// The direction the character looks in radians around the up axis.
+    pub yaw: f32,
+    pub jump: bool,
+    pub run: bool,
+    pub crouch: bool,
+    pub prone: bool,
+}
+
+impl MapEntities for CharacterInput {
+    fn map_entities<E: EntityMapper>(&mut self, _entity_mapper: &mut E) {}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum Stance {
+    #[default]
+    Standing,
+    Crouching,
+    Prone,
+}
+
+impl Stance {
+    /// The stance `input` asks for. Prone wins over crouching.
+    pub fn from_input(input: &CharacterInput) -> Self {
+        if input.prone {
+            Self::Prone
+        } else if input.crouch {
+            Self::Crouching
+        } else {
+            Self::Standing
+        }
+    }
+}
+
+/// How an on-foot character moves.
+///
+/// The character is kinematic. It is moved by [`character_movement_system`] with shape
+/// casts and never by the physics solver.
+#[derive(Debug, Clone, PartialEq, Component, Serialize, Deserialize)]
+#[require(CharacterState, ActionState<CharacterInput>, RigidBody::Kinematic)]
+pub struct CharacterController {
+    pub radius: f32,
+    pub standing_height: f32,
+    pub crouching_height: f32,
+    pub prone_height: f32,
+    /// Meters per second.
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub crouch_speed: f32,
+    pub prone_speed: f32,
+    /// In meters.
+    pub jump_height: f32,
+    /// Meters per second squared.
+    pub gravity: f32,
+    /// The steepest slope in radians that can be walked on.
+    pub max_slope: f32,
+    /// The highest step in meters that is walked up without jumping.
+    pub step_height: f32,
+    /// How fast the horizontal velocity can be changed in the air, in meters per second
+    /// squared.
+    pub air_acceleration: f32,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            radius: 0.3,
+            standing_height: 1.8,
+            crouching_height: 1.2,
+            prone_height: 0.6,
+            walk_speed: 4.0,
+            run_speed: 7.0,
+            crouch_speed: 2.0,
+            prone_speed: 1.0,
+            jump_height: 1.0,
+            gravity: 9.81,
+            max_slope: 45f32.to_radians(),
+            step_height: 0.35,
+            air_acceleration: 5.0,
+        }
+    }
+}
+
+impl CharacterController {
+    /// The total height of the capsule.
+    pub fn height(&self, stance: Stance) -> f32 {
+        let height = match stance {
+            Stance::Standing => self.standing_height,
+            Stance::Crouching => self.crouching_height,
+            Stance::Prone => self.prone_height,
+        };
+
+        height.max(self.radius * 2.0)
+    }
+
+    pub fn collider(&self, stance: Stance) -> Collider {
+        Collider::capsule(self.radius, self.height(stance) - self.radius * 2.0)
+    }
+
+    pub fn speed(&self, stance: Stance, run: bool) -> f32 {
+        match stance {
+            Stance::Standing if run => self.run_speed,
+            Stance::Standing => self.walk_speed,
+            Stance::Crouching => self.crouch_speed,
+            Stance::Prone => self.prone_speed,
+        }
+    }
+
+    /// The upwards velocity that reaches [`Self::jump_height`].
+    pub fn jump_velocity(&self) -> f32 {
+        (2.0 * self.gravity * self.jump_height).sqrt()
+    }
+
+    /// Whether a surface with the `normal` can be stood on.
+    pub fn is_walkable(&self, normal: Vec3) -> bool {
+        normal.angle_between(Vec3::Y) <= self.max_slope + 1e-4
+    }
+
+    /// The horizontal velocity `input` asks for.
+    pub fn target_velocity(&self, input: &CharacterInput, stance: Stance) -> Vec3 {
+        let movement = input.movement.clamp_length_max(1.0);
+        let direction = Quat::from_rotation_y(input.yaw) * Vec3::new(movement.x, 0.0, -movement.y);
+
+        direction * self.speed(stance, input.run)
+    }
+}
+
+/// The simulated state of a character, predicted on the owning client.
+#[derive(Debug, Clone, Default, PartialEq, Component, Serialize, Deserialize)]
+pub struct CharacterState {
+    pub velocity: Vec3,
+    pub grounded: bool,
+    pub stance: Stance,
+}
+
+/// Move the horizontal part of `velocity` towards `target` by at most `max_delta`.
+pub fn accelerate(velocity: Vec3, target: Vec3, max_delta: f32) -> Vec3 {
+    let horizontal = Vec3::new(velocity.x, 0.0, velocity.z);
+    let difference = Vec3::new(target.x, 0.0, target.z) - horizontal;
+    let horizontal = horizontal + difference.clamp_length_max(max_delta);
+
+    Vec3::new(horizontal.x, velocity.y, horizontal.z)
+}
+
+/// The result of [`Mover::slide`].
+struct Slide {
+    position: Vec3,
+    normals: Vec<Vec3>,
+}
+
+/// Shape casts of one character against everything else.
+struct Mover<'a, 'w, 's> {
+    spatial_query: &'a SpatialQuery<'w, 's>,
+    filter: SpatialQueryFilter,
+    shape: Collider,
+    controller: &'a CharacterController,
+}
+
+impl Mover<'_, '_, '_> {
+    /// How far the capsule can move from `position` into `direction`, up to `distance`.
+    fn cast(&self, position: Vec3, direction: Dir3, distance: f32) -> Option<ShapeHitData> {
+        self.spatial_query.cast_shape(
+            &self.shape,
+            position,
+            Quat::IDENTITY,
+            direction,
+            &ShapeCastConfig {
+                max_distance: distance + SKIN,
+                ignore_origin_penetration: true,
+                ..ShapeCastConfig::DEFAULT
+            },
+            &self.filter,
+        )
+    }
+
+    /// Move as far as possible and slide along the surfaces that are hit.
+    fn slide(&self, mut position: Vec3, mut displacement: Vec3) -> Slide {
+        let mut normals = Vec::new();
+
+        for _ in 0..MAX_SLIDES {
+            let Ok((direction, distance)) = Dir3::new_and_length(displacement) else {
+                break;
+            };
+            if distance < 1e-5 {
+                break;
+            }
+
+            let Some(hit) = self.cast(position, direction, distance) else {
+                position += displacement;
+                break;
+            };
+
+            let travel = (hit.distance - SKIN).max(0.0);
+            position += direction * travel;
+            displacement = direction * (distance - travel);
+
+            let normal = hit.normal1;
+            normals.push(normal);
+            // Steep slopes block like walls while moving up, but are slid down.
+            let plane = if normal.y > 0.0
+                && !self.controller.is_walkable(normal)
+                && displacement.y >= 0.0
+            {
+                Vec3::new(normal.x, 0.0, normal.z).normalize_or(normal)
+            } else {
+                normal
+            };
+            displacement -= plane * displacement.dot(plane).min(0.0);
+        }
+
+        Slide { position, normals }
+    }
+
+    /// Move up by the step height, forward and back down onto walkable ground.
+    fn step(&self, position: Vec3, displacement: Vec3) -> Option<Vec3> {
+        let height = match self.cast(position, Dir3::Y, self.controller.step_height) {
+            Some(hit) => (hit.distance - SKIN).max(0.0),
+            None => self.controller.step_height,
+        };
+        let raised = position + Vec3::Y * height;
+
+        let forward = self.slide(raised, displacement).position;
+        let hit = self.ground(forward, height + GROUND_DISTANCE)?;
+
+        Some(forward - Vec3::Y * (hit.distance - SKIN).max(0.0))
+    }
+
+    /// The walkable ground right below `position`.
+    fn ground(&self, position: Vec3, distance: f32) -> Option<ShapeHitData> {
+        self.cast(position, Dir3::NEG_Y, distance)
+            .filter(|hit| self.is_walkable(hit))
+    }
+
+    /// Whether the surface below the contact of `hit` can be stood on.
+    ///
+    /// The capsule touches edges at an angle, so the surface is checked with a short ray
+    /// at the contact point too.
+    fn is_walkable(&self, hit: &ShapeHitData) -> bool {
+        self.controller.is_walkable(hit.normal1)
+            || self
+                .spatial_query
+                .cast_ray(
+                    hit.point1 + Vec3::Y * GROUND_DISTANCE,
+                    Dir3::NEG_Y,
+                    GROUND_DISTANCE * 2.0,
+                    true,
+                    &self.filter,
+                )
+                .is_some_and(|ray| self.controller.is_walkable(ray.normal))
+    }
+}
+
+/// Move every character according to its [`ActionState<CharacterInput>`].
+///
+/// [`Seated`] characters are moved by their vehicle instead.
+///
+/// The [`Transform`] is the center of the capsule. It runs in [`FixedUpdate`] on the
+/// server and on the predicting client, so it must only depend on the input and state.
+pub fn character_movement_system(
+    mut commands: Commands,
+    spatial_query: SpatialQuery,
+    time: Res<Time>,
+    mut characters: Query<(
+        Entity,
+        &CharacterController,
+        &mut CharacterState,
+        &mut Transform,
+        &ActionState<CharacterInput>,
+    )>,
+    seated: Query<(), With<Seated>>,
+) {
+    let delta_secs = time.delta_secs();
+    if delta_secs <= 0.0 {
+        return;
+    }
+
+    for (entity, controller, mut state, mut transform, input) in characters.iter_mut() {
+        if seated.contains(entity) {
+            continue;
+        }
+
+        let filter = SpatialQueryFilter::from_excluded_entities([entity]);
+        let input = &input.0;
+
+        // Resize the capsule while keeping the feet in place, unless there is no room.
+        let stance = Stance::from_input(input);
+        if stance != state.stance {
+            let offset = (controller.height(stance) - controller.height(state.stance)) / 2.0;
+            let center = transform.translation + Vec3::Y * offset;
+            let blocked = offset > 0.0
+                && !spatial_query
+                    .shape_intersections(
+                        &Collider::capsule(
+                            controller.radius - SKIN,
+                            controller.height(stance) - controller.radius * 2.0,
+                        ),
+                        center,
+                        Quat::IDENTITY,
+                        &filter,
+                    )
+                    .is_empty();
+
+            if !blocked {
+                state.stance = stance;
+                transform.translation = center;
+                commands.entity(entity).insert(controller.collider(stance));
+            }
+        }
+
+        let mover = Mover {
+            spatial_query: &spatial_query,
+            filter,
+            shape: controller.collider(state.stance),
+            controller,
+        };
+
+        let mut velocity = state.velocity;
+        let grounded = velocity.y <= 0.0
+            && mover
+                .ground(transform.translation, GROUND_DISTANCE)
+                .is_some();
+
+        let target = controller.target_velocity(input, state.stance);
+        if grounded {
+            velocity = Vec3::new(target.x, 0.0, target.z);
+            if input.jump && state.stance == Stance::Standing {
+                velocity.y = controller.jump_velocity();
+            }
+        } else {
+            velocity = accelerate(velocity, target, controller.air_acceleration * delta_secs);
+            velocity.y -= controller.gravity * delta_secs;
+        }
+
+        let start = transform.translation;
+        let displacement = velocity * delta_secs;
+        let mut slide = mover.slide(start, displacement);
+
+        let blocked_by_wall = slide
+            .normals
+            .iter()
+            .any(|normal| !controller.is_walkable(*normal) && normal.y.abs() < 0.5);
+        if grounded && blocked_by_wall {
+            let horizontal = Vec3::new(displacement.x, 0.0, displacement.z);
+            if let Some(stepped) = mover.step(start, horizontal)
+                && (stepped - start).xz().length() > (slide.position - start).xz().length()
+            {
+                slide = Slide {
+                    position: stepped,
+                    normals: Vec::new(),
+                };
+            }
+        }
+
+        // Stay on the ground when walking down slopes and steps.
+        let mut position = slide.position;
+        if grounded
+            && velocity.y <= 0.0
+            && let Some(hit) = mover.ground(position, controller.step_height)
+        {
+            position.y -= (hit.distance - SKIN).max(0.0);
+        }
+
+        // Lose the velocity into the surfaces that were hit.
+        for normal in &slide.normals {
+            let into = velocity.dot(*normal);
+            if into < 0.0 {
+                velocity -= *normal * into;
+            }
+        }
+
+        transform.translation = position;
+        state.velocity = velocity;
+        state.grounded = grounded && velocity.y <= 0.0;
+    }
+}
+
+#[cfg(test)]
+mod character_test {
+    use std::time::Duration;
+
+    use avian3d::prelude::*;
+    use bevy::{prelude::*, time::TimeUpdateStrategy};
+    use lightyear::prelude::input::native::ActionState;
+
+    use crate::character::{
+        CharacterController, CharacterInput, CharacterPlugin, CharacterState, Stance, accelerate,
+    };
+
+    const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            AssetPlugin::default(),
+            bevy::mesh::MeshPlugin,
+            bevy::scene::ScenePlugin,
+            PhysicsPlugins::default(),
+            CharacterPlugin,
+        ))
+        .insert_resource(Time::<Fixed>::from_duration(TICK))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+        app.finish();
+        app.cleanup();
+
+        app.world_mut().spawn((
+            RigidBody::Static,
+            Collider::cuboid(100.0, 1.0, 100.0),
+            Transform::from_xyz(0.0, -0.5, 0.0),
+        ));
+
+        app
+    }
+
+    /// Spawn a character standing at `feet`.
+    fn spawn_character(app: &mut App, feet: Vec3) -> Entity {
+        let controller = CharacterController::default();
+        let center = feet + Vec3::Y * (controller.height(Stance::Standing) / 2.0 + 0.02);
+
+        app.world_mut()
+            .spawn((
+                controller.collider(Stance::Standing),
+                controller,
+                Transform::from_translation(center),
+            ))
+            .id()
+    }
+
+    fn set_input(app: &mut App, entity: Entity, input: CharacterInput) {
+        app.world_mut()
+            .entity_mut(entity)
+            .insert(ActionState(input));
+    }
+
+    fn feet(app: &App, entity: Entity) -> Vec3 {
+        let world = app.world();
+        let controller = world.get::<CharacterController>(entity).unwrap();
+        let state = world.get::<CharacterState>(entity).unwrap();
+
+        world.get::<Transform>(entity).unwrap().translation
+            - Vec3::Y * controller.height(state.stance) / 2.0
+    }
+
+    fn run(app: &mut App, ticks: usize) {
+        for _ in 0..ticks {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn accelerate_test() {
+        let velocity = accelerate(Vec3::new(0.0, -3.0, 0.0), Vec3::new(10.0, 5.0, 0.0), 2.0);
+
+        assert_eq!(velocity, Vec3::new(2.0, -3.0, 0.0));
+    }
+
+    #[test]
+    fn target_velocity_test() {
+        let controller = CharacterController::default();
+        let input = CharacterInput {
+            movement: Vec2::new(1.0, 1.0),
+            yaw: std::f32::consts::FRAC_PI_2,
+            run: true,
+            ..default()
+        };
+
+        // Diagonals are not faster and the yaw turns forward to -X.
+        let velocity = controller.target_velocity(&input, Stance::Standing);
+        assert!((velocity.length() - controller.run_speed).abs() < 1e-4);
+        assert!(velocity.x < 0.0);
+
+        let crouching = controller.target_velocity(&input, Stance::Crouching);
+        assert!((crouching.length() - controller.crouch_speed).abs() < 1e-4);
+    }
+
+    #[test]
+    fn jump_height_test() {
+        let mut app = app();
+        let character = spawn_character(&mut app, Vec3::ZERO);
+        run(&mut app, 30);
+        assert!(
+            app.world()
+                .get::<CharacterState>(character)
+                .unwrap()
+                .grounded
+        );
+        let start = feet(&app, character).y;
+
+        set_input(
+            &mut app,
+            character,
+            CharacterInput {
+                jump: true,
+                ..default()
+            },
+        );
+        run(&mut app, 1);
+        set_input(&mut app, character, CharacterInput::default());
+
+        let mut highest = start;
+        for _ in 0..120 {
+            run(&mut app, 1);
+            highest = highest.max(feet(&app, character).y);
+        }
+
+        let jump_height = CharacterController::default().jump_height;
+        assert!(
+            (highest - start - jump_height).abs() < 0.05 * jump_height,
+            "jumped {} high",
+            highest - start
+        );
+        assert!(
+            app.world()
+                .get::<CharacterState>(character)
+                .unwrap()
+                .grounded
+        );
+        assert!((feet(&app, character).y - start).abs() < 0.05);
+    }
+
+    /// Walk forward onto a ramp with the given slope.
+    fn walk_up_ramp(angle: f32) -> f32 {
+        let mut app = app();
+        let rotation = Quat::from_rotation_x(angle);
+        // The ramp starts at z = -2 and rises towards -Z.
+        app.world_mut().spawn((
+            RigidBody::Static,
+            Collider::cuboid(4.0, 0.2, 20.0),
+            Transform::from_translation(
+                Vec3::new(0.0, 0.0, -2.0) + rotation * Vec3::new(0.0, -0.1, -10.0),
+            )
+            .with_rotation(rotation),
+        ));
+        let character = spawn_character(&mut app, Vec3::ZERO);
+        run(&mut app, 10);
+
+        set_input(
+            &mut app,
+            character,
+            CharacterInput {
+                movement: Vec2::Y,
+                ..default()
+            },
+        );
+        run(&mut app, 120);
+
+        feet(&app, character).y
+    }
+
+    #[test]
+    fn slope_test() {
+        // 30 degrees are walked up, 60 degrees are too steep.
+        assert!(walk_up_ramp(30f32.to_radians()) > 2.0);
+        assert!(walk_up_ramp(60f32.to_radians()) < 0.5);
+    }
+
+    fn walk_onto_step(height: f32) -> f32 {
+        let mut app = app();
+        app.world_mut().spawn((
+            RigidBody::Static,
+            Collider::cuboid(4.0, height, 4.0),
+            Transform::from_xyz(0.0, height / 2.0, -3.0),
+        ));
+        let character = spawn_character(&mut app, Vec3::ZERO);
+        run(&mut app, 10);
+
+        set_input(
+            &mut app,
+            character,
+            CharacterInput {
+                movement: Vec2::Y,
+                ..default()
+            },
+        );
+        run(&mut app, 60);
+
+        feet(&app, character).y
+    }
+
+    #[test]
+    fn step_test() {
+        assert!((walk_onto_step(0.3) - 0.3).abs() < 0.05);
+        assert!(walk_onto_step(0.6) < 0.05);
+    }
+
+    #[test]
+    fn stance_test() {
+        let mut app = app();
+        let character = spawn_character(&mut app, Vec3::ZERO);
+        // A ceiling at 1.5 meters.
+        app.world_mut().spawn((
+            RigidBody::Static,
+            Collider::cuboid(4.0, 0.2, 4.0),
+            Transform::from_xyz(0.0, 1.6, -4.0),
+        ));
+        run(&mut app, 10);
+
+        set_input(
+            &mut app,
+            character,
+            CharacterInput {
+                crouch: true,
+                ..default()
+            },
+        );
+        run(&mut app, 2);
+        let state = app.world().get::<CharacterState>(character).unwrap();
+        assert_eq!(state.stance, Stance::Crouching);
+        assert!(feet(&app, character).y.abs() < 0.05);
+
+        // Crawl below the ceiling, where standing up is not possible.
+        set_input(
+            &mut app,
+            character,
+            CharacterInput {
+                crouch: true,
+                movement: Vec2::Y,
+                ..default()
+            },
+        );
+        run(&mut app, 120);
+        set_input(&mut app, character, CharacterInput::default());
+        run(&mut app, 10);
+
+        let world = app.world();
+        assert!(world.get::<Transform>(character).unwrap().translation.z < -3.0);
+        assert_eq!(
+            world.get::<CharacterState>(character).unwrap().stance,
+            Stance::Crouching
+        );
+    }
+}
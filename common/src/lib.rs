@@ -3,9 +3,18 @@ use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use bevy::prelude::*;
 use directories::ProjectDirs;
 
+use crate::{
+    character::CharacterPlugin, part_registry::PartRegistryPlugin, vehicle::VehiclePlugin,
+    world::terrain::TerrainPlugin,
+};
+
+pub mod character;
 pub mod name_list;
 pub mod network;
+pub mod part_registry;
 pub mod save_system;
+pub mod vehicle;
+pub mod world;
 
 pub const NAME: &str = "Awesome Vehicle Builder";
 pub const DIR_NAME: &str = "awesome_vehicle_builder";
@@ -18,7 +27,14 @@ pub type Name = String;
 pub struct CommonPlugins;
 
 impl Plugin for CommonPlugins {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            PartRegistryPlugin,
+            VehiclePlugin,
+            CharacterPlugin,
+            TerrainPlugin,
+        ));
+    }
 }
 
 pub fn ip_addr_into_socket_addr(ip: IpAddr, port: u16) -> SocketAddr {
@@ -31,26 +47,43 @@ pub fn ip_addr_into_socket_addr(ip: IpAddr, port: u16) -> SocketAddr {
 pub enum Paths {
     GameSave(Name),
     VehicleSave(Name),
+    /// The directory with additional part definitions.
+    PartDefinitions,
     SettingsSave,
+    /// The ban list and whitelist of the server.
+    AccessList,
 }
 
-impl Into<std::path::PathBuf> for Paths {
-    fn into(self) -> std::path::PathBuf {
+impl From<Paths> for std::path::PathBuf {
+    fn from(value: Paths) -> Self {
         let game_dirs = get_game_dirs();
-        match self {
-            Self::GameSave(name) => {
+        match value {
+            Paths::GameSave(name) => {
                 let mut dir = game_dirs.data_dir().to_path_buf();
                 dir.push(name);
 
                 dir
             }
-            Self::VehicleSave(name) => {
+            Paths::VehicleSave(name) => {
                 let mut dir = game_dirs.data_dir().to_path_buf();
-                dir.push(name);
+                dir.push("vehicles");
+                dir.push(format!("{name}.toml"));
+
+                dir
+            }
+            Paths::PartDefinitions => {
+                let mut dir = game_dirs.data_dir().to_path_buf();
+                dir.push("parts");
+
+                dir
+            }
+            Paths::SettingsSave => game_dirs.config_dir().to_path_buf(),
+            Paths::AccessList => {
+                let mut dir = game_dirs.data_dir().to_path_buf();
+                dir.push("access.toml");
 
                 dir
             }
-            Self::SettingsSave => game_dirs.config_dir().to_path_buf(),
         }
     }
 }
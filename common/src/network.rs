@@ -85,7 +85,7 @@ impl<'de> Deserialize<'de> for TokenResponse {
                     .ok_or_else(|| de::Error::missing_field("connect_toke_bytes"))?;
                 let connect_token =
                     ConnectToken::try_from_bytes(&connect_token_bytes).map_err(|e| {
-                        de::Error::custom(&format!("Failed to turn bytes into ConnectToken: {}", e))
+                        de::Error::custom(format!("Failed to turn bytes into ConnectToken: {}", e))
                     })?;
 
                 Ok(TokenResponse {
@@ -0,0 +1,1002 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    Paths,
+    vehicle::{
+        BlockRotation, Bounds, Face, PartId,
+        signal::{CompareOp, GateOp, MathOp},
+    },
+};
+
+/// The part definitions that ship with the game.
+pub const BUILTIN_PARTS: &str = include_str!("../parts/base.toml");
+
+/// The largest footprint of a part along each axis in blocks.
+pub const MAX_PART_SIZE: u32 = 64;
+
+/// The name used in errors for [`BUILTIN_PARTS`].
+const BUILTIN_SOURCE: &str = "<builtin>";
+
+/// Part definitions for tests, see [`PartRegistry::test`].
+#[cfg(test)]
+const TEST_PARTS: &str = r#"
+[[material]]
+id = "a"
+
+[[material]]
+id = "b"
+
+[[part]]
+id = "cube"
+name = "Cube"
+category = "Structure"
+mass = 1.0
+material = "a"
+
+[[part]]
+id = "cube_b"
+name = "Cube B"
+category = "Structure"
+mass = 1.0
+material = "b"
+
+[[part]]
+id = "heavy"
+name = "Heavy"
+category = "Structure"
+mass = 3.0
+material = "a"
+
+[[part]]
+id = "long"
+name = "Long"
+category = "Structure"
+size = [4, 1, 1]
+mass = 4.0
+material = "a"
+
+[[part]]
+id = "beam"
+name = "Beam"
+category = "Structure"
+size = [1, 3, 1]
+mass = 1.0
+material = "a"
+
+[[part]]
+id = "plate"
+name = "Plate"
+category = "Structure"
+size = [2, 1, 2]
+mass = 1.0
+material = "a"
+
+[[part]]
+id = "wood"
+name = "Wood"
+category = "Structure"
+size = [10, 10, 10]
+mass = 0.7
+material = "a"
+
+[[part]]
+id = "steel"
+name = "Steel"
+category = "Structure"
+size = [10, 10, 10]
+mass = 7.85
+material = "a"
+
+[[part]]
+id = "hub"
+name = "Hub"
+category = "Mechanics"
+mass = 1.0
+mesh = "hub.glb"
+material = "b"
+
+[[part]]
+id = "motor"
+name = "Motor"
+category = "Mechanics"
+mass = 1.0
+material = "a"
+function = { kind = "Motor", max_speed = 10.0, max_torque = 5.0 }
+
+[[part]]
+id = "wheel"
+name = "Wheel"
+category = "Mechanics"
+mass = 1.0
+material = "a"
+function = { kind = "Wheel", max_speed = 10.0, max_torque = 5.0 }
+
+[[part]]
+id = "thruster"
+name = "Thruster"
+category = "Propulsion"
+mass = 1.0
+material = "a"
+function = { kind = "Thruster", max_thrust = 100.0, fuel_per_second = 2.0 }
+
+[[part]]
+id = "propeller"
+name = "Propeller"
+category = "Propulsion"
+mass = 1.0
+material = "a"
+function = { kind = "Propeller", max_thrust = 50.0, max_speed = 10.0 }
+
+[[part]]
+id = "tank"
+name = "Tank"
+category = "Propulsion"
+mass = 1.0
+material = "a"
+function = { kind = "FuelTank", capacity = 20.0 }
+
+[[part]]
+id = "wing"
+name = "Wing"
+category = "Aerodynamics"
+size = [20, 1, 10]
+mass = 1.0
+material = "a"
+function = { kind = "Wing", lift = 6.0, drag = 0.1 }
+
+[[part]]
+id = "flap"
+name = "Flap"
+category = "Aerodynamics"
+size = [20, 1, 10]
+mass = 1.0
+material = "a"
+function = { kind = "ControlSurface", lift = 6.0, drag = 0.1, max_deflection = 10.0 }
+
+[[part]]
+id = "dial"
+name = "Dial"
+category = "Controls"
+mass = 1.0
+material = "a"
+function = { kind = "Dial" }
+
+[[part]]
+id = "seat"
+name = "Seat"
+category = "Controls"
+mass = 1.0
+attachment_faces = ["NegY"]
+material = "a"
+"#;
+
+#[derive(Debug)]
+pub struct PartRegistryPlugin;
+
+impl Plugin for PartRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        let registry = PartRegistry::load_default().expect("Failed to load part definitions");
+        info!(
+            "Loaded {} parts (checksum {:016x})",
+            registry.len(),
+            registry.checksum()
+        );
+
+        app.insert_resource(registry);
+    }
+}
+
+/// The compact id of a part used on the network.
+///
+/// Only valid for the [`PartRegistry`] it was taken from. Peers with the same
+/// [`PartRegistry::checksum`] share the same ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PartNetId(pub u16);
+
+/// The group a part is listed under in the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum PartCategory {
+    Structure,
+    Controls,
+    Logic,
+    Mechanics,
+    Propulsion,
+    Aerodynamics,
+}
+
+/// The definition of a single part.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartDefinition {
+    /// The stable id used in saved blueprints.
+    pub id: PartId,
+    /// The name shown to the player.
+    pub name: String,
+    pub category: PartCategory,
+    /// The footprint in blocks, starting at the block position.
+    #[serde(default = "PartDefinition::default_size")]
+    pub size: UVec3,
+    /// The mass in kilogram.
+    pub mass: f32,
+    /// The local faces other parts can be attached to.
+    #[serde(default = "PartDefinition::default_attachment_faces")]
+    pub attachment_faces: Vec<Face>,
+    /// The path of a mesh asset. Parts without a mesh are rendered as blocks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<String>,
+    /// The id of a [`MaterialDefinition`].
+    pub material: String,
+    /// What the part does besides being solid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<PartFunction>,
+}
+
+/// The behavior of a functional part.
+///
+/// The signal ports of every function are listed in [`crate::vehicle::signal`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", deny_unknown_fields)]
+pub enum PartFunction {
+    /// A player can sit on the top face and control the vehicle.
+    Seat,
+    /// Outputs the bool block property `on`.
+    Switch,
+    /// Outputs the number block property `value`.
+    Dial,
+    Gate {
+        op: GateOp,
+    },
+    Math {
+        op: MathOp,
+    },
+    Compare {
+        op: CompareOp,
+    },
+    /// Turns on once its input was on for the number block property `delay` in seconds.
+    Timer,
+    /// Turns on with `set` and off with `reset`. Reset wins if both are on.
+    Memory,
+    /// Turns the parts on its top face around its up axis.
+    ///
+    /// `max_speed` is in radians per second and `max_torque` in newton meters.
+    Motor {
+        max_speed: f32,
+        max_torque: f32,
+    },
+    /// A motor that turns itself instead of the parts on its top face. It is attached to
+    /// the vehicle by its bottom face.
+    Wheel {
+        max_speed: f32,
+        max_torque: f32,
+    },
+    /// Turns the parts on its top face to an angle of at most `max_angle` degrees to
+    /// either side.
+    Hinge {
+        max_angle: f32,
+        max_torque: f32,
+    },
+    /// Pushes the parts on its top face up to `max_extension` meters along its up axis.
+    ///
+    /// `max_force` is in newton.
+    Piston {
+        max_extension: f32,
+        max_force: f32,
+    },
+    /// Pushes the vehicle towards its top face with up to `max_thrust` newton.
+    ///
+    /// Burns `fuel_per_second` at full throttle. A thruster that burns nothing works
+    /// without fuel tanks.
+    Thruster {
+        max_thrust: f32,
+        #[serde(default)]
+        fuel_per_second: f32,
+    },
+    /// Pushes the vehicle towards or away from its top face with up to `max_thrust` newton.
+    ///
+    /// The thrust fades out as the speed along the axis approaches `max_speed` meters per
+    /// second.
+    Propeller {
+        max_thrust: f32,
+        max_speed: f32,
+        #[serde(default)]
+        fuel_per_second: f32,
+    },
+    /// Holds `capacity` units of fuel, full when the vehicle spawns.
+    FuelTank {
+        capacity: f32,
+    },
+    /// A thin wing whose top face lifts the vehicle in the airflow, with its front towards
+    /// the forward face.
+    ///
+    /// `lift` is the lift coefficient per radian angle of attack and `drag` the drag
+    /// coefficient while it does not lift.
+    Wing {
+        lift: f32,
+        drag: f32,
+    },
+    /// A wing that tilts by up to `max_deflection` degrees.
+    ControlSurface {
+        lift: f32,
+        drag: f32,
+        max_deflection: f32,
+    },
+}
+
+impl PartDefinition {
+    fn default_size() -> UVec3 {
+        UVec3::ONE
+    }
+
+    fn default_attachment_faces() -> Vec<Face> {
+        Face::ALL.to_vec()
+    }
+
+    /// Iterate over the grid offsets the part occupies relative to its block position.
+    pub fn cells(&self, rotation: BlockRotation) -> impl Iterator<Item = IVec3> {
+        let size = self.size.as_ivec3();
+        (0..size.x).flat_map(move |x| {
+            (0..size.y)
+                .flat_map(move |y| (0..size.z).map(move |z| rotation.rotate(IVec3::new(x, y, z))))
+        })
+    }
+
+    /// The bounds of [`Self::cells`], from the corners of the part.
+    pub fn cell_bounds(&self, rotation: BlockRotation) -> Bounds {
+        let max = self.size.as_ivec3() - IVec3::ONE;
+        let mut bounds = Bounds::from_position(IVec3::ZERO);
+        for corner in 1..8 {
+            let mask = BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
+            bounds.extend(rotation.rotate(IVec3::select(mask, max, IVec3::ZERO)));
+        }
+
+        bounds
+    }
+
+    /// Returns true if other parts can be attached to the rotated `face`.
+    pub fn can_attach(&self, rotation: BlockRotation, face: Face) -> bool {
+        self.attachment_faces
+            .iter()
+            .any(|local| rotation.rotate_face(*local) == face)
+    }
+}
+
+/// The look of a part.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDefinition {
+    pub id: String,
+    /// Linear RGBA.
+    #[serde(default = "MaterialDefinition::default_color")]
+    pub color: [f32; 4],
+    #[serde(default)]
+    pub metallic: f32,
+    #[serde(default = "MaterialDefinition::default_roughness")]
+    pub roughness: f32,
+}
+
+impl PartFunction {
+    /// Check that every parameter is a finite number within its range.
+    fn validate(&self) -> Result<(), String> {
+        let parameters: &[(&str, f32, f32)] = match *self {
+            PartFunction::Seat
+            | PartFunction::Switch
+            | PartFunction::Dial
+            | PartFunction::Gate { .. }
+            | PartFunction::Math { .. }
+            | PartFunction::Compare { .. }
+            | PartFunction::Timer
+            | PartFunction::Memory => &[],
+            PartFunction::Motor {
+                max_speed,
+                max_torque,
+            }
+            | PartFunction::Wheel {
+                max_speed,
+                max_torque,
+            } => &[
+                ("max_speed", max_speed, f32::MAX),
+                ("max_torque", max_torque, f32::MAX),
+            ],
+            PartFunction::Hinge {
+                max_angle,
+                max_torque,
+            } => &[
+                ("max_angle", max_angle, 180.0),
+                ("max_torque", max_torque, f32::MAX),
+            ],
+            PartFunction::Piston {
+                max_extension,
+                max_force,
+            } => &[
+                ("max_extension", max_extension, f32::MAX),
+                ("max_force", max_force, f32::MAX),
+            ],
+            PartFunction::Thruster {
+                max_thrust,
+                fuel_per_second,
+            } => &[
+                ("max_thrust", max_thrust, f32::MAX),
+                ("fuel_per_second", fuel_per_second, f32::MAX),
+            ],
+            PartFunction::Propeller {
+                max_thrust,
+                max_speed,
+                fuel_per_second,
+            } => &[
+                ("max_thrust", max_thrust, f32::MAX),
+                ("max_speed", max_speed, f32::MAX),
+                ("fuel_per_second", fuel_per_second, f32::MAX),
+            ],
+            PartFunction::FuelTank { capacity } => &[("capacity", capacity, f32::MAX)],
+            PartFunction::Wing { lift, drag } => {
+                &[("lift", lift, f32::MAX), ("drag", drag, f32::MAX)]
+            }
+            PartFunction::ControlSurface {
+                lift,
+                drag,
+                max_deflection,
+            } => &[
+                ("lift", lift, f32::MAX),
+                ("drag", drag, f32::MAX),
+                ("max_deflection", max_deflection, 90.0),
+            ],
+        };
+
+        for &(name, value, max) in parameters {
+            // Also false for NaN.
+            if !(0.0..=max).contains(&value) {
+                return Err(format!("`{name}` must be between 0 and {max}, got {value}"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl MaterialDefinition {
+    fn default_color() -> [f32; 4] {
+        [1.0; 4]
+    }
+
+    fn default_roughness() -> f32 {
+        0.5
+    }
+}
+
+/// The layout of a part definition file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartFile {
+    #[serde(default, rename = "material")]
+    materials: Vec<MaterialDefinition>,
+    #[serde(default, rename = "part")]
+    parts: Vec<PartDefinition>,
+}
+
+/// The central list of all available parts.
+///
+/// Parts are sorted by their id, the index of a part is its [`PartNetId`].
+#[derive(Debug, Clone, Resource)]
+pub struct PartRegistry {
+    parts: Vec<PartDefinition>,
+    net_ids: HashMap<PartId, PartNetId>,
+    materials: BTreeMap<String, MaterialDefinition>,
+    checksum: u64,
+}
+
+impl PartRegistry {
+    /// Create a registry only containing [`BUILTIN_PARTS`].
+    pub fn builtin() -> Self {
+        Self::from_sources([(BUILTIN_SOURCE, BUILTIN_PARTS)])
+            .expect("The builtin part definitions must be valid")
+    }
+
+    /// Create a registry with small parts of every kind for tests.
+    #[cfg(test)]
+    pub(crate) fn test() -> Self {
+        Self::from_sources([("test", TEST_PARTS)]).expect("The test part definitions must be valid")
+    }
+
+    /// Load [`BUILTIN_PARTS`] and all files in [`Paths::PartDefinitions`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file could not be read or a definition is invalid.
+    pub fn load_default() -> Result<Self, PartRegistryError> {
+        let dir: PathBuf = Paths::PartDefinitions.into();
+        if dir.is_dir() {
+            Self::load_dir(dir)
+        } else {
+            Ok(Self::builtin())
+        }
+    }
+
+    /// Load [`BUILTIN_PARTS`] and all `*.toml` files in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file could not be read or a definition is invalid.
+    pub fn load_dir<P>(dir: P) -> Result<Self, PartRegistryError>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let io_error = |source| PartRegistryError::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+
+        let mut paths = fs::read_dir(dir)
+            .map_err(io_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()
+            .map_err(io_error)?;
+        paths.retain(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        });
+        // Sorted to get the same errors on every platform.
+        paths.sort();
+
+        let mut sources = vec![(BUILTIN_SOURCE.to_string(), BUILTIN_PARTS.to_string())];
+        for path in paths {
+            let contents = fs::read_to_string(&path).map_err(|source| PartRegistryError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            sources.push((path.display().to_string(), contents));
+        }
+
+        Self::from_sources(
+            sources
+                .iter()
+                .map(|(name, contents)| (name.as_str(), contents.as_str())),
+        )
+    }
+
+    /// Create a registry from `(source name, TOML)` pairs.
+    ///
+    /// The source name is only used in errors.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if
+    /// - a source is not valid TOML or has unknown fields.
+    /// - a part or material id is defined twice.
+    /// - a part is invalid or references an unknown material.
+    /// - there are more parts than a [`PartNetId`] can address.
+    pub fn from_sources<'a, I>(sources: I) -> Result<Self, PartRegistryError>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut parts: BTreeMap<PartId, PartDefinition> = BTreeMap::new();
+        let mut materials: BTreeMap<String, MaterialDefinition> = BTreeMap::new();
+
+        for (source_name, contents) in sources {
+            let file: PartFile =
+                toml::from_str(contents).map_err(|error| PartRegistryError::Parse {
+                    source_name: source_name.to_string(),
+                    error,
+                })?;
+
+            for material in file.materials {
+                if materials.contains_key(&material.id) {
+                    return Err(PartRegistryError::DuplicateMaterial {
+                        id: material.id,
+                        source_name: source_name.to_string(),
+                    });
+                }
+                materials.insert(material.id.clone(), material);
+            }
+
+            for mut part in file.parts {
+                if let Err(reason) = Self::validate(&mut part) {
+                    return Err(PartRegistryError::InvalidPart {
+                        id: part.id,
+                        source_name: source_name.to_string(),
+                        reason,
+                    });
+                }
+                if parts.contains_key(&part.id) {
+                    return Err(PartRegistryError::DuplicatePart {
+                        id: part.id,
+                        source_name: source_name.to_string(),
+                    });
+                }
+                parts.insert(part.id.clone(), part);
+            }
+        }
+
+        // Materials can be defined in any file, so they are checked after everything is loaded.
+        if let Some(part) = parts
+            .values()
+            .find(|part| !materials.contains_key(&part.material))
+        {
+            return Err(PartRegistryError::UnknownMaterial {
+                id: part.id.clone(),
+                material: part.material.clone(),
+            });
+        }
+
+        if parts.len() > u16::MAX as usize + 1 {
+            return Err(PartRegistryError::TooManyParts {
+                max: u16::MAX as usize + 1,
+            });
+        }
+
+        let parts: Vec<PartDefinition> = parts.into_values().collect();
+        let net_ids = parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| (part.id.clone(), PartNetId(index as u16)))
+            .collect();
+        let checksum = Self::calculate_checksum(&parts, &materials);
+
+        Ok(Self {
+            parts,
+            net_ids,
+            materials,
+            checksum,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&PartDefinition> {
+        self.net_id(id)
+            .and_then(|net_id| self.get_by_net_id(net_id))
+    }
+
+    pub fn get_by_net_id(&self, net_id: PartNetId) -> Option<&PartDefinition> {
+        self.parts.get(net_id.0 as usize)
+    }
+
+    pub fn net_id(&self, id: &str) -> Option<PartNetId> {
+        self.net_ids.get(id).copied()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.net_ids.contains_key(id)
+    }
+
+    /// Iterate over all parts sorted by id.
+    pub fn iter(&self) -> impl Iterator<Item = &PartDefinition> {
+        self.parts.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    pub fn material(&self, id: &str) -> Option<&MaterialDefinition> {
+        self.materials.get(id)
+    }
+
+    /// Iterate over all materials sorted by id.
+    pub fn materials(&self) -> impl Iterator<Item = &MaterialDefinition> {
+        self.materials.values()
+    }
+
+    /// A stable hash over all definitions.
+    ///
+    /// Two registries with the same checksum assign the same [`PartNetId`]s.
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    /// Check a part and normalize its attachment faces.
+    fn validate(part: &mut PartDefinition) -> Result<(), String> {
+        if part.id.is_empty()
+            || !part
+                .id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err("the id may only contain lowercase letters, digits and `_`".into());
+        }
+        if part.name.trim().is_empty() {
+            return Err("the name must not be empty".into());
+        }
+        if !part.mass.is_finite() || part.mass <= 0.0 {
+            return Err(format!("the mass must be positive, got {}", part.mass));
+        }
+        if part.size.min_element() == 0 {
+            return Err(format!("the size must be at least 1, got {}", part.size));
+        }
+        if part.size.max_element() > MAX_PART_SIZE {
+            return Err(format!(
+                "the size must be at most {MAX_PART_SIZE}, got {}",
+                part.size
+            ));
+        }
+        if let Some(function) = &part.function {
+            function.validate()?;
+        }
+
+        part.attachment_faces.sort();
+        part.attachment_faces.dedup();
+
+        Ok(())
+    }
+
+    /// FNV-1a over the canonical TOML representation.
+    ///
+    /// [`std::hash::DefaultHasher`] is not guaranteed to be stable between Rust versions,
+    /// so client and server built with different compilers would disagree.
+    fn calculate_checksum(
+        parts: &[PartDefinition],
+        materials: &BTreeMap<String, MaterialDefinition>,
+    ) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let canonical = toml::to_string(&PartFile {
+            materials: materials.values().cloned().collect(),
+            parts: parts.to_vec(),
+        })
+        .expect("Part definitions are always serializable");
+
+        canonical.bytes().fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PartRegistryError {
+    #[error("failed to read part definitions from {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed to parse part definitions in {source_name}")]
+    Parse {
+        source_name: String,
+        #[source]
+        error: toml::de::Error,
+    },
+    #[error("part `{id}` in {source_name} is already defined")]
+    DuplicatePart { id: PartId, source_name: String },
+    #[error("material `{id}` in {source_name} is already defined")]
+    DuplicateMaterial { id: String, source_name: String },
+    #[error("part `{id}` in {source_name} is invalid: {reason}")]
+    InvalidPart {
+        id: PartId,
+        source_name: String,
+        reason: String,
+    },
+    #[error("part `{id}` uses the unknown material `{material}`")]
+    UnknownMaterial { id: PartId, material: String },
+    #[error("too many parts, at most {max} are supported")]
+    TooManyParts { max: usize },
+}
+
+#[cfg(test)]
+mod part_registry_test {
+    use std::fs;
+
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::{PartNetId, PartRegistry, PartRegistryError},
+        vehicle::{BlockRotation, Face},
+    };
+
+    const MATERIAL: &str = r#"
+        [[material]]
+        id = "test"
+    "#;
+
+    #[test]
+    fn builtin_test() {
+        let registry = PartRegistry::builtin();
+
+        assert!(!registry.is_empty());
+        let cube = registry.get("cube").unwrap();
+        assert_eq!(cube.size, UVec3::ONE);
+        assert_eq!(cube.attachment_faces, Face::ALL.to_vec());
+        assert!(registry.material(&cube.material).is_some());
+    }
+
+    #[test]
+    fn net_id_test() {
+        let registry = PartRegistry::from_sources([
+            ("materials", MATERIAL),
+            (
+                "parts",
+                r#"
+                [[part]]
+                id = "b"
+                name = "B"
+                category = "Structure"
+                mass = 1.0
+                material = "test"
+
+                [[part]]
+                id = "a"
+                name = "A"
+                category = "Logic"
+                mass = 2.0
+                material = "test"
+                "#,
+            ),
+        ])
+        .unwrap();
+
+        // Ids are assigned in sorted order, not in file order.
+        assert_eq!(registry.net_id("a"), Some(PartNetId(0)));
+        assert_eq!(registry.net_id("b"), Some(PartNetId(1)));
+        assert_eq!(registry.net_id("c"), None);
+        assert_eq!(registry.get_by_net_id(PartNetId(1)).unwrap().name, "B");
+    }
+
+    #[test]
+    fn checksum_test() {
+        let part = |mass: f32| {
+            format!(
+                "[[part]]\nid = \"a\"\nname = \"A\"\ncategory = \"Structure\"\nmass = {mass}\nmaterial = \"test\"\n"
+            )
+        };
+        let a = part(1.0);
+        let b = part(2.0);
+
+        let first = PartRegistry::from_sources([("m", MATERIAL), ("a", a.as_str())]).unwrap();
+        // The order of the sources does not matter.
+        let second = PartRegistry::from_sources([("a", a.as_str()), ("m", MATERIAL)]).unwrap();
+        let third = PartRegistry::from_sources([("m", MATERIAL), ("b", b.as_str())]).unwrap();
+
+        assert_eq!(first.checksum(), second.checksum());
+        assert_ne!(first.checksum(), third.checksum());
+        assert_eq!(
+            PartRegistry::builtin().checksum(),
+            PartRegistry::builtin().checksum()
+        );
+    }
+
+    #[test]
+    fn duplicate_test() {
+        let part = r#"
+            [[part]]
+            id = "a"
+            name = "A"
+            category = "Structure"
+            mass = 1.0
+            material = "test"
+        "#;
+
+        assert!(matches!(
+            PartRegistry::from_sources([("m", MATERIAL), ("first", part), ("second", part)]),
+            Err(PartRegistryError::DuplicatePart { id, source_name }) if id == "a" && source_name == "second"
+        ));
+        assert!(matches!(
+            PartRegistry::from_sources([("first", MATERIAL), ("second", MATERIAL)]),
+            Err(PartRegistryError::DuplicateMaterial { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_test() {
+        let invalid = [
+            r#"id = "Upper""#,
+            r#"id = """#,
+            r#"id = "a"
+            mass = -1.0"#,
+            r#"id = "a"
+            size = [1, 0, 1]"#,
+            r#"id = "a"
+            size = [1, 65, 1]"#,
+            r#"id = "a"
+            function = { kind = "Motor", max_speed = -1.0, max_torque = 5.0 }"#,
+            r#"id = "a"
+            function = { kind = "Wheel", max_speed = 10.0, max_torque = nan }"#,
+            r#"id = "a"
+            function = { kind = "Hinge", max_angle = 270.0, max_torque = 5.0 }"#,
+            r#"id = "a"
+            function = { kind = "Piston", max_extension = inf, max_force = 5.0 }"#,
+            r#"id = "a"
+            function = { kind = "Thruster", max_thrust = 100.0, fuel_per_second = -2.0 }"#,
+            r#"id = "a"
+            function = { kind = "FuelTank", capacity = -inf }"#,
+            r#"id = "a"
+            function = { kind = "ControlSurface", lift = 6.0, drag = 0.1, max_deflection = 91.0 }"#,
+        ];
+
+        for definition in invalid {
+            // Later keys override the defaults below.
+            let source = format!(
+                "[[part]]\n{definition}\nname = \"A\"\ncategory = \"Structure\"\nmaterial = \"test\"\n{}",
+                if definition.contains("mass") {
+                    ""
+                } else {
+                    "mass = 1.0\n"
+                }
+            );
+
+            assert!(
+                matches!(
+                    PartRegistry::from_sources([("m", MATERIAL), ("part", source.as_str())]),
+                    Err(PartRegistryError::InvalidPart { .. })
+                ),
+                "{source}"
+            );
+        }
+
+        assert!(matches!(
+            PartRegistry::from_sources([(
+                "part",
+                "[[part]]\nid = \"a\"\nname = \"A\"\ncategory = \"Structure\"\nmass = 1.0\nmaterial = \"missing\"\n"
+            )]),
+            Err(PartRegistryError::UnknownMaterial { .. })
+        ));
+        assert!(matches!(
+            PartRegistry::from_sources([("part", "[[part]]\nid = \"a\"\nunknown = 1\n")]),
+            Err(PartRegistryError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn cells_test() {
+        let registry = PartRegistry::from_sources([(
+            "part",
+            r#"
+            [[material]]
+            id = "test"
+
+            [[part]]
+            id = "long"
+            name = "Long"
+            category = "Structure"
+            size = [3, 1, 1]
+            mass = 1.0
+            attachment_faces = ["PosX", "PosX"]
+            material = "test"
+            "#,
+        )])
+        .unwrap();
+        let part = registry.get("long").unwrap();
+
+        let cells: Vec<IVec3> = part.cells(BlockRotation::IDENTITY).collect();
+        assert_eq!(cells, vec![IVec3::ZERO, IVec3::X, IVec3::X * 2]);
+
+        let rotated = BlockRotation::new(Face::PosX, 0);
+        let cells: Vec<IVec3> = part.cells(rotated).collect();
+        assert_eq!(cells, vec![IVec3::ZERO, IVec3::NEG_Y, IVec3::NEG_Y * 2]);
+
+        assert_eq!(part.attachment_faces, vec![Face::PosX]);
+        assert!(part.can_attach(BlockRotation::IDENTITY, Face::PosX));
+        assert!(!part.can_attach(BlockRotation::IDENTITY, Face::NegX));
+        assert!(part.can_attach(rotated, Face::NegY));
+    }
+
+    #[test]
+    fn load_dir_test() {
+        let dir = "./testparts";
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            format!("{dir}/extra.toml"),
+            "[[part]]\nid = \"extra\"\nname = \"Extra\"\ncategory = \"Structure\"\nmass = 1.0\nmaterial = \"steel\"\n",
+        )
+        .unwrap();
+        fs::write(format!("{dir}/ignored.txt"), "not toml").unwrap();
+
+        let registry = PartRegistry::load_dir(dir);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        let registry = registry.unwrap();
+        assert!(registry.contains("extra"));
+        assert!(registry.contains("cube"));
+        assert_ne!(registry.checksum(), PartRegistry::builtin().checksum());
+    }
+}
@@ -8,6 +8,9 @@ use bevy::prelude::*;
 use serde::{Serialize, de::DeserializeOwned};
 use thiserror::Error;
 
+pub mod peer_id;
+pub mod u64_string;
+
 #[derive(Debug)]
 pub struct SaveSystem;
 
@@ -21,7 +24,7 @@ impl SaveSystem {
     /// This function will return an error if
     /// - it fails to create all path components or the file.
     /// - the data failed to get serialized.
-    pub fn save_data<'a, P, T>(path: P, data: &T) -> Result<(), SaveSystemError>
+    pub fn save_data<P, T>(path: P, data: &T) -> Result<(), SaveSystemError>
     where
         P: Into<PathBuf>,
         T: Serialize,
@@ -36,7 +39,7 @@ impl SaveSystem {
     /// This function will return an error if
     /// - `path` not already exist.
     /// - the data failed to get deserialized.
-    pub fn load_data<'a, P, T>(path: P) -> Result<T, SaveSystemError>
+    pub fn load_data<P, T>(path: P) -> Result<T, SaveSystemError>
     where
         P: Into<PathBuf>,
         T: DeserializeOwned,
@@ -161,8 +164,8 @@ mod save_system_test {
         let path = "./test";
         let contents = b"Yay".to_vec();
 
-        SaveSystem::write(&path, &contents).unwrap();
-        let read_contents = SaveSystem::read(&path).unwrap();
+        SaveSystem::write(path, &contents).unwrap();
+        let read_contents = SaveSystem::read(path).unwrap();
 
         fs::remove_file(path).unwrap();
 
@@ -174,8 +177,8 @@ mod save_system_test {
         let path = "./testdir/test";
         let contents = b"Yay".to_vec();
 
-        SaveSystem::write(&path, &contents).unwrap();
-        let read_contents = SaveSystem::read(&path).unwrap();
+        SaveSystem::write(path, &contents).unwrap();
+        let read_contents = SaveSystem::read(path).unwrap();
 
         fs::remove_dir_all(PathBuf::from(path).parent().unwrap()).unwrap();
 
@@ -187,10 +190,10 @@ mod save_system_test {
         let path = "./testsaveload";
         let contents = Data { value: 101 };
 
-        SaveSystem::save(&path, &contents).unwrap();
-        let read_contents = SaveSystem::load(&path).unwrap();
+        SaveSystem::save(path, &contents).unwrap();
+        let read_contents = SaveSystem::load(path).unwrap();
 
-        fs::remove_file(&path).unwrap();
+        fs::remove_file(path).unwrap();
 
         assert_eq!(contents, read_contents);
     }
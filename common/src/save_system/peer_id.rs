@@ -0,0 +1,42 @@
+//! Saves a [`PeerId`] with its id as a string, see [`super::u64_string`].
+//!
+//! Use it with `#[serde(with = "common::save_system::peer_id")]`.
+
+use lightyear::prelude::PeerId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize)]
+enum SavedPeerId {
+    Entity(#[serde(with = "super::u64_string")] u64),
+    Netcode(#[serde(with = "super::u64_string")] u64),
+    Steam(#[serde(with = "super::u64_string")] u64),
+    Local(#[serde(with = "super::u64_string")] u64),
+    Server,
+}
+
+/// # Errors
+///
+/// This function will return an error if the serializer fails.
+pub fn serialize<S: Serializer>(id: &PeerId, serializer: S) -> Result<S::Ok, S::Error> {
+    let saved = match *id {
+        PeerId::Entity(id) => SavedPeerId::Entity(id),
+        PeerId::Netcode(id) => SavedPeerId::Netcode(id),
+        PeerId::Steam(id) => SavedPeerId::Steam(id),
+        PeerId::Local(id) => SavedPeerId::Local(id),
+        PeerId::Server => SavedPeerId::Server,
+    };
+    saved.serialize(serializer)
+}
+
+/// # Errors
+///
+/// This function will return an error if the value is not a saved [`PeerId`].
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PeerId, D::Error> {
+    Ok(match SavedPeerId::deserialize(deserializer)? {
+        SavedPeerId::Entity(id) => PeerId::Entity(id),
+        SavedPeerId::Netcode(id) => PeerId::Netcode(id),
+        SavedPeerId::Steam(id) => PeerId::Steam(id),
+        SavedPeerId::Local(id) => PeerId::Local(id),
+        SavedPeerId::Server => PeerId::Server,
+    })
+}
@@ -0,0 +1,31 @@
+//! Saves a `u64` as a string, because TOML has no integers above `i64::MAX`.
+//!
+//! Use it with `#[serde(with = "common::save_system::u64_string")]`. Integers are still
+//! accepted when loading.
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedU64 {
+    Integer(u64),
+    String(String),
+}
+
+/// # Errors
+///
+/// This function will return an error if the serializer fails.
+pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// # Errors
+///
+/// This function will return an error if the value is neither an integer nor a string
+/// holding one.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match SavedU64::deserialize(deserializer)? {
+        SavedU64::Integer(value) => Ok(value),
+        SavedU64::String(value) => value.parse().map_err(serde::de::Error::custom),
+    }
+}
@@ -0,0 +1,1137 @@
+use std::collections::{BTreeMap, HashMap};
+
+use avian3d::prelude::{Collider, NoAutoAngularInertia, NoAutoCenterOfMass, NoAutoMass};
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser::SerializeStruct};
+use thiserror::Error;
+
+use crate::{
+    Name, Paths,
+    part_registry::PartRegistry,
+    save_system::{SaveSystem, SaveSystemError},
+    vehicle::{
+        collider::VehicleColliderBuilder,
+        joint::VehicleBodies,
+        mass::MassAccumulator,
+        seat::SeatPlugin,
+        signal::{SignalType, Wire},
+    },
+};
+
+pub mod aerodynamics;
+pub mod collider;
+pub mod edit;
+pub mod joint;
+pub mod mass;
+pub mod mesh;
+pub mod occupancy;
+pub mod propulsion;
+pub mod raycast;
+pub mod seat;
+pub mod signal;
+
+#[derive(Debug)]
+pub struct VehiclePlugin;
+
+impl Plugin for VehiclePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(SeatPlugin)
+            .add_observer(insert_bodies_observer)
+            .add_observer(update_bodies_observer)
+            .add_observer(insert_mass_observer)
+            .add_observer(update_mass_observer)
+            .add_observer(insert_collider_observer)
+            .add_observer(update_collider_observer);
+    }
+}
+
+/// The edge length of a single block in meters.
+pub const BLOCK_SIZE: f32 = 0.01;
+
+/// The furthest a block of a spawned or edited vehicle may be from the origin along any axis.
+pub const MAX_EXTENT: i32 = 5_000;
+
+/// The stable identifier of a part, e.g. `"cube"`.
+pub type PartId = String;
+
+/// Arbitrary per-block configuration, e.g. the label of a button.
+pub type BlockProperties = BTreeMap<String, PropertyValue>;
+
+/// A value stored in [`BlockProperties`].
+///
+/// Human readable formats store the plain value. Binary formats can not guess the type
+/// of a value, so they store it tagged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl Serialize for PropertyValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            match self {
+                Self::Bool(value) => serializer.serialize_bool(*value),
+                Self::Number(value) => serializer.serialize_f64(*value),
+                Self::Text(value) => serializer.serialize_str(value),
+            }
+        } else {
+            TaggedPropertyValue::from(self.clone()).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PropertyValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            UntaggedPropertyValue::deserialize(deserializer).map(Self::from)
+        } else {
+            TaggedPropertyValue::deserialize(deserializer).map(Self::from)
+        }
+    }
+}
+
+/// One of the six faces of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::PosX,
+        Face::NegX,
+        Face::PosY,
+        Face::NegY,
+        Face::PosZ,
+        Face::NegZ,
+    ];
+
+    /// The outward facing unit normal of the face.
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Self::PosX => IVec3::X,
+            Self::NegX => IVec3::NEG_X,
+            Self::PosY => IVec3::Y,
+            Self::NegY => IVec3::NEG_Y,
+            Self::PosZ => IVec3::Z,
+            Self::NegZ => IVec3::NEG_Z,
+        }
+    }
+
+    /// Returns the face whose normal is `normal`.
+    ///
+    /// Returns [`None`] if `normal` is not an axis aligned unit vector.
+    pub fn from_normal(normal: IVec3) -> Option<Self> {
+        Self::ALL.into_iter().find(|face| face.normal() == normal)
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::PosX => Self::NegX,
+            Self::NegX => Self::PosX,
+            Self::PosY => Self::NegY,
+            Self::NegY => Self::PosY,
+            Self::PosZ => Self::NegZ,
+            Self::NegZ => Self::PosZ,
+        }
+    }
+
+    /// The index of the axis the face is perpendicular to. `0` is X, `1` is Y and `2` is Z.
+    pub fn axis(self) -> usize {
+        match self {
+            Self::PosX | Self::NegX => 0,
+            Self::PosY | Self::NegY => 1,
+            Self::PosZ | Self::NegZ => 2,
+        }
+    }
+
+    /// Returns true if the normal points into the positive direction of its axis.
+    pub fn is_positive(self) -> bool {
+        matches!(self, Self::PosX | Self::PosY | Self::PosZ)
+    }
+}
+
+/// One of the 24 axis aligned orientations of a block.
+///
+/// The rotation first points the blocks up (+Y) face to `up` and then turns the block
+/// `turns` times by 90 degrees around that face.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockRotation {
+    pub up: Face,
+    pub turns: u8,
+}
+
+impl BlockRotation {
+    pub const IDENTITY: Self = Self {
+        up: Face::PosY,
+        turns: 0,
+    };
+
+    pub fn new(up: Face, turns: u8) -> Self {
+        Self {
+            up,
+            turns: turns % 4,
+        }
+    }
+
+    /// Iterate over all 24 orientations.
+    pub fn all() -> impl Iterator<Item = Self> {
+        Face::ALL
+            .into_iter()
+            .flat_map(|up| (0..4).map(move |turns| Self::new(up, turns)))
+    }
+
+    pub fn to_quat(self) -> Quat {
+        use std::f32::consts::{FRAC_PI_2, PI};
+
+        let up = match self.up {
+            Face::PosY => Quat::IDENTITY,
+            Face::NegY => Quat::from_rotation_x(PI),
+            Face::PosX => Quat::from_rotation_z(-FRAC_PI_2),
+            Face::NegX => Quat::from_rotation_z(FRAC_PI_2),
+            Face::PosZ => Quat::from_rotation_x(FRAC_PI_2),
+            Face::NegZ => Quat::from_rotation_x(-FRAC_PI_2),
+        };
+
+        up * Quat::from_rotation_y(FRAC_PI_2 * (self.turns % 4) as f32)
+    }
+
+    /// Rotate a grid offset.
+    pub fn rotate(self, offset: IVec3) -> IVec3 {
+        (self.to_quat() * offset.as_vec3()).round().as_ivec3()
+    }
+
+    /// Returns the face a local `face` points to after rotating.
+    pub fn rotate_face(self, face: Face) -> Face {
+        Face::from_normal(self.rotate(face.normal()))
+            .expect("Rotating an axis aligned normal by 90 degree steps is axis aligned")
+    }
+
+    /// Returns the orientation turned once more around the up face.
+    pub fn next_turn(self) -> Self {
+        Self::new(self.up, self.turns + 1)
+    }
+
+    /// Returns the orientation with the up face changed to the next face in [`Face::ALL`].
+    pub fn next_up(self) -> Self {
+        let index = Face::ALL
+            .iter()
+            .position(|face| *face == self.up)
+            .expect("Face::ALL contains every face");
+
+        Self::new(Face::ALL[(index + 1) % Face::ALL.len()], self.turns)
+    }
+}
+
+impl Default for BlockRotation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A single part placed into a [`Blueprint`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Block {
+    /// The grid position in blocks.
+    pub position: IVec3,
+    pub part: PartId,
+    #[serde(default)]
+    pub rotation: BlockRotation,
+    #[serde(default)]
+    pub properties: BlockProperties,
+}
+
+impl Serialize for Block {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Binary formats have no field names and always have to write every field.
+        let skip_properties = serializer.is_human_readable() && self.properties.is_empty();
+
+        let mut state =
+            serializer.serialize_struct("Block", if skip_properties { 3 } else { 4 })?;
+        state.serialize_field("position", &self.position)?;
+        state.serialize_field("part", &self.part)?;
+        state.serialize_field("rotation", &self.rotation)?;
+        if skip_properties {
+            state.skip_field("properties")?;
+        } else {
+            state.serialize_field("properties", &self.properties)?;
+        }
+        state.end()
+    }
+}
+
+impl Block {
+    pub fn new(position: IVec3, part: impl Into<PartId>) -> Self {
+        Self {
+            position,
+            part: part.into(),
+            rotation: BlockRotation::IDENTITY,
+            properties: BlockProperties::new(),
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: BlockRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_property(mut self, key: impl Into<String>, value: PropertyValue) -> Self {
+        self.properties.insert(key.into(), value);
+        self
+    }
+}
+
+/// The inclusive axis aligned bounds of all blocks of a [`Blueprint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl Bounds {
+    pub fn from_position(position: IVec3) -> Self {
+        Self {
+            min: position,
+            max: position,
+        }
+    }
+
+    /// Grow the bounds to include `position`.
+    pub fn extend(&mut self, position: IVec3) {
+        self.min = self.min.min(position);
+        self.max = self.max.max(position);
+    }
+
+    pub fn contains(&self, position: IVec3) -> bool {
+        position.cmpge(self.min).all() && position.cmple(self.max).all()
+    }
+
+    /// Returns true if `position` lies on the outer shell of the bounds.
+    pub fn is_on_border(&self, position: IVec3) -> bool {
+        position.cmpeq(self.min).any() || position.cmpeq(self.max).any()
+    }
+
+    /// Returns true if the bounds lie within [`MAX_EXTENT`] of the origin.
+    pub fn is_within_extent(&self) -> bool {
+        self.min.cmpge(IVec3::splat(-MAX_EXTENT)).all()
+            && self.max.cmple(IVec3::splat(MAX_EXTENT)).all()
+    }
+
+    /// The size in blocks along every axis.
+    pub fn size(&self) -> IVec3 {
+        self.max - self.min + IVec3::ONE
+    }
+}
+
+/// A vehicle built from blocks on a sparse integer grid.
+///
+/// Wires connect the signal ports of blocks. Removing a block removes its wires as well.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "BlueprintData", into = "BlueprintData")]
+pub struct Blueprint {
+    name: Name,
+    blocks: HashMap<IVec3, Block>,
+    bounds: Option<Bounds>,
+    wires: Vec<Wire>,
+}
+
+impl Blueprint {
+    pub fn new(name: impl Into<Name>) -> Self {
+        Self {
+            name: name.into(),
+            blocks: HashMap::new(),
+            bounds: None,
+            wires: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: impl Into<Name>) {
+        self.name = name.into();
+    }
+
+    /// Add a block at [`Block::position`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the position is already occupied.
+    pub fn add(&mut self, block: Block) -> Result<(), BlueprintError> {
+        let position = block.position;
+        if self.blocks.contains_key(&position) {
+            return Err(BlueprintError::Occupied(position));
+        }
+
+        match &mut self.bounds {
+            Some(bounds) => bounds.extend(position),
+            None => self.bounds = Some(Bounds::from_position(position)),
+        }
+        self.blocks.insert(position, block);
+
+        Ok(())
+    }
+
+    /// Remove the block at `position` and its wires and return the block.
+    pub fn remove(&mut self, position: IVec3) -> Option<Block> {
+        let block = self.blocks.remove(&position)?;
+        self.wires
+            .retain(|wire| wire.from.block != position && wire.to.block != position);
+
+        // Only a block on the border can shrink the bounds.
+        if self
+            .bounds
+            .is_some_and(|bounds| bounds.is_on_border(position))
+        {
+            self.recalculate_bounds();
+        }
+
+        Some(block)
+    }
+
+    pub fn get(&self, position: IVec3) -> Option<&Block> {
+        self.blocks.get(&position)
+    }
+
+    pub fn contains(&self, position: IVec3) -> bool {
+        self.blocks.contains_key(&position)
+    }
+
+    /// Change the rotation of the block at `position` and return the old rotation.
+    pub fn set_rotation(
+        &mut self,
+        position: IVec3,
+        rotation: BlockRotation,
+    ) -> Option<BlockRotation> {
+        self.blocks
+            .get_mut(&position)
+            .map(|block| std::mem::replace(&mut block.rotation, rotation))
+    }
+
+    /// Set or remove (`value` is [`None`]) a property of the block at `position`.
+    ///
+    /// Returns the old value or [`None`] if there was no value or no block.
+    pub fn set_property(
+        &mut self,
+        position: IVec3,
+        key: impl Into<String>,
+        value: Option<PropertyValue>,
+    ) -> Option<PropertyValue> {
+        let block = self.blocks.get_mut(&position)?;
+        let key = key.into();
+        match value {
+            Some(value) => block.properties.insert(key, value),
+            None => block.properties.remove(&key),
+        }
+    }
+
+    /// Iterate over all blocks in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The bounds of all blocks or [`None`] if the blueprint is empty.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    /// Connect an output port to an input port.
+    ///
+    /// Port names and types are checked by
+    /// [`crate::vehicle::edit::EditOperation::validate`], which needs the part registry.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if one of the blocks does not exist or the input
+    /// is already connected.
+    pub fn connect(&mut self, wire: Wire) -> Result<(), BlueprintError> {
+        for position in [wire.from.block, wire.to.block] {
+            if !self.contains(position) {
+                return Err(BlueprintError::Empty(position));
+            }
+        }
+        if self.wires.iter().any(|other| other.to == wire.to) {
+            return Err(BlueprintError::InputConnected(wire.to.block, wire.to.port));
+        }
+
+        self.wires.push(wire);
+
+        Ok(())
+    }
+
+    /// Remove a wire. Returns false if it did not exist.
+    pub fn disconnect(&mut self, wire: &Wire) -> bool {
+        let len = self.wires.len();
+        self.wires.retain(|other| other != wire);
+
+        self.wires.len() != len
+    }
+
+    /// Iterate over all wires in the order they were connected.
+    pub fn wires(&self) -> impl Iterator<Item = &Wire> {
+        self.wires.iter()
+    }
+
+    /// Iterate over the wires from and to the block at `position`.
+    pub fn wires_of(&self, position: IVec3) -> impl Iterator<Item = &Wire> {
+        self.wires
+            .iter()
+            .filter(move |wire| wire.from.block == position || wire.to.block == position)
+    }
+
+    /// Save the blueprint to [`Paths::VehicleSave`] using its name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the blueprint could not be saved.
+    pub fn save(&self) -> Result<(), SaveSystemError> {
+        SaveSystem::save_data(Paths::VehicleSave(self.name.clone()), self)
+    }
+
+    /// Load the blueprint called `name` from [`Paths::VehicleSave`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the blueprint does not exist or could not be loaded.
+    pub fn load(name: impl Into<Name>) -> Result<Self, SaveSystemError> {
+        SaveSystem::load_data(Paths::VehicleSave(name.into()))
+    }
+
+    fn recalculate_bounds(&mut self) {
+        let mut positions = self.blocks.keys();
+        self.bounds = positions.next().map(|first| {
+            positions.fold(Bounds::from_position(*first), |mut bounds, position| {
+                bounds.extend(*position);
+                bounds
+            })
+        });
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BlueprintError {
+    #[error("position {0} is already occupied")]
+    Occupied(IVec3),
+    #[error("there is no block at position {0}")]
+    Empty(IVec3),
+    #[error("part `{0}` does not exist")]
+    UnknownPart(PartId),
+    #[error("the block at {0} has no port `{1}`")]
+    UnknownPort(IVec3, String),
+    #[error("can not connect a {0:?} output to a {1:?} input")]
+    WireTypeMismatch(SignalType, SignalType),
+    #[error("input `{1}` of the block at {0} is already connected")]
+    InputConnected(IVec3, String),
+    #[error("the ports are not connected")]
+    NotConnected,
+    #[error("the block at {0} reaches outside of the grid")]
+    OutOfRange(IVec3),
+}
+
+/// The blueprint of a vehicle that is spawned into the world.
+///
+/// Inserting it splits the vehicle into [`VehicleBodies`] and computes the mass properties
+/// and the collider of the first body. Trigger [`VehicleBlocksChanged`] after adding or
+/// removing blocks to keep them up to date.
+#[derive(Debug, Clone, PartialEq, Component, Deref, DerefMut, Serialize, Deserialize)]
+pub struct VehicleBlueprint(pub Blueprint);
+
+/// Triggered after blocks of a [`VehicleBlueprint`] were added or removed.
+#[derive(Debug, EntityEvent)]
+pub struct VehicleBlocksChanged {
+    pub entity: Entity,
+    pub added: Vec<Block>,
+    pub removed: Vec<Block>,
+}
+
+fn insert_bodies_observer(
+    trigger: On<Insert, VehicleBlueprint>,
+    mut commands: Commands,
+    vehicles: Query<&VehicleBlueprint>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(blueprint) = vehicles.get(entity) else {
+        return;
+    };
+
+    match VehicleBodies::split(blueprint, &registry) {
+        Ok(bodies) => {
+            commands.entity(entity).insert(bodies);
+        }
+        Err(e) => error!(
+            "Failed to split vehicle `{}` into bodies: {}",
+            blueprint.name(),
+            e
+        ),
+    }
+}
+
+/// Split the vehicle again if the edit can change its bodies, which also rebuilds its mass
+/// and collider.
+fn update_bodies_observer(
+    trigger: On<VehicleBlocksChanged>,
+    mut commands: Commands,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+) {
+    let event = trigger.event();
+    let Ok((blueprint, bodies)) = vehicles.get(event.entity) else {
+        return;
+    };
+
+    let is_joint = |block: &Block| {
+        registry
+            .get(&block.part)
+            .and_then(|part| part.function.as_ref())
+            .is_some_and(|function| function.is_joint())
+    };
+    if bodies.len() <= 1 && !event.added.iter().chain(&event.removed).any(is_joint) {
+        return;
+    }
+
+    match VehicleBodies::split(blueprint, &registry) {
+        Ok(bodies) => {
+            commands.entity(event.entity).insert(bodies);
+        }
+        Err(e) => error!(
+            "Failed to split vehicle {} into bodies: {}",
+            event.entity, e
+        ),
+    }
+}
+
+fn insert_mass_observer(
+    trigger: On<Insert, VehicleBodies>,
+    mut commands: Commands,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok((blueprint, bodies)) = vehicles.get(entity) else {
+        return;
+    };
+
+    match MassAccumulator::from_blueprint(&bodies.root_blueprint(blueprint), &registry) {
+        Ok(accumulator) => {
+            let mut entity_commands = commands.entity(entity);
+            entity_commands.insert((
+                accumulator,
+                // The blocks already account for everything, colliders must not add to it.
+                NoAutoMass,
+                NoAutoCenterOfMass,
+                NoAutoAngularInertia,
+            ));
+            if let Some(properties) = accumulator.properties() {
+                entity_commands.insert(properties.to_components());
+            }
+        }
+        Err(e) => error!(
+            "Failed to compute the mass of vehicle `{}`: {}",
+            blueprint.name(),
+            e
+        ),
+    }
+}
+
+/// Update the mass properties without iterating over the whole blueprint.
+///
+/// Vehicles with several bodies are split again by `update_bodies_observer` instead.
+fn update_mass_observer(
+    trigger: On<VehicleBlocksChanged>,
+    mut commands: Commands,
+    mut vehicles: Query<&mut MassAccumulator>,
+    bodies: Query<&VehicleBodies>,
+    registry: Res<PartRegistry>,
+) {
+    let event = trigger.event();
+    let Ok(mut accumulator) = vehicles.get_mut(event.entity) else {
+        return;
+    };
+    if bodies
+        .get(event.entity)
+        .is_ok_and(|bodies| bodies.len() > 1)
+    {
+        return;
+    }
+
+    let result = event
+        .removed
+        .iter()
+        .try_for_each(|block| accumulator.remove(block, &registry))
+        .and_then(|_| {
+            event
+                .added
+                .iter()
+                .try_for_each(|block| accumulator.add(block, &registry))
+        });
+    if let Err(e) = result {
+        error!(
+            "Failed to update the mass of vehicle {}: {}",
+            event.entity, e
+        );
+        return;
+    }
+
+    if let Some(properties) = accumulator.properties() {
+        commands
+            .entity(event.entity)
+            .insert(properties.to_components());
+    }
+}
+
+fn insert_collider_observer(
+    trigger: On<Insert, VehicleBodies>,
+    mut commands: Commands,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok((blueprint, bodies)) = vehicles.get(entity) else {
+        return;
+    };
+
+    match VehicleColliderBuilder::from_blueprint(&bodies.root_blueprint(blueprint), &registry) {
+        Ok(builder) => {
+            let mut entity_commands = commands.entity(entity);
+            match builder.collider() {
+                Some(collider) => entity_commands.insert(collider),
+                None => entity_commands.remove::<Collider>(),
+            };
+            entity_commands.insert(builder);
+        }
+        Err(e) => error!(
+            "Failed to build the collider of vehicle `{}`: {}",
+            blueprint.name(),
+            e
+        ),
+    }
+}
+
+/// Only decompose the chunks that were touched by the edit.
+///
+/// Vehicles with several bodies are split again by `update_bodies_observer` instead.
+fn update_collider_observer(
+    trigger: On<VehicleBlocksChanged>,
+    mut commands: Commands,
+    mut vehicles: Query<&mut VehicleColliderBuilder>,
+    bodies: Query<&VehicleBodies>,
+    registry: Res<PartRegistry>,
+) {
+    let event = trigger.event();
+    let Ok(mut builder) = vehicles.get_mut(event.entity) else {
+        return;
+    };
+    if bodies
+        .get(event.entity)
+        .is_ok_and(|bodies| bodies.len() > 1)
+    {
+        return;
+    }
+
+    let result = event
+        .removed
+        .iter()
+        .try_for_each(|block| builder.remove(block, &registry))
+        .and_then(|_| {
+            event
+                .added
+                .iter()
+                .try_for_each(|block| builder.add(block, &registry))
+        });
+    if let Err(e) = result {
+        error!(
+            "Failed to update the collider of vehicle {}: {}",
+            event.entity, e
+        );
+        return;
+    }
+
+    if !builder.rebuild() {
+        return;
+    }
+
+    let mut entity_commands = commands.entity(event.entity);
+    match builder.collider() {
+        Some(collider) => entity_commands.insert(collider),
+        None => entity_commands.remove::<Collider>(),
+    };
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum UntaggedPropertyValue {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize)]
+enum TaggedPropertyValue {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl From<PropertyValue> for TaggedPropertyValue {
+    fn from(value: PropertyValue) -> Self {
+        match value {
+            PropertyValue::Bool(value) => Self::Bool(value),
+            PropertyValue::Number(value) => Self::Number(value),
+            PropertyValue::Text(value) => Self::Text(value),
+        }
+    }
+}
+
+impl From<TaggedPropertyValue> for PropertyValue {
+    fn from(value: TaggedPropertyValue) -> Self {
+        match value {
+            TaggedPropertyValue::Bool(value) => Self::Bool(value),
+            TaggedPropertyValue::Number(value) => Self::Number(value),
+            TaggedPropertyValue::Text(value) => Self::Text(value),
+        }
+    }
+}
+
+impl From<UntaggedPropertyValue> for PropertyValue {
+    fn from(value: UntaggedPropertyValue) -> Self {
+        match value {
+            UntaggedPropertyValue::Bool(value) => Self::Bool(value),
+            UntaggedPropertyValue::Number(value) => Self::Number(value),
+            UntaggedPropertyValue::Text(value) => Self::Text(value),
+        }
+    }
+}
+
+/// The on disk representation of a [`Blueprint`].
+///
+/// TOML only supports string keys, so the blocks are stored as a sorted list.
+#[derive(Serialize, Deserialize)]
+struct BlueprintData {
+    name: Name,
+    #[serde(default)]
+    blocks: Vec<Block>,
+    #[serde(default)]
+    wires: Vec<Wire>,
+}
+
+impl From<Blueprint> for BlueprintData {
+    fn from(blueprint: Blueprint) -> Self {
+        let mut blocks: Vec<Block> = blueprint.blocks.into_values().collect();
+        blocks.sort_by_key(|block| block.position.to_array());
+
+        Self {
+            name: blueprint.name,
+            blocks,
+            wires: blueprint.wires,
+        }
+    }
+}
+
+impl From<BlueprintData> for Blueprint {
+    fn from(data: BlueprintData) -> Self {
+        let mut blueprint = Blueprint::new(data.name);
+        for block in data.blocks {
+            // A hand edited file may contain a position twice, the last block wins.
+            blueprint.remove(block.position);
+            blueprint
+                .add(block)
+                .expect("The position was freed right before");
+        }
+        // Wires are loaded like they were connected, which drops the ones to missing blocks
+        // and second wires into the same input. Port names need the part registry and are
+        // checked when the blueprint is used.
+        for wire in data.wires {
+            let _ = blueprint.connect(wire);
+        }
+
+        blueprint
+    }
+}
+
+#[cfg(test)]
+mod vehicle_test {
+    use std::fs;
+
+    use bevy::prelude::*;
+
+    use crate::{
+        save_system::SaveSystem,
+        vehicle::{
+            Block, BlockRotation, Blueprint, BlueprintError, Bounds, Face, PropertyValue,
+            signal::Wire,
+        },
+    };
+
+    #[test]
+    fn add_get_remove_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint.add(Block::new(IVec3::X, "cube")).unwrap();
+
+        assert_eq!(blueprint.len(), 2);
+        assert!(blueprint.contains(IVec3::X));
+        assert_eq!(blueprint.get(IVec3::ZERO).unwrap().part, "cube");
+        assert!(blueprint.get(IVec3::Y).is_none());
+
+        let removed = blueprint.remove(IVec3::X).unwrap();
+        assert_eq!(removed.position, IVec3::X);
+        assert!(blueprint.remove(IVec3::X).is_none());
+        assert_eq!(blueprint.len(), 1);
+    }
+
+    #[test]
+    fn add_occupied_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ONE, "cube")).unwrap();
+
+        assert!(blueprint.add(Block::new(IVec3::ONE, "cube")).is_err());
+        assert_eq!(blueprint.len(), 1);
+    }
+
+    #[test]
+    fn bounds_test() {
+        let mut blueprint = Blueprint::new("test");
+        assert_eq!(blueprint.bounds(), None);
+
+        blueprint
+            .add(Block::new(IVec3::new(0, 0, 0), "cube"))
+            .unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(3, -2, 1), "cube"))
+            .unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(1, 1, 5), "cube"))
+            .unwrap();
+        assert_eq!(
+            blueprint.bounds(),
+            Some(Bounds {
+                min: IVec3::new(0, -2, 0),
+                max: IVec3::new(3, 1, 5),
+            })
+        );
+        assert_eq!(blueprint.bounds().unwrap().size(), IVec3::new(4, 4, 6));
+
+        blueprint.remove(IVec3::new(1, 1, 5));
+        assert_eq!(
+            blueprint.bounds(),
+            Some(Bounds {
+                min: IVec3::new(0, -2, 0),
+                max: IVec3::new(3, 0, 1),
+            })
+        );
+
+        blueprint.remove(IVec3::new(0, 0, 0));
+        blueprint.remove(IVec3::new(3, -2, 1));
+        assert_eq!(blueprint.bounds(), None);
+    }
+
+    #[test]
+    fn set_rotation_and_property_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+
+        let rotation = BlockRotation::new(Face::PosX, 1);
+        assert_eq!(
+            blueprint.set_rotation(IVec3::ZERO, rotation),
+            Some(BlockRotation::IDENTITY)
+        );
+        assert_eq!(blueprint.get(IVec3::ZERO).unwrap().rotation, rotation);
+        assert_eq!(blueprint.set_rotation(IVec3::ONE, rotation), None);
+
+        assert_eq!(
+            blueprint.set_property(IVec3::ZERO, "on", Some(PropertyValue::Bool(true))),
+            None
+        );
+        assert_eq!(
+            blueprint.set_property(IVec3::ZERO, "on", None),
+            Some(PropertyValue::Bool(true))
+        );
+        assert!(blueprint.get(IVec3::ZERO).unwrap().properties.is_empty());
+    }
+
+    #[test]
+    fn connect_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint.add(Block::new(IVec3::X, "cube")).unwrap();
+
+        let wire = Wire::new(IVec3::ZERO, "out", IVec3::X, "a");
+        blueprint.connect(wire.clone()).unwrap();
+        assert!(matches!(
+            blueprint.connect(Wire::new(IVec3::X, "out", IVec3::X, "a")),
+            Err(BlueprintError::InputConnected(_, _))
+        ));
+        assert!(matches!(
+            blueprint.connect(Wire::new(IVec3::Y, "out", IVec3::X, "b")),
+            Err(BlueprintError::Empty(_))
+        ));
+
+        assert!(blueprint.disconnect(&wire));
+        assert!(!blueprint.disconnect(&wire));
+        assert_eq!(blueprint.wires().count(), 0);
+    }
+
+    #[test]
+    fn remove_wires_test() {
+        let mut blueprint = Blueprint::new("test");
+        for x in 0..3 {
+            blueprint
+                .add(Block::new(IVec3::new(x, 0, 0), "cube"))
+                .unwrap();
+        }
+        let wire = Wire::new(IVec3::ZERO, "out", IVec3::X, "a");
+        let other = Wire::new(IVec3::ZERO, "out", IVec3::new(2, 0, 0), "a");
+        blueprint.connect(wire.clone()).unwrap();
+        blueprint.connect(other.clone()).unwrap();
+        assert_eq!(blueprint.wires_of(IVec3::ZERO).count(), 2);
+
+        // A block placed at the same position does not inherit the wires.
+        blueprint.remove(IVec3::X).unwrap();
+        assert_eq!(blueprint.wires().collect::<Vec<_>>(), vec![&other]);
+        blueprint.add(Block::new(IVec3::X, "cube")).unwrap();
+        assert_eq!(blueprint.wires_of(IVec3::X).count(), 0);
+        blueprint.connect(wire).unwrap();
+    }
+
+    #[test]
+    fn rotation_test() {
+        assert_eq!(BlockRotation::all().count(), 24);
+
+        // Every orientation must be unique.
+        let mut quats: Vec<Quat> = BlockRotation::all().map(BlockRotation::to_quat).collect();
+        while let Some(quat) = quats.pop() {
+            assert!(quats.iter().all(|other| quat.dot(*other).abs() < 0.99));
+        }
+
+        for face in Face::ALL {
+            let rotation = BlockRotation::new(face, 3);
+            assert_eq!(rotation.rotate(IVec3::Y), face.normal());
+            assert_eq!(rotation.rotate_face(Face::PosY), face);
+        }
+
+        assert_eq!(
+            BlockRotation::new(Face::PosY, 1).rotate(IVec3::X),
+            IVec3::NEG_Z
+        );
+        assert_eq!(BlockRotation::new(Face::PosY, 3).next_turn().turns, 0);
+    }
+
+    #[test]
+    fn face_test() {
+        for face in Face::ALL {
+            assert_eq!(Face::from_normal(face.normal()), Some(face));
+            assert_eq!(face.opposite().normal(), -face.normal());
+            assert_eq!(face.normal()[face.axis()].abs(), 1);
+        }
+        assert_eq!(Face::from_normal(IVec3::ONE), None);
+    }
+
+    #[test]
+    fn save_load_test() {
+        let path = "./testblueprint";
+
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(
+                Block::new(IVec3::new(-1, 2, 3), "cube")
+                    .with_rotation(BlockRotation::new(Face::NegZ, 2))
+                    .with_property("label", PropertyValue::Text("Start".into()))
+                    .with_property("on", PropertyValue::Bool(false))
+                    .with_property("value", PropertyValue::Number(0.5)),
+            )
+            .unwrap();
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint
+            .connect(Wire::new(IVec3::ZERO, "out", IVec3::new(-1, 2, 3), "a"))
+            .unwrap();
+
+        SaveSystem::save(path, &blueprint).unwrap();
+        let loaded: Blueprint = SaveSystem::load(path).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(blueprint, loaded);
+        assert_eq!(blueprint.bounds(), loaded.bounds());
+    }
+
+    #[test]
+    fn dangling_wires_test() {
+        let blueprint: Blueprint = toml::from_str(
+            r#"
+            name = "test"
+
+            [[blocks]]
+            position = [0, 0, 0]
+            part = "cube"
+
+            [[blocks]]
+            position = [1, 0, 0]
+            part = "cube"
+
+            [[wires]]
+            from = { block = [0, 0, 0], port = "out" }
+            to = { block = [1, 0, 0], port = "a" }
+
+            [[wires]]
+            from = { block = [0, 0, 0], port = "out" }
+            to = { block = [1, 0, 0], port = "a" }
+
+            [[wires]]
+            from = { block = [5, 0, 0], port = "out" }
+            to = { block = [1, 0, 0], port = "b" }
+
+            [[wires]]
+            from = { block = [1, 0, 0], port = "out" }
+            to = { block = [0, 0, 7], port = "a" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            blueprint.wires().collect::<Vec<_>>(),
+            vec![&Wire::new(IVec3::ZERO, "out", IVec3::X, "a")]
+        );
+    }
+
+    #[test]
+    fn binary_test() {
+        // The network uses bincode, which can neither skip fields nor guess types.
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(
+                Block::new(IVec3::new(-1, 2, 3), "cube")
+                    .with_property("label", PropertyValue::Text("Start".into()))
+                    .with_property("on", PropertyValue::Bool(true))
+                    .with_property("value", PropertyValue::Number(0.5)),
+            )
+            .unwrap();
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint
+            .connect(Wire::new(IVec3::ZERO, "out", IVec3::new(-1, 2, 3), "a"))
+            .unwrap();
+
+        let config = bincode::config::standard();
+        let bytes = bincode::serde::encode_to_vec(&blueprint, config).unwrap();
+        let (decoded, _): (Blueprint, _) =
+            bincode::serde::decode_from_slice(&bytes, config).unwrap();
+
+        assert_eq!(blueprint, decoded);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::{
+    part_registry::{PartFunction, PartRegistry},
+    vehicle::{
+        BLOCK_SIZE, Blueprint, Face, VehicleBlueprint,
+        joint::{VehicleBodies, VehicleSubBody},
+        signal::{SignalGraph, evaluate_signals_system},
+    },
+};
+
+/// The density of air in kilogram per cubic meter.
+pub const AIR_DENSITY: f32 = 1.225;
+
+/// Applies lift and drag to all wings and control surfaces. Only the server adds it, like
+/// [`crate::vehicle::signal::SignalPlugin`].
+#[derive(Debug)]
+pub struct AerodynamicsPlugin;
+
+impl Plugin for AerodynamicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(insert_wings_observer).add_systems(
+            FixedUpdate,
+            aerodynamics_system.after(evaluate_signals_system),
+        );
+    }
+}
+
+/// A wing or control surface of a vehicle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WingDefinition {
+    /// The position of the wing block.
+    pub block: IVec3,
+    pub function: PartFunction,
+    /// The index of the body the wing is mounted on.
+    pub body: usize,
+    /// The center of the wing in the local space of the blueprint in meters.
+    pub point: Vec3,
+    /// The direction the top face points to in the local space of the blueprint.
+    pub normal: Vec3,
+    /// The direction the front of the wing points to in the local space of the blueprint.
+    pub chord: Vec3,
+    /// The area of the top face in square meters.
+    pub area: f32,
+}
+
+impl WingDefinition {
+    /// The deflection in radians, towards the top face is positive.
+    ///
+    /// Control surfaces follow their `deflect` input from -1 to 1 and stay level while it is
+    /// not wired, wings never deflect.
+    pub fn deflection(&self, graph: Option<&SignalGraph>) -> f32 {
+        let PartFunction::ControlSurface { max_deflection, .. } = self.function else {
+            return 0.0;
+        };
+
+        let command = graph
+            .and_then(|graph| graph.input(self.block, "deflect"))
+            .map_or(0.0, |signal| signal.as_number().clamp(-1.0, 1.0));
+        command * max_deflection.to_radians()
+    }
+
+    /// The lift and drag in the local space of the blueprint while the wing moves with
+    /// `velocity` through still air, deflected by `deflection` radians.
+    ///
+    /// The lift coefficient is `lift * sin(2a) / 2` at the angle of attack `a`, which starts
+    /// out as `lift * a` and fades out once the wing stalls. The drag coefficient grows from
+    /// `drag` to that of a flat plate facing the flow.
+    pub fn force(&self, velocity: Vec3, deflection: f32) -> Vec3 {
+        let (lift, drag) = match self.function {
+            PartFunction::Wing { lift, drag } | PartFunction::ControlSurface { lift, drag, .. } => {
+                (lift, drag)
+            }
+            _ => return Vec3::ZERO,
+        };
+
+        // Flow along the span does not lift.
+        let span = self.chord.cross(self.normal);
+        let flow = -velocity + velocity.dot(span) * span;
+        let speed_squared = flow.length_squared();
+        if speed_squared < 1e-6 {
+            return Vec3::ZERO;
+        }
+        let direction = flow / speed_squared.sqrt();
+
+        // Flow hitting the bottom face from the front is a positive angle of attack.
+        let angle = flow.dot(self.normal).atan2(-flow.dot(self.chord)) + deflection;
+        let lift_coefficient = lift * (2.0 * angle).sin() / 2.0;
+        let drag_coefficient = drag + 2.0 * angle.sin().powi(2);
+        let pressure = 0.5 * AIR_DENSITY * speed_squared * self.area;
+
+        pressure * (lift_coefficient * direction.cross(span) + drag_coefficient * direction)
+    }
+}
+
+/// The wings and control surfaces of a vehicle. Inserted whenever [`VehicleBodies`] are
+/// inserted.
+#[derive(Debug, Clone, Default, PartialEq, Component)]
+pub struct VehicleWings(Vec<WingDefinition>);
+
+impl VehicleWings {
+    /// Collect the wings and control surfaces of `blueprint`. Blocks with a part that is
+    /// not in `registry` are skipped.
+    pub fn collect(blueprint: &Blueprint, registry: &PartRegistry, bodies: &VehicleBodies) -> Self {
+        let mut wings: Vec<_> = blueprint
+            .iter()
+            .filter_map(|block| {
+                let part = registry.get(&block.part)?;
+                let function = part.function.as_ref().filter(|function| {
+                    matches!(
+                        function,
+                        PartFunction::Wing { .. } | PartFunction::ControlSurface { .. }
+                    )
+                })?;
+
+                let cells: Vec<_> = part.cells(block.rotation).collect();
+                let center = block.position.as_vec3()
+                    + cells.iter().map(|cell| cell.as_vec3()).sum::<Vec3>() / cells.len() as f32;
+                Some(WingDefinition {
+                    block: block.position,
+                    function: function.clone(),
+                    body: bodies.body_of(block.position),
+                    point: center * BLOCK_SIZE,
+                    normal: block.rotation.rotate_face(Face::PosY).normal().as_vec3(),
+                    chord: block.rotation.rotate_face(Face::NegZ).normal().as_vec3(),
+                    area: (part.size.x * part.size.z) as f32 * BLOCK_SIZE * BLOCK_SIZE,
+                })
+            })
+            .collect();
+        wings.sort_by_key(|wing| wing.block.to_array());
+
+        Self(wings)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &WingDefinition> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn insert_wings_observer(
+    trigger: On<Insert, VehicleBodies>,
+    mut commands: Commands,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok((blueprint, bodies)) = vehicles.get(entity) else {
+        return;
+    };
+
+    commands
+        .entity(entity)
+        .insert(VehicleWings::collect(blueprint, &registry, bodies));
+}
+
+fn aerodynamics_system(
+    vehicles: Query<(Entity, &VehicleWings, Option<&SignalGraph>)>,
+    sub_bodies: Query<(Entity, &VehicleSubBody)>,
+    mut bodies: Query<(Forces, &ComputedCenterOfMass)>,
+) {
+    let sub_bodies: HashMap<_, _> = sub_bodies
+        .iter()
+        .map(|(entity, sub_body)| ((sub_body.vehicle, sub_body.body), entity))
+        .collect();
+
+    for (vehicle, wings, graph) in vehicles.iter() {
+        if wings.is_empty() {
+            continue;
+        }
+
+        for wing in wings.iter() {
+            let entity = match wing.body {
+                0 => vehicle,
+                body => match sub_bodies.get(&(vehicle, body)) {
+                    Some(entity) => *entity,
+                    None => continue,
+                },
+            };
+            let Ok((mut body, center_of_mass)) = bodies.get_mut(entity) else {
+                continue;
+            };
+
+            // Sub-bodies share the origin of the blueprint, so their local space is the
+            // local space of the blueprint.
+            let (position, rotation) = (body.position().0, body.rotation().0);
+            let point = position + rotation * wing.point;
+            let center_of_mass = position + rotation * center_of_mass.0;
+            let velocity =
+                body.linear_velocity() + body.angular_velocity().cross(point - center_of_mass);
+
+            let force = wing.force(rotation.inverse() * velocity, wing.deflection(graph));
+            body.apply_force_at_point(rotation * force, point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod aerodynamics_test {
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            Block, BlockRotation, Blueprint, Face, PropertyValue,
+            aerodynamics::{AIR_DENSITY, VehicleWings, WingDefinition},
+            joint::VehicleBodies,
+            signal::{DIAL_PROPERTY, SignalGraph, Wire},
+        },
+    };
+
+    fn wings(blueprint: &Blueprint) -> Vec<WingDefinition> {
+        let registry = PartRegistry::test();
+        let bodies = VehicleBodies::split(blueprint, &registry).unwrap();
+        VehicleWings::collect(blueprint, &registry, &bodies)
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn wing(rotation: BlockRotation) -> WingDefinition {
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(Block::new(IVec3::ZERO, "wing").with_rotation(rotation))
+            .unwrap();
+
+        wings(&blueprint).remove(0)
+    }
+
+    /// The velocity of a wing flying forward with `speed` at an angle of attack of `angle`.
+    fn velocity(wing: &WingDefinition, speed: f32, angle: f32) -> Vec3 {
+        (wing.chord * angle.cos() - wing.normal * angle.sin()) * speed
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3 * b.abs().max(1.0), "{a} != {b}");
+    }
+
+    #[test]
+    fn collect_test() {
+        let wing = wing(BlockRotation::default());
+        assert_eq!(wing.normal, Vec3::Y);
+        assert_eq!(wing.chord, Vec3::NEG_Z);
+        assert_close(wing.area, 0.02);
+    }
+
+    #[test]
+    fn lift_test() {
+        let wing = wing(BlockRotation::default());
+        let speed = 20.0;
+        let pressure = 0.5 * AIR_DENSITY * speed * speed * wing.area;
+
+        // Level flight does not lift and only has the base drag.
+        let force = wing.force(velocity(&wing, speed, 0.0), 0.0);
+        assert_close(force.y, 0.0);
+        assert_close(force.z, pressure * 0.1);
+
+        for angle in [5.0_f32, 10.0, -5.0] {
+            let angle = angle.to_radians();
+            let velocity = velocity(&wing, speed, angle);
+            let force = wing.force(velocity, 0.0);
+            let direction = -velocity.normalize();
+
+            // Lift is perpendicular to the flow and points up for positive angles.
+            let lift = force - force.dot(direction) * direction;
+            assert_close(lift.x, 0.0);
+            assert_eq!(lift.y > 0.0, angle > 0.0);
+            assert_close(
+                lift.length(),
+                pressure * 6.0 * (2.0 * angle).sin().abs() / 2.0,
+            );
+            assert_close(
+                force.dot(direction),
+                pressure * (0.1 + 2.0 * angle.sin().powi(2)),
+            );
+        }
+
+        // Small angles lift about linearly.
+        let velocity = velocity(&wing, speed, 0.02);
+        let direction = -velocity.normalize();
+        let force = wing.force(velocity, 0.0);
+        let lift = force - force.dot(direction) * direction;
+        assert!((lift.length() / (pressure * 6.0 * 0.02) - 1.0).abs() < 0.01);
+        assert_eq!(wing.force(Vec3::ZERO, 0.0), Vec3::ZERO);
+    }
+
+    #[test]
+    fn rotated_lift_test() {
+        // A fin standing up lifts sideways.
+        let fin = wing(BlockRotation::new(Face::PosX, 0));
+        assert_eq!(fin.normal, Vec3::X);
+        let force = fin.force(velocity(&fin, 20.0, 5_f32.to_radians()), 0.0);
+        assert!(force.x > 0.0);
+        assert_close(force.y, 0.0);
+
+        // Flow along the span is ignored.
+        let wing = wing(BlockRotation::default());
+        assert_eq!(wing.force(Vec3::X * 20.0, 0.0), Vec3::ZERO);
+    }
+
+    #[test]
+    fn deflection_test() {
+        let registry = PartRegistry::test();
+        let mut blueprint = Blueprint::new("test");
+        let (dial, flap) = (IVec3::new(0, 0, 20), IVec3::ZERO);
+        blueprint.add(Block::new(flap, "flap")).unwrap();
+        blueprint.add(Block::new(dial, "dial")).unwrap();
+        blueprint.set_property(dial, DIAL_PROPERTY, Some(PropertyValue::Number(-2.0)));
+        let flap = wings(&blueprint).remove(0);
+
+        // An unwired control surface stays level.
+        let mut graph = SignalGraph::compile(&blueprint, &registry);
+        graph.step(None, 0.1);
+        assert_eq!(flap.deflection(Some(&graph)), 0.0);
+
+        blueprint
+            .connect(Wire::new(dial, "out", flap.block, "deflect"))
+            .unwrap();
+        let mut graph = SignalGraph::compile(&blueprint, &registry);
+        graph.step(None, 0.1);
+        let deflection = flap.deflection(Some(&graph));
+        assert_close(deflection, -10_f32.to_radians());
+
+        // Deflecting the flap down in level flight pushes it down like a negative angle.
+        let speed = 20.0;
+        let pressure = 0.5 * AIR_DENSITY * speed * speed * flap.area;
+        let force = flap.force(velocity(&flap, speed, 0.0), deflection);
+        assert_close(force.y, pressure * 6.0 * (2.0 * deflection).sin() / 2.0);
+        assert!(force.y < 0.0);
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::{
+    part_registry::PartRegistry,
+    vehicle::{BLOCK_SIZE, Block, Blueprint, BlueprintError},
+};
+
+/// The edge length of a chunk in blocks.
+///
+/// Boxes never cross chunk borders, so an edit only has to decompose a single chunk again.
+pub const CHUNK_SIZE: i32 = 16;
+
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// An axis aligned box of cells, both corners are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ColliderBox {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl ColliderBox {
+    /// The size in blocks.
+    pub fn size(&self) -> IVec3 {
+        self.max - self.min + IVec3::ONE
+    }
+
+    /// The number of cells inside the box.
+    pub fn volume(&self) -> i32 {
+        self.size().element_product()
+    }
+
+    /// The center in meters in the local space of the blueprint.
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max).as_vec3() * 0.5 * BLOCK_SIZE
+    }
+
+    pub fn to_collider(&self) -> Collider {
+        let size = self.size().as_vec3() * BLOCK_SIZE;
+        Collider::cuboid(size.x, size.y, size.z)
+    }
+}
+
+/// The cells of a vehicle split into chunks, together with their box decomposition.
+///
+/// Adding or removing blocks marks their chunks as dirty and [`Self::rebuild`] only
+/// decomposes those chunks again.
+#[derive(Debug, Clone, Default, Component)]
+pub struct VehicleColliderBuilder {
+    chunks: HashMap<IVec3, ColliderChunk>,
+    dirty: HashSet<IVec3>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ColliderChunk {
+    /// How many parts occupy a cell, parts may overlap.
+    cells: HashMap<IVec3, u16>,
+    boxes: Vec<ColliderBox>,
+}
+
+impl VehicleColliderBuilder {
+    /// Add and decompose every block of `blueprint`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a block uses a part that is not in `registry`.
+    pub fn from_blueprint(
+        blueprint: &Blueprint,
+        registry: &PartRegistry,
+    ) -> Result<Self, BlueprintError> {
+        let mut builder = Self::default();
+        for block in blueprint.iter() {
+            builder.add(block, registry)?;
+        }
+        builder.rebuild();
+
+        Ok(builder)
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn add(&mut self, block: &Block, registry: &PartRegistry) -> Result<(), BlueprintError> {
+        for cell in Self::cells(block, registry)? {
+            let chunk = chunk_of(cell);
+            *self
+                .chunks
+                .entry(chunk)
+                .or_default()
+                .cells
+                .entry(cell)
+                .or_default() += 1;
+            self.dirty.insert(chunk);
+        }
+
+        Ok(())
+    }
+
+    /// Remove a block that was added before.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn remove(&mut self, block: &Block, registry: &PartRegistry) -> Result<(), BlueprintError> {
+        for cell in Self::cells(block, registry)? {
+            let chunk = chunk_of(cell);
+            let Some(cells) = self.chunks.get_mut(&chunk).map(|chunk| &mut chunk.cells) else {
+                continue;
+            };
+
+            if let Some(count) = cells.get_mut(&cell) {
+                *count -= 1;
+                if *count == 0 {
+                    cells.remove(&cell);
+                }
+                self.dirty.insert(chunk);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decompose every dirty chunk. Returns `true` if anything changed.
+    pub fn rebuild(&mut self) -> bool {
+        let changed = !self.dirty.is_empty();
+
+        for chunk_position in std::mem::take(&mut self.dirty) {
+            let Some(chunk) = self.chunks.get_mut(&chunk_position) else {
+                continue;
+            };
+
+            if chunk.cells.is_empty() {
+                self.chunks.remove(&chunk_position);
+            } else {
+                chunk.boxes = decompose(chunk_position * CHUNK_SIZE, &chunk.cells);
+            }
+        }
+
+        changed
+    }
+
+    /// Every box, sorted so that the result does not depend on the edit order.
+    ///
+    /// Chunks that were edited since the last [`Self::rebuild`] may be outdated.
+    pub fn boxes(&self) -> Vec<ColliderBox> {
+        let chunks: BTreeMap<_, _> = self
+            .chunks
+            .iter()
+            .map(|(position, chunk)| (position.to_array(), chunk))
+            .collect();
+
+        chunks
+            .values()
+            .flat_map(|chunk| chunk.boxes.iter().copied())
+            .collect()
+    }
+
+    /// The compound collider of all boxes or [`None`] if there are no blocks.
+    pub fn collider(&self) -> Option<Collider> {
+        let boxes = self.boxes();
+        if boxes.is_empty() {
+            return None;
+        }
+
+        Some(Collider::compound(
+            boxes
+                .iter()
+                .map(|collider_box| {
+                    (
+                        collider_box.center(),
+                        Quat::IDENTITY,
+                        collider_box.to_collider(),
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    fn cells(
+        block: &Block,
+        registry: &PartRegistry,
+    ) -> Result<impl Iterator<Item = IVec3>, BlueprintError> {
+        let part = registry
+            .get(&block.part)
+            .ok_or_else(|| BlueprintError::UnknownPart(block.part.clone()))?;
+        let position = block.position;
+
+        Ok(part
+            .cells(block.rotation)
+            .map(move |offset| position + offset))
+    }
+}
+
+fn chunk_of(cell: IVec3) -> IVec3 {
+    cell.div_euclid(IVec3::splat(CHUNK_SIZE))
+}
+
+/// Greedily merge the cells of one chunk into boxes.
+///
+/// Starting at the lowest free cell, a box is grown along x, then y and then z for as
+/// long as every cell it would cover is filled and not yet part of another box.
+fn decompose(origin: IVec3, cells: &HashMap<IVec3, u16>) -> Vec<ColliderBox> {
+    let index = |local: IVec3| (local.x + CHUNK_SIZE * (local.y + CHUNK_SIZE * local.z)) as usize;
+
+    let mut free = vec![false; CHUNK_VOLUME];
+    for cell in cells.keys() {
+        free[index(*cell - origin)] = true;
+    }
+
+    let mut boxes = Vec::new();
+    for z in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let min = IVec3::new(x, y, z);
+                if !free[index(min)] {
+                    continue;
+                }
+
+                let mut max = min;
+                while max.x + 1 < CHUNK_SIZE && free[index(IVec3::new(max.x + 1, y, z))] {
+                    max.x += 1;
+                }
+
+                let row_is_free =
+                    |y: i32, z: i32| (min.x..=max.x).all(|x| free[index(IVec3::new(x, y, z))]);
+                while max.y + 1 < CHUNK_SIZE && row_is_free(max.y + 1, z) {
+                    max.y += 1;
+                }
+                while max.z + 1 < CHUNK_SIZE && (min.y..=max.y).all(|y| row_is_free(y, max.z + 1)) {
+                    max.z += 1;
+                }
+
+                for z in min.z..=max.z {
+                    for y in min.y..=max.y {
+                        for x in min.x..=max.x {
+                            free[index(IVec3::new(x, y, z))] = false;
+                        }
+                    }
+                }
+
+                boxes.push(ColliderBox {
+                    min: origin + min,
+                    max: origin + max,
+                });
+            }
+        }
+    }
+
+    boxes
+}
+
+#[cfg(test)]
+mod collider_test {
+    use avian3d::prelude::*;
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            Block, Blueprint, VehicleBlocksChanged, VehicleBlueprint, VehiclePlugin,
+            collider::{CHUNK_SIZE, VehicleColliderBuilder},
+        },
+    };
+
+    fn builder(positions: impl IntoIterator<Item = IVec3>) -> VehicleColliderBuilder {
+        let mut blueprint = Blueprint::new("test");
+        for position in positions {
+            blueprint.add(Block::new(position, "cube")).unwrap();
+        }
+
+        VehicleColliderBuilder::from_blueprint(&blueprint, &PartRegistry::test()).unwrap()
+    }
+
+    fn volume(builder: &VehicleColliderBuilder) -> i32 {
+        builder
+            .boxes()
+            .iter()
+            .map(|collider_box| collider_box.volume())
+            .sum()
+    }
+
+    fn cube(size: i32) -> impl Iterator<Item = IVec3> {
+        (0..size * size * size)
+            .map(move |index| IVec3::new(index % size, index / size % size, index / size / size))
+    }
+
+    #[test]
+    fn empty_test() {
+        let builder = builder([]);
+
+        assert!(builder.boxes().is_empty());
+        assert!(builder.collider().is_none());
+    }
+
+    #[test]
+    fn solid_test() {
+        let builder = builder(cube(4));
+
+        assert_eq!(builder.boxes().len(), 1);
+        assert_eq!(volume(&builder), 64);
+        assert!(builder.collider().is_some());
+    }
+
+    #[test]
+    fn shape_test() {
+        // An L shape needs two boxes.
+        let builder = builder([IVec3::ZERO, IVec3::X, IVec3::new(2, 0, 0), IVec3::Y]);
+
+        assert_eq!(builder.boxes().len(), 2);
+        assert_eq!(volume(&builder), 4);
+    }
+
+    #[test]
+    fn chunk_border_test() {
+        // A row crossing a chunk border is split at the border.
+        let builder = builder((-2..2).map(|x| IVec3::new(x, 0, 0)));
+
+        assert_eq!(builder.boxes().len(), 2);
+        assert_eq!(volume(&builder), 4);
+        for collider_box in builder.boxes() {
+            assert_eq!(
+                collider_box.min.div_euclid(IVec3::splat(CHUNK_SIZE)),
+                collider_box.max.div_euclid(IVec3::splat(CHUNK_SIZE))
+            );
+        }
+    }
+
+    #[test]
+    fn multi_cell_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "beam")).unwrap();
+        let builder =
+            VehicleColliderBuilder::from_blueprint(&blueprint, &PartRegistry::test()).unwrap();
+
+        assert_eq!(builder.boxes().len(), 1);
+        assert_eq!(builder.boxes()[0].size(), IVec3::new(1, 3, 1));
+    }
+
+    #[test]
+    fn incremental_test() {
+        let registry = PartRegistry::test();
+        let mut builder = builder(cube(3));
+
+        // Hollow out the center of one face.
+        let removed = Block::new(IVec3::new(1, 1, 0), "cube");
+        builder.remove(&removed, &registry).unwrap();
+        assert!(builder.rebuild());
+        assert_eq!(volume(&builder), 26);
+        assert!(builder.boxes().len() > 1);
+
+        // Nothing is dirty, so nothing changes.
+        assert!(!builder.rebuild());
+
+        builder.add(&removed, &registry).unwrap();
+        builder.rebuild();
+        assert_eq!(builder.boxes(), self::builder(cube(3)).boxes());
+    }
+
+    #[test]
+    fn remove_all_test() {
+        let registry = PartRegistry::test();
+        let mut builder = builder(cube(2));
+
+        for position in cube(2) {
+            builder
+                .remove(&Block::new(position, "cube"), &registry)
+                .unwrap();
+        }
+        builder.rebuild();
+
+        assert!(builder.boxes().is_empty());
+        assert!(builder.collider().is_none());
+    }
+
+    #[test]
+    fn vehicle_collider_test() {
+        let mut app = App::new();
+        app.insert_resource(PartRegistry::test())
+            .add_plugins(VehiclePlugin);
+
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        let vehicle = app.world_mut().spawn(VehicleBlueprint(blueprint)).id();
+        app.update();
+
+        assert!(app.world().get::<Collider>(vehicle).is_some());
+
+        let removed = app
+            .world_mut()
+            .get_mut::<VehicleBlueprint>(vehicle)
+            .unwrap()
+            .remove(IVec3::ZERO)
+            .unwrap();
+        app.world_mut().trigger(VehicleBlocksChanged {
+            entity: vehicle,
+            added: Vec::new(),
+            removed: vec![removed],
+        });
+        app.update();
+
+        assert!(app.world().get::<Collider>(vehicle).is_none());
+        assert!(
+            app.world()
+                .get::<VehicleColliderBuilder>(vehicle)
+                .unwrap()
+                .boxes()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    part_registry::PartRegistry,
+    vehicle::{
+        Block, BlockRotation, Blueprint, BlueprintError, PropertyValue,
+        occupancy::Occupancy,
+        signal::{Wire, validate_wire},
+    },
+};
+
+/// The block property holding the paint color as a hex string like `#ff8800`.
+pub const PAINT_PROPERTY: &str = "paint";
+
+/// A single reversible change to a [`Blueprint`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EditOperation {
+    Place(Block),
+    Remove(IVec3),
+    Rotate {
+        position: IVec3,
+        rotation: BlockRotation,
+    },
+    /// Paint a block or remove its paint with [`None`].
+    Paint {
+        position: IVec3,
+        color: Option<String>,
+    },
+    /// Set a property of a block or remove it with [`None`].
+    SetProperty {
+        position: IVec3,
+        key: String,
+        value: Option<PropertyValue>,
+    },
+    /// Connect an output port to a free input port.
+    Connect(Wire),
+    Disconnect(Wire),
+}
+
+/// Why a batch of operations is applied. The editor history needs to know where the
+/// inverses of an applied batch belong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditKind {
+    /// A new edit, its inverses go onto the undo stack.
+    Do,
+    /// Its inverses go onto the redo stack.
+    Undo,
+    /// Its inverses go back onto the undo stack.
+    Redo,
+}
+
+/// The result of applying an [`EditOperation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedEdit {
+    /// The operations that revert the applied one, in the order they have to be applied.
+    pub inverse: Vec<EditOperation>,
+    /// Blocks that were added, for [`crate::vehicle::VehicleBlocksChanged`].
+    pub added: Vec<Block>,
+    /// Blocks that were removed, for [`crate::vehicle::VehicleBlocksChanged`].
+    pub removed: Vec<Block>,
+}
+
+impl EditOperation {
+    /// The position of the block the operation changes.
+    pub fn position(&self) -> IVec3 {
+        match self {
+            Self::Place(block) => block.position,
+            Self::Remove(position)
+            | Self::Rotate { position, .. }
+            | Self::Paint { position, .. }
+            | Self::SetProperty { position, .. } => *position,
+            Self::Connect(wire) | Self::Disconnect(wire) => wire.to.block,
+        }
+    }
+
+    /// Apply the operation to `blueprint`.
+    ///
+    /// A rotated block is reported as removed and added again, because its mass and shape
+    /// moved. Property and wire changes do not change the shape and report no blocks.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the position is occupied when placing a block
+    /// or empty for any other operation. The blueprint is not changed in that case.
+    pub fn apply(&self, blueprint: &mut Blueprint) -> Result<AppliedEdit, BlueprintError> {
+        match self {
+            Self::Place(block) => {
+                blueprint.add(block.clone())?;
+
+                Ok(AppliedEdit {
+                    inverse: vec![Self::Remove(block.position)],
+                    added: vec![block.clone()],
+                    removed: Vec::new(),
+                })
+            }
+            Self::Remove(position) => {
+                let wires: Vec<_> = blueprint.wires_of(*position).cloned().collect();
+                let block = blueprint
+                    .remove(*position)
+                    .ok_or(BlueprintError::Empty(*position))?;
+
+                // Removing a block removes its wires, placing it again has to restore them.
+                Ok(AppliedEdit {
+                    inverse: std::iter::once(Self::Place(block.clone()))
+                        .chain(wires.into_iter().map(Self::Connect))
+                        .collect(),
+                    added: Vec::new(),
+                    removed: vec![block],
+                })
+            }
+            Self::Rotate { position, rotation } => {
+                let removed = blueprint
+                    .get(*position)
+                    .cloned()
+                    .ok_or(BlueprintError::Empty(*position))?;
+                blueprint.set_rotation(*position, *rotation);
+
+                Ok(AppliedEdit {
+                    inverse: vec![Self::Rotate {
+                        position: *position,
+                        rotation: removed.rotation,
+                    }],
+                    added: vec![removed.clone().with_rotation(*rotation)],
+                    removed: vec![removed],
+                })
+            }
+            Self::Paint { position, color } => {
+                let old = Self::set_property(
+                    blueprint,
+                    *position,
+                    PAINT_PROPERTY,
+                    color.clone().map(PropertyValue::Text),
+                )?;
+                let color = match old {
+                    Some(PropertyValue::Text(color)) => Some(color),
+                    _ => None,
+                };
+
+                Ok(AppliedEdit {
+                    inverse: vec![Self::Paint {
+                        position: *position,
+                        color,
+                    }],
+                    added: Vec::new(),
+                    removed: Vec::new(),
+                })
+            }
+            Self::SetProperty {
+                position,
+                key,
+                value,
+            } => {
+                let old = Self::set_property(blueprint, *position, key, value.clone())?;
+
+                Ok(AppliedEdit {
+                    inverse: vec![Self::SetProperty {
+                        position: *position,
+                        key: key.clone(),
+                        value: old,
+                    }],
+                    added: Vec::new(),
+                    removed: Vec::new(),
+                })
+            }
+            Self::Connect(wire) => {
+                blueprint.connect(wire.clone())?;
+
+                Ok(AppliedEdit {
+                    inverse: vec![Self::Disconnect(wire.clone())],
+                    added: Vec::new(),
+                    removed: Vec::new(),
+                })
+            }
+            Self::Disconnect(wire) => {
+                if !blueprint.disconnect(wire) {
+                    return Err(BlueprintError::NotConnected);
+                }
+
+                Ok(AppliedEdit {
+                    inverse: vec![Self::Connect(wire.clone())],
+                    added: Vec::new(),
+                    removed: Vec::new(),
+                })
+            }
+        }
+    }
+
+    /// Check that the operation keeps the blueprint valid.
+    ///
+    /// Placed parts have to exist and must not overlap other parts and rotated parts must
+    /// still fit. Wires have to connect existing ports of the same type. The remaining
+    /// operations only need an existing block, which [`Self::apply`] checks itself.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error describing why the operation is invalid.
+    pub fn validate(
+        &self,
+        blueprint: &Blueprint,
+        occupancy: &Occupancy,
+        registry: &PartRegistry,
+    ) -> Result<(), BlueprintError> {
+        match self {
+            Self::Place(block) => {
+                if !occupancy.can_place(block, registry)? {
+                    return Err(BlueprintError::Occupied(block.position));
+                }
+            }
+            Self::Rotate { position, rotation } => {
+                let block = blueprint
+                    .get(*position)
+                    .ok_or(BlueprintError::Empty(*position))?;
+
+                if !occupancy.can_replace(&block.clone().with_rotation(*rotation), registry)? {
+                    return Err(BlueprintError::Occupied(*position));
+                }
+            }
+            Self::Connect(wire) => validate_wire(blueprint, registry, wire)?,
+            Self::Remove(_)
+            | Self::Paint { .. }
+            | Self::SetProperty { .. }
+            | Self::Disconnect(_) => {}
+        }
+
+        Ok(())
+    }
+
+    fn set_property(
+        blueprint: &mut Blueprint,
+        position: IVec3,
+        key: &str,
+        value: Option<PropertyValue>,
+    ) -> Result<Option<PropertyValue>, BlueprintError> {
+        if !blueprint.contains(position) {
+            return Err(BlueprintError::Empty(position));
+        }
+
+        Ok(blueprint.set_property(position, key, value))
+    }
+}
+
+/// The result of [`apply_all`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AppliedEdits {
+    /// The operations that were applied, in order.
+    pub applied: Vec<EditOperation>,
+    /// The operations that revert the applied ones, in the order they have to be applied.
+    pub inverse: Vec<EditOperation>,
+    pub added: Vec<Block>,
+    pub removed: Vec<Block>,
+}
+
+impl AppliedEdits {
+    /// Record an applied operation.
+    pub fn push(&mut self, operation: EditOperation, applied: AppliedEdit) {
+        self.applied.push(operation);
+        // The last operation has to be reverted first.
+        self.inverse.splice(0..0, applied.inverse);
+        self.added.extend(applied.added);
+        self.removed.extend(applied.removed);
+    }
+}
+
+/// Apply every operation that can be applied and skip the others.
+///
+/// Operations fail if another player changed the same block in the meantime, skipping
+/// them keeps the rest of a batch intact.
+pub fn apply_all(
+    operations: impl IntoIterator<Item = EditOperation>,
+    blueprint: &mut Blueprint,
+) -> AppliedEdits {
+    let mut edits = AppliedEdits::default();
+    for operation in operations {
+        match operation.apply(blueprint) {
+            Ok(applied) => edits.push(operation, applied),
+            Err(e) => debug!("Skipped edit {:?}: {}", operation, e),
+        }
+    }
+
+    edits
+}
+
+#[cfg(test)]
+mod edit_test {
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            Block, BlockRotation, Blueprint, BlueprintError, Face, PropertyValue,
+            edit::{EditOperation, PAINT_PROPERTY, apply_all},
+            occupancy::Occupancy,
+            signal::Wire,
+        },
+    };
+
+    fn blueprint() -> Blueprint {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint
+            .add(Block::new(IVec3::X, "cube").with_property("power", PropertyValue::Number(1.0)))
+            .unwrap();
+
+        blueprint
+    }
+
+    #[test]
+    fn inverse_test() {
+        let operations = [
+            EditOperation::Place(Block::new(IVec3::Y, "wood_cube")),
+            EditOperation::Remove(IVec3::X),
+            EditOperation::Rotate {
+                position: IVec3::ZERO,
+                rotation: BlockRotation::new(Face::PosZ, 3),
+            },
+            EditOperation::Paint {
+                position: IVec3::ZERO,
+                color: Some("#ff0000".to_string()),
+            },
+            EditOperation::SetProperty {
+                position: IVec3::X,
+                key: "power".to_string(),
+                value: None,
+            },
+            EditOperation::SetProperty {
+                position: IVec3::X,
+                key: "name".to_string(),
+                value: Some(PropertyValue::Text("engine".to_string())),
+            },
+            EditOperation::Connect(Wire::new(IVec3::ZERO, "out", IVec3::X, "a")),
+        ];
+
+        for operation in operations {
+            let original = blueprint();
+            let mut edited = original.clone();
+
+            let applied = operation.apply(&mut edited).unwrap();
+            assert_ne!(edited, original, "{operation:?} changed nothing");
+
+            for inverse in applied.inverse {
+                inverse.apply(&mut edited).unwrap();
+            }
+            assert_eq!(edited, original, "{operation:?} was not reverted");
+        }
+    }
+
+    #[test]
+    fn remove_wired_test() {
+        let mut original = blueprint();
+        original
+            .connect(Wire::new(IVec3::ZERO, "out", IVec3::X, "a"))
+            .unwrap();
+        original
+            .connect(Wire::new(IVec3::X, "out", IVec3::ZERO, "a"))
+            .unwrap();
+        let mut blueprint = original.clone();
+
+        let edits = apply_all([EditOperation::Remove(IVec3::X)], &mut blueprint);
+        assert_eq!(blueprint.wires().count(), 0);
+        assert_eq!(edits.inverse.len(), 3);
+
+        // Undoing the removal restores the wires.
+        apply_all(edits.inverse, &mut blueprint);
+        assert_eq!(blueprint, original);
+    }
+
+    #[test]
+    fn changed_blocks_test() {
+        let mut blueprint = blueprint();
+
+        let applied = EditOperation::Rotate {
+            position: IVec3::ZERO,
+            rotation: BlockRotation::new(Face::NegX, 0),
+        }
+        .apply(&mut blueprint)
+        .unwrap();
+        assert_eq!(applied.removed, vec![Block::new(IVec3::ZERO, "cube")]);
+        assert_eq!(applied.added[0].rotation, BlockRotation::new(Face::NegX, 0));
+
+        let applied = EditOperation::Paint {
+            position: IVec3::ZERO,
+            color: Some("#00ff00".to_string()),
+        }
+        .apply(&mut blueprint)
+        .unwrap();
+        assert!(applied.added.is_empty() && applied.removed.is_empty());
+        assert_eq!(
+            blueprint.get(IVec3::ZERO).unwrap().properties[PAINT_PROPERTY],
+            PropertyValue::Text("#00ff00".to_string())
+        );
+    }
+
+    #[test]
+    fn error_test() {
+        let mut blueprint = blueprint();
+        let original = blueprint.clone();
+
+        assert!(matches!(
+            EditOperation::Place(Block::new(IVec3::ZERO, "cube")).apply(&mut blueprint),
+            Err(BlueprintError::Occupied(_))
+        ));
+        assert!(matches!(
+            EditOperation::Remove(IVec3::Z).apply(&mut blueprint),
+            Err(BlueprintError::Empty(_))
+        ));
+        assert!(matches!(
+            EditOperation::Paint {
+                position: IVec3::Z,
+                color: None
+            }
+            .apply(&mut blueprint),
+            Err(BlueprintError::Empty(_))
+        ));
+        assert!(matches!(
+            EditOperation::Disconnect(Wire::new(IVec3::ZERO, "out", IVec3::X, "a"))
+                .apply(&mut blueprint),
+            Err(BlueprintError::NotConnected)
+        ));
+        assert_eq!(blueprint, original);
+    }
+
+    #[test]
+    fn apply_all_test() {
+        let original = blueprint();
+        let mut blueprint = original.clone();
+
+        let edits = apply_all(
+            [
+                EditOperation::Place(Block::new(IVec3::Y, "cube")),
+                // Conflicts with the first operation and is skipped.
+                EditOperation::Place(Block::new(IVec3::Y, "wood_cube")),
+                EditOperation::Remove(IVec3::ZERO),
+            ],
+            &mut blueprint,
+        );
+        assert_eq!(edits.applied.len(), 2);
+        assert_eq!(edits.added.len(), 1);
+        assert_eq!(edits.removed.len(), 1);
+
+        let reverted = apply_all(edits.inverse, &mut blueprint);
+        assert_eq!(reverted.applied.len(), 2);
+        assert_eq!(blueprint, original);
+    }
+
+    #[test]
+    fn validate_test() {
+        let registry = PartRegistry::builtin();
+        let blueprint = blueprint();
+        let occupancy = Occupancy::from_blueprint(&blueprint, &registry).unwrap();
+        let validate =
+            |operation: EditOperation| operation.validate(&blueprint, &occupancy, &registry);
+
+        assert!(validate(EditOperation::Place(Block::new(IVec3::Y, "cube"))).is_ok());
+        assert!(matches!(
+            validate(EditOperation::Place(Block::new(IVec3::X, "cube"))),
+            Err(BlueprintError::Occupied(_))
+        ));
+        assert!(matches!(
+            validate(EditOperation::Place(Block::new(IVec3::Y, "missing"))),
+            Err(BlueprintError::UnknownPart(_))
+        ));
+        assert!(
+            validate(EditOperation::Rotate {
+                position: IVec3::ZERO,
+                rotation: BlockRotation::new(Face::NegZ, 1),
+            })
+            .is_ok()
+        );
+        assert!(matches!(
+            validate(EditOperation::Rotate {
+                position: IVec3::Z,
+                rotation: BlockRotation::IDENTITY,
+            }),
+            Err(BlueprintError::Empty(_))
+        ));
+        assert!(matches!(
+            validate(EditOperation::Connect(Wire::new(
+                IVec3::ZERO,
+                "out",
+                IVec3::X,
+                "a"
+            ))),
+            Err(BlueprintError::UnknownPort(_, _))
+        ));
+    }
+}
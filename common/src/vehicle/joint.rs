@@ -0,0 +1,808 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    f32::consts::{PI, TAU},
+};
+
+use avian3d::prelude::*;
+use bevy::{ecs::entity::MapEntities, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    part_registry::{PartFunction, PartRegistry},
+    vehicle::{
+        BLOCK_SIZE, Blueprint, BlueprintError, Face,
+        collider::VehicleColliderBuilder,
+        mass::{MassAccumulator, MassProperties},
+        seat::VehicleControls,
+        signal::{SignalGraph, evaluate_signals_system},
+    },
+};
+
+/// Full torque or force is used once the speed of a joint is this far off its target, in
+/// radians or meters per second.
+const DRIVE_RESPONSE: f32 = 0.5;
+/// How fast hinges and pistons close the distance to their target, per second.
+const POSITION_GAIN: f32 = 5.0;
+
+/// Drives the joints of all vehicles and removes the bodies and joints of despawned
+/// vehicles. Only the server adds it, like [`crate::vehicle::signal::SignalPlugin`].
+#[derive(Debug)]
+pub struct VehicleJointPlugin;
+
+impl Plugin for VehicleJointPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            drive_joints_system.after(evaluate_signals_system),
+        )
+        .add_systems(Update, despawn_orphans_system);
+    }
+}
+
+impl PartFunction {
+    /// Returns true if the part splits the vehicle into two bodies.
+    pub fn is_joint(&self) -> bool {
+        matches!(
+            self,
+            Self::Motor { .. } | Self::Wheel { .. } | Self::Hinge { .. } | Self::Piston { .. }
+        )
+    }
+}
+
+/// A joint between two bodies of a vehicle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JointDefinition {
+    /// The position of the joint block.
+    pub block: IVec3,
+    pub function: PartFunction,
+    /// The index of the body the joint is mounted on.
+    pub base: usize,
+    /// The index of the body the joint moves.
+    pub rotor: usize,
+    /// The pivot in the local space of the blueprint in meters.
+    pub anchor: Vec3,
+    /// The hinge or slider axis in the local space of the blueprint.
+    pub axis: Vec3,
+}
+
+impl JointDefinition {
+    /// The input port that sets the target of the joint.
+    pub fn input(&self) -> &'static str {
+        match self.function {
+            PartFunction::Hinge { .. } => "angle",
+            PartFunction::Piston { .. } => "extend",
+            _ => "speed",
+        }
+    }
+
+    /// The drive command, from -1 to 1 or from 0 to 1 for pistons.
+    ///
+    /// A wired input wins. Otherwise the joint follows the occupied seat with the lowest
+    /// position: motors the throttle, hinges the steering and pistons extend while up is
+    /// held. Wheels with a sideways axis turn so that the throttle rolls them forward,
+    /// whichever side of the vehicle they are on.
+    pub fn command(&self, graph: Option<&SignalGraph>, controls: Option<&VehicleControls>) -> f32 {
+        let input = self.input();
+        if let Some(graph) = graph.filter(|graph| graph.is_connected(self.block, input)) {
+            let value = graph
+                .input(self.block, input)
+                .map_or(0.0, |signal| signal.as_number());
+            return match self.function {
+                PartFunction::Piston { .. } => value.clamp(0.0, 1.0),
+                _ => value.clamp(-1.0, 1.0),
+            };
+        }
+
+        let Some(seat) = controls.and_then(|controls| {
+            controls
+                .iter()
+                .min_by_key(|(position, _)| position.to_array())
+                .map(|(_, signals)| *signals)
+        }) else {
+            return 0.0;
+        };
+
+        match self.function {
+            PartFunction::Motor { .. } => seat.throttle,
+            // Rolling forward turns a wheel around the left axis.
+            PartFunction::Wheel { .. } => seat.throttle * self.axis.dot(Vec3::NEG_X).round(),
+            PartFunction::Hinge { .. } => seat.steer,
+            PartFunction::Piston { .. } => seat.up as u8 as f32,
+            _ => 0.0,
+        }
+    }
+
+    /// Insert the avian joint connecting `base` and `rotor`, whose origins are both at the
+    /// origin of the blueprint.
+    pub fn insert_joint(&self, entity: &mut EntityCommands, base: Entity, rotor: Entity) {
+        match self.function {
+            PartFunction::Piston { max_extension, .. } => {
+                entity.insert(
+                    PrismaticJoint::new(base, rotor)
+                        .with_local_anchor1(self.anchor)
+                        .with_local_anchor2(self.anchor)
+                        .with_slider_axis(self.axis)
+                        .with_limits(0.0, max_extension),
+                );
+            }
+            PartFunction::Hinge { max_angle, .. } => {
+                let max_angle = max_angle.to_radians();
+                entity.insert(
+                    RevoluteJoint::new(base, rotor)
+                        .with_local_anchor1(self.anchor)
+                        .with_local_anchor2(self.anchor)
+                        .with_hinge_axis(self.axis)
+                        .with_angle_limits(-max_angle, max_angle),
+                );
+            }
+            _ => {
+                entity.insert(
+                    RevoluteJoint::new(base, rotor)
+                        .with_local_anchor1(self.anchor)
+                        .with_local_anchor2(self.anchor)
+                        .with_hinge_axis(self.axis),
+                );
+            }
+        }
+    }
+}
+
+/// The rigid bodies a vehicle is split into by its joint parts.
+///
+/// Motors, hinges and pistons separate the parts on their top face, wheels separate
+/// themselves from the parts on their bottom face. Body 0 is simulated by the vehicle
+/// entity, every other body by a [`VehicleSubBody`]. Inserted whenever a
+/// [`crate::vehicle::VehicleBlueprint`] is inserted.
+#[derive(Debug, Clone, Default, PartialEq, Component)]
+pub struct VehicleBodies {
+    /// The block positions of every body, sorted.
+    bodies: Vec<Vec<IVec3>>,
+    joints: Vec<JointDefinition>,
+}
+
+impl VehicleBodies {
+    /// Split `blueprint` along its joints.
+    ///
+    /// The body with the most blocks becomes body 0, the others are ordered by their
+    /// lowest block. A joint whose sides are connected anyway is rigid.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a block uses a part that is not in `registry`.
+    pub fn split(blueprint: &Blueprint, registry: &PartRegistry) -> Result<Self, BlueprintError> {
+        let mut blocks: Vec<_> = blueprint.iter().collect();
+        blocks.sort_by_key(|block| block.position.to_array());
+
+        // Later blocks win where parts overlap.
+        let mut owners = HashMap::new();
+        let mut cells = Vec::with_capacity(blocks.len());
+        for (index, block) in blocks.iter().enumerate() {
+            let part = registry
+                .get(&block.part)
+                .ok_or_else(|| BlueprintError::UnknownPart(block.part.clone()))?;
+            let block_cells: Vec<_> = part
+                .cells(block.rotation)
+                .map(|offset| block.position + offset)
+                .collect();
+            for cell in &block_cells {
+                owners.insert(*cell, index);
+            }
+            cells.push(block_cells);
+        }
+
+        // The blocks on the moving face of every joint block.
+        let mut mounts: Vec<(usize, &PartFunction, Vec<usize>)> = Vec::new();
+        let mut cut = HashSet::new();
+        for (index, block) in blocks.iter().enumerate() {
+            let Some(function) = registry
+                .get(&block.part)
+                .and_then(|part| part.function.as_ref())
+                .filter(|function| function.is_joint())
+            else {
+                continue;
+            };
+
+            let up = block.rotation.rotate_face(Face::PosY).normal();
+            let direction = match function {
+                PartFunction::Wheel { .. } => -up,
+                _ => up,
+            };
+            let mut across: Vec<usize> = cells[index]
+                .iter()
+                .filter_map(|cell| owners.get(&(*cell + direction)).copied())
+                .filter(|owner| *owner != index)
+                .collect();
+            across.sort_unstable();
+            across.dedup();
+
+            for other in &across {
+                cut.insert((index.min(*other), index.max(*other)));
+            }
+            mounts.push((index, function, across));
+        }
+
+        let mut union = UnionFind::new(blocks.len());
+        for (index, block_cells) in cells.iter().enumerate() {
+            for cell in block_cells {
+                for face in Face::ALL {
+                    let Some(other) = owners.get(&(*cell + face.normal())).copied() else {
+                        continue;
+                    };
+                    if other != index && !cut.contains(&(index.min(other), index.max(other))) {
+                        union.join(index, other);
+                    }
+                }
+            }
+        }
+        // Everything on the same face of a joint moves together.
+        for (_, _, across) in &mounts {
+            for pair in across.windows(2) {
+                union.join(pair[0], pair[1]);
+            }
+        }
+
+        // Blocks are sorted, so the first block of every group is its lowest.
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut group_of_root = HashMap::new();
+        for index in 0..blocks.len() {
+            let root = union.find(index);
+            let group = *group_of_root.entry(root).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push(index);
+        }
+        if let Some(largest) = (0..groups.len()).max_by_key(|group| {
+            // The first of several equally large groups wins.
+            (groups[*group].len(), std::cmp::Reverse(*group))
+        }) {
+            let group = groups.remove(largest);
+            groups.insert(0, group);
+        }
+
+        let mut body_of = vec![0; blocks.len()];
+        for (body, group) in groups.iter().enumerate() {
+            for index in group {
+                body_of[*index] = body;
+            }
+        }
+
+        let joints = mounts
+            .into_iter()
+            .filter_map(|(index, function, across)| {
+                let other = *across.first()?;
+                let (base, rotor) = match function {
+                    PartFunction::Wheel { .. } => (body_of[other], body_of[index]),
+                    _ => (body_of[index], body_of[other]),
+                };
+                if base == rotor {
+                    return None;
+                }
+
+                let block = blocks[index];
+                let center = cells[index].iter().map(|cell| cell.as_vec3()).sum::<Vec3>()
+                    / cells[index].len() as f32;
+                Some(JointDefinition {
+                    block: block.position,
+                    function: function.clone(),
+                    base,
+                    rotor,
+                    anchor: center * BLOCK_SIZE,
+                    axis: block.rotation.rotate_face(Face::PosY).normal().as_vec3(),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            bodies: groups
+                .into_iter()
+                .map(|group| {
+                    group
+                        .into_iter()
+                        .map(|index| blocks[index].position)
+                        .collect()
+                })
+                .collect(),
+            joints,
+        })
+    }
+
+    /// The number of bodies.
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn joints(&self) -> &[JointDefinition] {
+        &self.joints
+    }
+
+    /// The index of the body the block at `position` belongs to.
+    ///
+    /// Blocks added since the split belong to body 0.
+    pub fn body_of(&self, position: IVec3) -> usize {
+        self.bodies
+            .iter()
+            .enumerate()
+            .skip(1)
+            .find(|(_, body)| {
+                body.binary_search_by_key(&position.to_array(), |block| block.to_array())
+                    .is_ok()
+            })
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// The blocks of body 0, borrowed if there is no other body.
+    pub fn root_blueprint<'a>(&self, blueprint: &'a Blueprint) -> Cow<'a, Blueprint> {
+        if self.len() <= 1 {
+            Cow::Borrowed(blueprint)
+        } else {
+            Cow::Owned(self.body_blueprint(blueprint, 0))
+        }
+    }
+
+    /// A copy of `blueprint` with only the blocks of body `index`.
+    ///
+    /// Body 0 keeps every block that is in no other body, including blocks added since the
+    /// split.
+    pub fn body_blueprint(&self, blueprint: &Blueprint, index: usize) -> Blueprint {
+        if index == 0 {
+            let mut body = blueprint.clone();
+            for position in self.bodies.iter().skip(1).flatten() {
+                body.remove(*position);
+            }
+            return body;
+        }
+
+        let mut body = Blueprint::new(blueprint.name());
+        for position in self.bodies.get(index).into_iter().flatten() {
+            if let Some(block) = blueprint.get(*position) {
+                // The blocks come from a valid blueprint, they can not overlap.
+                let _ = body.add(block.clone());
+            }
+        }
+
+        body
+    }
+}
+
+/// A rigid body of a vehicle other than the vehicle entity itself.
+///
+/// Its origin is the origin of the blueprint, like the one of the vehicle entity.
+#[derive(Debug, Clone, Copy, PartialEq, Component, Serialize, Deserialize)]
+pub struct VehicleSubBody {
+    pub vehicle: Entity,
+    /// The index in [`VehicleBodies`].
+    pub body: usize,
+}
+
+impl MapEntities for VehicleSubBody {
+    fn map_entities<E: EntityMapper>(&mut self, entity_mapper: &mut E) {
+        self.vehicle = entity_mapper.get_mapped(self.vehicle);
+    }
+}
+
+/// Drives the avian joint on the same entity.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct VehicleJoint {
+    pub vehicle: Entity,
+    /// The index in [`VehicleBodies::joints`].
+    pub index: usize,
+    pub base: Entity,
+    pub rotor: Entity,
+}
+
+/// The collider and mass of a body.
+///
+/// Wheels get a cylinder so that they roll, every other part is made of boxes.
+///
+/// # Errors
+///
+/// This function will return an error if a block uses a part that is not in `registry`.
+pub fn body_physics(
+    blueprint: &Blueprint,
+    registry: &PartRegistry,
+) -> Result<(Option<Collider>, Option<MassProperties>), BlueprintError> {
+    let mass = MassAccumulator::from_blueprint(blueprint, registry)?.properties();
+
+    let mut boxes = Blueprint::new(blueprint.name());
+    let mut shapes = Vec::new();
+    for block in blueprint.iter() {
+        let part = registry
+            .get(&block.part)
+            .ok_or_else(|| BlueprintError::UnknownPart(block.part.clone()))?;
+        if !matches!(part.function, Some(PartFunction::Wheel { .. })) {
+            let _ = boxes.add(block.clone());
+            continue;
+        }
+
+        let (min, max) = part
+            .cells(block.rotation)
+            .fold((IVec3::MAX, IVec3::MIN), |(min, max), cell| {
+                (min.min(cell), max.max(cell))
+            });
+        let size = (max - min + IVec3::ONE).as_vec3() * BLOCK_SIZE;
+        let axis = block.rotation.rotate_face(Face::PosY).normal().as_vec3();
+        let center = (block.position.as_vec3() + (min + max).as_vec3() * 0.5) * BLOCK_SIZE;
+        let height = size.dot(axis).abs();
+        let radius = (size * (Vec3::ONE - axis.abs())).max_element() * 0.5;
+
+        shapes.push((
+            center,
+            Quat::from_rotation_arc(Vec3::Y, axis),
+            Collider::cylinder(radius, height),
+        ));
+    }
+
+    let builder = VehicleColliderBuilder::from_blueprint(&boxes, registry)?;
+    shapes.extend(builder.boxes().iter().map(|collider_box| {
+        (
+            collider_box.center(),
+            Quat::IDENTITY,
+            collider_box.to_collider(),
+        )
+    }));
+
+    let collider = (!shapes.is_empty()).then(|| Collider::compound(shapes));
+
+    Ok((collider, mass))
+}
+
+/// The torque or force that accelerates a joint from `speed` towards `target_speed`.
+pub fn drive_effort(target_speed: f32, speed: f32, max_effort: f32) -> f32 {
+    max_effort * ((target_speed - speed) / DRIVE_RESPONSE).clamp(-1.0, 1.0)
+}
+
+/// The angle of `rotation2` relative to `rotation1` around the local `axis`, from -π to π.
+pub fn hinge_angle(rotation1: Quat, rotation2: Quat, axis: Vec3) -> f32 {
+    let relative = rotation1.inverse() * rotation2;
+    let twist = Vec3::new(relative.x, relative.y, relative.z).dot(axis);
+    let angle = 2.0 * twist.atan2(relative.w);
+
+    if angle > PI {
+        angle - TAU
+    } else if angle < -PI {
+        angle + TAU
+    } else {
+        angle
+    }
+}
+
+/// How far the origin of the second body moved along the local `axis` of the first one.
+pub fn piston_extension(position1: Vec3, rotation1: Quat, position2: Vec3, axis: Vec3) -> f32 {
+    (rotation1.inverse() * (position2 - position1)).dot(axis)
+}
+
+fn drive_joints_system(
+    joints: Query<&VehicleJoint>,
+    vehicles: Query<(
+        &VehicleBodies,
+        Option<&SignalGraph>,
+        Option<&VehicleControls>,
+    )>,
+    mut bodies: Query<Forces>,
+) {
+    for joint in joints.iter() {
+        let Ok((vehicle_bodies, graph, controls)) = vehicles.get(joint.vehicle) else {
+            continue;
+        };
+        let Some(definition) = vehicle_bodies.joints.get(joint.index) else {
+            continue;
+        };
+        let Ok([mut base, mut rotor]) = bodies.get_many_mut([joint.base, joint.rotor]) else {
+            continue;
+        };
+
+        let command = definition.command(graph, controls);
+        let axis = base.rotation().0 * definition.axis;
+        match definition.function {
+            PartFunction::Motor {
+                max_speed,
+                max_torque,
+            }
+            | PartFunction::Wheel {
+                max_speed,
+                max_torque,
+            } => {
+                let speed = (rotor.angular_velocity() - base.angular_velocity()).dot(axis);
+                let torque = drive_effort(command * max_speed, speed, max_torque);
+                rotor.apply_torque(axis * torque);
+                base.apply_torque(-axis * torque);
+            }
+            PartFunction::Hinge {
+                max_angle,
+                max_torque,
+            } => {
+                let angle = hinge_angle(base.rotation().0, rotor.rotation().0, definition.axis);
+                let target = command * max_angle.to_radians();
+                let speed = (rotor.angular_velocity() - base.angular_velocity()).dot(axis);
+                let torque = drive_effort((target - angle) * POSITION_GAIN, speed, max_torque);
+                rotor.apply_torque(axis * torque);
+                base.apply_torque(-axis * torque);
+            }
+            PartFunction::Piston {
+                max_extension,
+                max_force,
+            } => {
+                let extension = piston_extension(
+                    base.position().0,
+                    base.rotation().0,
+                    rotor.position().0,
+                    definition.axis,
+                );
+                let target = command * max_extension;
+                let speed = (rotor.linear_velocity() - base.linear_velocity()).dot(axis);
+                let force = drive_effort((target - extension) * POSITION_GAIN, speed, max_force);
+                rotor.apply_force(axis * force);
+                base.apply_force(-axis * force);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Bodies and joints do not keep their vehicle alive.
+fn despawn_orphans_system(
+    mut commands: Commands,
+    sub_bodies: Query<(Entity, &VehicleSubBody)>,
+    joints: Query<(Entity, &VehicleJoint)>,
+    vehicles: Query<(), With<VehicleBodies>>,
+) {
+    let orphans = sub_bodies
+        .iter()
+        .map(|(entity, sub_body)| (entity, sub_body.vehicle))
+        .chain(joints.iter().map(|(entity, joint)| (entity, joint.vehicle)))
+        .filter(|(_, vehicle)| !vehicles.contains(*vehicle));
+    for (entity, _) in orphans {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// A disjoint set forest over block indices.
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, index: usize) -> usize {
+        let mut root = index;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+
+        // Point the whole path at the root.
+        let mut current = index;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+
+        root
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        // The lower index stays the root, which keeps the result independent of the order.
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod joint_test {
+    use std::f32::consts::FRAC_PI_2;
+
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            BLOCK_SIZE, Block, BlockRotation, Blueprint, Face, PropertyValue,
+            joint::{VehicleBodies, body_physics, drive_effort, hinge_angle, piston_extension},
+            seat::{SeatSignals, VehicleControls},
+            signal::{DIAL_PROPERTY, SignalGraph, Wire},
+        },
+    };
+
+    /// Four cubes along x with a motor on the second one carrying two cubes.
+    fn motor_blueprint() -> Blueprint {
+        let mut blueprint = Blueprint::new("test");
+        for x in 0..4 {
+            blueprint
+                .add(Block::new(IVec3::new(x, 0, 0), "cube"))
+                .unwrap();
+        }
+        blueprint
+            .add(Block::new(IVec3::new(1, 1, 0), "motor"))
+            .unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(1, 2, 0), "cube"))
+            .unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(1, 3, 0), "cube"))
+            .unwrap();
+
+        blueprint
+    }
+
+    #[test]
+    fn split_test() {
+        let blueprint = motor_blueprint();
+        let bodies = VehicleBodies::split(&blueprint, &PartRegistry::test()).unwrap();
+
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(
+            bodies.body_blueprint(&blueprint, 1).len(),
+            2,
+            "The rotor holds the cubes on top"
+        );
+        assert!(
+            bodies
+                .body_blueprint(&blueprint, 0)
+                .contains(IVec3::new(1, 1, 0))
+        );
+        assert!(
+            !bodies
+                .body_blueprint(&blueprint, 0)
+                .contains(IVec3::new(1, 2, 0))
+        );
+
+        let joint = &bodies.joints()[0];
+        assert_eq!((joint.base, joint.rotor), (0, 1));
+        assert_eq!(joint.axis, Vec3::Y);
+        assert_eq!(joint.anchor, Vec3::new(1.0, 1.0, 0.0) * BLOCK_SIZE);
+    }
+
+    #[test]
+    fn single_body_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint.add(Block::new(IVec3::X, "cube")).unwrap();
+
+        let bodies = VehicleBodies::split(&blueprint, &PartRegistry::test()).unwrap();
+        assert_eq!(bodies.len(), 1);
+        assert!(bodies.joints().is_empty());
+        assert_eq!(*bodies.root_blueprint(&blueprint), blueprint);
+    }
+
+    #[test]
+    fn rigid_loop_test() {
+        // The cubes on the motor also touch the chassis through another column.
+        let mut blueprint = motor_blueprint();
+        blueprint
+            .add(Block::new(IVec3::new(2, 1, 0), "cube"))
+            .unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(2, 2, 0), "cube"))
+            .unwrap();
+
+        let bodies = VehicleBodies::split(&blueprint, &PartRegistry::test()).unwrap();
+        assert_eq!(bodies.len(), 1);
+        assert!(bodies.joints().is_empty());
+    }
+
+    #[test]
+    fn wheel_test() {
+        let mut blueprint = Blueprint::new("test");
+        for x in 0..3 {
+            blueprint
+                .add(Block::new(IVec3::new(x, 0, 0), "cube"))
+                .unwrap();
+        }
+        // Wheels on both sides, their top faces point away from the chassis.
+        blueprint
+            .add(
+                Block::new(IVec3::new(-1, 0, 0), "wheel")
+                    .with_rotation(BlockRotation::new(Face::NegX, 0)),
+            )
+            .unwrap();
+        blueprint
+            .add(
+                Block::new(IVec3::new(3, 0, 0), "wheel")
+                    .with_rotation(BlockRotation::new(Face::PosX, 0)),
+            )
+            .unwrap();
+
+        let bodies = VehicleBodies::split(&blueprint, &PartRegistry::test()).unwrap();
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies.body_blueprint(&blueprint, 0).len(), 3);
+
+        let mut controls = VehicleControls::default();
+        controls.insert(
+            IVec3::ZERO,
+            SeatSignals {
+                throttle: 1.0,
+                ..default()
+            },
+        );
+        let commands: Vec<_> = bodies
+            .joints()
+            .iter()
+            .map(|joint| {
+                assert_eq!(joint.base, 0);
+                (joint.axis, joint.command(None, Some(&controls)))
+            })
+            .collect();
+
+        // Both wheels turn around the same world axis.
+        assert_eq!(commands, vec![(Vec3::NEG_X, 1.0), (Vec3::X, -1.0)]);
+    }
+
+    #[test]
+    fn body_physics_test() {
+        let registry = PartRegistry::test();
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "wheel")).unwrap();
+        blueprint.add(Block::new(IVec3::Y, "cube")).unwrap();
+
+        let (collider, mass) = body_physics(&blueprint, &registry).unwrap();
+        assert!(collider.is_some());
+        assert_eq!(mass.unwrap().mass, 2.0);
+
+        let (collider, mass) = body_physics(&Blueprint::new("empty"), &registry).unwrap();
+        assert!(collider.is_none() && mass.is_none());
+    }
+
+    #[test]
+    fn command_test() {
+        let registry = PartRegistry::test();
+        let mut blueprint = motor_blueprint();
+        let dial = IVec3::new(3, 1, 0);
+        let motor = IVec3::new(1, 1, 0);
+        blueprint.add(Block::new(dial, "dial")).unwrap();
+        blueprint.set_property(dial, DIAL_PROPERTY, Some(PropertyValue::Number(2.0)));
+
+        let bodies = VehicleBodies::split(&blueprint, &registry).unwrap();
+        let joint = &bodies.joints()[0];
+        let mut controls = VehicleControls::default();
+        controls.insert(
+            IVec3::ZERO,
+            SeatSignals {
+                throttle: -0.5,
+                ..default()
+            },
+        );
+
+        // Without a wire the motor follows the seat.
+        let mut graph = SignalGraph::compile(&blueprint, &registry);
+        graph.step(None, 0.1);
+        assert_eq!(joint.command(Some(&graph), Some(&controls)), -0.5);
+        assert_eq!(joint.command(Some(&graph), None), 0.0);
+
+        // A wired value wins and is clamped.
+        blueprint
+            .connect(Wire::new(dial, "out", motor, "speed"))
+            .unwrap();
+        let mut graph = SignalGraph::compile(&blueprint, &registry);
+        graph.step(None, 0.1);
+        assert_eq!(joint.command(Some(&graph), Some(&controls)), 1.0);
+    }
+
+    #[test]
+    fn drive_test() {
+        assert_eq!(drive_effort(10.0, 0.0, 5.0), 5.0);
+        assert_eq!(drive_effort(-10.0, 0.0, 5.0), -5.0);
+        assert_eq!(drive_effort(1.0, 1.0, 5.0), 0.0);
+        assert!(drive_effort(1.1, 1.0, 5.0) > 0.0 && drive_effort(1.1, 1.0, 5.0) < 5.0);
+
+        let base = Quat::from_rotation_y(1.0);
+        let rotor = base * Quat::from_rotation_x(FRAC_PI_2);
+        assert!((hinge_angle(base, rotor, Vec3::X) - FRAC_PI_2).abs() < 1e-5);
+        assert!((hinge_angle(base, rotor, Vec3::NEG_X) + FRAC_PI_2).abs() < 1e-5);
+        assert!(hinge_angle(base, rotor, Vec3::Y).abs() < 1e-5);
+
+        let extension =
+            piston_extension(Vec3::ONE, base, Vec3::ONE + base * Vec3::Y * 0.3, Vec3::Y);
+        assert!((extension - 0.3).abs() < 1e-5);
+    }
+}
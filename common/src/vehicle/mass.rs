@@ -0,0 +1,413 @@
+use avian3d::prelude::*;
+use bevy::{
+    math::{DMat3, DVec3},
+    prelude::*,
+};
+
+use crate::{
+    part_registry::PartRegistry,
+    vehicle::{BLOCK_SIZE, Block, Blueprint, BlueprintError},
+};
+
+/// The mass properties of a vehicle in the local space of its blueprint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MassProperties {
+    /// The total mass in kilogram.
+    pub mass: f32,
+    pub center_of_mass: Vec3,
+    /// The inertia tensor around the center of mass.
+    pub inertia: Mat3,
+}
+
+impl MassProperties {
+    /// Convert into the avian components that override the mass computed from colliders.
+    pub fn to_components(&self) -> (Mass, CenterOfMass, AngularInertia) {
+        (
+            Mass(self.mass),
+            CenterOfMass(self.center_of_mass),
+            AngularInertia::from_mat3_unchecked(self.inertia),
+        )
+    }
+}
+
+/// Running sums of the mass distribution of a vehicle.
+///
+/// Blocks can be added and removed in constant time. Sums are kept in double precision
+/// so that many edits do not accumulate a noticeable error.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct MassAccumulator {
+    mass: f64,
+    /// Sum of every cells mass times its position.
+    moment: DVec3,
+    /// The inertia tensor around the blueprint origin.
+    inertia: DMat3,
+}
+
+impl Default for MassAccumulator {
+    fn default() -> Self {
+        // The default of a matrix is the identity, not zero.
+        Self {
+            mass: 0.0,
+            moment: DVec3::ZERO,
+            inertia: DMat3::ZERO,
+        }
+    }
+}
+
+impl MassAccumulator {
+    /// Accumulate every block of `blueprint`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a block uses a part that is not in `registry`.
+    pub fn from_blueprint(
+        blueprint: &Blueprint,
+        registry: &PartRegistry,
+    ) -> Result<Self, BlueprintError> {
+        let mut accumulator = Self::default();
+        for block in blueprint.iter() {
+            accumulator.add(block, registry)?;
+        }
+
+        Ok(accumulator)
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn add(&mut self, block: &Block, registry: &PartRegistry) -> Result<(), BlueprintError> {
+        self.accumulate(block, registry, 1.0)
+    }
+
+    /// Remove a block that was added before.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn remove(&mut self, block: &Block, registry: &PartRegistry) -> Result<(), BlueprintError> {
+        self.accumulate(block, registry, -1.0)
+    }
+
+    /// The mass properties or [`None`] if there is no mass.
+    pub fn properties(&self) -> Option<MassProperties> {
+        // Removing every block can leave rounding noise behind.
+        if self.mass <= f64::EPSILON {
+            return None;
+        }
+
+        let center = self.moment / self.mass;
+        // Parallel axis theorem, moved from the origin to the center of mass.
+        let inertia = self.inertia - Self::point_inertia(self.mass, center);
+
+        Some(MassProperties {
+            mass: self.mass as f32,
+            center_of_mass: center.as_vec3(),
+            inertia: inertia.as_mat3(),
+        })
+    }
+
+    /// Every cell of a part is a solid cube carrying an equal share of the parts mass.
+    fn accumulate(
+        &mut self,
+        block: &Block,
+        registry: &PartRegistry,
+        sign: f64,
+    ) -> Result<(), BlueprintError> {
+        let part = registry
+            .get(&block.part)
+            .ok_or_else(|| BlueprintError::UnknownPart(block.part.clone()))?;
+
+        let size = BLOCK_SIZE as f64;
+        let cell_count = part.size.element_product() as f64;
+        let cell_mass = part.mass as f64 / cell_count * sign;
+        // Inertia of a solid cube around its own center.
+        let cell_inertia = DMat3::from_diagonal(DVec3::splat(cell_mass * size * size / 6.0));
+
+        for offset in part.cells(block.rotation) {
+            let position = (block.position + offset).as_dvec3() * size;
+
+            self.mass += cell_mass;
+            self.moment += position * cell_mass;
+            self.inertia += cell_inertia + Self::point_inertia(cell_mass, position);
+        }
+
+        Ok(())
+    }
+
+    /// The inertia tensor of a point mass at `position` around the origin.
+    fn point_inertia(mass: f64, position: DVec3) -> DMat3 {
+        (DMat3::from_diagonal(DVec3::splat(position.length_squared()))
+            - DMat3::from_cols(
+                position * position.x,
+                position * position.y,
+                position * position.z,
+            ))
+            * mass
+    }
+}
+
+/// Compute the mass properties of `blueprint`.
+///
+/// Returns [`None`] if the blueprint is empty.
+///
+/// # Errors
+///
+/// This function will return an error if a block uses a part that is not in `registry`.
+pub fn compute_mass_properties(
+    blueprint: &Blueprint,
+    registry: &PartRegistry,
+) -> Result<Option<MassProperties>, BlueprintError> {
+    MassAccumulator::from_blueprint(blueprint, registry).map(|accumulator| accumulator.properties())
+}
+
+#[cfg(test)]
+mod mass_test {
+    use avian3d::prelude::*;
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            BLOCK_SIZE, Block, BlockRotation, Blueprint, Face, VehicleBlocksChanged,
+            VehicleBlueprint, VehiclePlugin,
+            mass::{MassAccumulator, compute_mass_properties},
+        },
+    };
+
+    const S: f32 = BLOCK_SIZE;
+
+    fn assert_close(actual: f32, expected: f32) {
+        let tolerance = expected.abs().max(1e-12) * 1e-4;
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    fn assert_mat_close(actual: Mat3, expected: Mat3) {
+        let scale = expected
+            .abs()
+            .to_cols_array()
+            .into_iter()
+            .fold(0.0, f32::max);
+        for (actual, expected) in actual
+            .to_cols_array()
+            .into_iter()
+            .zip(expected.to_cols_array())
+        {
+            assert!(
+                (actual - expected).abs() <= scale * 1e-4,
+                "expected {expected}, got {actual}"
+            );
+        }
+    }
+
+    fn blueprint(blocks: impl IntoIterator<Item = Block>) -> Blueprint {
+        let mut blueprint = Blueprint::new("test");
+        for block in blocks {
+            blueprint.add(block).unwrap();
+        }
+
+        blueprint
+    }
+
+    #[test]
+    fn single_block_test() {
+        let properties = compute_mass_properties(
+            &blueprint([Block::new(IVec3::new(2, 0, 0), "heavy")]),
+            &PartRegistry::test(),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_close(properties.mass, 3.0);
+        assert_eq!(properties.center_of_mass, Vec3::new(2.0 * S, 0.0, 0.0));
+        // Solid cube: m * a^2 / 6
+        assert_mat_close(
+            properties.inertia,
+            Mat3::from_diagonal(Vec3::splat(3.0 * S * S / 6.0)),
+        );
+    }
+
+    #[test]
+    fn rod_test() {
+        let n = 4;
+        let properties = compute_mass_properties(
+            &blueprint((0..n).map(|x| Block::new(IVec3::new(x, 0, 0), "cube"))),
+            &PartRegistry::test(),
+        )
+        .unwrap()
+        .unwrap();
+
+        // Solid box with the side lengths L, S, S.
+        let mass = n as f32;
+        let length = n as f32 * S;
+        assert_close(properties.mass, mass);
+        assert_close(properties.center_of_mass.x, 1.5 * S);
+        assert_mat_close(
+            properties.inertia,
+            Mat3::from_diagonal(Vec3::new(
+                mass * (S * S + S * S) / 12.0,
+                mass * (length * length + S * S) / 12.0,
+                mass * (length * length + S * S) / 12.0,
+            )),
+        );
+    }
+
+    #[test]
+    fn multi_cell_part_test() {
+        // A rotated 4x1x1 part must be equal to a rod of four 1x1x1 blocks along Y.
+        let registry = PartRegistry::test();
+        let long = compute_mass_properties(
+            &blueprint([
+                Block::new(IVec3::ZERO, "long").with_rotation(BlockRotation::new(Face::NegX, 0))
+            ]),
+            &registry,
+        )
+        .unwrap()
+        .unwrap();
+        let rod = compute_mass_properties(
+            &blueprint((0..4).map(|y| Block::new(IVec3::new(0, y, 0), "cube"))),
+            &registry,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_close(long.mass, rod.mass);
+        assert_eq!(long.center_of_mass, rod.center_of_mass);
+        assert_mat_close(long.inertia, rod.inertia);
+    }
+
+    #[test]
+    fn two_masses_test() {
+        let distance = 10;
+        let properties = compute_mass_properties(
+            &blueprint([
+                Block::new(IVec3::ZERO, "cube"),
+                Block::new(IVec3::new(distance, 0, 0), "heavy"),
+            ]),
+            &PartRegistry::test(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let d = distance as f32 * S;
+        let reduced_mass = 1.0 * 3.0 / (1.0 + 3.0);
+        let cubes = 4.0 * S * S / 6.0;
+        assert_close(properties.mass, 4.0);
+        assert_close(properties.center_of_mass.x, 3.0 * d / 4.0);
+        assert_mat_close(
+            properties.inertia,
+            Mat3::from_diagonal(Vec3::new(
+                cubes,
+                cubes + reduced_mass * d * d,
+                cubes + reduced_mass * d * d,
+            )),
+        );
+    }
+
+    #[test]
+    fn product_of_inertia_test() {
+        let properties = compute_mass_properties(
+            &blueprint([
+                Block::new(IVec3::ZERO, "cube"),
+                Block::new(IVec3::new(1, 1, 0), "cube"),
+            ]),
+            &PartRegistry::test(),
+        )
+        .unwrap()
+        .unwrap();
+
+        // Both cells are (±S/2, ±S/2, 0) away from the center of mass.
+        assert_close(properties.inertia.x_axis.y, -0.5 * S * S);
+        assert_close(properties.inertia.y_axis.x, -0.5 * S * S);
+        assert_close(properties.inertia.z_axis.z, 2.0 * S * S / 6.0 + S * S);
+    }
+
+    #[test]
+    fn incremental_test() {
+        let registry = PartRegistry::test();
+        let blocks = [
+            Block::new(IVec3::ZERO, "cube"),
+            Block::new(IVec3::new(3, 1, -2), "heavy"),
+            Block::new(IVec3::new(-5, 2, 0), "long"),
+            Block::new(IVec3::new(1, -4, 7), "heavy"),
+        ];
+
+        let mut accumulator = MassAccumulator::default();
+        for block in &blocks {
+            accumulator.add(block, &registry).unwrap();
+        }
+        accumulator.remove(&blocks[1], &registry).unwrap();
+        accumulator.remove(&blocks[3], &registry).unwrap();
+
+        let expected = compute_mass_properties(
+            &blueprint([blocks[0].clone(), blocks[2].clone()]),
+            &registry,
+        )
+        .unwrap()
+        .unwrap();
+        let actual = accumulator.properties().unwrap();
+        assert_close(actual.mass, expected.mass);
+        assert_close(actual.center_of_mass.x, expected.center_of_mass.x);
+        assert_close(actual.center_of_mass.y, expected.center_of_mass.y);
+        assert_mat_close(actual.inertia, expected.inertia);
+
+        accumulator.remove(&blocks[0], &registry).unwrap();
+        accumulator.remove(&blocks[2], &registry).unwrap();
+        assert_eq!(accumulator.properties(), None);
+    }
+
+    #[test]
+    fn unknown_part_test() {
+        assert!(
+            compute_mass_properties(
+                &blueprint([Block::new(IVec3::ZERO, "missing")]),
+                &PartRegistry::test()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn vehicle_components_test() {
+        let mut app = App::new();
+        app.insert_resource(PartRegistry::test())
+            .add_plugins(VehiclePlugin);
+
+        let vehicle = app
+            .world_mut()
+            .spawn(VehicleBlueprint(blueprint([Block::new(
+                IVec3::ZERO,
+                "cube",
+            )])))
+            .id();
+        app.update();
+
+        assert_eq!(app.world().get::<Mass>(vehicle), Some(&Mass(1.0)));
+        assert_eq!(
+            app.world().get::<CenterOfMass>(vehicle),
+            Some(&CenterOfMass(Vec3::ZERO))
+        );
+
+        let added = Block::new(IVec3::new(4, 0, 0), "heavy");
+        app.world_mut()
+            .get_mut::<VehicleBlueprint>(vehicle)
+            .unwrap()
+            .add(added.clone())
+            .unwrap();
+        app.world_mut().trigger(VehicleBlocksChanged {
+            entity: vehicle,
+            added: vec![added],
+            removed: Vec::new(),
+        });
+        app.update();
+
+        assert_eq!(app.world().get::<Mass>(vehicle), Some(&Mass(4.0)));
+        assert_close(
+            app.world().get::<CenterOfMass>(vehicle).unwrap().0.x,
+            3.0 * S,
+        );
+        assert!(app.world().get::<AngularInertia>(vehicle).is_some());
+    }
+}
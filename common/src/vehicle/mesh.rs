@@ -0,0 +1,517 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    mesh::{Indices, PrimitiveTopology},
+    prelude::*,
+};
+
+use crate::{
+    part_registry::PartRegistry,
+    vehicle::{BLOCK_SIZE, BlockRotation, Blueprint, BlueprintError, Face},
+};
+
+/// The edge length of the chunks a vehicle is meshed in, in blocks.
+const MESH_CHUNK_SIZE: i32 = 16;
+
+/// A part that brings its own mesh asset and is not merged into the block mesh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomMeshPart {
+    pub position: IVec3,
+    pub rotation: BlockRotation,
+    /// The path of the mesh asset.
+    pub mesh: String,
+    pub material: String,
+}
+
+/// The merged faces of all blocks sharing a material.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialMesh {
+    /// The id of the [`crate::part_registry::MaterialDefinition`].
+    pub material: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Measured in blocks, so a repeating texture keeps its size on merged faces.
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl MaterialMesh {
+    fn new(material: String) -> Self {
+        Self {
+            material,
+            ..default()
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// The number of merged faces. Every quad consists of two triangles.
+    pub fn quad_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    pub fn to_mesh(&self) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs.clone())
+        .with_inserted_indices(Indices::U32(self.indices.clone()))
+    }
+
+    /// Push a quad with counter clockwise `corners` in blocks.
+    fn push_quad(&mut self, corners: [Vec3; 4], uvs: [[f32; 2]; 4], normal: Vec3) {
+        let start = self.positions.len() as u32;
+
+        self.positions
+            .extend(corners.map(|corner| (corner * BLOCK_SIZE).to_array()));
+        self.normals.extend([normal.to_array(); 4]);
+        self.uvs.extend(uvs);
+        self.indices
+            .extend([start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
+}
+
+/// The render data of a vehicle.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VehicleMesh {
+    /// One mesh per material, sorted by material id.
+    pub groups: Vec<MaterialMesh>,
+    pub custom: Vec<CustomMeshPart>,
+}
+
+impl VehicleMesh {
+    pub fn vertex_count(&self) -> usize {
+        self.groups.iter().map(MaterialMesh::vertex_count).sum()
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.groups.iter().map(MaterialMesh::quad_count).sum()
+    }
+}
+
+/// Build the mesh of `blueprint`.
+///
+/// Faces between two blocks are culled and neighbouring faces with the same material are
+/// merged into as few quads as possible (greedy meshing). The output only depends on the
+/// blocks, not on the order they were added in.
+///
+/// # Errors
+///
+/// This function will return an error if a block uses a part that is not in `registry` or
+/// reaches past the largest grid coordinate.
+pub fn build_vehicle_mesh(
+    blueprint: &Blueprint,
+    registry: &PartRegistry,
+) -> Result<VehicleMesh, BlueprintError> {
+    let mut blocks: Vec<_> = blueprint.iter().collect();
+    // Overlapping parts would otherwise depend on the hash map order.
+    blocks.sort_by_key(|block| block.position.to_array());
+
+    let mut materials: BTreeMap<&str, u16> = BTreeMap::new();
+    let mut cells: Vec<(IVec3, &str)> = Vec::new();
+    let mut custom = Vec::new();
+    for block in blocks {
+        let part = registry
+            .get(&block.part)
+            .ok_or_else(|| BlueprintError::UnknownPart(block.part.clone()))?;
+
+        match &part.mesh {
+            // Custom meshes are not full cubes, so they can not hide faces of their neighbours.
+            Some(mesh) => custom.push(CustomMeshPart {
+                position: block.position,
+                rotation: block.rotation,
+                mesh: mesh.clone(),
+                material: part.material.clone(),
+            }),
+            None => {
+                materials.insert(&part.material, 0);
+                for offset in part.cells(block.rotation) {
+                    let position = block
+                        .position
+                        .checked_add(offset)
+                        .ok_or(BlueprintError::OutOfRange(block.position))?;
+                    cells.push((position, part.material.as_str()));
+                }
+            }
+        }
+    }
+
+    // 0 is an empty cell, so materials start at 1.
+    for (index, material) in materials.values_mut().enumerate() {
+        *material = index as u16 + 1;
+    }
+    let mut groups: Vec<MaterialMesh> = materials
+        .keys()
+        .map(|material| MaterialMesh::new(material.to_string()))
+        .collect();
+
+    let grid = Grid::new(
+        cells
+            .iter()
+            .map(|(position, material)| (*position, materials[material])),
+    );
+    for chunk in grid.chunks.values().copied() {
+        for face in Face::ALL {
+            grid.mesh_face(chunk, face, &mut groups);
+        }
+    }
+
+    Ok(VehicleMesh { groups, custom })
+}
+
+/// The cells of a vehicle, meshed chunk by chunk.
+///
+/// Only the cells are stored, so the memory does not depend on how far apart the blocks
+/// are. Quads never cross chunk borders.
+struct Grid {
+    /// The material index of every cell.
+    cells: HashMap<IVec3, u16>,
+    /// The inclusive bounds of the cells in every chunk.
+    chunks: BTreeMap<[i32; 3], (IVec3, IVec3)>,
+}
+
+impl Grid {
+    fn new(cells: impl Iterator<Item = (IVec3, u16)>) -> Self {
+        let mut grid = Self {
+            cells: HashMap::new(),
+            chunks: BTreeMap::new(),
+        };
+        for (position, material) in cells {
+            // The first part wins if parts overlap.
+            grid.cells.entry(position).or_insert(material);
+            grid.chunks
+                .entry(
+                    position
+                        .div_euclid(IVec3::splat(MESH_CHUNK_SIZE))
+                        .to_array(),
+                )
+                .and_modify(|(min, max)| {
+                    *min = min.min(position);
+                    *max = max.max(position);
+                })
+                .or_insert((position, position));
+        }
+
+        grid
+    }
+
+    /// The material at `position`, 0 if empty.
+    fn get(&self, position: Option<IVec3>) -> u16 {
+        position
+            .and_then(|position| self.cells.get(&position))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Greedy mesh every visible `face` of the chunk bounded by `min` and `max` slice by
+    /// slice.
+    fn mesh_face(&self, (min, max): (IVec3, IVec3), face: Face, groups: &mut [MaterialMesh]) {
+        let d = face.axis();
+        // u x v points into the positive direction of d.
+        let (u, v) = match d {
+            0 => (1, 2),
+            1 => (2, 0),
+            _ => (0, 1),
+        };
+        let normal = face.normal();
+        // Chunks are at most MESH_CHUNK_SIZE wide, so the local coordinates are small.
+        let size = max - min + IVec3::ONE;
+        let (width, height) = (size[u], size[v]);
+        let mut mask = vec![0u16; (width * height) as usize];
+
+        for slice in 0..size[d] {
+            for j in 0..height {
+                for i in 0..width {
+                    let mut local = IVec3::ZERO;
+                    local[d] = slice;
+                    local[u] = i;
+                    local[v] = j;
+
+                    let position = min + local;
+                    let material = self.get(Some(position));
+                    mask[(i + j * width) as usize] =
+                        if material != 0 && self.get(position.checked_add(normal)) == 0 {
+                            material
+                        } else {
+                            0
+                        };
+                }
+            }
+
+            for j in 0..height {
+                let mut i = 0;
+                while i < width {
+                    let material = mask[(i + j * width) as usize];
+                    if material == 0 {
+                        i += 1;
+                        continue;
+                    }
+
+                    let mut quad_width = 1;
+                    while i + quad_width < width
+                        && mask[(i + quad_width + j * width) as usize] == material
+                    {
+                        quad_width += 1;
+                    }
+
+                    let mut quad_height = 1;
+                    'grow: while j + quad_height < height {
+                        for k in i..i + quad_width {
+                            if mask[(k + (j + quad_height) * width) as usize] != material {
+                                break 'grow;
+                            }
+                        }
+                        quad_height += 1;
+                    }
+
+                    for l in j..j + quad_height {
+                        for k in i..i + quad_width {
+                            mask[(k + l * width) as usize] = 0;
+                        }
+                    }
+
+                    let group = &mut groups[material as usize - 1];
+                    push_quad(
+                        group,
+                        min,
+                        face,
+                        (d, u, v),
+                        slice,
+                        IVec2::new(i, j),
+                        IVec2::new(quad_width, quad_height),
+                    );
+
+                    i += quad_width;
+                }
+            }
+        }
+    }
+}
+
+fn push_quad(
+    group: &mut MaterialMesh,
+    min: IVec3,
+    face: Face,
+    (d, u, v): (usize, usize, usize),
+    slice: i32,
+    start: IVec2,
+    size: IVec2,
+) {
+    // Cells are centered on their position, so faces lie half a block away.
+    let plane = (min[d] + slice) as f32 + if face.is_positive() { 0.5 } else { -0.5 };
+    let u0 = (min[u] + start.x) as f32 - 0.5;
+    let v0 = (min[v] + start.y) as f32 - 0.5;
+    let (u1, v1) = (u0 + size.x as f32, v0 + size.y as f32);
+
+    let corner = |u_value: f32, v_value: f32| {
+        let mut corner = Vec3::ZERO;
+        corner[d] = plane;
+        corner[u] = u_value;
+        corner[v] = v_value;
+        corner
+    };
+    let (w, h) = (size.x as f32, size.y as f32);
+
+    let (corners, uvs) = if face.is_positive() {
+        (
+            [
+                corner(u0, v0),
+                corner(u1, v0),
+                corner(u1, v1),
+                corner(u0, v1),
+            ],
+            [[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]],
+        )
+    } else {
+        (
+            [
+                corner(u0, v0),
+                corner(u0, v1),
+                corner(u1, v1),
+                corner(u1, v0),
+            ],
+            [[0.0, 0.0], [0.0, h], [w, h], [w, 0.0]],
+        )
+    };
+
+    group.push_quad(corners, uvs, face.normal().as_vec3());
+}
+
+#[cfg(test)]
+mod mesh_test {
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            BLOCK_SIZE, Block, Blueprint, BlueprintError,
+            mesh::{VehicleMesh, build_vehicle_mesh},
+        },
+    };
+
+    fn mesh(blocks: impl IntoIterator<Item = (IVec3, &'static str)>) -> VehicleMesh {
+        let mut blueprint = Blueprint::new("test");
+        for (position, part) in blocks {
+            blueprint.add(Block::new(position, part)).unwrap();
+        }
+
+        build_vehicle_mesh(&blueprint, &PartRegistry::test()).unwrap()
+    }
+
+    #[test]
+    fn empty_test() {
+        let mesh = mesh([]);
+
+        assert!(mesh.groups.is_empty());
+        assert_eq!(mesh.quad_count(), 0);
+    }
+
+    #[test]
+    fn single_block_test() {
+        let mesh = mesh([(IVec3::ZERO, "cube")]);
+
+        assert_eq!(mesh.groups.len(), 1);
+        assert_eq!(mesh.quad_count(), 6);
+        assert_eq!(mesh.vertex_count(), 24);
+
+        let half = BLOCK_SIZE / 2.0;
+        for position in &mesh.groups[0].positions {
+            assert!(position.iter().all(|value| value.abs() == half));
+        }
+    }
+
+    #[test]
+    fn merge_test() {
+        // A 3x3x3 cube is still only six quads.
+        let cube =
+            mesh((0..27).map(|index| (IVec3::new(index % 3, index / 3 % 3, index / 9), "cube")));
+        assert_eq!(cube.quad_count(), 6);
+        assert_eq!(cube.vertex_count(), 24);
+
+        // An L shape: the Z faces split into two quads, the outer X and Y sides merge.
+        let l = mesh([
+            (IVec3::ZERO, "cube"),
+            (IVec3::X, "cube"),
+            (IVec3::Y, "cube"),
+        ]);
+        assert_eq!(l.quad_count(), 10);
+    }
+
+    #[test]
+    fn material_test() {
+        let mesh = mesh([(IVec3::ZERO, "cube"), (IVec3::X, "cube_b")]);
+
+        // The shared face is culled, but faces of different materials are not merged.
+        assert_eq!(mesh.quad_count(), 10);
+        assert_eq!(mesh.groups.len(), 2);
+        assert_eq!(mesh.groups[0].material, "a");
+        assert_eq!(mesh.groups[0].quad_count(), 5);
+        assert_eq!(mesh.groups[1].material, "b");
+        assert_eq!(mesh.groups[1].quad_count(), 5);
+    }
+
+    #[test]
+    fn hidden_face_test() {
+        // A block enclosed on all sides has no visible faces left.
+        let mut blocks = vec![(IVec3::ZERO, "cube_b")];
+        blocks.extend(
+            [
+                IVec3::X,
+                IVec3::NEG_X,
+                IVec3::Y,
+                IVec3::NEG_Y,
+                IVec3::Z,
+                IVec3::NEG_Z,
+            ]
+            .map(|position| (position, "cube")),
+        );
+        let mesh = mesh(blocks);
+
+        assert_eq!(mesh.groups[1].material, "b");
+        assert_eq!(mesh.groups[1].quad_count(), 0);
+        assert_eq!(mesh.groups[0].quad_count(), 6 * 5);
+    }
+
+    #[test]
+    fn multi_cell_and_custom_test() {
+        let mesh = mesh([(IVec3::ZERO, "plate"), (IVec3::new(0, 1, 0), "hub")]);
+
+        // The plate is a 2x1x2 box, the hub does not hide the top face.
+        assert_eq!(mesh.quad_count(), 6);
+        assert_eq!(mesh.custom.len(), 1);
+        assert_eq!(mesh.custom[0].mesh, "hub.glb");
+    }
+
+    #[test]
+    fn winding_test() {
+        let mesh = mesh([(IVec3::ZERO, "cube")]);
+        let group = &mesh.groups[0];
+
+        // Counter clockwise triangles point in the direction of their normal.
+        for triangle in group.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(group.positions[triangle[i] as usize]));
+            let normal = Vec3::from(group.normals[triangle[0] as usize]);
+
+            assert!((b - a).cross(c - a).dot(normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn deterministic_test() {
+        let positions: Vec<IVec3> = (0..64)
+            .filter(|index| index % 5 != 0)
+            .map(|index| IVec3::new(index % 4, index / 4 % 4, index / 16))
+            .collect();
+
+        let forward = mesh(positions.iter().map(|position| (*position, "cube")));
+        let backward = mesh(positions.iter().rev().map(|position| (*position, "cube")));
+
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn far_apart_test() {
+        // Memory and time only depend on the cells, not on the space between them.
+        let mesh = mesh([
+            (IVec3::splat(-1_000_000_000), "cube"),
+            (IVec3::splat(1_000_000_000), "cube"),
+            (IVec3::splat(i32::MAX), "cube"),
+            (IVec3::splat(i32::MIN), "cube"),
+        ]);
+        assert_eq!(mesh.quad_count(), 4 * 6);
+    }
+
+    #[test]
+    fn chunk_border_test() {
+        // Faces are culled across chunk borders, quads are split at them.
+        let bar = mesh((-8..24).map(|x| (IVec3::new(x, 0, 0), "cube")));
+        assert_eq!(bar.quad_count(), 2 + 4 * 3);
+    }
+
+    #[test]
+    fn out_of_range_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(Block::new(IVec3::splat(i32::MAX), "plate"))
+            .unwrap();
+
+        assert!(matches!(
+            build_vehicle_mesh(&blueprint, &PartRegistry::test()),
+            Err(BlueprintError::OutOfRange(position)) if position == IVec3::splat(i32::MAX)
+        ));
+    }
+
+    #[test]
+    fn to_mesh_test() {
+        let mesh = mesh([(IVec3::ZERO, "cube")]).groups[0].to_mesh();
+
+        assert_eq!(mesh.count_vertices(), 24);
+        assert_eq!(mesh.indices().unwrap().len(), 36);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    part_registry::PartRegistry,
+    vehicle::{Block, Blueprint, BlueprintError, Bounds, raycast::BlockHit},
+};
+
+/// Which block occupies a cell of a blueprint.
+///
+/// A [`Blueprint`] only knows the position of every block, but parts can span several
+/// cells. Editing tools need to know the owner of every cell.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Occupancy {
+    /// Cell to the position of the block occupying it.
+    cells: HashMap<IVec3, IVec3>,
+    bounds: Option<Bounds>,
+}
+
+impl Occupancy {
+    /// # Errors
+    ///
+    /// This function will return an error if a block uses a part that is not in `registry`.
+    pub fn from_blueprint(
+        blueprint: &Blueprint,
+        registry: &PartRegistry,
+    ) -> Result<Self, BlueprintError> {
+        let mut occupancy = Self::default();
+        for block in blueprint.iter() {
+            occupancy.add(block, registry)?;
+        }
+
+        Ok(occupancy)
+    }
+
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn add(&mut self, block: &Block, registry: &PartRegistry) -> Result<(), BlueprintError> {
+        for cell in Self::cells(block, registry)? {
+            self.cells.insert(cell, block.position);
+            match &mut self.bounds {
+                Some(bounds) => bounds.extend(cell),
+                None => self.bounds = Some(Bounds::from_position(cell)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the cells of a block that was added before.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn remove(&mut self, block: &Block, registry: &PartRegistry) -> Result<(), BlueprintError> {
+        let mut on_border = false;
+        for cell in Self::cells(block, registry)? {
+            if self.cells.get(&cell) == Some(&block.position) {
+                self.cells.remove(&cell);
+                on_border |= self.bounds.is_some_and(|bounds| bounds.is_on_border(cell));
+            }
+        }
+
+        if on_border {
+            self.bounds = self.cells.keys().fold(None, |bounds, cell| match bounds {
+                None => Some(Bounds::from_position(*cell)),
+                Some(mut bounds) => {
+                    bounds.extend(*cell);
+                    Some(bounds)
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The position of the block occupying `cell`.
+    pub fn owner(&self, cell: IVec3) -> Option<IVec3> {
+        self.cells.get(&cell).copied()
+    }
+
+    pub fn is_occupied(&self, cell: IVec3) -> bool {
+        self.cells.contains_key(&cell)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The bounds of all occupied cells.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    /// Returns true if every cell `block` would occupy is free.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn can_place(
+        &self,
+        block: &Block,
+        registry: &PartRegistry,
+    ) -> Result<bool, BlueprintError> {
+        Ok(Self::cells(block, registry)?.all(|cell| !self.is_occupied(cell)))
+    }
+
+    /// Returns true if every cell `block` would occupy is free or already owned by the block
+    /// at its position, so it can replace that block.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the block uses a part that is not in `registry`.
+    pub fn can_replace(
+        &self,
+        block: &Block,
+        registry: &PartRegistry,
+    ) -> Result<bool, BlueprintError> {
+        Ok(Self::cells(block, registry)?
+            .all(|cell| self.owner(cell).is_none_or(|owner| owner == block.position)))
+    }
+
+    /// Returns true if `block` can be placed against the face of `hit`.
+    ///
+    /// The cells must be free and both the hit block and the new block must allow
+    /// attachments on the touching faces.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a block uses a part that is not in `registry`.
+    pub fn can_attach(
+        &self,
+        blueprint: &Blueprint,
+        hit: &BlockHit,
+        block: &Block,
+        registry: &PartRegistry,
+    ) -> Result<bool, BlueprintError> {
+        if !self.can_place(block, registry)? {
+            return Ok(false);
+        }
+
+        let Some(target) = self.owner(hit.cell).and_then(|owner| blueprint.get(owner)) else {
+            return Ok(false);
+        };
+        let target_part = registry
+            .get(&target.part)
+            .ok_or_else(|| BlueprintError::UnknownPart(target.part.clone()))?;
+        let part = registry
+            .get(&block.part)
+            .ok_or_else(|| BlueprintError::UnknownPart(block.part.clone()))?;
+
+        let touches =
+            Self::cells(block, registry)?.any(|cell| cell == hit.cell + hit.face.normal());
+        Ok(touches
+            && target_part.can_attach(target.rotation, hit.face)
+            && part.can_attach(block.rotation, hit.face.opposite()))
+    }
+
+    fn cells(
+        block: &Block,
+        registry: &PartRegistry,
+    ) -> Result<impl Iterator<Item = IVec3>, BlueprintError> {
+        let part = registry
+            .get(&block.part)
+            .ok_or_else(|| BlueprintError::UnknownPart(block.part.clone()))?;
+        let position = block.position;
+
+        Ok(part
+            .cells(block.rotation)
+            .map(move |offset| position + offset))
+    }
+}
+
+#[cfg(test)]
+mod occupancy_test {
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{Block, BlockRotation, Blueprint, Face, occupancy::Occupancy, raycast::BlockHit},
+    };
+
+    fn hit(cell: IVec3, face: Face) -> BlockHit {
+        BlockHit {
+            cell,
+            face,
+            distance: 0.0,
+        }
+    }
+
+    #[test]
+    fn multi_cell_test() {
+        let registry = PartRegistry::test();
+        let mut blueprint = Blueprint::new("test");
+        let beam = Block::new(IVec3::ZERO, "beam");
+        blueprint.add(beam.clone()).unwrap();
+        let mut occupancy = Occupancy::from_blueprint(&blueprint, &registry).unwrap();
+
+        assert_eq!(occupancy.owner(IVec3::new(0, 2, 0)), Some(IVec3::ZERO));
+        assert_eq!(occupancy.bounds().unwrap().max, IVec3::new(0, 2, 0));
+        assert!(
+            !occupancy
+                .can_place(&Block::new(IVec3::Y, "cube"), &registry)
+                .unwrap()
+        );
+        // Rotated to the side the beam fits next to the first one.
+        let side = Block::new(IVec3::X, "beam").with_rotation(BlockRotation::new(Face::PosX, 0));
+        assert!(occupancy.can_place(&side, &registry).unwrap());
+        // The beam may turn within its own cells, but nothing else may take them.
+        let turned = beam
+            .clone()
+            .with_rotation(BlockRotation::new(Face::PosX, 0));
+        assert!(occupancy.can_replace(&turned, &registry).unwrap());
+        assert!(
+            !occupancy
+                .can_replace(&Block::new(IVec3::Y, "cube"), &registry)
+                .unwrap()
+        );
+
+        occupancy.remove(&beam, &registry).unwrap();
+        assert!(occupancy.is_empty());
+        assert!(occupancy.bounds().is_none());
+    }
+
+    #[test]
+    fn attach_test() {
+        let registry = PartRegistry::test();
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint.add(Block::new(IVec3::X, "seat")).unwrap();
+        let occupancy = Occupancy::from_blueprint(&blueprint, &registry).unwrap();
+
+        let on_top = hit(IVec3::ZERO, Face::PosY);
+        assert!(
+            occupancy
+                .can_attach(
+                    &blueprint,
+                    &on_top,
+                    &Block::new(IVec3::Y, "cube"),
+                    &registry
+                )
+                .unwrap()
+        );
+        // The seat only attaches with its bottom, so it can sit on top but not on the side.
+        assert!(
+            occupancy
+                .can_attach(
+                    &blueprint,
+                    &on_top,
+                    &Block::new(IVec3::Y, "seat"),
+                    &registry
+                )
+                .unwrap()
+        );
+        let front = hit(IVec3::ZERO, Face::NegZ);
+        assert!(
+            !occupancy
+                .can_attach(
+                    &blueprint,
+                    &front,
+                    &Block::new(IVec3::NEG_Z, "seat"),
+                    &registry
+                )
+                .unwrap()
+        );
+        // Nothing attaches to the top of the seat.
+        let seat_top = hit(IVec3::X, Face::PosY);
+        assert!(
+            !occupancy
+                .can_attach(
+                    &blueprint,
+                    &seat_top,
+                    &Block::new(IVec3::new(1, 1, 0), "cube"),
+                    &registry
+                )
+                .unwrap()
+        );
+        // Occupied cells can not be placed into.
+        let side = hit(IVec3::ZERO, Face::PosX);
+        assert!(
+            !occupancy
+                .can_attach(&blueprint, &side, &Block::new(IVec3::X, "cube"), &registry)
+                .unwrap()
+        );
+    }
+}
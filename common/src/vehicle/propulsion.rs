@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    part_registry::{PartFunction, PartRegistry},
+    vehicle::{
+        BLOCK_SIZE, Blueprint, Face, VehicleBlueprint,
+        joint::{VehicleBodies, VehicleSubBody},
+        seat::VehicleControls,
+        signal::{SignalGraph, evaluate_signals_system},
+    },
+};
+
+/// Applies the thrust of all thrusters and propellers. Only the server adds it, like
+/// [`crate::vehicle::signal::SignalPlugin`].
+#[derive(Debug)]
+pub struct PropulsionPlugin;
+
+impl Plugin for PropulsionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(insert_thrusters_observer)
+            .add_systems(FixedUpdate, thrust_system.after(evaluate_signals_system));
+    }
+}
+
+/// A thruster or propeller of a vehicle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrusterDefinition {
+    /// The position of the thruster block.
+    pub block: IVec3,
+    pub function: PartFunction,
+    /// The index of the body the thruster is mounted on.
+    pub body: usize,
+    /// The point the thrust acts on in the local space of the blueprint in meters.
+    pub point: Vec3,
+    /// The direction of positive thrust in the local space of the blueprint.
+    pub direction: Vec3,
+}
+
+impl ThrusterDefinition {
+    /// The throttle, from 0 to 1 for thrusters and from -1 to 1 for propellers.
+    ///
+    /// A wired `throttle` input wins. Otherwise the thruster pushes the vehicle the way the
+    /// seat with the lowest position wants to go.
+    pub fn command(&self, graph: Option<&SignalGraph>, controls: Option<&VehicleControls>) -> f32 {
+        let command =
+            if let Some(graph) = graph.filter(|graph| graph.is_connected(self.block, "throttle")) {
+                graph
+                    .input(self.block, "throttle")
+                    .map_or(0.0, |signal| signal.as_number())
+            } else {
+                controls
+                    .and_then(|controls| {
+                        controls
+                            .iter()
+                            .min_by_key(|(position, _)| position.to_array())
+                            .map(|(_, signals)| *signals)
+                    })
+                    .map_or(0.0, |seat| {
+                        let up = seat.up as u8 as f32 - seat.down as u8 as f32;
+                        // Forward is -Z in the blueprint.
+                        Vec3::new(0.0, up, -seat.throttle).dot(self.direction)
+                    })
+            };
+
+        match self.function {
+            PartFunction::Thruster { .. } => command.clamp(0.0, 1.0),
+            _ => command.clamp(-1.0, 1.0),
+        }
+    }
+
+    /// The fuel burned per second at full throttle.
+    pub fn fuel_per_second(&self) -> f32 {
+        match self.function {
+            PartFunction::Thruster {
+                fuel_per_second, ..
+            }
+            | PartFunction::Propeller {
+                fuel_per_second, ..
+            } => fuel_per_second,
+            _ => 0.0,
+        }
+    }
+
+    /// The thrust in newton along [`Self::direction`] at `command`, while the thruster moves
+    /// with `axial_speed` meters per second along it.
+    pub fn thrust(&self, command: f32, axial_speed: f32) -> f32 {
+        match self.function {
+            PartFunction::Thruster { max_thrust, .. } => command * max_thrust,
+            PartFunction::Propeller {
+                max_thrust,
+                max_speed,
+                ..
+            } => {
+                let falloff = if max_speed > 0.0 {
+                    (1.0 - axial_speed * command.signum() / max_speed).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                command * max_thrust * falloff
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// The thrusters and fuel tanks of a vehicle. Inserted whenever
+/// [`VehicleBodies`] are inserted.
+#[derive(Debug, Clone, Default, PartialEq, Component)]
+pub struct VehicleThrusters {
+    thrusters: Vec<ThrusterDefinition>,
+    fuel_capacity: f32,
+}
+
+impl VehicleThrusters {
+    /// Collect the thrusters and fuel tanks of `blueprint`. Blocks with a part that is not
+    /// in `registry` are skipped.
+    pub fn collect(blueprint: &Blueprint, registry: &PartRegistry, bodies: &VehicleBodies) -> Self {
+        let mut thrusters = Vec::new();
+        let mut fuel_capacity = 0.0;
+        for block in blueprint.iter() {
+            let Some(part) = registry.get(&block.part) else {
+                continue;
+            };
+            match &part.function {
+                Some(
+                    function @ (PartFunction::Thruster { .. } | PartFunction::Propeller { .. }),
+                ) => {
+                    let cells: Vec<_> = part.cells(block.rotation).collect();
+                    let center = block.position.as_vec3()
+                        + cells.iter().map(|cell| cell.as_vec3()).sum::<Vec3>()
+                            / cells.len() as f32;
+                    thrusters.push(ThrusterDefinition {
+                        block: block.position,
+                        function: function.clone(),
+                        body: bodies.body_of(block.position),
+                        point: center * BLOCK_SIZE,
+                        direction: block.rotation.rotate_face(Face::PosY).normal().as_vec3(),
+                    });
+                }
+                Some(PartFunction::FuelTank { capacity }) => fuel_capacity += capacity,
+                _ => {}
+            }
+        }
+        thrusters.sort_by_key(|thruster| thruster.block.to_array());
+
+        Self {
+            thrusters,
+            fuel_capacity,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ThrusterDefinition> {
+        self.thrusters.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.thrusters.is_empty()
+    }
+
+    /// The sum of the capacity of all fuel tanks.
+    pub fn fuel_capacity(&self) -> f32 {
+        self.fuel_capacity
+    }
+}
+
+/// The fuel left in the tanks of a vehicle. Full when it is first inserted.
+#[derive(Debug, Clone, Copy, PartialEq, Component, Serialize, Deserialize)]
+pub struct VehicleFuel {
+    pub amount: f32,
+    pub capacity: f32,
+}
+
+impl VehicleFuel {
+    pub fn full(capacity: f32) -> Self {
+        Self {
+            amount: capacity,
+            capacity,
+        }
+    }
+
+    /// Burn up to `demand` fuel and return the share of it that was available.
+    pub fn burn(&mut self, demand: f32) -> f32 {
+        if demand <= 0.0 {
+            return 1.0;
+        }
+
+        let burned = demand.min(self.amount);
+        self.amount -= burned;
+        burned / demand
+    }
+}
+
+/// The total force and the torque about `center_of_mass` of forces applied at points.
+pub fn wrench(
+    center_of_mass: Vec3,
+    forces: impl IntoIterator<Item = (Vec3, Vec3)>,
+) -> (Vec3, Vec3) {
+    forces.into_iter().fold(
+        (Vec3::ZERO, Vec3::ZERO),
+        |(total, torque), (point, force)| {
+            (
+                total + force,
+                torque + (point - center_of_mass).cross(force),
+            )
+        },
+    )
+}
+
+fn insert_thrusters_observer(
+    trigger: On<Insert, VehicleBodies>,
+    mut commands: Commands,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok((blueprint, bodies)) = vehicles.get(entity) else {
+        return;
+    };
+
+    let thrusters = VehicleThrusters::collect(blueprint, &registry, bodies);
+    // Splitting the vehicle again keeps the fuel that is left.
+    commands
+        .entity(entity)
+        .insert_if_new(VehicleFuel::full(thrusters.fuel_capacity()))
+        .insert(thrusters);
+}
+
+type ThrustData = (
+    Entity,
+    &'static VehicleThrusters,
+    &'static mut VehicleFuel,
+    Option<&'static SignalGraph>,
+    Option<&'static VehicleControls>,
+);
+
+fn thrust_system(
+    time: Res<Time>,
+    mut vehicles: Query<ThrustData>,
+    sub_bodies: Query<(Entity, &VehicleSubBody)>,
+    mut bodies: Query<(Forces, &ComputedCenterOfMass)>,
+) {
+    let sub_bodies: HashMap<_, _> = sub_bodies
+        .iter()
+        .map(|(entity, sub_body)| ((sub_body.vehicle, sub_body.body), entity))
+        .collect();
+
+    for (vehicle, thrusters, mut fuel, graph, controls) in vehicles.iter_mut() {
+        if thrusters.is_empty() {
+            continue;
+        }
+
+        let commands: Vec<_> = thrusters
+            .iter()
+            .map(|thruster| thruster.command(graph, controls))
+            .collect();
+        let demand = thrusters
+            .iter()
+            .zip(&commands)
+            .map(|(thruster, command)| thruster.fuel_per_second() * command.abs())
+            .sum::<f32>()
+            * time.delta_secs();
+        let supplied = fuel.burn(demand);
+
+        let mut forces: HashMap<usize, Vec<(Vec3, Vec3)>> = HashMap::new();
+        for (thruster, command) in thrusters.iter().zip(commands) {
+            let command = if thruster.fuel_per_second() > 0.0 {
+                command * supplied
+            } else {
+                command
+            };
+            if command == 0.0 {
+                continue;
+            }
+
+            let entity = match thruster.body {
+                0 => vehicle,
+                body => match sub_bodies.get(&(vehicle, body)) {
+                    Some(entity) => *entity,
+                    None => continue,
+                },
+            };
+            let Ok((body, _)) = bodies.get_mut(entity) else {
+                continue;
+            };
+
+            let axial_speed = body
+                .linear_velocity()
+                .dot(body.rotation().0 * thruster.direction);
+            let thrust = thruster.thrust(command, axial_speed);
+            forces
+                .entry(thruster.body)
+                .or_default()
+                .push((thruster.point, thruster.direction * thrust));
+        }
+
+        for (body, forces) in forces {
+            let entity = match body {
+                0 => vehicle,
+                body => sub_bodies[&(vehicle, body)],
+            };
+            let Ok((mut body, center_of_mass)) = bodies.get_mut(entity) else {
+                continue;
+            };
+
+            // Sub-bodies share the origin of the blueprint, so their local space is the
+            // local space of the blueprint.
+            let (force, torque) = wrench(center_of_mass.0, forces);
+            let rotation = body.rotation().0;
+            body.apply_force(rotation * force);
+            body.apply_torque(rotation * torque);
+        }
+    }
+}
+
+#[cfg(test)]
+mod propulsion_test {
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            BLOCK_SIZE, Block, BlockRotation, Blueprint, Face,
+            joint::VehicleBodies,
+            propulsion::{VehicleFuel, VehicleThrusters, wrench},
+            seat::{SeatSignals, VehicleControls},
+        },
+    };
+
+    fn thrusters(blueprint: &Blueprint) -> VehicleThrusters {
+        let registry = PartRegistry::test();
+        let bodies = VehicleBodies::split(blueprint, &registry).unwrap();
+        VehicleThrusters::collect(blueprint, &registry, &bodies)
+    }
+
+    #[test]
+    fn collect_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint
+            .add(Block::new(IVec3::Z, "thruster").with_rotation(BlockRotation::new(Face::NegZ, 0)))
+            .unwrap();
+        blueprint.add(Block::new(IVec3::X, "tank")).unwrap();
+        blueprint.add(Block::new(IVec3::NEG_X, "tank")).unwrap();
+
+        let thrusters = thrusters(&blueprint);
+        assert_eq!(thrusters.fuel_capacity(), 40.0);
+        let thruster = thrusters.iter().next().unwrap();
+        assert_eq!(thruster.body, 0);
+        assert_eq!(thruster.point, Vec3::Z * BLOCK_SIZE);
+        assert_eq!(thruster.direction, Vec3::NEG_Z);
+    }
+
+    #[test]
+    fn command_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(
+                Block::new(IVec3::ZERO, "thruster")
+                    .with_rotation(BlockRotation::new(Face::NegZ, 0)),
+            )
+            .unwrap();
+        blueprint.add(Block::new(IVec3::Y, "propeller")).unwrap();
+        let thrusters = thrusters(&blueprint);
+        let mut thrusters = thrusters.iter();
+        let (forward, up) = (thrusters.next().unwrap(), thrusters.next().unwrap());
+
+        let mut controls = VehicleControls::default();
+        controls.insert(
+            IVec3::ZERO,
+            SeatSignals {
+                throttle: -0.5,
+                down: true,
+                ..default()
+            },
+        );
+
+        // Thrusters can not push backwards, propellers can.
+        assert_eq!(forward.command(None, Some(&controls)), 0.0);
+        assert_eq!(up.command(None, Some(&controls)), -1.0);
+        assert_eq!(up.command(None, None), 0.0);
+
+        controls.insert(
+            IVec3::ZERO,
+            SeatSignals {
+                throttle: 0.5,
+                ..default()
+            },
+        );
+        assert_eq!(forward.command(None, Some(&controls)), 0.5);
+        assert_eq!(up.command(None, Some(&controls)), 0.0);
+    }
+
+    #[test]
+    fn thrust_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "thruster")).unwrap();
+        blueprint.add(Block::new(IVec3::Y, "propeller")).unwrap();
+        let thrusters = thrusters(&blueprint);
+        let mut thrusters = thrusters.iter();
+        let (thruster, propeller) = (thrusters.next().unwrap(), thrusters.next().unwrap());
+
+        assert_eq!(thruster.thrust(0.5, 100.0), 50.0);
+        assert_eq!(propeller.thrust(1.0, 0.0), 50.0);
+        assert_eq!(propeller.thrust(1.0, 5.0), 25.0);
+        assert_eq!(propeller.thrust(1.0, 20.0), 0.0);
+        // Reversing against the motion has full thrust.
+        assert_eq!(propeller.thrust(-1.0, 5.0), -50.0);
+        assert_eq!(propeller.thrust(-1.0, -5.0), -25.0);
+    }
+
+    #[test]
+    fn wrench_test() {
+        let center_of_mass = Vec3::new(0.0, 1.0, 0.0);
+
+        // A force through the center of mass does not turn the body.
+        let (force, torque) = wrench(center_of_mass, [(Vec3::new(0.0, 3.0, 0.0), Vec3::Y)]);
+        assert_eq!(force, Vec3::Y);
+        assert_eq!(torque, Vec3::ZERO);
+
+        // Pushing up at +X turns the body around +Z.
+        let (force, torque) = wrench(
+            center_of_mass,
+            [(Vec3::new(2.0, 1.0, 0.0), Vec3::new(0.0, 10.0, 0.0))],
+        );
+        assert_eq!(force, Vec3::new(0.0, 10.0, 0.0));
+        assert_eq!(torque, Vec3::new(0.0, 0.0, 20.0));
+
+        // Opposite thrusters on both sides cancel.
+        let (force, torque) = wrench(
+            center_of_mass,
+            [
+                (Vec3::new(2.0, 1.0, 0.0), Vec3::Y),
+                (Vec3::new(-2.0, 1.0, 0.0), Vec3::Y),
+            ],
+        );
+        assert_eq!(force, Vec3::Y * 2.0);
+        assert_eq!(torque, Vec3::ZERO);
+
+        // A pair pushing in opposite directions only turns the body.
+        let (force, torque) = wrench(
+            center_of_mass,
+            [
+                (Vec3::new(0.0, 1.0, 1.0), Vec3::X),
+                (Vec3::new(0.0, 1.0, -1.0), Vec3::NEG_X),
+            ],
+        );
+        assert_eq!(force, Vec3::ZERO);
+        assert_eq!(torque, Vec3::new(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn fuel_test() {
+        let mut fuel = VehicleFuel::full(3.0);
+        assert_eq!(fuel.burn(0.0), 1.0);
+        assert_eq!(fuel.burn(2.0), 1.0);
+        assert_eq!(fuel.amount, 1.0);
+        assert_eq!(fuel.burn(4.0), 0.25);
+        assert_eq!(fuel.amount, 0.0);
+        assert_eq!(fuel.burn(1.0), 0.0);
+    }
+}
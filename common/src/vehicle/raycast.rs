@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+
+use crate::vehicle::{BLOCK_SIZE, Bounds, Face};
+
+/// The first solid cell along a ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockHit {
+    pub cell: IVec3,
+    /// The face the ray entered the cell through.
+    pub face: Face,
+    /// The distance from the ray origin in meters.
+    pub distance: f32,
+}
+
+impl BlockHit {
+    /// The free cell in front of the hit face.
+    pub fn adjacent(&self) -> IVec3 {
+        self.cell + self.face.normal()
+    }
+}
+
+/// Walk the block grid along a ray and return the first cell for which `is_solid` is true.
+///
+/// `origin` and `direction` are in the local space of the blueprint in meters. Only cells
+/// inside `bounds` are visited, so the cost does not depend on how far away the origin is.
+pub fn raycast_blocks(
+    origin: Vec3,
+    direction: Dir3,
+    max_distance: f32,
+    bounds: Bounds,
+    is_solid: impl Fn(IVec3) -> bool,
+) -> Option<BlockHit> {
+    // Work in block units with cell edges on integer coordinates.
+    let origin = origin / BLOCK_SIZE + Vec3::splat(0.5);
+    let direction = direction.as_vec3();
+    let max_distance = max_distance / BLOCK_SIZE;
+
+    let (enter, exit, enter_axis) = clip(
+        origin,
+        direction,
+        bounds.min.as_vec3(),
+        (bounds.max + IVec3::ONE).as_vec3(),
+    )?;
+    if enter > max_distance || exit < 0.0 {
+        return None;
+    }
+
+    let start = enter.max(0.0);
+    let step = direction.signum().as_ivec3();
+    let delta = direction.recip().abs();
+
+    // Nudge into the first cell, so starting on a cell edge picks the cell behind it.
+    let mut cell = (origin + direction * (start + 1e-4))
+        .floor()
+        .as_ivec3()
+        .clamp(bounds.min, bounds.max);
+    let mut next = Vec3::ZERO;
+    for axis in 0..3 {
+        next[axis] = if direction[axis] == 0.0 {
+            f32::INFINITY
+        } else {
+            let edge = cell[axis] as f32 + if step[axis] > 0 { 1.0 } else { 0.0 };
+            (edge - origin[axis]) / direction[axis]
+        };
+    }
+
+    // The axis of the face the ray entered the bounds through.
+    let mut axis = if enter > 0.0 {
+        enter_axis
+    } else {
+        direction.abs().max_position()
+    };
+    let mut distance = start;
+
+    while bounds.contains(cell) && distance <= max_distance {
+        if is_solid(cell) {
+            let mut normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+
+            return Some(BlockHit {
+                cell,
+                face: Face::from_normal(normal).unwrap_or(Face::PosY),
+                distance: distance * BLOCK_SIZE,
+            });
+        }
+
+        axis = next.min_position();
+        distance = next[axis];
+        cell[axis] += step[axis];
+        next[axis] += delta[axis];
+    }
+
+    None
+}
+
+/// Slab test of a ray against a box. Returns the entry and exit distance and the axis of
+/// the entered face.
+fn clip(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<(f32, f32, usize)> {
+    let inverse = direction.recip();
+    let a = (min - origin) * inverse;
+    let b = (max - origin) * inverse;
+
+    let near = a.min(b);
+    let enter = near.max_element();
+    let exit = a.max(b).min_element();
+
+    (!enter.is_nan() && enter <= exit).then_some((enter, exit, near.max_position()))
+}
+
+#[cfg(test)]
+mod raycast_test {
+    use bevy::prelude::*;
+
+    use crate::vehicle::{
+        BLOCK_SIZE, Bounds, Face,
+        raycast::{BlockHit, raycast_blocks},
+    };
+
+    fn bounds() -> Bounds {
+        Bounds {
+            min: IVec3::splat(-4),
+            max: IVec3::splat(4),
+        }
+    }
+
+    fn cast(origin: Vec3, direction: Vec3, solid: &[IVec3]) -> Option<BlockHit> {
+        raycast_blocks(
+            origin * BLOCK_SIZE,
+            Dir3::new(direction).unwrap(),
+            100.0,
+            bounds(),
+            |cell| solid.contains(&cell),
+        )
+    }
+
+    #[test]
+    fn axis_test() {
+        let hit = cast(Vec3::new(0.0, 10.0, 0.0), Vec3::NEG_Y, &[IVec3::ZERO]).unwrap();
+
+        assert_eq!(hit.cell, IVec3::ZERO);
+        assert_eq!(hit.face, Face::PosY);
+        assert_eq!(hit.adjacent(), IVec3::Y);
+        assert!((hit.distance - 9.5 * BLOCK_SIZE).abs() < 1e-5);
+
+        let hit = cast(Vec3::new(-10.0, 0.0, 0.0), Vec3::X, &[IVec3::ZERO]).unwrap();
+        assert_eq!(hit.face, Face::NegX);
+    }
+
+    #[test]
+    fn nearest_test() {
+        let solid = [IVec3::new(2, 0, 0), IVec3::new(-2, 0, 0)];
+        let hit = cast(Vec3::ZERO, Vec3::X, &solid).unwrap();
+
+        assert_eq!(hit.cell, IVec3::new(2, 0, 0));
+        assert_eq!(hit.face, Face::NegX);
+    }
+
+    #[test]
+    fn diagonal_test() {
+        let solid = [IVec3::new(2, 2, 0)];
+        let hit = cast(Vec3::new(-0.1, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), &solid).unwrap();
+
+        assert_eq!(hit.cell, IVec3::new(2, 2, 0));
+        assert_eq!(hit.face, Face::NegX);
+    }
+
+    #[test]
+    fn miss_test() {
+        assert!(cast(Vec3::new(0.0, 10.0, 0.0), Vec3::Y, &[IVec3::ZERO]).is_none());
+        assert!(cast(Vec3::new(10.0, 10.0, 0.0), Vec3::NEG_Y, &[IVec3::ZERO]).is_none());
+
+        // Too far away.
+        let hit = raycast_blocks(
+            Vec3::new(0.0, 10.0, 0.0) * BLOCK_SIZE,
+            Dir3::NEG_Y,
+            5.0 * BLOCK_SIZE,
+            bounds(),
+            |cell| cell == IVec3::ZERO,
+        );
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn far_origin_test() {
+        // The ray is clipped to the bounds, so far origins do not walk millions of cells.
+        let hit = cast(
+            Vec3::new(0.0, 0.0, 5000.0),
+            Vec3::NEG_Z,
+            &[IVec3::new(0, 0, -3)],
+        )
+        .unwrap();
+
+        assert_eq!(hit.cell, IVec3::new(0, 0, -3));
+        assert_eq!(hit.face, Face::PosZ);
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use avian3d::prelude::*;
+use bevy::{ecs::entity::MapEntities, prelude::*};
+use lightyear::prelude::input::native::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{CharacterController, CharacterInput, CharacterState, Stance},
+    part_registry::{PartDefinition, PartFunction, PartRegistry},
+    vehicle::{BLOCK_SIZE, Block, Blueprint, VehicleBlueprint},
+};
+
+/// How far away from a seat a character can sit down, in meters.
+pub const SEAT_REACH: f32 = 2.0;
+/// The distance between the seat surface and the center of a seated character.
+pub const SITTING_HEIGHT: f32 = 0.5;
+/// Where a character tries to get out, in the space of the seat. The first free one wins.
+const EXIT_OFFSETS: [Vec3; 5] = [
+    Vec3::new(-1.0, 0.5, 0.0),
+    Vec3::new(1.0, 0.5, 0.0),
+    Vec3::new(0.0, 0.5, 1.0),
+    Vec3::new(0.0, 0.5, -1.0),
+    Vec3::new(0.0, 2.0, 0.0),
+];
+
+#[derive(Debug)]
+pub struct SeatPlugin;
+
+impl Plugin for SeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, seat_input_system)
+            .add_systems(
+                FixedPostUpdate,
+                follow_seat_system.after(PhysicsSystems::Writeback),
+            )
+            .add_observer(sit_down_observer)
+            .add_observer(stand_up_observer);
+    }
+}
+
+/// A character sitting on a seat of a vehicle.
+///
+/// It does not move on its own, it follows the seat and its inputs control the vehicle.
+#[derive(Debug, Clone, PartialEq, Component, Serialize, Deserialize)]
+pub struct Seated {
+    pub vehicle: Entity,
+    /// The position of the seat block in the blueprint.
+    pub seat: IVec3,
+}
+
+impl MapEntities for Seated {
+    fn map_entities<E: EntityMapper>(&mut self, entity_mapper: &mut E) {
+        self.vehicle = entity_mapper.get_mapped(self.vehicle);
+    }
+}
+
+/// The control signals a seat outputs, mapped from the inputs of the seated player.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SeatSignals {
+    /// Forward is positive, from W and S.
+    pub throttle: f32,
+    /// Right is positive, from A and D.
+    pub steer: f32,
+    /// From jump.
+    pub up: bool,
+    /// From crouch.
+    pub down: bool,
+    /// From run.
+    pub boost: bool,
+}
+
+impl SeatSignals {
+    pub fn from_input(input: &CharacterInput) -> Self {
+        Self {
+            throttle: input.movement.y.clamp(-1.0, 1.0),
+            steer: input.movement.x.clamp(-1.0, 1.0),
+            up: input.jump,
+            down: input.crouch,
+            boost: input.run,
+        }
+    }
+}
+
+/// The signals of every occupied seat of a vehicle, by the position of the seat block.
+///
+/// Updated every fixed tick, seats without a player are absent.
+#[derive(Debug, Clone, Default, PartialEq, Component, Deref, DerefMut)]
+pub struct VehicleControls(pub HashMap<IVec3, SeatSignals>);
+
+/// Returns true if `part` is a seat.
+pub fn is_seat(part: &PartDefinition) -> bool {
+    matches!(part.function, Some(PartFunction::Seat))
+}
+
+/// Where a character sits on `block`, in the local space of the blueprint.
+///
+/// The character is centered above the top face of the part and looks along its forward
+/// axis.
+pub fn seat_transform(block: &Block, part: &PartDefinition) -> Transform {
+    let mut cells = part.cells(block.rotation);
+    let first = cells.next().unwrap_or_default();
+    let (min, max) = cells.fold((first, first), |(min, max), cell| {
+        (min.min(cell), max.max(cell))
+    });
+
+    let rotation = block.rotation.to_quat();
+    let up = rotation * Vec3::Y;
+    let center = (block.position.as_vec3() + (min + max).as_vec3() * 0.5) * BLOCK_SIZE;
+    // Cells are centered on their position, the top face is half a cell further out.
+    let half_height = ((max - min).as_vec3().dot(up).abs() * 0.5 + 0.5) * BLOCK_SIZE;
+
+    Transform::from_translation(center + up * (half_height + SITTING_HEIGHT))
+        .with_rotation(rotation)
+}
+
+/// The [`seat_transform`] of the seat at `position`, or [`None`] if there is no seat.
+pub fn seat_at(
+    blueprint: &Blueprint,
+    position: IVec3,
+    registry: &PartRegistry,
+) -> Option<Transform> {
+    let block = blueprint.get(position)?;
+    let part = registry.get(&block.part).filter(|part| is_seat(part))?;
+
+    Some(seat_transform(block, part))
+}
+
+/// Iterate over the seats of a blueprint with their [`seat_transform`].
+pub fn seats<'a>(
+    blueprint: &'a Blueprint,
+    registry: &'a PartRegistry,
+) -> impl Iterator<Item = (IVec3, Transform)> + 'a {
+    blueprint.iter().filter_map(|block| {
+        let part = registry.get(&block.part)?;
+        is_seat(part).then(|| (block.position, seat_transform(block, part)))
+    })
+}
+
+/// The closest seat to `position` that is within [`SEAT_REACH`] and not in `occupied`.
+///
+/// `vehicles` are the vehicle entities with their blueprint and transform.
+pub fn nearest_free_seat<'a>(
+    vehicles: impl IntoIterator<Item = (Entity, &'a Blueprint, &'a Transform)>,
+    registry: &PartRegistry,
+    occupied: &[(Entity, IVec3)],
+    position: Vec3,
+) -> Option<(Entity, IVec3)> {
+    vehicles
+        .into_iter()
+        .flat_map(|(entity, blueprint, transform)| {
+            seats(blueprint, registry).map(move |(seat, local)| {
+                let distance = transform
+                    .transform_point(local.translation)
+                    .distance(position);
+                (entity, seat, distance)
+            })
+        })
+        .filter(|(entity, seat, distance)| {
+            *distance <= SEAT_REACH && !occupied.contains(&(*entity, *seat))
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(entity, seat, _)| (entity, seat))
+}
+
+/// The first place next to the seat at `seat` in world space where `collider` fits.
+///
+/// Returns [`None`] if every exit is blocked, the character has to stay seated then.
+pub fn exit_position(
+    spatial_query: &SpatialQuery,
+    seat: &Transform,
+    collider: &Collider,
+    filter: &SpatialQueryFilter,
+) -> Option<Vec3> {
+    EXIT_OFFSETS.into_iter().find_map(|offset| {
+        let position = seat.transform_point(offset);
+        spatial_query
+            .shape_intersections(collider, position, Quat::IDENTITY, filter)
+            .is_empty()
+            .then_some(position)
+    })
+}
+
+/// Route the inputs of seated characters to their vehicle instead of the character.
+pub fn seat_input_system(
+    characters: Query<(&Seated, &ActionState<CharacterInput>)>,
+    mut vehicles: Query<&mut VehicleControls>,
+) {
+    for mut controls in vehicles.iter_mut() {
+        controls.clear();
+    }
+
+    for (seated, input) in characters.iter() {
+        if let Ok(mut controls) = vehicles.get_mut(seated.vehicle) {
+            controls.insert(seated.seat, SeatSignals::from_input(&input.0));
+        }
+    }
+}
+
+/// Keep seated characters on their seat after the vehicle moved.
+///
+/// Characters whose vehicle or seat is gone stand up where they are.
+fn follow_seat_system(
+    mut commands: Commands,
+    mut characters: Query<(Entity, &Seated, &mut Transform), Without<VehicleBlueprint>>,
+    vehicles: Query<(&VehicleBlueprint, &Transform)>,
+    registry: Res<PartRegistry>,
+) {
+    for (entity, seated, mut transform) in characters.iter_mut() {
+        let seat = vehicles
+            .get(seated.vehicle)
+            .ok()
+            .and_then(|(blueprint, vehicle)| {
+                seat_at(blueprint, seated.seat, &registry).map(|seat| vehicle.mul_transform(seat))
+            });
+
+        match seat {
+            Some(seat) => transform.translation = seat.translation,
+            None => {
+                commands.entity(entity).remove::<Seated>();
+            }
+        }
+    }
+}
+
+/// Seated characters do not collide, so they can not push their own vehicle.
+fn sit_down_observer(
+    trigger: On<Add, Seated>,
+    mut commands: Commands,
+    mut characters: Query<&mut CharacterState>,
+) {
+    let entity = trigger.event().entity;
+    if let Ok(mut state) = characters.get_mut(entity) {
+        state.velocity = Vec3::ZERO;
+        state.grounded = false;
+        state.stance = Stance::Standing;
+    }
+    commands.entity(entity).insert(ColliderDisabled);
+}
+
+fn stand_up_observer(
+    trigger: On<Remove, Seated>,
+    mut commands: Commands,
+    characters: Query<&CharacterController>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(controller) = characters.get(entity) else {
+        return;
+    };
+
+    commands
+        .entity(entity)
+        .try_remove::<ColliderDisabled>()
+        .try_insert(controller.collider(Stance::Standing));
+}
+
+#[cfg(test)]
+mod seat_test {
+    use bevy::prelude::*;
+
+    use crate::{
+        character::CharacterInput,
+        part_registry::PartRegistry,
+        vehicle::{
+            BLOCK_SIZE, Block, BlockRotation, Blueprint, Face,
+            seat::{
+                SEAT_REACH, SITTING_HEIGHT, SeatSignals, nearest_free_seat, seat_transform, seats,
+            },
+        },
+    };
+
+    #[test]
+    fn seats_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(0, 1, 0), "seat"))
+            .unwrap();
+
+        let seats: Vec<_> = seats(&blueprint, &registry).collect();
+        assert_eq!(seats.len(), 1);
+        assert_eq!(seats[0].0, IVec3::new(0, 1, 0));
+    }
+
+    #[test]
+    fn seat_transform_test() {
+        let registry = PartRegistry::builtin();
+        let part = registry.get("seat").unwrap();
+
+        let transform = seat_transform(&Block::new(IVec3::ZERO, "seat"), part);
+        let size = part.size.as_vec3();
+        // Centered above the top face of the cells 0..size.
+        let expected = Vec3::new(
+            (size.x - 1.0) * 0.5 * BLOCK_SIZE,
+            (size.y - 0.5) * BLOCK_SIZE + SITTING_HEIGHT,
+            (size.z - 1.0) * 0.5 * BLOCK_SIZE,
+        );
+        assert!(transform.translation.abs_diff_eq(expected, 1e-5));
+
+        // Upside down, the character sits below the seat.
+        let block =
+            Block::new(IVec3::ZERO, "seat").with_rotation(BlockRotation::new(Face::NegY, 0));
+        let transform = seat_transform(&block, part);
+        assert!(transform.translation.y < -SITTING_HEIGHT);
+        assert!((transform.rotation * Vec3::Y).abs_diff_eq(Vec3::NEG_Y, 1e-5));
+    }
+
+    #[test]
+    fn nearest_free_seat_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "seat")).unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(100, 0, 0), "seat"))
+            .unwrap();
+
+        let vehicle = Entity::from_raw_u32(1).unwrap();
+        let transform = Transform::from_xyz(10.0, 0.0, 0.0);
+        let vehicles = || [(vehicle, &blueprint, &transform)];
+
+        // Closer to the second seat, one meter along x.
+        let position = Vec3::new(11.2, 0.5, 0.2);
+        assert_eq!(
+            nearest_free_seat(vehicles(), &registry, &[], position),
+            Some((vehicle, IVec3::new(100, 0, 0)))
+        );
+        assert_eq!(
+            nearest_free_seat(
+                vehicles(),
+                &registry,
+                &[(vehicle, IVec3::new(100, 0, 0))],
+                position
+            ),
+            Some((vehicle, IVec3::ZERO))
+        );
+        assert_eq!(
+            nearest_free_seat(
+                vehicles(),
+                &registry,
+                &[],
+                Vec3::new(10.0 + SEAT_REACH * 2.0, 0.0, 0.0) + Vec3::X
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn signals_test() {
+        let signals = SeatSignals::from_input(&CharacterInput {
+            movement: Vec2::new(-1.0, 1.0),
+            jump: true,
+            run: true,
+            ..default()
+        });
+
+        assert_eq!(
+            signals,
+            SeatSignals {
+                throttle: 1.0,
+                steer: -1.0,
+                up: true,
+                down: false,
+                boost: true,
+            }
+        );
+    }
+}
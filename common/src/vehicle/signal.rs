@@ -0,0 +1,856 @@
+use std::collections::{BTreeSet, HashMap};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    part_registry::{PartFunction, PartRegistry},
+    vehicle::{
+        BlockProperties, Blueprint, BlueprintError, PropertyValue, VehicleBlueprint,
+        seat::{VehicleControls, seat_input_system},
+    },
+};
+
+/// The bool block property a [`PartFunction::Switch`] outputs.
+pub const SWITCH_PROPERTY: &str = "on";
+/// The number block property a [`PartFunction::Dial`] outputs.
+pub const DIAL_PROPERTY: &str = "value";
+/// The number block property with the delay of a [`PartFunction::Timer`] in seconds.
+pub const DELAY_PROPERTY: &str = "delay";
+/// The delay of a timer without a [`DELAY_PROPERTY`].
+const DEFAULT_DELAY: f32 = 1.0;
+
+/// Evaluates the signal graphs of all vehicles. Only the server adds it, clients see the
+/// results through the vehicles.
+#[derive(Debug)]
+pub struct SignalPlugin;
+
+impl Plugin for SignalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (compile_signal_graph_system, evaluate_signals_system)
+                .chain()
+                .after(seat_input_system),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SignalType {
+    Bool,
+    Number,
+}
+
+/// The value on a wire.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Signal {
+    Bool(bool),
+    Number(f32),
+}
+
+impl Signal {
+    /// The value of an unconnected port.
+    pub fn default_of(signal_type: SignalType) -> Self {
+        match signal_type {
+            SignalType::Bool => Self::Bool(false),
+            SignalType::Number => Self::Number(0.0),
+        }
+    }
+
+    pub fn signal_type(self) -> SignalType {
+        match self {
+            Self::Bool(_) => SignalType::Bool,
+            Self::Number(_) => SignalType::Number,
+        }
+    }
+
+    /// Numbers are on if they are not zero.
+    pub fn as_bool(self) -> bool {
+        match self {
+            Self::Bool(value) => value,
+            Self::Number(value) => value != 0.0,
+        }
+    }
+
+    /// Bools are one if they are on.
+    pub fn as_number(self) -> f32 {
+        match self {
+            Self::Bool(value) => value as u8 as f32,
+            Self::Number(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GateOp {
+    And,
+    Or,
+    Xor,
+    /// Only uses the input `a`.
+    Not,
+    Nand,
+    Nor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    /// Dividing by zero outputs zero.
+    Divide,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CompareOp {
+    Greater,
+    Less,
+    Equal,
+}
+
+/// A named input or output of a part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortDefinition {
+    pub name: &'static str,
+    pub signal_type: SignalType,
+}
+
+const fn port(name: &'static str, signal_type: SignalType) -> PortDefinition {
+    PortDefinition { name, signal_type }
+}
+
+const NO_PORTS: &[PortDefinition] = &[];
+const SEAT_OUTPUTS: &[PortDefinition] = &[
+    port("throttle", SignalType::Number),
+    port("steer", SignalType::Number),
+    port("up", SignalType::Bool),
+    port("down", SignalType::Bool),
+    port("boost", SignalType::Bool),
+];
+const BOOL_OUTPUT: &[PortDefinition] = &[port("out", SignalType::Bool)];
+const NUMBER_OUTPUT: &[PortDefinition] = &[port("out", SignalType::Number)];
+const BOOL_INPUT: &[PortDefinition] = &[port("a", SignalType::Bool)];
+const BOOL_INPUTS: &[PortDefinition] = &[port("a", SignalType::Bool), port("b", SignalType::Bool)];
+const NUMBER_INPUTS: &[PortDefinition] =
+    &[port("a", SignalType::Number), port("b", SignalType::Number)];
+const TIMER_INPUTS: &[PortDefinition] = &[port("in", SignalType::Bool)];
+const SPEED_INPUT: &[PortDefinition] = &[port("speed", SignalType::Number)];
+const ANGLE_INPUT: &[PortDefinition] = &[port("angle", SignalType::Number)];
+const EXTEND_INPUT: &[PortDefinition] = &[port("extend", SignalType::Number)];
+const THROTTLE_INPUT: &[PortDefinition] = &[port("throttle", SignalType::Number)];
+const DEFLECT_INPUT: &[PortDefinition] = &[port("deflect", SignalType::Number)];
+const MEMORY_INPUTS: &[PortDefinition] = &[
+    port("set", SignalType::Bool),
+    port("reset", SignalType::Bool),
+];
+
+impl PartFunction {
+    pub fn inputs(&self) -> &'static [PortDefinition] {
+        match self {
+            Self::Seat | Self::Switch | Self::Dial | Self::FuelTank { .. } | Self::Wing { .. } => {
+                NO_PORTS
+            }
+            Self::Gate { op: GateOp::Not } => BOOL_INPUT,
+            Self::Gate { .. } => BOOL_INPUTS,
+            Self::Math { .. } | Self::Compare { .. } => NUMBER_INPUTS,
+            Self::Timer => TIMER_INPUTS,
+            Self::Memory => MEMORY_INPUTS,
+            Self::Motor { .. } | Self::Wheel { .. } => SPEED_INPUT,
+            Self::Hinge { .. } => ANGLE_INPUT,
+            Self::Piston { .. } => EXTEND_INPUT,
+            Self::Thruster { .. } | Self::Propeller { .. } => THROTTLE_INPUT,
+            Self::ControlSurface { .. } => DEFLECT_INPUT,
+        }
+    }
+
+    pub fn outputs(&self) -> &'static [PortDefinition] {
+        match self {
+            Self::Seat => SEAT_OUTPUTS,
+            Self::Motor { .. }
+            | Self::Wheel { .. }
+            | Self::Hinge { .. }
+            | Self::Piston { .. }
+            | Self::Thruster { .. }
+            | Self::Propeller { .. }
+            | Self::FuelTank { .. }
+            | Self::Wing { .. }
+            | Self::ControlSurface { .. } => NO_PORTS,
+            Self::Dial | Self::Math { .. } => NUMBER_OUTPUT,
+            Self::Switch
+            | Self::Gate { .. }
+            | Self::Compare { .. }
+            | Self::Timer
+            | Self::Memory => BOOL_OUTPUT,
+        }
+    }
+}
+
+/// A port of a block in a [`Blueprint`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PortRef {
+    pub block: IVec3,
+    pub port: String,
+}
+
+/// Connects an output port to an input port. Inputs take at most one wire.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Wire {
+    pub from: PortRef,
+    pub to: PortRef,
+}
+
+impl Wire {
+    pub fn new(
+        from: IVec3,
+        output: impl Into<String>,
+        to: IVec3,
+        input: impl Into<String>,
+    ) -> Self {
+        Self {
+            from: PortRef {
+                block: from,
+                port: output.into(),
+            },
+            to: PortRef {
+                block: to,
+                port: input.into(),
+            },
+        }
+    }
+}
+
+/// The function of the block at `position`, if it has one.
+pub fn block_function<'a>(
+    blueprint: &Blueprint,
+    registry: &'a PartRegistry,
+    position: IVec3,
+) -> Option<&'a PartFunction> {
+    let block = blueprint.get(position)?;
+    registry.get(&block.part)?.function.as_ref()
+}
+
+/// Check that both ports of `wire` exist and carry the same type.
+///
+/// # Errors
+///
+/// This function will return an error if a port does not exist or the types differ.
+pub fn validate_wire(
+    blueprint: &Blueprint,
+    registry: &PartRegistry,
+    wire: &Wire,
+) -> Result<(), BlueprintError> {
+    let find = |port: &PortRef, ports: fn(&PartFunction) -> &'static [PortDefinition]| {
+        block_function(blueprint, registry, port.block)
+            .and_then(|function| {
+                ports(function)
+                    .iter()
+                    .find(|definition| definition.name == port.port)
+            })
+            .ok_or_else(|| BlueprintError::UnknownPort(port.block, port.port.clone()))
+    };
+
+    let output = find(&wire.from, PartFunction::outputs)?;
+    let input = find(&wire.to, PartFunction::inputs)?;
+    if output.signal_type != input.signal_type {
+        return Err(BlueprintError::WireTypeMismatch(
+            output.signal_type,
+            input.signal_type,
+        ));
+    }
+
+    Ok(())
+}
+
+/// The compiled signal network of a vehicle together with the current signals.
+///
+/// Every functional block is a node. Nodes are evaluated in a fixed order where every
+/// node comes after the nodes it reads from, ties are broken by block position. Nodes
+/// in a cycle are evaluated in position order and read the values of the previous tick
+/// where the order can not be kept.
+#[derive(Debug, Clone, Default, Component)]
+pub struct SignalGraph {
+    nodes: Vec<SignalNode>,
+    nodes_by_position: HashMap<IVec3, usize>,
+    order: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct SignalNode {
+    position: IVec3,
+    function: PartFunction,
+    properties: BlockProperties,
+    /// The node and output index every input reads from.
+    sources: Vec<Option<(usize, usize)>>,
+    inputs: Vec<Signal>,
+    outputs: Vec<Signal>,
+    /// Seconds a timer is on or whether a memory is set.
+    state: f32,
+}
+
+impl SignalGraph {
+    /// Build the graph of every functional block and the valid wires of `blueprint`.
+    ///
+    /// Wires with missing or mismatched ports are ignored.
+    pub fn compile(blueprint: &Blueprint, registry: &PartRegistry) -> Self {
+        let mut blocks: Vec<_> = blueprint
+            .iter()
+            .filter_map(|block| {
+                let function = registry.get(&block.part)?.function.clone()?;
+                Some((block, function))
+            })
+            .collect();
+        blocks.sort_by_key(|(block, _)| block.position.to_array());
+
+        let mut nodes: Vec<SignalNode> = blocks
+            .into_iter()
+            .map(|(block, function)| SignalNode {
+                position: block.position,
+                properties: block.properties.clone(),
+                sources: vec![None; function.inputs().len()],
+                inputs: function
+                    .inputs()
+                    .iter()
+                    .map(|port| Signal::default_of(port.signal_type))
+                    .collect(),
+                outputs: function
+                    .outputs()
+                    .iter()
+                    .map(|port| Signal::default_of(port.signal_type))
+                    .collect(),
+                function,
+                state: 0.0,
+            })
+            .collect();
+        let nodes_by_position: HashMap<IVec3, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.position, index))
+            .collect();
+
+        for wire in blueprint.wires() {
+            if validate_wire(blueprint, registry, wire).is_err() {
+                continue;
+            }
+            let (Some(&from), Some(&to)) = (
+                nodes_by_position.get(&wire.from.block),
+                nodes_by_position.get(&wire.to.block),
+            ) else {
+                continue;
+            };
+
+            let output = Self::port_index(nodes[from].function.outputs(), &wire.from.port);
+            let input = Self::port_index(nodes[to].function.inputs(), &wire.to.port);
+            if let (Some(output), Some(input)) = (output, input) {
+                nodes[to].sources[input] = Some((from, output));
+            }
+        }
+
+        let order = Self::evaluation_order(&nodes);
+
+        Self {
+            nodes,
+            nodes_by_position,
+            order,
+        }
+    }
+
+    /// Evaluate every node once.
+    ///
+    /// Seats output the signals of their player in `controls`.
+    pub fn step(&mut self, controls: Option<&VehicleControls>, delta_secs: f32) {
+        for &index in &self.order {
+            let inputs: Vec<Signal> = self.nodes[index]
+                .sources
+                .iter()
+                .zip(&self.nodes[index].inputs)
+                .map(|(source, current)| match source {
+                    Some((node, output)) => self.nodes[*node].outputs[*output],
+                    None => *current,
+                })
+                .collect();
+
+            let node = &mut self.nodes[index];
+            node.inputs = inputs;
+            node.evaluate(controls, delta_secs);
+        }
+    }
+
+    /// The value of an output port, [`None`] if there is no such port.
+    pub fn output(&self, position: IVec3, port: &str) -> Option<Signal> {
+        let node = &self.nodes[*self.nodes_by_position.get(&position)?];
+        let index = Self::port_index(node.function.outputs(), port)?;
+
+        Some(node.outputs[index])
+    }
+
+    /// The value of an input port as of the last [`Self::step`], [`None`] if there is no
+    /// such port.
+    pub fn input(&self, position: IVec3, port: &str) -> Option<Signal> {
+        let node = &self.nodes[*self.nodes_by_position.get(&position)?];
+        let index = Self::port_index(node.function.inputs(), port)?;
+
+        Some(node.inputs[index])
+    }
+
+    /// Returns true if a valid wire leads into the input port.
+    pub fn is_connected(&self, position: IVec3, port: &str) -> bool {
+        self.nodes_by_position
+            .get(&position)
+            .map(|index| &self.nodes[*index])
+            .and_then(|node| {
+                let index = Self::port_index(node.function.inputs(), port)?;
+                node.sources[index]
+            })
+            .is_some()
+    }
+
+    fn port_index(ports: &[PortDefinition], name: &str) -> Option<usize> {
+        ports.iter().position(|port| port.name == name)
+    }
+
+    /// Kahn's algorithm that takes the lowest ready node first. If no node is ready the
+    /// rest contains a cycle, which is broken at its lowest node.
+    fn evaluation_order(nodes: &[SignalNode]) -> Vec<usize> {
+        let mut dependents = vec![Vec::new(); nodes.len()];
+        let mut pending = vec![0usize; nodes.len()];
+        for (index, node) in nodes.iter().enumerate() {
+            for (source, _) in node.sources.iter().flatten() {
+                dependents[*source].push(index);
+                pending[index] += 1;
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = (0..nodes.len())
+            .filter(|index| pending[*index] == 0)
+            .collect();
+        let mut done = vec![false; nodes.len()];
+        let mut order = Vec::with_capacity(nodes.len());
+        while order.len() < nodes.len() {
+            let next = match ready.pop_first() {
+                Some(next) => next,
+                None => (0..nodes.len())
+                    .find(|index| !done[*index])
+                    .expect("Not every node is done yet"),
+            };
+            if done[next] {
+                continue;
+            }
+
+            done[next] = true;
+            order.push(next);
+            for &dependent in &dependents[next] {
+                pending[dependent] -= 1;
+                if pending[dependent] == 0 && !done[dependent] {
+                    ready.insert(dependent);
+                }
+            }
+        }
+
+        order
+    }
+}
+
+impl SignalNode {
+    fn evaluate(&mut self, controls: Option<&VehicleControls>, delta_secs: f32) {
+        let bool_input = |index: usize| self.inputs.get(index).is_some_and(|s| s.as_bool());
+        let number_input = |index: usize| self.inputs.get(index).map_or(0.0, |s| s.as_number());
+
+        match &self.function {
+            PartFunction::Seat => {
+                let signals = controls
+                    .and_then(|controls| controls.get(&self.position))
+                    .copied()
+                    .unwrap_or_default();
+                self.outputs = vec![
+                    Signal::Number(signals.throttle),
+                    Signal::Number(signals.steer),
+                    Signal::Bool(signals.up),
+                    Signal::Bool(signals.down),
+                    Signal::Bool(signals.boost),
+                ];
+            }
+            PartFunction::Switch => {
+                let on = matches!(
+                    self.properties.get(SWITCH_PROPERTY),
+                    Some(PropertyValue::Bool(true))
+                );
+                self.outputs = vec![Signal::Bool(on)];
+            }
+            PartFunction::Dial => {
+                let value = match self.properties.get(DIAL_PROPERTY) {
+                    Some(PropertyValue::Number(value)) => *value as f32,
+                    _ => 0.0,
+                };
+                self.outputs = vec![Signal::Number(value)];
+            }
+            PartFunction::Gate { op } => {
+                let (a, b) = (bool_input(0), bool_input(1));
+                let out = match op {
+                    GateOp::And => a && b,
+                    GateOp::Or => a || b,
+                    GateOp::Xor => a != b,
+                    GateOp::Not => !a,
+                    GateOp::Nand => !(a && b),
+                    GateOp::Nor => !(a || b),
+                };
+                self.outputs = vec![Signal::Bool(out)];
+            }
+            PartFunction::Math { op } => {
+                let (a, b) = (number_input(0), number_input(1));
+                let out = match op {
+                    MathOp::Add => a + b,
+                    MathOp::Subtract => a - b,
+                    MathOp::Multiply => a * b,
+                    MathOp::Divide if b == 0.0 => 0.0,
+                    MathOp::Divide => a / b,
+                    MathOp::Min => a.min(b),
+                    MathOp::Max => a.max(b),
+                };
+                // Keep infinities and NaN from spreading through the network.
+                let out = if out.is_finite() { out } else { 0.0 };
+                self.outputs = vec![Signal::Number(out)];
+            }
+            PartFunction::Compare { op } => {
+                let (a, b) = (number_input(0), number_input(1));
+                let out = match op {
+                    CompareOp::Greater => a > b,
+                    CompareOp::Less => a < b,
+                    CompareOp::Equal => a == b,
+                };
+                self.outputs = vec![Signal::Bool(out)];
+            }
+            PartFunction::Timer => {
+                let delay = match self.properties.get(DELAY_PROPERTY) {
+                    Some(PropertyValue::Number(delay)) => *delay as f32,
+                    _ => DEFAULT_DELAY,
+                };
+                self.state = if bool_input(0) {
+                    self.state + delta_secs
+                } else {
+                    0.0
+                };
+                self.outputs = vec![Signal::Bool(bool_input(0) && self.state >= delay)];
+            }
+            PartFunction::Memory => {
+                if bool_input(1) {
+                    self.state = 0.0;
+                } else if bool_input(0) {
+                    self.state = 1.0;
+                }
+                self.outputs = vec![Signal::Bool(self.state != 0.0)];
+            }
+            // Actuators only read their inputs, see `crate::vehicle::joint`,
+            // `crate::vehicle::propulsion` and `crate::vehicle::aerodynamics`.
+            PartFunction::Motor { .. }
+            | PartFunction::Wheel { .. }
+            | PartFunction::Hinge { .. }
+            | PartFunction::Piston { .. }
+            | PartFunction::Thruster { .. }
+            | PartFunction::Propeller { .. }
+            | PartFunction::FuelTank { .. }
+            | PartFunction::Wing { .. }
+            | PartFunction::ControlSurface { .. } => {}
+        }
+    }
+}
+
+/// Rebuild the graph of vehicles whose blueprint changed. Signals start over.
+fn compile_signal_graph_system(
+    mut commands: Commands,
+    vehicles: Query<(Entity, &VehicleBlueprint), Changed<VehicleBlueprint>>,
+    registry: Res<PartRegistry>,
+) {
+    for (entity, blueprint) in vehicles.iter() {
+        commands
+            .entity(entity)
+            .insert(SignalGraph::compile(blueprint, &registry));
+    }
+}
+
+pub fn evaluate_signals_system(
+    time: Res<Time>,
+    mut vehicles: Query<(&mut SignalGraph, Option<&VehicleControls>)>,
+) {
+    for (mut graph, controls) in vehicles.iter_mut() {
+        graph.step(controls, time.delta_secs());
+    }
+}
+
+#[cfg(test)]
+mod signal_test {
+    use bevy::prelude::*;
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{
+            Block, Blueprint, BlueprintError, PropertyValue,
+            seat::{SeatSignals, VehicleControls},
+            signal::{
+                DELAY_PROPERTY, DIAL_PROPERTY, SWITCH_PROPERTY, Signal, SignalGraph, Wire,
+                validate_wire,
+            },
+        },
+    };
+
+    fn add(blueprint: &mut Blueprint, x: i32, part: &str) -> IVec3 {
+        let position = IVec3::new(x, 0, 0);
+        blueprint.add(Block::new(position, part)).unwrap();
+
+        position
+    }
+
+    fn switch(blueprint: &mut Blueprint, x: i32, on: bool) -> IVec3 {
+        let position = add(blueprint, x, "switch");
+        blueprint.set_property(position, SWITCH_PROPERTY, Some(PropertyValue::Bool(on)));
+
+        position
+    }
+
+    fn dial(blueprint: &mut Blueprint, x: i32, value: f64) -> IVec3 {
+        let position = add(blueprint, x, "dial");
+        blueprint.set_property(position, DIAL_PROPERTY, Some(PropertyValue::Number(value)));
+
+        position
+    }
+
+    fn connect(blueprint: &mut Blueprint, from: IVec3, to: IVec3, input: &str) {
+        blueprint
+            .connect(Wire::new(from, "out", to, input))
+            .unwrap();
+    }
+
+    fn compile(blueprint: &Blueprint) -> SignalGraph {
+        SignalGraph::compile(blueprint, &PartRegistry::builtin())
+    }
+
+    #[test]
+    fn order_test() {
+        // Every node sits before the node it reads from, a naive evaluation in position
+        // order would need three ticks.
+        let mut blueprint = Blueprint::new("test");
+        let third = add(&mut blueprint, 0, "not_gate");
+        let second = add(&mut blueprint, 1, "not_gate");
+        let first = add(&mut blueprint, 2, "not_gate");
+        let input = switch(&mut blueprint, 3, true);
+        connect(&mut blueprint, input, first, "a");
+        connect(&mut blueprint, first, second, "a");
+        connect(&mut blueprint, second, third, "a");
+
+        let mut graph = compile(&blueprint);
+        graph.step(None, 0.1);
+
+        assert_eq!(graph.output(first, "out"), Some(Signal::Bool(false)));
+        assert_eq!(graph.output(second, "out"), Some(Signal::Bool(true)));
+        assert_eq!(graph.output(third, "out"), Some(Signal::Bool(false)));
+        assert_eq!(graph.input(third, "a"), Some(Signal::Bool(true)));
+        assert!(graph.is_connected(third, "a"));
+        assert!(!graph.is_connected(input, "a"));
+    }
+
+    #[test]
+    fn cycle_test() {
+        // A NOT gate feeding itself reads its output of the last tick and blinks.
+        let mut blueprint = Blueprint::new("test");
+        let gate = add(&mut blueprint, 0, "not_gate");
+        let follower = add(&mut blueprint, 1, "or_gate");
+        connect(&mut blueprint, gate, gate, "a");
+        connect(&mut blueprint, gate, follower, "a");
+
+        let mut graph = compile(&blueprint);
+        let mut outputs = Vec::new();
+        for _ in 0..4 {
+            graph.step(None, 0.1);
+            outputs.push(graph.output(gate, "out").unwrap().as_bool());
+            // The follower still sees the value of this tick.
+            assert_eq!(graph.output(follower, "out"), graph.output(gate, "out"));
+        }
+
+        assert_eq!(outputs, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn gate_latch_test() {
+        // Two cross coupled NOR gates form a latch.
+        let mut blueprint = Blueprint::new("test");
+        let set = add(&mut blueprint, 0, "switch");
+        let reset = add(&mut blueprint, 1, "switch");
+        let q = add(&mut blueprint, 2, "nor_gate");
+        let not_q = add(&mut blueprint, 3, "nor_gate");
+        connect(&mut blueprint, reset, q, "a");
+        connect(&mut blueprint, not_q, q, "b");
+        connect(&mut blueprint, set, not_q, "a");
+        connect(&mut blueprint, q, not_q, "b");
+
+        let run =
+            |blueprint: &mut Blueprint, set_on: bool, reset_on: bool, graph: &mut SignalGraph| {
+                blueprint.set_property(set, SWITCH_PROPERTY, Some(PropertyValue::Bool(set_on)));
+                blueprint.set_property(reset, SWITCH_PROPERTY, Some(PropertyValue::Bool(reset_on)));
+                let mut compiled = compile(blueprint);
+                // Keep the latch state, only the switches changed.
+                compiled.nodes[2].outputs = graph.nodes[2].outputs.clone();
+                compiled.nodes[3].outputs = graph.nodes[3].outputs.clone();
+                *graph = compiled;
+                for _ in 0..3 {
+                    graph.step(None, 0.1);
+                }
+                graph.output(q, "out").unwrap().as_bool()
+            };
+
+        let mut graph = compile(&blueprint);
+        assert!(run(&mut blueprint, true, false, &mut graph));
+        assert!(run(&mut blueprint, false, false, &mut graph));
+        assert!(!run(&mut blueprint, false, true, &mut graph));
+        assert!(!run(&mut blueprint, false, false, &mut graph));
+    }
+
+    #[test]
+    fn math_test() {
+        let mut blueprint = Blueprint::new("test");
+        let a = dial(&mut blueprint, 0, 6.0);
+        let b = dial(&mut blueprint, 1, 3.0);
+        let zero = dial(&mut blueprint, 2, 0.0);
+        let mut ops = Vec::new();
+        for (x, part) in ["add", "subtract", "multiply", "divide", "min", "max"]
+            .into_iter()
+            .enumerate()
+        {
+            let position = add(&mut blueprint, 10 + x as i32, part);
+            connect(&mut blueprint, a, position, "a");
+            connect(&mut blueprint, b, position, "b");
+            ops.push(position);
+        }
+        let by_zero = add(&mut blueprint, 20, "divide");
+        connect(&mut blueprint, a, by_zero, "a");
+        connect(&mut blueprint, zero, by_zero, "b");
+        let greater = add(&mut blueprint, 21, "greater");
+        connect(&mut blueprint, a, greater, "a");
+        connect(&mut blueprint, b, greater, "b");
+
+        let mut graph = compile(&blueprint);
+        graph.step(None, 0.1);
+
+        let outputs: Vec<f32> = ops
+            .iter()
+            .map(|position| graph.output(*position, "out").unwrap().as_number())
+            .collect();
+        assert_eq!(outputs, vec![9.0, 3.0, 18.0, 2.0, 3.0, 6.0]);
+        assert_eq!(graph.output(by_zero, "out"), Some(Signal::Number(0.0)));
+        assert_eq!(graph.output(greater, "out"), Some(Signal::Bool(true)));
+    }
+
+    #[test]
+    fn timer_test() {
+        let mut blueprint = Blueprint::new("test");
+        let input = switch(&mut blueprint, 0, true);
+        let timer = add(&mut blueprint, 1, "timer");
+        blueprint.set_property(timer, DELAY_PROPERTY, Some(PropertyValue::Number(0.5)));
+        connect(&mut blueprint, input, timer, "in");
+
+        let mut graph = compile(&blueprint);
+        let mut ticks = 0;
+        while !graph.output(timer, "out").unwrap().as_bool() {
+            graph.step(None, 0.125);
+            ticks += 1;
+            assert!(ticks < 100);
+        }
+
+        assert_eq!(ticks, 4);
+    }
+
+    #[test]
+    fn memory_test() {
+        let mut blueprint = Blueprint::new("test");
+        let set = add(&mut blueprint, 0, "switch");
+        let memory = add(&mut blueprint, 1, "memory");
+        connect(&mut blueprint, set, memory, "set");
+
+        let mut graph = compile(&blueprint);
+        graph.step(None, 0.1);
+        assert_eq!(graph.output(memory, "out"), Some(Signal::Bool(false)));
+
+        // Pulse the set input through a fresh node and carry the state over.
+        blueprint.set_property(set, SWITCH_PROPERTY, Some(PropertyValue::Bool(true)));
+        let state = graph.nodes[1].state;
+        graph = compile(&blueprint);
+        graph.nodes[1].state = state;
+        graph.step(None, 0.1);
+        assert_eq!(graph.output(memory, "out"), Some(Signal::Bool(true)));
+
+        blueprint.set_property(set, SWITCH_PROPERTY, Some(PropertyValue::Bool(false)));
+        let state = graph.nodes[1].state;
+        graph = compile(&blueprint);
+        graph.nodes[1].state = state;
+        graph.step(None, 0.1);
+        assert_eq!(graph.output(memory, "out"), Some(Signal::Bool(true)));
+    }
+
+    #[test]
+    fn seat_test() {
+        let mut blueprint = Blueprint::new("test");
+        let seat = add(&mut blueprint, 0, "seat");
+        let gate = add(&mut blueprint, 100, "not_gate");
+        blueprint.connect(Wire::new(seat, "up", gate, "a")).unwrap();
+
+        let mut controls = VehicleControls::default();
+        controls.insert(
+            seat,
+            SeatSignals {
+                throttle: 0.5,
+                up: true,
+                ..default()
+            },
+        );
+
+        let mut graph = compile(&blueprint);
+        graph.step(Some(&controls), 0.1);
+
+        assert_eq!(graph.output(seat, "throttle"), Some(Signal::Number(0.5)));
+        assert_eq!(graph.output(gate, "out"), Some(Signal::Bool(false)));
+
+        graph.step(None, 0.1);
+        assert_eq!(graph.output(gate, "out"), Some(Signal::Bool(true)));
+    }
+
+    #[test]
+    fn validate_wire_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = Blueprint::new("test");
+        let input = switch(&mut blueprint, 0, true);
+        let value = dial(&mut blueprint, 1, 1.0);
+        let gate = add(&mut blueprint, 2, "and_gate");
+
+        assert!(validate_wire(&blueprint, &registry, &Wire::new(input, "out", gate, "b")).is_ok());
+        assert!(matches!(
+            validate_wire(&blueprint, &registry, &Wire::new(value, "out", gate, "a")),
+            Err(BlueprintError::WireTypeMismatch(_, _))
+        ));
+        assert!(matches!(
+            validate_wire(&blueprint, &registry, &Wire::new(input, "out", gate, "c")),
+            Err(BlueprintError::UnknownPort(_, _))
+        ));
+        assert!(matches!(
+            validate_wire(&blueprint, &registry, &Wire::new(gate, "a", input, "out")),
+            Err(BlueprintError::UnknownPort(_, _))
+        ));
+    }
+
+    #[test]
+    fn invalid_wire_ignored_test() {
+        let mut blueprint = Blueprint::new("test");
+        let value = dial(&mut blueprint, 0, 1.0);
+        let gate = add(&mut blueprint, 1, "not_gate");
+        blueprint
+            .connect(Wire::new(value, "out", gate, "a"))
+            .unwrap();
+
+        let mut graph = compile(&blueprint);
+        graph.step(None, 0.1);
+
+        assert_eq!(graph.input(gate, "a"), Some(Signal::Bool(false)));
+        assert_eq!(graph.output(gate, "out"), Some(Signal::Bool(true)));
+        assert!(!graph.is_connected(gate, "a"));
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+/// The edge length of a world chunk in meters.
+pub const CHUNK_SIZE: f32 = 64.0;
+
+/// The chunk `position` lies in, chunks are columns on the XZ plane.
+pub fn chunk_of(position: Vec3) -> IVec2 {
+    (position.xz() / CHUNK_SIZE).floor().as_ivec2()
+}
+
+/// The center of `chunk` at y = 0.
+pub fn chunk_center(chunk: IVec2) -> Vec3 {
+    ((chunk.as_vec2() + 0.5) * CHUNK_SIZE).extend(0.0).xzy()
+}
+
+/// The chunk of an entity that was in `current` and moved to `position`.
+///
+/// It only changes chunks once it is more than `margin` meters outside of `current`, so
+/// an entity moving along a border does not switch back and forth.
+pub fn chunk_with_margin(current: IVec2, position: Vec3, margin: f32) -> IVec2 {
+    let min = current.as_vec2() * CHUNK_SIZE - margin;
+    let max = (current.as_vec2() + 1.0) * CHUNK_SIZE + margin;
+    let position = position.xz();
+    if position.cmpge(min).all() && position.cmple(max).all() {
+        current
+    } else {
+        chunk_of(position.extend(0.0).xzy())
+    }
+}
+
+/// The number of chunks between `a` and `b`, counting diagonal steps as one.
+pub fn chunk_distance(a: IVec2, b: IVec2) -> i32 {
+    (a - b).abs().max_element()
+}
+
+/// The chunks a peer around `center` has to load and unload, given the chunks it has
+/// `loaded`.
+///
+/// Chunks are loaded within `load_radius` but only unloaded beyond `unload_radius`, which
+/// keeps a peer moving along a border from loading the same chunks over and over.
+pub fn chunk_changes(
+    loaded: &HashSet<IVec2>,
+    center: IVec2,
+    load_radius: i32,
+    unload_radius: i32,
+) -> (Vec<IVec2>, Vec<IVec2>) {
+    let mut load: Vec<_> = (-load_radius..=load_radius)
+        .flat_map(|x| (-load_radius..=load_radius).map(move |z| center + IVec2::new(x, z)))
+        .filter(|chunk| !loaded.contains(chunk))
+        .collect();
+    let mut unload: Vec<_> = loaded
+        .iter()
+        .filter(|chunk| chunk_distance(**chunk, center) > unload_radius)
+        .copied()
+        .collect();
+    load.sort_by_key(|chunk| chunk.to_array());
+    unload.sort_by_key(|chunk| chunk.to_array());
+
+    (load, unload)
+}
+
+#[cfg(test)]
+mod chunk_test {
+    use std::collections::HashSet;
+
+    use bevy::prelude::*;
+
+    use crate::world::chunk::{
+        CHUNK_SIZE, chunk_center, chunk_changes, chunk_distance, chunk_of, chunk_with_margin,
+    };
+
+    #[test]
+    fn chunk_of_test() {
+        assert_eq!(chunk_of(Vec3::ZERO), IVec2::ZERO);
+        assert_eq!(chunk_of(Vec3::new(63.9, 100.0, 0.0)), IVec2::ZERO);
+        assert_eq!(chunk_of(Vec3::new(64.0, 0.0, -0.1)), IVec2::new(1, -1));
+        assert_eq!(chunk_of(chunk_center(IVec2::new(-3, 2))), IVec2::new(-3, 2));
+        assert_eq!(
+            chunk_center(IVec2::ZERO),
+            Vec3::new(CHUNK_SIZE / 2.0, 0.0, CHUNK_SIZE / 2.0)
+        );
+    }
+
+    #[test]
+    fn margin_test() {
+        let chunk = IVec2::ZERO;
+        assert_eq!(
+            chunk_with_margin(chunk, Vec3::new(-5.0, 0.0, 10.0), 8.0),
+            chunk
+        );
+        assert_eq!(
+            chunk_with_margin(chunk, Vec3::new(70.0, 0.0, 10.0), 8.0),
+            chunk
+        );
+        assert_eq!(
+            chunk_with_margin(chunk, Vec3::new(73.0, 0.0, 10.0), 8.0),
+            IVec2::X
+        );
+
+        // Moving back does not switch right away either.
+        assert_eq!(
+            chunk_with_margin(IVec2::X, Vec3::new(60.0, 0.0, 10.0), 8.0),
+            IVec2::X
+        );
+    }
+
+    #[test]
+    fn changes_test() {
+        assert_eq!(chunk_distance(IVec2::ZERO, IVec2::new(2, -3)), 3);
+
+        let (load, unload) = chunk_changes(&HashSet::new(), IVec2::ZERO, 1, 2);
+        assert_eq!(load.len(), 9);
+        assert!(unload.is_empty());
+
+        // One step further only loads the new column and unloads nothing.
+        let mut loaded: HashSet<_> = load.into_iter().collect();
+        let (load, unload) = chunk_changes(&loaded, IVec2::X, 1, 2);
+        assert_eq!(
+            load,
+            vec![IVec2::new(2, -1), IVec2::new(2, 0), IVec2::new(2, 1)]
+        );
+        assert!(unload.is_empty());
+        loaded.extend(load);
+
+        // Stepping back loads nothing.
+        assert_eq!(
+            chunk_changes(&loaded, IVec2::ZERO, 1, 2),
+            (Vec::new(), Vec::new())
+        );
+
+        // Far away everything is unloaded.
+        let (_, unload) = chunk_changes(&loaded, IVec2::new(10, 0), 1, 2);
+        assert_eq!(unload.len(), loaded.len());
+    }
+}
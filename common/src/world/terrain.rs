@@ -0,0 +1,431 @@
+use avian3d::prelude::*;
+use bevy::{
+    asset::RenderAssetUsages,
+    mesh::{Indices, PrimitiveTopology},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::world::chunk::{CHUNK_SIZE, chunk_center};
+
+/// The edge length of the generated terrain in meters.
+pub const TERRAIN_SIZE: f32 = 512.0;
+/// The distance between two height samples in meters.
+pub const TERRAIN_SPACING: f32 = 2.0;
+/// The number of height samples along each edge of a chunk. Neighbouring chunks share
+/// the samples on their border.
+pub const CHUNK_RESOLUTION: usize = (CHUNK_SIZE / TERRAIN_SPACING) as usize + 1;
+
+/// The terrain is flat at y = 0 within this distance of the origin, where players and
+/// vehicles spawn.
+const PLATEAU_RADIUS: f32 = 30.0;
+/// The distance over which the plateau blends into the hills.
+const PLATEAU_BLEND: f32 = 30.0;
+const MAX_HILL_HEIGHT: f32 = 25.0;
+const SEA_FLOOR: f32 = -12.0;
+/// The share of the radius where the coast starts and where the sea floor is reached.
+const COAST: (f32, f32) = (0.55, 0.95);
+/// The size of the largest hills and coast bays in meters.
+const HILL_SCALE: f32 = 60.0;
+const COAST_SCALE: f32 = 90.0;
+const OCTAVES: u32 = 5;
+
+/// Generates the [`Heightmap`] and collider of every [`TerrainChunk`].
+///
+/// Both the server and the clients add it, the clients only receive the chunks near them.
+#[derive(Debug)]
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(generate_chunk_observer);
+    }
+}
+
+/// The island the game takes place on. Only the server has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct Terrain {
+    pub seed: u64,
+}
+
+/// A chunk of the [`Terrain`]. Replicated from the server, every peer generates its heights
+/// from the seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component, Serialize, Deserialize)]
+pub struct TerrainChunk {
+    pub seed: u64,
+    pub chunk: IVec2,
+}
+
+/// The chunks the terrain is split into.
+pub fn terrain_chunks() -> impl Iterator<Item = IVec2> {
+    let half = (TERRAIN_SIZE / CHUNK_SIZE / 2.0) as i32;
+    (-half..half).flat_map(move |x| (-half..half).map(move |z| IVec2::new(x, z)))
+}
+
+/// Heights on a square grid centered on the entity.
+#[derive(Debug, Clone, PartialEq, Component)]
+pub struct Heightmap {
+    resolution: usize,
+    size: f32,
+    /// Indexed by `x * resolution + z`.
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// Generate the part of the island for `seed` around `center` on the XZ plane.
+    ///
+    /// Only basic float arithmetic is used, so every platform generates the same heights.
+    pub fn generate(seed: u64, center: Vec2, resolution: usize, size: f32) -> Self {
+        let spacing = size / (resolution - 1) as f32;
+        let mut heights = Vec::with_capacity(resolution * resolution);
+        for x in 0..resolution {
+            for z in 0..resolution {
+                let position = center + Vec2::new(x as f32, z as f32) * spacing - size / 2.0;
+                heights.push(island_height(seed, position));
+            }
+        }
+
+        Self {
+            resolution,
+            size,
+            heights,
+        }
+    }
+
+    /// The number of samples along each edge.
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    /// The edge length in meters.
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// The distance between two samples in meters.
+    pub fn spacing(&self) -> f32 {
+        self.size / (self.resolution - 1) as f32
+    }
+
+    /// The height of the sample at `x` and `z`.
+    pub fn height(&self, x: usize, z: usize) -> f32 {
+        self.heights[x * self.resolution + z]
+    }
+
+    /// The interpolated height below `position` relative to the center, which is clamped to
+    /// the heightmap.
+    pub fn height_at(&self, position: Vec2) -> f32 {
+        let max = (self.resolution - 1) as f32;
+        let grid =
+            ((position + self.size / 2.0) / self.spacing()).clamp(Vec2::ZERO, Vec2::splat(max));
+        let cell = grid.floor().min(Vec2::splat(max - 1.0));
+        let t = grid - cell;
+        let (x, z) = (cell.x as usize, cell.y as usize);
+
+        let near = self.height(x, z) * (1.0 - t.y) + self.height(x, z + 1) * t.y;
+        let far = self.height(x + 1, z) * (1.0 - t.y) + self.height(x + 1, z + 1) * t.y;
+        near * (1.0 - t.x) + far * t.x
+    }
+
+    pub fn collider(&self) -> Collider {
+        let heights = self
+            .heights
+            .chunks(self.resolution)
+            .map(|row| row.to_vec())
+            .collect();
+        Collider::heightfield(heights, Vec3::new(self.size, 1.0, self.size))
+    }
+
+    /// A mesh colored by height, from sand at the water to rock on the hills.
+    pub fn mesh(&self) -> Mesh {
+        let resolution = self.resolution;
+        let spacing = self.spacing();
+        let mut positions = Vec::with_capacity(self.heights.len());
+        let mut normals = Vec::with_capacity(self.heights.len());
+        let mut colors = Vec::with_capacity(self.heights.len());
+        for x in 0..resolution {
+            for z in 0..resolution {
+                let height = self.height(x, z);
+                positions.push([
+                    x as f32 * spacing - self.size / 2.0,
+                    height,
+                    z as f32 * spacing - self.size / 2.0,
+                ]);
+
+                let slope_x = self.height((x + 1).min(resolution - 1), z)
+                    - self.height(x.saturating_sub(1), z);
+                let slope_z = self.height(x, (z + 1).min(resolution - 1))
+                    - self.height(x, z.saturating_sub(1));
+                normals.push(
+                    Vec3::new(-slope_x, 2.0 * spacing, -slope_z)
+                        .normalize()
+                        .to_array(),
+                );
+
+                let color = if height < 1.0 {
+                    LinearRgba::rgb(0.76, 0.7, 0.5)
+                } else if height < 12.0 {
+                    LinearRgba::rgb(0.25, 0.45, 0.2)
+                } else {
+                    LinearRgba::rgb(0.45, 0.43, 0.4)
+                };
+                colors.push(color.to_f32_array());
+            }
+        }
+
+        let mut indices = Vec::with_capacity((resolution - 1) * (resolution - 1) * 6);
+        for x in 0..resolution - 1 {
+            for z in 0..resolution - 1 {
+                let index = (x * resolution + z) as u32;
+                let (next_x, next_z) = (index + resolution as u32, index + 1);
+                // Counter clockwise seen from above.
+                indices.extend([index, next_z, next_x, next_x, next_z, next_x + 1]);
+            }
+        }
+
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+        .with_inserted_indices(Indices::U32(indices))
+    }
+}
+
+/// The height of the island at `position` in meters.
+fn island_height(seed: u64, position: Vec2) -> f32 {
+    // Noise moves the coast in and out to form bays and capes.
+    let coast = fractal_noise(seed, position / COAST_SCALE) - 0.5;
+    let radius = position.length() / (TERRAIN_SIZE / 2.0) + coast * 0.3;
+    let land = 1.0 - smoothstep(COAST.0, COAST.1, radius);
+
+    let hills = fractal_noise(seed.wrapping_add(1), position / HILL_SCALE);
+    let height = SEA_FLOOR + (hills * hills * MAX_HILL_HEIGHT + 1.0 - SEA_FLOOR) * land;
+
+    let plateau = smoothstep(
+        PLATEAU_RADIUS,
+        PLATEAU_RADIUS + PLATEAU_BLEND,
+        position.length(),
+    );
+    height * plateau
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Several octaves of [`value_noise`], from 0 to 1.
+fn fractal_noise(seed: u64, position: Vec2) -> f32 {
+    let (mut sum, mut total) = (0.0, 0.0);
+    let (mut amplitude, mut frequency) = (1.0, 1.0);
+    for octave in 0..OCTAVES {
+        sum += value_noise(
+            seed.wrapping_add(octave as u64 * 1013),
+            position * frequency,
+        ) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    sum / total
+}
+
+/// Smoothly interpolated random values on a grid with a spacing of 1, from 0 to 1.
+fn value_noise(seed: u64, position: Vec2) -> f32 {
+    let cell = position.floor();
+    let t = position - cell;
+    let t = t * t * (Vec2::splat(3.0) - 2.0 * t);
+    let (x, z) = (cell.x as i32, cell.y as i32);
+
+    let near = lerp(hash(seed, x, z), hash(seed, x, z + 1), t.y);
+    let far = lerp(hash(seed, x + 1, z), hash(seed, x + 1, z + 1), t.y);
+    lerp(near, far, t.x)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// A random value from 0 to 1 for a grid point, using the SplitMix64 finalizer.
+fn hash(seed: u64, x: i32, z: i32) -> f32 {
+    let mut hash = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (z as u32 as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn generate_chunk_observer(
+    trigger: On<Add, TerrainChunk>,
+    mut commands: Commands,
+    chunks: Query<&TerrainChunk>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(chunk) = chunks.get(entity) else {
+        return;
+    };
+
+    let center = chunk_center(chunk.chunk);
+    let heightmap = Heightmap::generate(chunk.seed, center.xz(), CHUNK_RESOLUTION, CHUNK_SIZE);
+    commands.entity(entity).insert((
+        Transform::from_translation(center),
+        RigidBody::Static,
+        heightmap.collider(),
+        heightmap,
+    ));
+}
+
+#[cfg(test)]
+mod terrain_test {
+    use bevy::prelude::*;
+
+    use crate::world::{
+        chunk::{CHUNK_SIZE, chunk_center},
+        terrain::{CHUNK_RESOLUTION, Heightmap, TERRAIN_SIZE, TERRAIN_SPACING, terrain_chunks},
+        water::Water,
+    };
+
+    const TERRAIN_RESOLUTION: usize = (TERRAIN_SIZE / TERRAIN_SPACING) as usize + 1;
+
+    fn island(seed: u64) -> Heightmap {
+        Heightmap::generate(seed, Vec2::ZERO, TERRAIN_RESOLUTION, TERRAIN_SIZE)
+    }
+
+    #[test]
+    fn determinism_test() {
+        let a = island(42);
+        let b = island(42);
+        assert_eq!(a, b);
+        assert_eq!(a.mesh().count_vertices(), b.mesh().count_vertices());
+
+        let c = island(43);
+        assert_ne!(a, c);
+
+        // Pin a few heights so a change to the generator is noticed, it changes every
+        // existing world.
+        let heightmap = Heightmap::generate(7, Vec2::ZERO, 9, 512.0);
+        let heights: Vec<_> = (0..9).map(|x| heightmap.height(x, 4)).collect();
+        assert_eq!(
+            heights,
+            [
+                -12.0, 11.020163, 18.124323, 2.9424753, 0.0, 11.80327, 9.434832, -4.161078, -12.0
+            ]
+        );
+    }
+
+    #[test]
+    fn island_test() {
+        let level = Water::default().level;
+        for seed in [0, 1, 2, u64::MAX] {
+            let heightmap = island(seed);
+            let last = TERRAIN_RESOLUTION - 1;
+
+            // The spawn plateau is flat and dry.
+            for position in [Vec2::ZERO, Vec2::new(10.0, -10.0), Vec2::new(-25.0, 0.0)] {
+                assert_eq!(heightmap.height_at(position), 0.0);
+            }
+            assert!(level < 0.0);
+
+            // The edges are under water.
+            for (x, z) in [(0, 0), (0, last), (last, 0), (last, last), (last / 2, 0)] {
+                assert!(heightmap.height(x, z) < level - 5.0);
+            }
+            // And there are hills.
+            let max = (0..TERRAIN_RESOLUTION)
+                .flat_map(|x| (0..TERRAIN_RESOLUTION).map(move |z| (x, z)))
+                .map(|(x, z)| heightmap.height(x, z))
+                .fold(f32::MIN, f32::max);
+            assert!(max > 3.0, "seed {seed} max {max}");
+        }
+    }
+
+    #[test]
+    fn height_at_test() {
+        let heightmap = Heightmap::generate(3, Vec2::ZERO, 65, 128.0);
+        let spacing = heightmap.spacing();
+        assert_eq!(spacing, 2.0);
+
+        // Samples are exact, between samples the height is interpolated.
+        assert_eq!(
+            heightmap.height_at(Vec2::new(-64.0, -64.0)),
+            heightmap.height(0, 0)
+        );
+        assert_eq!(
+            heightmap.height_at(Vec2::new(64.0, 64.0)),
+            heightmap.height(64, 64)
+        );
+        assert_eq!(
+            heightmap.height_at(Vec2::new(-63.0, -64.0)),
+            (heightmap.height(0, 0) + heightmap.height(1, 0)) / 2.0
+        );
+        assert_eq!(
+            heightmap.height_at(Vec2::new(-100.0, -100.0)),
+            heightmap.height(0, 0)
+        );
+    }
+
+    #[test]
+    fn collider_test() {
+        let heightmap = Heightmap::generate(5, Vec2::ZERO, 65, 128.0);
+        let collider = heightmap.collider();
+
+        // The collider matches the samples, including the orientation of its axes.
+        for position in [
+            Vec2::new(-50.0, 20.0),
+            Vec2::new(40.0, -30.0),
+            Vec2::ZERO,
+            Vec2::new(54.0, 54.0),
+            Vec2::new(-44.0, -54.0),
+        ] {
+            let (distance, _) = collider
+                .cast_ray(
+                    Vec3::ZERO,
+                    Quat::IDENTITY,
+                    position.extend(100.0).xzy(),
+                    Vec3::NEG_Y,
+                    1000.0,
+                    false,
+                )
+                .unwrap();
+            let height = 100.0 - distance;
+            assert!(
+                (height - heightmap.height_at(position)).abs() < 0.01,
+                "{position}: {height} != {}",
+                heightmap.height_at(position)
+            );
+        }
+    }
+
+    #[test]
+    fn chunk_test() {
+        let island = island(9);
+        let chunks: Vec<_> = terrain_chunks().collect();
+        assert_eq!(chunks.len(), 64);
+
+        // Chunks match the whole island, including the samples they share.
+        for chunk in chunks {
+            let center = chunk_center(chunk).xz();
+            let heightmap = Heightmap::generate(9, center, CHUNK_RESOLUTION, CHUNK_SIZE);
+            assert_eq!(heightmap.spacing(), TERRAIN_SPACING);
+
+            let offset = ((center - CHUNK_SIZE / 2.0 + TERRAIN_SIZE / 2.0) / TERRAIN_SPACING)
+                .as_uvec2()
+                .as_usizevec2();
+            for x in [0, 7, CHUNK_RESOLUTION - 1] {
+                for z in [0, 13, CHUNK_RESOLUTION - 1] {
+                    assert_eq!(
+                        heightmap.height(x, z),
+                        island.height(offset.x + x, offset.y + z)
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+use crate::{
+    part_registry::PartRegistry,
+    vehicle::{
+        BLOCK_SIZE, Blueprint, BlueprintError, VehicleBlueprint,
+        collider::VehicleColliderBuilder,
+        joint::{VehicleBodies, VehicleSubBody},
+    },
+};
+
+/// Lets bodies with [`Buoyancy`] float in the [`Water`]. Only the server adds it, like
+/// [`crate::vehicle::signal::SignalPlugin`].
+#[derive(Debug)]
+pub struct WaterPlugin;
+
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Water>()
+            .add_observer(insert_vehicle_buoyancy_observer)
+            .add_observer(insert_sub_body_buoyancy_observer)
+            .add_systems(FixedUpdate, buoyancy_system);
+    }
+}
+
+/// The sea around the island, an endless plane of water.
+#[derive(Debug, Clone, Copy, PartialEq, Resource)]
+pub struct Water {
+    /// The height of the surface in meters.
+    pub level: f32,
+    /// In kilogram per cubic meter.
+    pub density: f32,
+    /// The share of its horizontal velocity submerged water slows a body by per second.
+    pub drag: f32,
+    /// Like [`Self::drag`] for the vertical velocity. Waves carry bobbing away much faster
+    /// than water resists sliding through it.
+    pub heave_drag: f32,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            level: -0.5,
+            density: 1000.0,
+            drag: 0.3,
+            heave_drag: 4.0,
+        }
+    }
+}
+
+/// A box of a body that displaces water.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuoyancyVolume {
+    /// The center in the local space of the body in meters.
+    pub center: Vec3,
+    pub half_size: Vec3,
+}
+
+impl BuoyancyVolume {
+    /// The share of the volume below `level` and the point the buoyancy acts on, if any of it
+    /// is submerged.
+    pub fn submerged(&self, position: Vec3, rotation: Quat, level: f32) -> Option<(f32, Vec3)> {
+        let center = position + rotation * self.center;
+        // The vertical half extent of the rotated box.
+        let extent = (Mat3::from_quat(rotation).row(1).abs() * self.half_size).element_sum();
+        let bottom = center.y - extent;
+        let depth = (level - bottom).clamp(0.0, 2.0 * extent);
+        if depth <= 0.0 {
+            return None;
+        }
+
+        let fraction = if extent > 0.0 {
+            depth / (2.0 * extent)
+        } else {
+            1.0
+        };
+        Some((
+            fraction,
+            Vec3::new(center.x, bottom + depth / 2.0, center.z),
+        ))
+    }
+
+    /// In cubic meters.
+    pub fn volume(&self) -> f32 {
+        (self.half_size * 2.0).element_product()
+    }
+}
+
+/// The boxes of a rigid body that displace water.
+#[derive(Debug, Clone, Default, PartialEq, Component)]
+pub struct Buoyancy {
+    pub volumes: Vec<BuoyancyVolume>,
+}
+
+impl Buoyancy {
+    /// Every block of `blueprint` displaces its own volume.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a block uses a part that is not in `registry`.
+    pub fn from_blueprint(
+        blueprint: &Blueprint,
+        registry: &PartRegistry,
+    ) -> Result<Self, BlueprintError> {
+        // Boxes never cross chunk borders, which keeps them small enough for the torque to
+        // follow the shape of the hull.
+        let volumes = VehicleColliderBuilder::from_blueprint(blueprint, registry)?
+            .boxes()
+            .iter()
+            .map(|collider_box| BuoyancyVolume {
+                center: collider_box.center(),
+                half_size: collider_box.size().as_vec3() * BLOCK_SIZE / 2.0,
+            })
+            .collect();
+
+        Ok(Self { volumes })
+    }
+}
+
+fn insert_vehicle_buoyancy_observer(
+    trigger: On<Insert, VehicleBodies>,
+    mut commands: Commands,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok((blueprint, bodies)) = vehicles.get(entity) else {
+        return;
+    };
+
+    match Buoyancy::from_blueprint(&bodies.root_blueprint(blueprint), &registry) {
+        Ok(buoyancy) => {
+            commands.entity(entity).insert(buoyancy);
+        }
+        Err(e) => error!(
+            "Failed to build the buoyancy of vehicle `{}`: {}",
+            blueprint.name(),
+            e
+        ),
+    }
+}
+
+fn insert_sub_body_buoyancy_observer(
+    trigger: On<Insert, VehicleSubBody>,
+    mut commands: Commands,
+    sub_bodies: Query<&VehicleSubBody>,
+    vehicles: Query<(&VehicleBlueprint, &VehicleBodies)>,
+    registry: Res<PartRegistry>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(sub_body) = sub_bodies.get(entity) else {
+        return;
+    };
+    let Ok((blueprint, bodies)) = vehicles.get(sub_body.vehicle) else {
+        return;
+    };
+
+    match Buoyancy::from_blueprint(&bodies.body_blueprint(blueprint, sub_body.body), &registry) {
+        Ok(buoyancy) => {
+            commands.entity(entity).insert(buoyancy);
+        }
+        Err(e) => error!(
+            "Failed to build the buoyancy of body {} of vehicle `{}`: {}",
+            sub_body.body,
+            blueprint.name(),
+            e
+        ),
+    }
+}
+
+/// Push every submerged volume up with the weight of the water it displaces and slow it
+/// down by the drag of the water.
+fn buoyancy_system(
+    water: Res<Water>,
+    gravity: Res<Gravity>,
+    mut bodies: Query<(Forces, &ComputedCenterOfMass, &Buoyancy)>,
+) {
+    for (mut body, center_of_mass, buoyancy) in bodies.iter_mut() {
+        let position = body.position().0;
+        let rotation = body.rotation().0;
+        let center_of_mass = position + rotation * center_of_mass.0;
+        let (linear_velocity, angular_velocity) = (body.linear_velocity(), body.angular_velocity());
+
+        for volume in &buoyancy.volumes {
+            let Some((fraction, point)) = volume.submerged(position, rotation, water.level) else {
+                continue;
+            };
+
+            let displaced = water.density * volume.volume() * fraction;
+            let velocity = linear_velocity + angular_velocity.cross(point - center_of_mass);
+            let drag = Vec3::new(water.drag, water.heave_drag, water.drag);
+            let force = (-gravity.0 - velocity * drag) * displaced;
+            body.apply_force_at_point(force, point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod water_test {
+    use std::time::Duration;
+
+    use avian3d::prelude::*;
+    use bevy::{prelude::*, time::TimeUpdateStrategy};
+
+    use crate::{
+        part_registry::PartRegistry,
+        vehicle::{Block, Blueprint, joint::body_physics},
+        world::water::{Buoyancy, BuoyancyVolume, Water, WaterPlugin},
+    };
+
+    const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            AssetPlugin::default(),
+            bevy::mesh::MeshPlugin,
+            bevy::scene::ScenePlugin,
+            PhysicsPlugins::default(),
+        ))
+        .insert_resource(Time::<Fixed>::from_duration(TICK))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(TICK))
+        .insert_resource(PartRegistry::test())
+        .add_plugins(WaterPlugin);
+        app.finish();
+        app.cleanup();
+
+        app
+    }
+
+    /// A hull of `size` parts of `part`, each 10 blocks large.
+    fn hull(part: &str, size: IVec3) -> Blueprint {
+        let mut blueprint = Blueprint::new("hull");
+        for x in 0..size.x {
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    blueprint
+                        .add(Block::new(IVec3::new(x, y, z) * 10, part))
+                        .unwrap();
+                }
+            }
+        }
+
+        blueprint
+    }
+
+    /// Drop `blueprint` with its center of mass at `position` and return the body.
+    fn drop(app: &mut App, blueprint: &Blueprint, transform: Transform) -> Entity {
+        let registry = PartRegistry::test();
+        let (collider, mass) = body_physics(blueprint, &registry).unwrap();
+        let mass = mass.unwrap();
+
+        app.world_mut()
+            .spawn((
+                RigidBody::Dynamic,
+                collider.unwrap(),
+                mass.to_components(),
+                (NoAutoMass, NoAutoCenterOfMass, NoAutoAngularInertia),
+                Buoyancy::from_blueprint(blueprint, &registry).unwrap(),
+                Transform::from_translation(
+                    transform.translation - transform.rotation * mass.center_of_mass,
+                )
+                .with_rotation(transform.rotation),
+            ))
+            .id()
+    }
+
+    fn center_of_mass(app: &App, entity: Entity) -> Vec3 {
+        let world = app.world();
+        let position = world.get::<Position>(entity).unwrap().0;
+        let rotation = world.get::<Rotation>(entity).unwrap().0;
+
+        position + rotation * world.get::<ComputedCenterOfMass>(entity).unwrap().0
+    }
+
+    fn run(app: &mut App, seconds: f32) {
+        for _ in 0..(seconds / TICK.as_secs_f32()) as usize {
+            app.update();
+        }
+    }
+
+    #[test]
+    fn submerged_test() {
+        let volume = BuoyancyVolume {
+            center: Vec3::ZERO,
+            half_size: Vec3::new(1.0, 0.5, 1.0),
+        };
+        assert_eq!(volume.volume(), 4.0);
+
+        assert_eq!(volume.submerged(Vec3::Y, Quat::IDENTITY, 0.0), None);
+        assert_eq!(
+            volume.submerged(Vec3::ZERO, Quat::IDENTITY, 0.0),
+            Some((0.5, Vec3::new(0.0, -0.25, 0.0)))
+        );
+        assert_eq!(
+            volume.submerged(Vec3::new(3.0, -2.0, 1.0), Quat::IDENTITY, 0.0),
+            Some((1.0, Vec3::new(3.0, -2.0, 1.0)))
+        );
+
+        // Standing on its side the box reaches 1 meter down.
+        let (fraction, point) = volume
+            .submerged(
+                Vec3::ZERO,
+                Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                -0.5,
+            )
+            .unwrap();
+        assert!((fraction - 0.25).abs() < 1e-5);
+        assert!((point.y + 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn float_test() {
+        let mut app = app();
+        let level = app.world().resource::<Water>().level;
+        let blueprint = hull("wood", IVec3::new(5, 2, 5));
+        let hull = drop(
+            &mut app,
+            &blueprint,
+            Transform::from_xyz(0.0, level + 0.5, 0.0),
+        );
+        run(&mut app, 10.0);
+
+        // Wood has 70 % of the density of water, so 14 of the 20 centimeters are submerged.
+        let height = center_of_mass(&app, hull).y - level;
+        assert!((height - (0.1 - 0.14)).abs() < 0.01, "height {height}");
+        let velocity = app.world().get::<LinearVelocity>(hull).unwrap().0;
+        assert!(velocity.length() < 0.05, "velocity {velocity}");
+    }
+
+    #[test]
+    fn sink_test() {
+        let mut app = app();
+        let level = app.world().resource::<Water>().level;
+        let blueprint = hull("steel", IVec3::new(5, 2, 5));
+        let hull = drop(&mut app, &blueprint, Transform::from_xyz(0.0, level, 0.0));
+        run(&mut app, 3.0);
+
+        assert!(center_of_mass(&app, hull).y < level - 1.0);
+    }
+
+    #[test]
+    fn upright_test() {
+        let mut app = app();
+        let level = app.world().resource::<Water>().level;
+
+        // A wide raft rights itself.
+        let raft = drop(
+            &mut app,
+            &hull("wood", IVec3::new(6, 1, 6)),
+            Transform::from_xyz(0.0, level, 0.0).with_rotation(Quat::from_rotation_x(0.5)),
+        );
+        // A tall mast capsizes and floats on its side.
+        let mast = drop(
+            &mut app,
+            &hull("wood", IVec3::new(1, 6, 1)),
+            Transform::from_xyz(5.0, level, 0.0).with_rotation(Quat::from_rotation_x(0.1)),
+        );
+        run(&mut app, 15.0);
+
+        let up = |entity| app.world().get::<Rotation>(entity).unwrap().0 * Vec3::Y;
+        assert!(up(raft).y > 0.99, "raft up {}", up(raft));
+        assert!(up(mast).y.abs() < 0.1, "mast up {}", up(mast));
+    }
+}
@@ -1,7 +1,17 @@
 use bevy::prelude::*;
+use lightyear::prelude::*;
 
 pub struct ProtocolChannelsPlugin;
 
 impl Plugin for ProtocolChannelsPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_channel::<ReliableChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            ..default()
+        })
+        .add_direction(NetworkDirection::Bidirectional);
+    }
 }
+
+/// Ordered and reliable. Used for everything that has to arrive in order, e.g. the handshake.
+pub struct ReliableChannel;
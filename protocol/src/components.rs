@@ -1,7 +1,45 @@
 use bevy::prelude::*;
+use common::{
+    character::{CharacterController, CharacterState},
+    vehicle::{VehicleBlueprint, joint::VehicleSubBody, seat::Seated},
+    world::terrain::TerrainChunk,
+};
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::messages::VehicleId;
 
 pub struct ProtocolComponentsPlugin;
 
 impl Plugin for ProtocolComponentsPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.register_component::<CharacterController>();
+        app.register_component::<CharacterState>().add_prediction();
+        app.register_component::<Seated>()
+            .add_prediction()
+            .add_map_entities();
+        app.register_component::<SpawnedVehicle>();
+        app.register_component::<VehicleBlueprint>();
+        app.register_component::<VehicleSubBody>()
+            .add_map_entities();
+        app.register_component::<TerrainChunk>();
+        app.register_component::<Transform>()
+            .add_prediction()
+            .add_interpolation_with(lerp_transform);
+    }
+}
+
+/// A vehicle that a player spawned into the world.
+#[derive(Debug, Clone, PartialEq, Component, Serialize, Deserialize)]
+pub struct SpawnedVehicle {
+    pub id: VehicleId,
+    pub owner: PeerId,
+}
+
+fn lerp_transform(start: Transform, end: Transform, t: f32) -> Transform {
+    Transform {
+        translation: start.translation.lerp(end.translation, t),
+        rotation: start.rotation.slerp(end.rotation, t),
+        scale: start.scale.lerp(end.scale, t),
+    }
 }
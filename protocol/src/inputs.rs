@@ -1,7 +1,11 @@
 use bevy::prelude::*;
+use common::character::CharacterInput;
+use lightyear::prelude::input::native::InputPlugin;
 
 pub struct ProtocolInputsPlugin;
 
 impl Plugin for ProtocolInputsPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_plugins(InputPlugin::<CharacterInput>::default());
+    }
 }
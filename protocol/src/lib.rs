@@ -5,10 +5,10 @@ use crate::{
     inputs::ProtocolInputsPlugin, messages::ProtocolMessagesPlugin,
 };
 
-mod channels;
-mod components;
+pub mod channels;
+pub mod components;
 mod inputs;
-mod messages;
+pub mod messages;
 
 pub const PROTOCOL_ID: u64 = 0;
 
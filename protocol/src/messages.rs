@@ -1,7 +1,251 @@
 use bevy::prelude::*;
+use common::vehicle::{
+    Blueprint,
+    edit::{EditKind, EditOperation},
+};
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub struct ProtocolMessagesPlugin;
 
 impl Plugin for ProtocolMessagesPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.register_message::<PartRegistryChecksum>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<Kicked>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<ServerShutdown>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<SendChat>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<ChatMessage>()
+            .add_direction(NetworkDirection::ServerToClient);
+
+        app.register_message::<ListEditorSessions>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<CreateEditorSession>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<JoinEditorSession>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<LeaveEditorSession>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<KickFromEditorSession>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<InviteToEditorSession>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<EditorSessionList>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<EditorSessionRejected>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<KickedFromEditorSession>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<EditRequest>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<EditorSnapshot>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<EditApplied>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<EditRejected>()
+            .add_direction(NetworkDirection::ServerToClient);
+
+        app.register_message::<SpawnVehicle>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<DespawnVehicle>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<RespawnVehicle>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<EnterSeat>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<ExitSeat>()
+            .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<VehicleRejected>()
+            .add_direction(NetworkDirection::ServerToClient);
+    }
+}
+
+/// Sent by the client after connecting.
+///
+/// The server disconnects clients whose [`common::part_registry::PartRegistry::checksum`]
+/// differs from its own, because they would disagree on the part ids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartRegistryChecksum(pub u64);
+
+/// Sent by the server right before it disconnects the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Kicked {
+    pub reason: String,
+}
+
+/// A chat line of the client. Lines starting with `/` run a server command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendChat {
+    pub text: String,
+}
+
+/// A chat line shown to the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// The player that wrote it, [`None`] for the server and command replies.
+    pub sender: Option<PeerId>,
+    pub text: String,
+}
+
+/// Sent by the server while it counts down to shutting down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerShutdown {
+    /// The seconds until the server disconnects everyone.
+    pub seconds: u32,
+}
+
+/// Identifies a building session on the server.
+pub type SessionId = u32;
+
+/// Identifies a vehicle spawned into the world.
+pub type VehicleId = u32;
+
+/// Ask for the building sessions. Answered with an [`EditorSessionList`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListEditorSessions;
+
+/// Create a building session with an empty vehicle and join it as its owner.
+///
+/// Answered with an [`EditorSnapshot`] or an [`EditorSessionRejected`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateEditorSession {
+    pub name: String,
+}
+
+/// Join a building session and leave the current one.
+///
+/// Answered with an [`EditorSnapshot`] or an [`EditorSessionRejected`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinEditorSession {
+    pub session: SessionId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaveEditorSession {
+    pub session: SessionId,
+}
+
+/// Remove a member from a building session. Only the owner of the session may kick.
+///
+/// The member can not join again until it is invited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KickFromEditorSession {
+    pub session: SessionId,
+    pub client: PeerId,
+}
+
+/// Let a player build in a building session. Only the owner of the session may invite.
+///
+/// Everybody else who joins only watches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteToEditorSession {
+    pub session: SessionId,
+    pub client: PeerId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditorSessionList {
+    pub sessions: Vec<EditorSessionInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EditorSessionInfo {
+    pub id: SessionId,
+    pub name: String,
+    pub owner: PeerId,
+    pub members: Vec<PeerId>,
+    /// The players besides the owner that may build.
+    pub builders: Vec<PeerId>,
+}
+
+/// Sent if a building session could not be created, joined or a member could not be kicked
+/// or invited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditorSessionRejected {
+    pub reason: String,
+}
+
+/// Sent to a member that was kicked by the owner of the session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KickedFromEditorSession {
+    pub session: SessionId,
+}
+
+/// Ask the server to apply edits to the vehicle of a session.
+///
+/// The client does not apply the operations itself, it waits for the [`EditApplied`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditRequest {
+    pub session: SessionId,
+    pub kind: EditKind,
+    pub operations: Vec<EditOperation>,
+}
+
+/// The whole vehicle of a session, sent after joining.
+///
+/// Every [`EditApplied`] with a higher sequence number has to be applied on top of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditorSnapshot {
+    pub session: SessionId,
+    pub sequence: u64,
+    pub blueprint: Blueprint,
+}
+
+/// Edits the server applied, sent to every member of the session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditApplied {
+    pub session: SessionId,
+    /// Increases by one with every applied [`EditRequest`].
+    pub sequence: u64,
+    pub kind: EditKind,
+    /// True for the member that requested the edit.
+    pub own: bool,
+    /// Only the operations that could be applied.
+    pub operations: Vec<EditOperation>,
+}
+
+/// Sent to the requesting client if none of its operations could be applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditRejected {
+    pub session: SessionId,
+    /// The kind of the rejected [`EditRequest`].
+    pub kind: EditKind,
+    pub reason: String,
+}
+
+/// Spawn a saved vehicle at a free spawn point, owned by the sender.
+///
+/// Rejected with a [`VehicleRejected`] if the blueprint is invalid or there is no room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnVehicle {
+    pub blueprint: Blueprint,
+}
+
+/// Remove a vehicle from the world. Only works for the owner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DespawnVehicle {
+    pub vehicle: VehicleId,
+}
+
+/// Put a vehicle back onto a free spawn point and stop it. Only works for the owner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RespawnVehicle {
+    pub vehicle: VehicleId,
+}
+
+/// Sit down on the closest free seat within [`common::vehicle::seat::SEAT_REACH`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnterSeat;
+
+/// Get up from the seat next to it, if there is room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitSeat;
+
+/// Sent if a vehicle could not be spawned, despawned, respawned, entered or exited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VehicleRejected {
+    pub reason: String,
 }
@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use lightyear::prelude::{Connected, MessageReceiver, MessageSender, RemoteId, server::ClientOf};
+use log::info;
+use protocol::{
+    channels::ReliableChannel,
+    messages::{ChatMessage, SendChat},
+};
+
+use crate::command::{CommandSource, RunCommand};
+
+/// The most characters of a chat line, longer lines are cut.
+const MAX_CHAT_LENGTH: usize = 256;
+
+/// Relays chat lines to everyone and runs the ones starting with `/` as commands.
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, chat_system);
+    }
+}
+
+fn chat_system(
+    mut clients: Query<(Entity, &RemoteId, &mut MessageReceiver<SendChat>), With<ClientOf>>,
+    mut senders: Query<&mut MessageSender<ChatMessage>, (With<ClientOf>, With<Connected>)>,
+    mut commands: MessageWriter<RunCommand>,
+) {
+    for (client, remote_id, mut receiver) in clients.iter_mut() {
+        for SendChat { text } in receiver.receive() {
+            let text: String = text.trim().chars().take(MAX_CHAT_LENGTH).collect();
+            if text.is_empty() {
+                continue;
+            }
+
+            if let Some(line) = text.strip_prefix('/') {
+                commands.write(RunCommand {
+                    source: CommandSource::Player {
+                        client,
+                        id: remote_id.0,
+                    },
+                    line: line.to_string(),
+                });
+                continue;
+            }
+
+            info!("<{:?}> {}", remote_id.0, text);
+            for mut sender in senders.iter_mut() {
+                sender.send::<ReliableChannel>(ChatMessage {
+                    sender: Some(remote_id.0),
+                    text: text.clone(),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use lightyear::prelude::{MessageSender, PeerId, RemoteId, server::ClientOf};
+use log::{info, warn};
+use protocol::{
+    channels::ReliableChannel,
+    messages::{ChatMessage, VehicleId},
+};
+use thiserror::Error;
+
+use crate::{
+    command::builtin::register_builtin_commands,
+    config::{Config, role::Role},
+    network::access::AccessTarget,
+};
+
+mod builtin;
+
+/// Runs the commands of the console and of players in the chat.
+pub struct CommandPlugin;
+
+impl Plugin for CommandPlugin {
+    fn build(&self, app: &mut App) {
+        let mut registry = CommandRegistry::default();
+        register_builtin_commands(&mut registry);
+        app.insert_resource(registry);
+
+        app.add_message::<RunCommand>();
+        app.add_systems(PostUpdate, run_commands_system);
+    }
+}
+
+/// Run a command line, without the leading slash of chat commands.
+#[derive(Debug, Clone, Message)]
+pub struct RunCommand {
+    pub source: CommandSource,
+    pub line: String,
+}
+
+/// Who runs a command. Replies go back to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    Console,
+    /// A player in the chat, with its [`ClientOf`] entity.
+    Player {
+        client: Entity,
+        id: PeerId,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("unknown command `{0}`, try `help`")]
+    UnknownCommand(String),
+    #[error("you need the {1} role to run `{0}`")]
+    PermissionDenied(&'static str, Role),
+    #[error("missing <{0}>, usage: {1}")]
+    MissingArgument(&'static str, String),
+    #[error("`{value}` is not a valid {kind} for <{name}>")]
+    InvalidArgument {
+        name: &'static str,
+        kind: ArgumentKind,
+        value: String,
+    },
+    #[error("too many arguments, usage: {0}")]
+    TooManyArguments(String),
+    #[error("{0:?} has the {1} role, which is not below yours")]
+    Outranked(PeerId, Role),
+    #[error("player {0:?} is not online")]
+    UnknownPlayer(PeerId),
+    #[error("vehicle {0} does not exist")]
+    UnknownVehicle(VehicleId),
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// The type of a command argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentKind {
+    /// A client id.
+    Player,
+    Integer,
+    /// Three numbers.
+    Position,
+    Vehicle,
+    /// A client id or an IP address or range.
+    Target,
+    /// A number with a unit like `30m`, `12h` or `7d`.
+    Duration,
+    /// `on` or `off`.
+    Switch,
+    /// The rest of the line.
+    Text,
+}
+
+impl Display for ArgumentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Player => "player id",
+            Self::Integer => "whole number",
+            Self::Position => "position",
+            Self::Vehicle => "vehicle id",
+            Self::Target => "client id or IP range",
+            Self::Duration => "duration",
+            Self::Switch => "on or off",
+            Self::Text => "text",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentValue {
+    Player(PeerId),
+    Integer(i64),
+    Position(Vec3),
+    Vehicle(VehicleId),
+    Target(AccessTarget),
+    Duration(Duration),
+    Switch(bool),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Argument {
+    pub name: &'static str,
+    pub kind: ArgumentKind,
+    pub optional: bool,
+}
+
+impl Argument {
+    pub const fn required(name: &'static str, kind: ArgumentKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub const fn optional(name: &'static str, kind: ArgumentKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: true,
+        }
+    }
+}
+
+/// Runs a command and returns the reply.
+pub type CommandFn = fn(&mut World, &CommandContext) -> Result<String, CommandError>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CommandDefinition {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// The lowest role that may run the command.
+    pub role: Role,
+    pub arguments: &'static [Argument],
+    pub run: CommandFn,
+}
+
+impl CommandDefinition {
+    /// The command with its arguments, like `tp <player> <position>`.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for argument in self.arguments {
+            if argument.optional {
+                usage.push_str(&format!(" [{}]", argument.name));
+            } else {
+                usage.push_str(&format!(" <{}>", argument.name));
+            }
+        }
+
+        usage
+    }
+
+    /// Parse the arguments after the command name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a required argument is missing, an argument
+    /// has the wrong type or there are arguments left over.
+    pub fn parse(&self, input: &str) -> Result<HashMap<&'static str, ArgumentValue>, CommandError> {
+        let mut tokens = input.split_whitespace();
+        let mut values = HashMap::new();
+        for argument in self.arguments {
+            let Some(token) = tokens.next() else {
+                if argument.optional {
+                    continue;
+                }
+                return Err(CommandError::MissingArgument(argument.name, self.usage()));
+            };
+
+            let invalid = |value: &str| CommandError::InvalidArgument {
+                name: argument.name,
+                kind: argument.kind,
+                value: value.to_string(),
+            };
+            let number = |token: &str| {
+                token
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|number| number.is_finite())
+                    .ok_or_else(|| invalid(token))
+            };
+            let value = match argument.kind {
+                ArgumentKind::Player => ArgumentValue::Player(PeerId::Netcode(
+                    token.parse().map_err(|_| invalid(token))?,
+                )),
+                ArgumentKind::Integer => {
+                    ArgumentValue::Integer(token.parse().map_err(|_| invalid(token))?)
+                }
+                ArgumentKind::Vehicle => {
+                    ArgumentValue::Vehicle(token.parse().map_err(|_| invalid(token))?)
+                }
+                ArgumentKind::Target => {
+                    ArgumentValue::Target(token.parse().map_err(|_| invalid(token))?)
+                }
+                ArgumentKind::Duration => {
+                    ArgumentValue::Duration(parse_duration(token).ok_or_else(|| invalid(token))?)
+                }
+                ArgumentKind::Switch => ArgumentValue::Switch(match token {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid(token)),
+                }),
+                ArgumentKind::Position => {
+                    let mut position = Vec3::new(number(token)?, 0.0, 0.0);
+                    for axis in 1..3 {
+                        let token = tokens
+                            .next()
+                            .ok_or(CommandError::MissingArgument(argument.name, self.usage()))?;
+                        position[axis] = number(token)?;
+                    }
+                    ArgumentValue::Position(position)
+                }
+                ArgumentKind::Text => ArgumentValue::Text(
+                    std::iter::once(token)
+                        .chain(tokens.by_ref())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+            };
+            values.insert(argument.name, value);
+        }
+
+        if tokens.next().is_some() {
+            return Err(CommandError::TooManyArguments(self.usage()));
+        }
+
+        Ok(values)
+    }
+}
+
+/// Every command, by name.
+#[derive(Debug, Default, Resource)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, CommandDefinition>,
+}
+
+impl CommandRegistry {
+    /// Add a command, replacing one with the same name.
+    pub fn register(&mut self, command: CommandDefinition) {
+        self.commands.insert(command.name, command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CommandDefinition> {
+        self.commands.get(name)
+    }
+
+    /// The commands sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &CommandDefinition> {
+        self.commands.values()
+    }
+}
+
+/// The parsed arguments of a running command.
+#[derive(Debug)]
+pub struct CommandContext {
+    pub source: CommandSource,
+    /// The role of whoever runs the command.
+    pub role: Role,
+    usage: String,
+    arguments: HashMap<&'static str, ArgumentValue>,
+}
+
+impl CommandContext {
+    fn get(&self, name: &'static str) -> Result<&ArgumentValue, CommandError> {
+        self.arguments
+            .get(name)
+            .ok_or_else(|| CommandError::MissingArgument(name, self.usage.clone()))
+    }
+
+    /// Whether the command may act on a player with `role`, which needs a higher role. The
+    /// console may act on everybody.
+    pub fn outranks(&self, role: Role) -> bool {
+        self.source == CommandSource::Console || self.role > role
+    }
+
+    /// Whether the optional argument `name` was given.
+    pub fn has(&self, name: &'static str) -> bool {
+        self.arguments.contains_key(name)
+    }
+
+    pub fn player(&self, name: &'static str) -> Result<PeerId, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Player(id) => Ok(*id),
+            value => panic!("<{name}> is not a player but {value:?}"),
+        }
+    }
+
+    pub fn integer(&self, name: &'static str) -> Result<i64, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Integer(integer) => Ok(*integer),
+            value => panic!("<{name}> is not an integer but {value:?}"),
+        }
+    }
+
+    pub fn position(&self, name: &'static str) -> Result<Vec3, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Position(position) => Ok(*position),
+            value => panic!("<{name}> is not a position but {value:?}"),
+        }
+    }
+
+    pub fn vehicle(&self, name: &'static str) -> Result<VehicleId, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Vehicle(id) => Ok(*id),
+            value => panic!("<{name}> is not a vehicle but {value:?}"),
+        }
+    }
+
+    pub fn target(&self, name: &'static str) -> Result<AccessTarget, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Target(target) => Ok(*target),
+            value => panic!("<{name}> is not a target but {value:?}"),
+        }
+    }
+
+    pub fn duration(&self, name: &'static str) -> Result<Duration, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Duration(duration) => Ok(*duration),
+            value => panic!("<{name}> is not a duration but {value:?}"),
+        }
+    }
+
+    pub fn switch(&self, name: &'static str) -> Result<bool, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Switch(switch) => Ok(*switch),
+            value => panic!("<{name}> is not a switch but {value:?}"),
+        }
+    }
+
+    pub fn text(&self, name: &'static str) -> Result<&str, CommandError> {
+        match self.get(name)? {
+            ArgumentValue::Text(text) => Ok(text),
+            value => panic!("<{name}> is not text but {value:?}"),
+        }
+    }
+}
+
+/// Parse and run `line` as `source`.
+///
+/// # Errors
+///
+/// This function will return an error if the command does not exist, `source` may not run
+/// it, the arguments are invalid or the command fails.
+pub fn run_command(
+    world: &mut World,
+    source: CommandSource,
+    line: &str,
+) -> Result<String, CommandError> {
+    let line = line.trim();
+    let (name, input) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let command = *world
+        .resource::<CommandRegistry>()
+        .get(name)
+        .ok_or_else(|| CommandError::UnknownCommand(name.to_string()))?;
+
+    let role = match source {
+        CommandSource::Console => Role::Admin,
+        CommandSource::Player { id, .. } => world.resource::<Config>().roles.role(id),
+    };
+    if role < command.role {
+        return Err(CommandError::PermissionDenied(command.name, command.role));
+    }
+
+    let context = CommandContext {
+        source,
+        role,
+        usage: command.usage(),
+        arguments: command.parse(input)?,
+    };
+    (command.run)(world, &context)
+}
+
+/// The [`ClientOf`] entity of the connected player `id`.
+pub fn client_of(world: &mut World, id: PeerId) -> Result<Entity, CommandError> {
+    world
+        .query_filtered::<(Entity, &RemoteId), With<ClientOf>>()
+        .iter(world)
+        .find(|(_, remote_id)| remote_id.0 == id)
+        .map(|(entity, _)| entity)
+        .ok_or(CommandError::UnknownPlayer(id))
+}
+
+/// Parse a duration like `90s`, `30m`, `12h`, `7d` or `2w`.
+fn parse_duration(token: &str) -> Option<Duration> {
+    let split = token.len().checked_sub(1)?;
+    let (number, unit) = token.split_at_checked(split)?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .filter(|seconds| *seconds > 0)
+        .map(Duration::from_secs)
+}
+
+fn run_commands_system(world: &mut World) {
+    let requests: Vec<_> = world
+        .resource_mut::<Messages<RunCommand>>()
+        .drain()
+        .collect();
+    for RunCommand { source, line } in requests {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = run_command(world, source, &line);
+        let reply = match &result {
+            Ok(reply) => reply.clone(),
+            Err(e) => format!("Error: {e}"),
+        };
+        match source {
+            CommandSource::Console if result.is_ok() => info!("{reply}"),
+            CommandSource::Console => warn!("{reply}"),
+            CommandSource::Player { client, id } => {
+                info!("Player {:?} ran `{}`: {}", id, line, reply);
+                match world.get_mut::<MessageSender<ChatMessage>>(client) {
+                    Some(mut sender) => sender.send::<ReliableChannel>(ChatMessage {
+                        sender: None,
+                        text: reply,
+                    }),
+                    None => warn!("Could not reply to {:?}", id),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod command_test {
+    use bevy::prelude::*;
+    use lightyear::prelude::PeerId;
+
+    use std::time::Duration;
+
+    use crate::{
+        command::{
+            Argument, ArgumentKind, ArgumentValue, CommandDefinition, CommandError,
+            CommandRegistry, CommandSource, builtin::register_builtin_commands, parse_duration,
+            run_command,
+        },
+        config::{Config, role::Role},
+        network::access::AccessList,
+    };
+
+    const TP: CommandDefinition = CommandDefinition {
+        name: "tp",
+        help: "",
+        role: Role::Moderator,
+        arguments: &[
+            Argument::required("player", ArgumentKind::Player),
+            Argument::required("position", ArgumentKind::Position),
+        ],
+        run: |_, _| Ok(String::new()),
+    };
+    const KICK: CommandDefinition = CommandDefinition {
+        name: "kick",
+        help: "",
+        role: Role::Moderator,
+        arguments: &[
+            Argument::required("player", ArgumentKind::Player),
+            Argument::optional("reason", ArgumentKind::Text),
+        ],
+        run: |_, _| Ok(String::new()),
+    };
+
+    #[test]
+    fn usage_test() {
+        assert_eq!(TP.usage(), "tp <player> <position>");
+        assert_eq!(KICK.usage(), "kick <player> [reason]");
+    }
+
+    #[test]
+    fn parse_test() {
+        let values = TP.parse("42 1 -2.5 3").unwrap();
+        assert_eq!(values["player"], ArgumentValue::Player(PeerId::Netcode(42)));
+        assert_eq!(
+            values["position"],
+            ArgumentValue::Position(Vec3::new(1.0, -2.5, 3.0))
+        );
+
+        let values = KICK.parse("7   spamming  the chat").unwrap();
+        assert_eq!(
+            values["reason"],
+            ArgumentValue::Text("spamming the chat".to_string())
+        );
+        assert!(!KICK.parse("7").unwrap().contains_key("reason"));
+    }
+
+    #[test]
+    fn parse_error_test() {
+        assert!(matches!(
+            TP.parse("42 1 2"),
+            Err(CommandError::MissingArgument("position", _))
+        ));
+        assert!(matches!(
+            TP.parse(""),
+            Err(CommandError::MissingArgument("player", _))
+        ));
+        assert!(matches!(
+            TP.parse("me 1 2 3"),
+            Err(CommandError::InvalidArgument {
+                name: "player",
+                kind: ArgumentKind::Player,
+                ..
+            })
+        ));
+        assert!(matches!(
+            TP.parse("42 1 2 NaN"),
+            Err(CommandError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            TP.parse("42 1 2 3 4"),
+            Err(CommandError::TooManyArguments(_))
+        ));
+    }
+
+    #[test]
+    fn parse_duration_test() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("2d"), Some(Duration::from_secs(172800)));
+        assert_eq!(parse_duration("0h"), None);
+        assert_eq!(parse_duration("12"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("ä"), None);
+    }
+
+    #[test]
+    fn rank_test() {
+        let mut world = World::new();
+        let mut config = Config::default();
+        config.roles.admins.push(1);
+        config.roles.moderators.extend([2, 3]);
+        world.insert_resource(config);
+        world.insert_resource(AccessList::default());
+        let mut registry = CommandRegistry::default();
+        register_builtin_commands(&mut registry);
+        world.insert_resource(registry);
+
+        let moderator = CommandSource::Player {
+            client: Entity::PLACEHOLDER,
+            id: PeerId::Netcode(2),
+        };
+        for line in ["ban 1", "tempban 1 1d", "ban 3"] {
+            assert!(matches!(
+                run_command(&mut world, moderator, line),
+                Err(CommandError::Outranked(_, _))
+            ));
+        }
+        assert_eq!(world.resource::<AccessList>().bans().count(), 0);
+    }
+}
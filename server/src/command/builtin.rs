@@ -0,0 +1,476 @@
+use avian3d::prelude::{LinearVelocity, Position};
+use bevy::prelude::*;
+use common::character::CharacterController;
+use lightyear::prelude::{
+    Connected, ControlledBy, MessageSender, PeerAddr, PeerId, RemoteId, server::ClientOf,
+};
+use protocol::{channels::ReliableChannel, components::SpawnedVehicle, messages::ChatMessage};
+
+use crate::{
+    command::{
+        Argument, ArgumentKind, CommandContext, CommandDefinition, CommandError, CommandRegistry,
+        client_of,
+    },
+    config::{Config, role::Role},
+    game::save::SaveWorld,
+    network::{
+        KickClient,
+        access::{AccessEntry, AccessList, AccessTarget, Refusal, expiry, unix_time},
+    },
+    shutdown::Shutdown,
+};
+
+type ConnectedClient = (With<ClientOf>, With<Connected>);
+
+pub fn register_builtin_commands(registry: &mut CommandRegistry) {
+    for command in [
+        HELP,
+        LIST,
+        KICK,
+        BAN,
+        TEMPBAN,
+        UNBAN,
+        BANLIST,
+        WHITELIST,
+        ALLOW,
+        DISALLOW,
+        SAY,
+        SAVE,
+        STOP,
+        SET_MAX_PLAYERS,
+        TP,
+        DESPAWN,
+    ] {
+        registry.register(command);
+    }
+}
+
+const HELP: CommandDefinition = CommandDefinition {
+    name: "help",
+    help: "List the commands you may run or explain one.",
+    role: Role::Guest,
+    arguments: &[Argument::optional("command", ArgumentKind::Text)],
+    run: help,
+};
+
+const LIST: CommandDefinition = CommandDefinition {
+    name: "list",
+    help: "List the connected players with their position and vehicles.",
+    role: Role::Moderator,
+    arguments: &[],
+    run: list,
+};
+
+const KICK: CommandDefinition = CommandDefinition {
+    name: "kick",
+    help: "Disconnect a player.",
+    role: Role::Moderator,
+    arguments: &[
+        Argument::required("player", ArgumentKind::Player),
+        Argument::optional("reason", ArgumentKind::Text),
+    ],
+    run: kick,
+};
+
+const BAN: CommandDefinition = CommandDefinition {
+    name: "ban",
+    help: "Disconnect a client id or IP range and refuse it from now on.",
+    role: Role::Moderator,
+    arguments: &[
+        Argument::required("target", ArgumentKind::Target),
+        Argument::optional("reason", ArgumentKind::Text),
+    ],
+    run: ban,
+};
+
+const TEMPBAN: CommandDefinition = CommandDefinition {
+    name: "tempban",
+    help: "Disconnect a client id or IP range and refuse it for a while.",
+    role: Role::Moderator,
+    arguments: &[
+        Argument::required("target", ArgumentKind::Target),
+        Argument::required("duration", ArgumentKind::Duration),
+        Argument::optional("reason", ArgumentKind::Text),
+    ],
+    run: ban,
+};
+
+const UNBAN: CommandDefinition = CommandDefinition {
+    name: "unban",
+    help: "Lift the ban of a client id or IP range.",
+    role: Role::Moderator,
+    arguments: &[Argument::required("target", ArgumentKind::Target)],
+    run: unban,
+};
+
+const BANLIST: CommandDefinition = CommandDefinition {
+    name: "banlist",
+    help: "List the bans.",
+    role: Role::Moderator,
+    arguments: &[],
+    run: banlist,
+};
+
+const WHITELIST: CommandDefinition = CommandDefinition {
+    name: "whitelist",
+    help: "Turn the whitelist on or off, or list it. Nobody is kicked.",
+    role: Role::Admin,
+    arguments: &[Argument::optional("enabled", ArgumentKind::Switch)],
+    run: whitelist,
+};
+
+const ALLOW: CommandDefinition = CommandDefinition {
+    name: "allow",
+    help: "Put a client id or IP range on the whitelist.",
+    role: Role::Admin,
+    arguments: &[
+        Argument::required("target", ArgumentKind::Target),
+        Argument::optional("note", ArgumentKind::Text),
+    ],
+    run: allow,
+};
+
+const DISALLOW: CommandDefinition = CommandDefinition {
+    name: "disallow",
+    help: "Remove a client id or IP range from the whitelist.",
+    role: Role::Admin,
+    arguments: &[Argument::required("target", ArgumentKind::Target)],
+    run: disallow,
+};
+
+const SAY: CommandDefinition = CommandDefinition {
+    name: "say",
+    help: "Send a chat message from the server to everyone.",
+    role: Role::Moderator,
+    arguments: &[Argument::required("message", ArgumentKind::Text)],
+    run: say,
+};
+
+const SAVE: CommandDefinition = CommandDefinition {
+    name: "save",
+    help: "Write the world save.",
+    role: Role::Admin,
+    arguments: &[],
+    run: save,
+};
+
+const STOP: CommandDefinition = CommandDefinition {
+    name: "stop",
+    help: "Warn everyone, save the world and shut the server down.",
+    role: Role::Admin,
+    arguments: &[],
+    run: stop,
+};
+
+const SET_MAX_PLAYERS: CommandDefinition = CommandDefinition {
+    name: "setmaxplayers",
+    help: "Change how many players may be connected. Nobody is kicked.",
+    role: Role::Admin,
+    arguments: &[Argument::required("count", ArgumentKind::Integer)],
+    run: set_max_players,
+};
+
+const TP: CommandDefinition = CommandDefinition {
+    name: "tp",
+    help: "Move the character of a player.",
+    role: Role::Moderator,
+    arguments: &[
+        Argument::required("player", ArgumentKind::Player),
+        Argument::required("position", ArgumentKind::Position),
+    ],
+    run: tp,
+};
+
+const DESPAWN: CommandDefinition = CommandDefinition {
+    name: "despawn",
+    help: "Remove a vehicle from the world.",
+    role: Role::Moderator,
+    arguments: &[Argument::required("vehicle", ArgumentKind::Vehicle)],
+    run: despawn,
+};
+
+fn help(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let registry = world.resource::<CommandRegistry>();
+    if context.has("command") {
+        let name = context.text("command")?;
+        let command = registry
+            .get(name)
+            .ok_or_else(|| CommandError::UnknownCommand(name.to_string()))?;
+        return Ok(format!("{}: {}", command.usage(), command.help));
+    }
+
+    let lines: Vec<_> = registry
+        .iter()
+        .filter(|command| command.role <= context.role)
+        .map(|command| format!("{}: {}", command.usage(), command.help))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+fn list(world: &mut World, _: &CommandContext) -> Result<String, CommandError> {
+    let config = world.resource::<Config>();
+    let (max_players, roles) = (config.max_players, config.roles.clone());
+    let clients: Vec<_> = world
+        .query_filtered::<(Entity, &RemoteId, Option<&PeerAddr>), ConnectedClient>()
+        .iter(world)
+        .map(|(entity, remote_id, addr)| (entity, remote_id.0, addr.map(|addr| addr.0.ip())))
+        .collect();
+
+    let mut lines = vec![format!("{}/{} players online", clients.len(), max_players)];
+    for (client, id, ip) in clients {
+        let position = world
+            .query_filtered::<(&Transform, &ControlledBy), With<CharacterController>>()
+            .iter(world)
+            .find(|(_, controlled_by)| controlled_by.owner == client)
+            .map(|(transform, _)| transform.translation);
+        let vehicles: Vec<_> = world
+            .query::<&SpawnedVehicle>()
+            .iter(world)
+            .filter(|vehicle| vehicle.owner == id)
+            .map(|vehicle| vehicle.id.to_string())
+            .collect();
+
+        let position = match position {
+            Some(position) => format!("{:.1} {:.1} {:.1}", position.x, position.y, position.z),
+            None => "no character".to_string(),
+        };
+        let ip = ip.map_or_else(|| "unknown address".to_string(), |ip| ip.to_string());
+        lines.push(format!(
+            "{:?} ({}) from {} at {}, vehicles [{}]",
+            id,
+            roles.role(id),
+            ip,
+            position,
+            vehicles.join(", ")
+        ));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+fn kick(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let id = context.player("player")?;
+    let client = client_of(world, id)?;
+    check_rank(world, context, id)?;
+    let reason = context.text("reason").unwrap_or("Kicked by a moderator");
+
+    world.trigger(KickClient {
+        entity: client,
+        reason: reason.to_string(),
+    });
+    Ok(format!("Kicked {id:?}"))
+}
+
+fn ban(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let target = context.target("target")?;
+    let duration = if context.has("duration") {
+        Some(context.duration("duration")?.as_secs())
+    } else {
+        None
+    };
+    let expires = duration
+        .map(|duration| expiry(unix_time(), duration))
+        .transpose()
+        .map_err(|e| CommandError::Failed(e.to_string()))?;
+    let entry = AccessEntry {
+        target,
+        reason: context.text("reason").unwrap_or_default().to_string(),
+        expires,
+    };
+    let refusal = Refusal::Banned {
+        reason: entry.reason.clone(),
+        remaining: duration,
+    };
+    let reply = format!("Banned {entry}");
+
+    let clients: Vec<_> = world
+        .query_filtered::<(Entity, &RemoteId, Option<&PeerAddr>), With<Connected>>()
+        .iter(world)
+        .filter(|(_, remote_id, addr)| target.matches(remote_id.0, addr.map(|addr| addr.0.ip())))
+        .map(|(entity, remote_id, _)| (entity, remote_id.0))
+        .collect();
+    // Offline players can only be checked by their client id.
+    if let AccessTarget::Client(id) = target {
+        check_rank(world, context, PeerId::Netcode(id))?;
+    }
+    for (_, id) in &clients {
+        check_rank(world, context, *id)?;
+    }
+
+    update_access(world, |access| access.ban(entry))?;
+    for (client, _) in clients {
+        world.trigger(KickClient {
+            entity: client,
+            reason: refusal.to_string(),
+        });
+    }
+
+    Ok(reply)
+}
+
+fn unban(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let target = context.target("target")?;
+    if update_access(world, |access| access.unban(target))? {
+        Ok(format!("Unbanned {target}"))
+    } else {
+        Err(CommandError::Failed(format!("{target} is not banned")))
+    }
+}
+
+fn banlist(world: &mut World, _: &CommandContext) -> Result<String, CommandError> {
+    let now = unix_time();
+    let access = world.resource::<AccessList>();
+    let bans: Vec<_> = access
+        .bans()
+        .filter(|entry| !entry.is_expired(now))
+        .collect();
+
+    let mut lines = vec![format!("{} bans", bans.len())];
+    lines.extend(bans.iter().map(|entry| entry.to_string()));
+    Ok(lines.join("\n"))
+}
+
+fn whitelist(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    if context.has("enabled") {
+        let enabled = context.switch("enabled")?;
+        update_access(world, |access| access.whitelist_enabled = enabled)?;
+        return Ok(format!("The whitelist is {}", switch_name(enabled)));
+    }
+
+    let access = world.resource::<AccessList>();
+    let entries: Vec<_> = access.whitelist().collect();
+    let mut lines = vec![format!(
+        "The whitelist is {} with {} entries",
+        switch_name(access.whitelist_enabled),
+        entries.len()
+    )];
+    lines.extend(entries.iter().map(|entry| entry.to_string()));
+    Ok(lines.join("\n"))
+}
+
+fn allow(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let entry = AccessEntry {
+        target: context.target("target")?,
+        reason: context.text("note").unwrap_or_default().to_string(),
+        expires: None,
+    };
+    let reply = format!("Put {} on the whitelist", entry.target);
+
+    update_access(world, |access| access.allow(entry))?;
+    Ok(reply)
+}
+
+fn disallow(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let target = context.target("target")?;
+    if update_access(world, |access| access.disallow(target))? {
+        Ok(format!("Removed {target} from the whitelist"))
+    } else {
+        Err(CommandError::Failed(format!(
+            "{target} is not on the whitelist"
+        )))
+    }
+}
+
+fn say(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let text = context.text("message")?;
+    let mut senders = world.query_filtered::<&mut MessageSender<ChatMessage>, ConnectedClient>();
+    for mut sender in senders.iter_mut(world) {
+        sender.send::<ReliableChannel>(ChatMessage {
+            sender: None,
+            text: text.to_string(),
+        });
+    }
+
+    Ok(format!("[Server] {text}"))
+}
+
+fn save(world: &mut World, _: &CommandContext) -> Result<String, CommandError> {
+    world.write_message(SaveWorld);
+    Ok("Saving the world".to_string())
+}
+
+fn stop(world: &mut World, _: &CommandContext) -> Result<String, CommandError> {
+    world.trigger(Shutdown { now: false });
+    Ok("Stopping the server".to_string())
+}
+
+fn set_max_players(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let count = context.integer("count")?;
+    let count = u32::try_from(count)
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| CommandError::Failed(format!("{count} is not a valid player count")))?;
+
+    world.resource_mut::<Config>().max_players = count;
+    Ok(format!("Up to {count} players may be connected"))
+}
+
+fn tp(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let id = context.player("player")?;
+    let target = context.position("position")?;
+    let client = client_of(world, id)?;
+
+    let mut characters = world.query_filtered::<(
+        &mut Transform,
+        Option<&mut Position>,
+        Option<&mut LinearVelocity>,
+        &ControlledBy,
+    ), With<CharacterController>>();
+    let (mut transform, position, velocity, _) = characters
+        .iter_mut(world)
+        .find(|(_, _, _, controlled_by)| controlled_by.owner == client)
+        .ok_or_else(|| CommandError::Failed(format!("{id:?} has no character")))?;
+
+    transform.translation = target;
+    if let Some(mut position) = position {
+        position.0 = target;
+    }
+    if let Some(mut velocity) = velocity {
+        velocity.0 = Vec3::ZERO;
+    }
+    Ok(format!(
+        "Moved {:?} to {:.1} {:.1} {:.1}",
+        id, target.x, target.y, target.z
+    ))
+}
+
+fn despawn(world: &mut World, context: &CommandContext) -> Result<String, CommandError> {
+    let id = context.vehicle("vehicle")?;
+    let vehicle = world
+        .query::<(Entity, &SpawnedVehicle)>()
+        .iter(world)
+        .find(|(_, vehicle)| vehicle.id == id)
+        .map(|(entity, _)| entity)
+        .ok_or(CommandError::UnknownVehicle(id))?;
+
+    world.despawn(vehicle);
+    Ok(format!("Despawned vehicle {id}"))
+}
+
+/// Change the [`AccessList`] and save it.
+/// Refuse to act on `id` unless the command outranks it.
+fn check_rank(world: &World, context: &CommandContext, id: PeerId) -> Result<(), CommandError> {
+    let role = world.resource::<Config>().roles.role(id);
+    if context.outranks(role) {
+        Ok(())
+    } else {
+        Err(CommandError::Outranked(id, role))
+    }
+}
+
+fn update_access<R>(
+    world: &mut World,
+    update: impl FnOnce(&mut AccessList) -> R,
+) -> Result<R, CommandError> {
+    let mut access = world.resource_mut::<AccessList>();
+    let result = update(&mut access);
+    access.save().map_err(|e| {
+        CommandError::Failed(format!("Failed to save the ban list and whitelist: {e}"))
+    })?;
+
+    Ok(result)
+}
+
+fn switch_name(enabled: bool) -> &'static str {
+    if enabled { "on" } else { "off" }
+}
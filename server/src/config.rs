@@ -8,9 +8,10 @@ use clap::Parser;
 use common::ip_addr_into_socket_addr;
 use serde::{Deserialize, Serialize};
 
-use crate::config::cli::Cli;
+use crate::config::{cli::Cli, role::Roles};
 
 mod cli;
+pub mod role;
 
 /// FIXME: Use directories crate.
 const CONFIG_PATH: &str = "./config.toml";
@@ -27,20 +28,56 @@ impl Plugin for ConfigPlugins {
 pub struct Config {
     pub addr: SocketAddr,
     pub max_players: u32,
+    /// The seed of the island. A random island is generated every start if it is unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// The name of the world save.
+    #[serde(default = "default_save")]
+    pub save: String,
+    /// Start a new world instead of loading the save. Only set from the CLI.
+    #[serde(skip)]
+    pub fresh: bool,
+    /// The seconds clients are warned before the server shuts down.
+    #[serde(default = "default_shutdown_countdown")]
+    pub shutdown_countdown: u32,
+    /// What each player may do.
+    #[serde(default)]
+    pub roles: Roles,
 }
 
 impl Config {
     pub fn new() -> Result<Self, BevyError> {
         let cli = Cli::parse();
+        let seed = cli.seed;
+        let save = cli.save.clone();
+        let fresh = cli.fresh;
+        let shutdown_countdown = cli.shutdown_countdown;
 
-        if let Some(validated_cli) = cli.into_validated() {
-            Ok(Self {
+        let mut config = if let Some(validated_cli) = cli.into_validated() {
+            Self {
                 addr: ip_addr_into_socket_addr(validated_cli.ip, validated_cli.port),
                 max_players: validated_cli.max_players,
-            })
+                seed: None,
+                save: default_save(),
+                fresh: false,
+                shutdown_countdown: default_shutdown_countdown(),
+                roles: Roles::default(),
+            }
         } else {
-            Self::open()
+            Self::open()?
+        };
+        if seed.is_some() {
+            config.seed = seed;
+        }
+        if let Some(save) = save {
+            config.save = save;
         }
+        config.fresh = fresh;
+        if let Some(shutdown_countdown) = shutdown_countdown {
+            config.shutdown_countdown = shutdown_countdown;
+        }
+
+        Ok(config)
     }
 
     pub fn open() -> Result<Self, BevyError> {
@@ -65,6 +102,7 @@ impl Config {
         }
     }
 
+    #[allow(dead_code)]
     pub fn write(&self) -> Result<(), BevyError> {
         Self::write_to_file(self)
     }
@@ -82,6 +120,19 @@ impl Default for Config {
                 common::DEFAULT_PORT,
             )),
             max_players: 4,
+            seed: None,
+            save: default_save(),
+            fresh: false,
+            shutdown_countdown: default_shutdown_countdown(),
+            roles: Roles::default(),
         }
     }
 }
+
+fn default_save() -> String {
+    "world".to_string()
+}
+
+fn default_shutdown_countdown() -> u32 {
+    10
+}
@@ -15,6 +15,21 @@ pub struct Cli {
 
     #[arg(long, short, default_value = None)]
     pub max_players: Option<u32>,
+
+    /// The seed of the island, overrides the one in the config.
+    #[arg(long, default_value = None)]
+    pub seed: Option<u64>,
+
+    /// The name of the world save to load and write, overrides the one in the config.
+    #[arg(long, default_value = None)]
+    pub save: Option<String>,
+    /// Ignore the existing world save and start a new world.
+    #[arg(long)]
+    pub fresh: bool,
+    /// The seconds clients are warned before the server shuts down, overrides the one in the
+    /// config.
+    #[arg(long, default_value = None)]
+    pub shutdown_countdown: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -26,13 +41,14 @@ pub struct ValidatedCli {
 }
 
 impl Cli {
-    /// Returns true if all fields are [`Some`].
+    /// Returns true if all fields except `seed`, the save and the shutdown options are [`Some`].
     pub fn into_validated(self) -> Option<ValidatedCli> {
         match self {
             Self {
                 ip: Some(ip),
                 port: Some(port),
                 max_players: Some(max_players),
+                ..
             } => Some(ValidatedCli {
                 ip,
                 port,
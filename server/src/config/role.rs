@@ -0,0 +1,163 @@
+use std::fmt::Display;
+
+use lightyear::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// What a player may do, from the least to the most trusted.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Drives, chats and watches building sessions.
+    Guest,
+    /// Builds and spawns its own vehicles.
+    #[default]
+    Builder,
+    /// Manages the vehicles and building sessions of others and players.
+    Moderator,
+    /// May do everything. The console is an admin.
+    Admin,
+}
+
+impl Role {
+    /// # Errors
+    ///
+    /// This function will return an error if the role does not grant `permission`.
+    pub fn check(self, permission: Permission) -> Result<(), PermissionDenied> {
+        if self >= permission.role() {
+            Ok(())
+        } else {
+            Err(PermissionDenied(permission))
+        }
+    }
+
+    pub fn allows(self, permission: Permission) -> bool {
+        self.check(permission).is_ok()
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Guest => "guest",
+            Self::Builder => "builder",
+            Self::Moderator => "moderator",
+            Self::Admin => "admin",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Actions outside of commands that not every [`Role`] may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Create building sessions and edit the vehicles of the sessions it owns or was invited
+    /// to.
+    Build,
+    SpawnVehicles,
+    /// Despawn and respawn vehicles of others.
+    ManageVehicles,
+    /// Kick and invite members and build in the building sessions of others.
+    ManageSessions,
+}
+
+impl Permission {
+    /// The lowest role with the permission.
+    pub fn role(self) -> Role {
+        match self {
+            Self::Build | Self::SpawnVehicles => Role::Builder,
+            Self::ManageVehicles | Self::ManageSessions => Role::Moderator,
+        }
+    }
+
+    fn action(self) -> &'static str {
+        match self {
+            Self::Build => "build vehicles",
+            Self::SpawnVehicles => "spawn vehicles",
+            Self::ManageVehicles => "manage the vehicles of others",
+            Self::ManageSessions => "manage the building sessions of others",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("you need the {} role to {}", .0.role(), .0.action())]
+pub struct PermissionDenied(pub Permission);
+
+/// The roles of the players by client id.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Roles {
+    /// The role of every player that is not listed.
+    #[serde(default)]
+    pub default: Role,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moderators: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub builders: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub guests: Vec<u64>,
+}
+
+impl Roles {
+    /// The role of `id`. A player listed more than once gets the highest role.
+    pub fn role(&self, id: PeerId) -> Role {
+        let PeerId::Netcode(id) = id else {
+            return self.default;
+        };
+
+        [
+            (Role::Admin, &self.admins),
+            (Role::Moderator, &self.moderators),
+            (Role::Builder, &self.builders),
+            (Role::Guest, &self.guests),
+        ]
+        .into_iter()
+        .find(|(_, ids)| ids.contains(&id))
+        .map_or(self.default, |(role, _)| role)
+    }
+}
+
+#[cfg(test)]
+mod role_test {
+    use lightyear::prelude::PeerId;
+
+    use crate::config::role::{Permission, PermissionDenied, Role, Roles};
+
+    #[test]
+    fn role_test() {
+        let roles: Roles = toml::from_str(
+            r#"
+            default = "guest"
+            admins = [1]
+            moderators = [1, 2]
+            builders = [3]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(roles.role(PeerId::Netcode(1)), Role::Admin);
+        assert_eq!(roles.role(PeerId::Netcode(2)), Role::Moderator);
+        assert_eq!(roles.role(PeerId::Netcode(3)), Role::Builder);
+        assert_eq!(roles.role(PeerId::Netcode(4)), Role::Guest);
+        assert_eq!(Roles::default().role(PeerId::Netcode(4)), Role::Builder);
+    }
+
+    #[test]
+    fn permission_test() {
+        assert!(Role::Builder.allows(Permission::SpawnVehicles));
+        assert!(!Role::Builder.allows(Permission::ManageVehicles));
+        assert!(Role::Admin.allows(Permission::ManageSessions));
+        assert_eq!(
+            Role::Guest.check(Permission::Build),
+            Err(PermissionDenied(Permission::Build))
+        );
+        assert_eq!(
+            PermissionDenied(Permission::Build).to_string(),
+            "you need the builder role to build vehicles"
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::{
+    io,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use bevy::prelude::*;
+use log::warn;
+
+use crate::command::{CommandSource, RunCommand};
+
+/// Runs the lines typed into stdin as commands.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+        // Reading stdin blocks, so it gets its own thread. It ends with stdin.
+        let spawned = thread::Builder::new()
+            .name("console".to_string())
+            .spawn(move || {
+                for line in io::stdin().lines().map_while(Result::ok) {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("Failed to start the console: {e}");
+        }
+
+        app.insert_non_send_resource(ConsoleInput(receiver));
+        app.add_systems(Update, console_system);
+    }
+}
+
+struct ConsoleInput(Receiver<String>);
+
+fn console_system(input: NonSend<ConsoleInput>, mut commands: MessageWriter<RunCommand>) {
+    for line in input.0.try_iter() {
+        commands.write(RunCommand {
+            source: CommandSource::Console,
+            line,
+        });
+    }
+}
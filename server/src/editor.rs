@@ -1,7 +1,308 @@
 use bevy::prelude::*;
+use common::{part_registry::PartRegistry, vehicle::Blueprint};
+use lightyear::prelude::{MessageReceiver, MessageSender, RemoteId, server::ClientOf};
+use log::info;
+use protocol::{
+    channels::ReliableChannel,
+    messages::{
+        CreateEditorSession, EditApplied, EditRejected, EditRequest, EditorSessionList,
+        EditorSessionRejected, EditorSnapshot, InviteToEditorSession, JoinEditorSession,
+        KickFromEditorSession, KickedFromEditorSession, LeaveEditorSession, ListEditorSessions,
+        SessionId,
+    },
+};
+
+use crate::{
+    config::{
+        Config,
+        role::{Permission, Role},
+    },
+    editor::session::{EditorSession, EditorSessions, SessionError},
+};
+
+pub mod session;
 
 pub struct EditorPlugins;
 
 impl Plugin for EditorPlugins {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorSessions>()
+            .add_systems(
+                Update,
+                (
+                    list_sessions_system,
+                    create_session_system,
+                    join_session_system,
+                    leave_session_system,
+                    kick_member_system,
+                    invite_member_system,
+                    edit_system,
+                )
+                    .chain(),
+            )
+            .add_observer(remove_client_observer);
+    }
+}
+
+fn list_sessions_system(
+    mut clients: Query<(Entity, &mut MessageReceiver<ListEditorSessions>), With<ClientOf>>,
+    mut senders: Query<&mut MessageSender<EditorSessionList>, With<ClientOf>>,
+    sessions: Res<EditorSessions>,
+) {
+    for (entity, mut receiver) in clients.iter_mut() {
+        if receiver.receive().count() == 0 {
+            continue;
+        }
+
+        if let Ok(mut sender) = senders.get_mut(entity) {
+            sender.send::<ReliableChannel>(EditorSessionList {
+                sessions: sessions.list(),
+            });
+        }
+    }
+}
+
+fn create_session_system(
+    mut clients: Query<(Entity, &RemoteId, &mut MessageReceiver<CreateEditorSession>)>,
+    mut snapshot_senders: Query<&mut MessageSender<EditorSnapshot>, With<ClientOf>>,
+    mut rejected_senders: Query<&mut MessageSender<EditorSessionRejected>, With<ClientOf>>,
+    mut sessions: ResMut<EditorSessions>,
+    registry: Res<PartRegistry>,
+    config: Res<Config>,
+) {
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        for CreateEditorSession { name } in receiver.receive() {
+            let result = config
+                .roles
+                .role(remote_id.0)
+                .check(Permission::Build)
+                .map_err(SessionError::from)
+                .and_then(|_| {
+                    EditorSession::new(&name, remote_id.0, Blueprint::new(&name), &registry)
+                })
+                .and_then(|session| {
+                    let id = sessions.create(session);
+                    sessions
+                        .join(id, entity, remote_id.0)
+                        .map(|session| (id, session))
+                });
+
+            match result {
+                Ok((id, session)) => {
+                    info!(
+                        "Client {:?} created editor session {} `{}`",
+                        remote_id.0,
+                        id,
+                        session.name()
+                    );
+                    send_snapshot(&mut snapshot_senders, entity, id, session);
+                }
+                Err(e) => reject(&mut rejected_senders, entity, e),
+            }
+        }
+    }
+}
+
+fn join_session_system(
+    mut clients: Query<(Entity, &RemoteId, &mut MessageReceiver<JoinEditorSession>)>,
+    mut snapshot_senders: Query<&mut MessageSender<EditorSnapshot>, With<ClientOf>>,
+    mut rejected_senders: Query<&mut MessageSender<EditorSessionRejected>, With<ClientOf>>,
+    mut sessions: ResMut<EditorSessions>,
+) {
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        for JoinEditorSession { session: id } in receiver.receive() {
+            match sessions.join(id, entity, remote_id.0) {
+                Ok(session) => {
+                    info!("Client {:?} joined editor session {}", remote_id.0, id);
+                    send_snapshot(&mut snapshot_senders, entity, id, session);
+                }
+                Err(e) => reject(&mut rejected_senders, entity, e),
+            }
+        }
+    }
+}
+
+fn leave_session_system(
+    mut clients: Query<(Entity, &mut MessageReceiver<LeaveEditorSession>), With<ClientOf>>,
+    mut sessions: ResMut<EditorSessions>,
+) {
+    for (entity, mut receiver) in clients.iter_mut() {
+        for LeaveEditorSession { session: id } in receiver.receive() {
+            if sessions.leave(id, entity) {
+                info!("Client {} left editor session {}", entity, id);
+            }
+        }
+    }
+}
+
+/// Let the owner of a session and moderators remove other members until they are invited
+/// again.
+fn kick_member_system(
+    mut clients: Query<(
+        Entity,
+        &RemoteId,
+        &mut MessageReceiver<KickFromEditorSession>,
+    )>,
+    mut kicked_senders: Query<&mut MessageSender<KickedFromEditorSession>, With<ClientOf>>,
+    mut rejected_senders: Query<&mut MessageSender<EditorSessionRejected>, With<ClientOf>>,
+    mut sessions: ResMut<EditorSessions>,
+    config: Res<Config>,
+) {
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        let role = config.roles.role(remote_id.0);
+        for KickFromEditorSession {
+            session: id,
+            client,
+        } in receiver.receive()
+        {
+            let result = check_manager(&sessions, id, remote_id, role)
+                .and_then(|_| sessions.kick(id, client));
+
+            let member = match result {
+                Ok(member) => member,
+                Err(e) => {
+                    reject(&mut rejected_senders, entity, e);
+                    continue;
+                }
+            };
+
+            if let Ok(mut sender) = kicked_senders.get_mut(member) {
+                sender.send::<ReliableChannel>(KickedFromEditorSession { session: id });
+            }
+            info!("Client {:?} was kicked from editor session {}", client, id);
+        }
+    }
+}
+
+/// Let the owner of a session and moderators allow others to build in it.
+fn invite_member_system(
+    mut clients: Query<(
+        Entity,
+        &RemoteId,
+        &mut MessageReceiver<InviteToEditorSession>,
+    )>,
+    mut rejected_senders: Query<&mut MessageSender<EditorSessionRejected>, With<ClientOf>>,
+    mut sessions: ResMut<EditorSessions>,
+    config: Res<Config>,
+) {
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        let role = config.roles.role(remote_id.0);
+        for InviteToEditorSession {
+            session: id,
+            client,
+        } in receiver.receive()
+        {
+            let result =
+                check_manager(&sessions, id, remote_id, role).and_then(|_| sessions.get_mut(id));
+            match result {
+                Ok(session) => {
+                    session.invite(client);
+                    info!("Client {:?} was invited to editor session {}", client, id);
+                }
+                Err(e) => reject(&mut rejected_senders, entity, e),
+            }
+        }
+    }
+}
+
+/// Check that `remote_id` owns the session `id` or may manage the sessions of others.
+fn check_manager(
+    sessions: &EditorSessions,
+    id: SessionId,
+    remote_id: &RemoteId,
+    role: Role,
+) -> Result<(), SessionError> {
+    if sessions.get(id)?.owner() != remote_id.0 && !role.allows(Permission::ManageSessions) {
+        return Err(SessionError::NotOwner(id));
+    }
+
+    Ok(())
+}
+
+/// Apply the edits of members that may build and broadcast them to the whole session.
+fn edit_system(
+    mut clients: Query<(Entity, &RemoteId, &mut MessageReceiver<EditRequest>)>,
+    mut rejected_senders: Query<&mut MessageSender<EditRejected>, With<ClientOf>>,
+    mut members: Query<&mut MessageSender<EditApplied>, With<ClientOf>>,
+    mut sessions: ResMut<EditorSessions>,
+    registry: Res<PartRegistry>,
+    config: Res<Config>,
+) {
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        let role = config.roles.role(remote_id.0);
+        for request in receiver.receive() {
+            let result = sessions.get_mut(request.session).and_then(|session| {
+                if !session.is_member(entity) {
+                    return Err(SessionError::NotMember(request.session));
+                }
+                role.check(Permission::Build)?;
+                if !session.can_build(remote_id.0) && !role.allows(Permission::ManageSessions) {
+                    return Err(SessionError::NotInvited(request.session));
+                }
+
+                let (sequence, operations) = session.apply(request.operations, &registry)?;
+                Ok((session, sequence, operations))
+            });
+
+            let (session, sequence, operations) = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    if let Ok(mut sender) = rejected_senders.get_mut(entity) {
+                        sender.send::<ReliableChannel>(EditRejected {
+                            session: request.session,
+                            kind: request.kind,
+                            reason: e.to_string(),
+                        });
+                    }
+                    continue;
+                }
+            };
+
+            for member in session.members() {
+                let Ok(mut sender) = members.get_mut(member) else {
+                    continue;
+                };
+
+                sender.send::<ReliableChannel>(EditApplied {
+                    session: request.session,
+                    sequence,
+                    kind: request.kind,
+                    own: member == entity,
+                    operations: operations.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn send_snapshot(
+    senders: &mut Query<&mut MessageSender<EditorSnapshot>, With<ClientOf>>,
+    client: Entity,
+    id: SessionId,
+    session: &EditorSession,
+) {
+    if let Ok(mut sender) = senders.get_mut(client) {
+        sender.send::<ReliableChannel>(EditorSnapshot {
+            session: id,
+            sequence: session.sequence(),
+            blueprint: session.blueprint().clone(),
+        });
+    }
+}
+
+fn reject(
+    senders: &mut Query<&mut MessageSender<EditorSessionRejected>, With<ClientOf>>,
+    client: Entity,
+    error: SessionError,
+) {
+    if let Ok(mut sender) = senders.get_mut(client) {
+        sender.send::<ReliableChannel>(EditorSessionRejected {
+            reason: error.to_string(),
+        });
+    }
+}
+
+/// Disconnected clients leave all their sessions.
+fn remove_client_observer(trigger: On<Remove, ClientOf>, mut sessions: ResMut<EditorSessions>) {
+    sessions.leave_all(trigger.event().entity);
 }
@@ -0,0 +1,729 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::prelude::*;
+use common::{
+    part_registry::PartRegistry,
+    vehicle::{Blueprint, BlueprintError, edit::EditOperation, occupancy::Occupancy},
+};
+use lightyear::prelude::PeerId;
+use protocol::messages::{EditorSessionInfo, SessionId};
+use thiserror::Error;
+
+use crate::{
+    config::role::PermissionDenied,
+    game::vehicle::limits::{
+        LimitError, MAX_BLOCKS, MAX_NAME_LENGTH, MAX_PROPERTIES, MAX_TEXT_LENGTH, check_block,
+        check_length, check_property,
+    },
+};
+
+/// The most operations a single [`protocol::messages::EditRequest`] may contain.
+pub const MAX_OPERATIONS_PER_REQUEST: usize = 4096;
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("session {0} does not exist")]
+    UnknownSession(SessionId),
+    #[error("not a member of session {0}")]
+    NotMember(SessionId),
+    #[error("only the owner of session {0} can do that")]
+    NotOwner(SessionId),
+    #[error("{0:?} is not a member of session {1}")]
+    UnknownMember(PeerId, SessionId),
+    #[error("you were kicked from session {0}, ask its owner for an invite")]
+    Kicked(SessionId),
+    #[error("only invited members may build in session {0}")]
+    NotInvited(SessionId),
+    #[error("invalid session name `{0}`")]
+    InvalidName(String),
+    #[error("too many operations ({count}, at most {max})")]
+    TooManyOperations { count: usize, max: usize },
+    #[error("none of the operations could be applied")]
+    NothingApplied,
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error(transparent)]
+    Blueprint(#[from] BlueprintError),
+    #[error(transparent)]
+    PermissionDenied(#[from] PermissionDenied),
+}
+
+/// A vehicle that is built together by the members of the session.
+///
+/// The server holds the authoritative blueprint. Every applied batch of operations gets the
+/// next sequence number, so clients can apply the batches in order on top of a snapshot.
+#[derive(Debug, Clone)]
+pub struct EditorSession {
+    name: String,
+    owner: PeerId,
+    blueprint: Blueprint,
+    occupancy: Occupancy,
+    sequence: u64,
+    /// The [`lightyear::prelude::server::ClientOf`] entities of the members.
+    members: BTreeMap<Entity, PeerId>,
+    /// The invited players besides the owner, they may build.
+    builders: HashSet<PeerId>,
+    /// The players that may not join until they are invited.
+    kicked: HashSet<PeerId>,
+}
+
+impl EditorSession {
+    /// # Errors
+    ///
+    /// This function will return an error if `name` is empty or too long or if a block
+    /// uses a part that is not in `registry`.
+    pub fn new(
+        name: &str,
+        owner: PeerId,
+        blueprint: Blueprint,
+        registry: &PartRegistry,
+    ) -> Result<Self, SessionError> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(SessionError::InvalidName(name.to_string()));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            owner,
+            occupancy: Occupancy::from_blueprint(&blueprint, registry)?,
+            blueprint,
+            sequence: 0,
+            members: BTreeMap::new(),
+            builders: HashSet::new(),
+            kicked: HashSet::new(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn owner(&self) -> PeerId {
+        self.owner
+    }
+
+    pub fn blueprint(&self) -> &Blueprint {
+        &self.blueprint
+    }
+
+    /// The sequence number of the last applied batch.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.members.keys().copied()
+    }
+
+    pub fn is_member(&self, client: Entity) -> bool {
+        self.members.contains_key(&client)
+    }
+
+    /// The entity of the member with the id `peer`.
+    pub fn member(&self, peer: PeerId) -> Option<Entity> {
+        self.members
+            .iter()
+            .find_map(|(entity, id)| (*id == peer).then_some(*entity))
+    }
+
+    /// Returns false if `client` already was a member.
+    pub fn join(&mut self, client: Entity, peer: PeerId) -> bool {
+        self.members.insert(client, peer).is_none()
+    }
+
+    /// Returns false if `client` was no member.
+    pub fn leave(&mut self, client: Entity) -> bool {
+        self.members.remove(&client).is_some()
+    }
+
+    /// Returns true if `peer` is the owner or was invited.
+    pub fn can_build(&self, peer: PeerId) -> bool {
+        peer == self.owner || self.builders.contains(&peer)
+    }
+
+    pub fn is_kicked(&self, peer: PeerId) -> bool {
+        self.kicked.contains(&peer)
+    }
+
+    /// Let `peer` join again and build.
+    pub fn invite(&mut self, peer: PeerId) {
+        self.kicked.remove(&peer);
+        if peer != self.owner {
+            self.builders.insert(peer);
+        }
+    }
+
+    /// Remove the member `peer` and keep it from joining again until it is invited.
+    /// Returns its entity.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `peer` is no member.
+    fn kick(&mut self, id: SessionId, peer: PeerId) -> Result<Entity, SessionError> {
+        let member = self
+            .member(peer)
+            .ok_or(SessionError::UnknownMember(peer, id))?;
+        self.leave(member);
+        self.builders.remove(&peer);
+        self.kicked.insert(peer);
+
+        Ok(member)
+    }
+
+    pub fn info(&self, id: SessionId) -> EditorSessionInfo {
+        EditorSessionInfo {
+            id,
+            name: self.name.clone(),
+            owner: self.owner,
+            members: self.members.values().copied().collect(),
+            builders: self.builders.iter().copied().collect(),
+        }
+    }
+
+    /// Validate and apply a batch of operations from a member.
+    ///
+    /// Invalid operations are skipped, the others are applied in order. Returns the new
+    /// sequence number and the applied operations.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the batch is too large or if nothing could be
+    /// applied. The sequence number does not change in that case.
+    pub fn apply(
+        &mut self,
+        operations: Vec<EditOperation>,
+        registry: &PartRegistry,
+    ) -> Result<(u64, Vec<EditOperation>), SessionError> {
+        if operations.len() > MAX_OPERATIONS_PER_REQUEST {
+            return Err(SessionError::TooManyOperations {
+                count: operations.len(),
+                max: MAX_OPERATIONS_PER_REQUEST,
+            });
+        }
+
+        let mut applied = Vec::with_capacity(operations.len());
+        for operation in operations {
+            if let Err(e) = self.apply_one(&operation, registry) {
+                debug!("Rejected edit {:?}: {}", operation, e);
+                continue;
+            }
+            applied.push(operation);
+        }
+
+        if applied.is_empty() {
+            return Err(SessionError::NothingApplied);
+        }
+        self.sequence += 1;
+
+        Ok((self.sequence, applied))
+    }
+
+    fn apply_one(
+        &mut self,
+        operation: &EditOperation,
+        registry: &PartRegistry,
+    ) -> Result<(), SessionError> {
+        self.check_limits(operation, registry)?;
+        operation.validate(&self.blueprint, &self.occupancy, registry)?;
+        let applied = operation.apply(&mut self.blueprint)?;
+
+        for block in &applied.removed {
+            self.occupancy.remove(block, registry)?;
+        }
+        for block in &applied.added {
+            self.occupancy.add(block, registry)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check the limits that keep a client from growing the session without bounds.
+    fn check_limits(
+        &self,
+        operation: &EditOperation,
+        registry: &PartRegistry,
+    ) -> Result<(), SessionError> {
+        match operation {
+            EditOperation::Place(block) => {
+                if self.blueprint.len() >= MAX_BLOCKS {
+                    return Err(LimitError::Blocks.into());
+                }
+                check_block(block, registry)?;
+            }
+            EditOperation::Paint {
+                color: Some(color), ..
+            } => check_length("color", color, MAX_TEXT_LENGTH)?,
+            EditOperation::SetProperty {
+                position,
+                key,
+                value,
+            } => {
+                check_property(key, value.as_ref())?;
+                if value.is_some()
+                    && let Some(block) = self.blueprint.get(*position)
+                    && !block.properties.contains_key(key)
+                    && block.properties.len() >= MAX_PROPERTIES
+                {
+                    return Err(LimitError::Properties.into());
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Every building session on the server.
+///
+/// A client is a member of at most one session. Sessions are closed once their last
+/// member left.
+#[derive(Debug, Default, Resource)]
+pub struct EditorSessions {
+    sessions: BTreeMap<SessionId, EditorSession>,
+    next_id: SessionId,
+}
+
+impl EditorSessions {
+    /// Add a session and return its id.
+    pub fn create(&mut self, session: EditorSession) -> SessionId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.sessions.insert(id, session);
+
+        id
+    }
+
+    pub fn get(&self, id: SessionId) -> Result<&EditorSession, SessionError> {
+        self.sessions
+            .get(&id)
+            .ok_or(SessionError::UnknownSession(id))
+    }
+
+    pub fn get_mut(&mut self, id: SessionId) -> Result<&mut EditorSession, SessionError> {
+        self.sessions
+            .get_mut(&id)
+            .ok_or(SessionError::UnknownSession(id))
+    }
+
+    /// Make `client` a member of the session `id` and leave its previous session.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the session does not exist or `peer` was
+    /// kicked from it.
+    pub fn join(
+        &mut self,
+        id: SessionId,
+        client: Entity,
+        peer: PeerId,
+    ) -> Result<&mut EditorSession, SessionError> {
+        match self.sessions.get(&id) {
+            None => return Err(SessionError::UnknownSession(id)),
+            Some(session) if session.is_kicked(peer) => return Err(SessionError::Kicked(id)),
+            Some(_) => {}
+        }
+
+        if self.session_of(client).is_some_and(|current| current != id) {
+            self.leave_all(client);
+        }
+
+        let session = self.get_mut(id)?;
+        session.join(client, peer);
+
+        Ok(session)
+    }
+
+    /// Remove `client` from the session `id`. Returns false if it was no member.
+    pub fn leave(&mut self, id: SessionId, client: Entity) -> bool {
+        let Some(session) = self.sessions.get_mut(&id) else {
+            return false;
+        };
+
+        let left = session.leave(client);
+        if left && session.members.is_empty() {
+            self.sessions.remove(&id);
+        }
+
+        left
+    }
+
+    /// Remove the member `peer` from the session `id` until it is invited again and return
+    /// its entity.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the session does not exist or `peer` is no
+    /// member.
+    pub fn kick(&mut self, id: SessionId, peer: PeerId) -> Result<Entity, SessionError> {
+        let session = self.get_mut(id)?;
+        let member = session.kick(id, peer)?;
+        if session.members.is_empty() {
+            self.sessions.remove(&id);
+        }
+
+        Ok(member)
+    }
+
+    /// Remove `client` from every session, e.g. after it disconnected.
+    pub fn leave_all(&mut self, client: Entity) {
+        let ids: Vec<_> = self.sessions.keys().copied().collect();
+        for id in ids {
+            self.leave(id, client);
+        }
+    }
+
+    /// The session `client` is a member of.
+    pub fn session_of(&self, client: Entity) -> Option<SessionId> {
+        self.sessions
+            .iter()
+            .find_map(|(id, session)| session.is_member(client).then_some(*id))
+    }
+
+    pub fn list(&self) -> Vec<EditorSessionInfo> {
+        self.sessions
+            .iter()
+            .map(|(id, session)| session.info(*id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod session_test {
+    use bevy::prelude::*;
+    use common::{
+        part_registry::PartRegistry,
+        vehicle::{
+            Block, Blueprint, MAX_EXTENT, PropertyValue,
+            edit::{EditOperation, apply_all},
+        },
+    };
+    use lightyear::prelude::PeerId;
+
+    use crate::{
+        editor::session::{
+            EditorSession, EditorSessions, MAX_OPERATIONS_PER_REQUEST, SessionError,
+        },
+        game::vehicle::limits::{
+            LimitError, MAX_BLOCKS, MAX_NAME_LENGTH, MAX_PROPERTIES, MAX_PROPERTY_KEY_LENGTH,
+            MAX_TEXT_LENGTH,
+        },
+    };
+
+    const OWNER: PeerId = PeerId::Netcode(1);
+
+    fn session() -> EditorSession {
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+
+        EditorSession::new("test", OWNER, blueprint, &PartRegistry::builtin()).unwrap()
+    }
+
+    #[test]
+    fn apply_test() {
+        let registry = PartRegistry::builtin();
+        let mut session = session();
+
+        let (sequence, applied) = session
+            .apply(
+                vec![
+                    EditOperation::Place(Block::new(IVec3::X, "cube")),
+                    // Unknown parts and occupied cells are skipped.
+                    EditOperation::Place(Block::new(IVec3::Y, "missing")),
+                    EditOperation::Place(Block::new(IVec3::X, "wood_cube")),
+                    EditOperation::Remove(IVec3::ZERO),
+                ],
+                &registry,
+            )
+            .unwrap();
+
+        assert_eq!(sequence, 1);
+        assert_eq!(applied.len(), 2);
+        assert_eq!(session.blueprint().len(), 1);
+        assert!(session.blueprint().contains(IVec3::X));
+
+        assert!(matches!(
+            session.apply(vec![EditOperation::Remove(IVec3::ZERO)], &registry),
+            Err(SessionError::NothingApplied)
+        ));
+        assert_eq!(session.sequence(), 1);
+    }
+
+    #[test]
+    fn too_many_operations_test() {
+        let operations = vec![EditOperation::Remove(IVec3::ZERO); MAX_OPERATIONS_PER_REQUEST + 1];
+
+        assert!(matches!(
+            session().apply(operations, &PartRegistry::builtin()),
+            Err(SessionError::TooManyOperations { .. })
+        ));
+    }
+
+    #[test]
+    fn limits_test() {
+        let registry = PartRegistry::builtin();
+        let session = session();
+        let text = |length: usize| "a".repeat(length);
+        let set_property = |key: String, value: String| EditOperation::SetProperty {
+            position: IVec3::ZERO,
+            key,
+            value: Some(PropertyValue::Text(value)),
+        };
+
+        assert!(matches!(
+            session.check_limits(
+                &EditOperation::Place(Block::new(IVec3::new(0, MAX_EXTENT + 1, 0), "cube")),
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Extent))
+        ));
+        assert!(matches!(
+            session.check_limits(
+                &EditOperation::Place(Block::new(IVec3::splat(i32::MIN), "cube")),
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Extent))
+        ));
+        // The 40 blocks wide seat would reach past the extent.
+        assert!(matches!(
+            session.check_limits(
+                &EditOperation::Place(Block::new(IVec3::new(MAX_EXTENT - 38, 0, 0), "seat")),
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Extent))
+        ));
+        assert!(matches!(
+            session.check_limits(
+                &EditOperation::Paint {
+                    position: IVec3::ZERO,
+                    color: Some(text(MAX_TEXT_LENGTH + 1)),
+                },
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Length("color", _)))
+        ));
+        assert!(matches!(
+            session.check_limits(
+                &set_property(text(MAX_PROPERTY_KEY_LENGTH + 1), text(1)),
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Length("property key", _)))
+        ));
+        assert!(matches!(
+            session.check_limits(&set_property(text(1), text(MAX_TEXT_LENGTH + 1)), &registry),
+            Err(SessionError::Limit(LimitError::Length("property value", _)))
+        ));
+        assert!(matches!(
+            session.check_limits(
+                &EditOperation::Place(
+                    Block::new(IVec3::X, "cube").with_property(
+                        text(MAX_PROPERTY_KEY_LENGTH + 1),
+                        PropertyValue::Bool(true)
+                    )
+                ),
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Length("property key", _)))
+        ));
+        assert!(
+            session
+                .check_limits(
+                    &set_property(text(MAX_PROPERTY_KEY_LENGTH), text(MAX_TEXT_LENGTH)),
+                    &registry
+                )
+                .is_ok()
+        );
+
+        // Too many properties, on a new block and on an existing one.
+        let mut block = Block::new(IVec3::ZERO, "cube");
+        for index in 0..MAX_PROPERTIES {
+            block = block.with_property(index.to_string(), PropertyValue::Bool(true));
+        }
+        let mut full = block.clone();
+        full.position = IVec3::X;
+        assert!(matches!(
+            session.check_limits(
+                &EditOperation::Place(full.with_property("last", PropertyValue::Bool(true))),
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Properties))
+        ));
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(block).unwrap();
+        let session =
+            EditorSession::new("test", OWNER, blueprint, &PartRegistry::builtin()).unwrap();
+        assert!(matches!(
+            session.check_limits(&set_property("last".to_string(), text(1)), &registry),
+            Err(SessionError::Limit(LimitError::Properties))
+        ));
+        assert!(
+            session
+                .check_limits(&set_property("0".to_string(), text(1)), &registry)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn too_many_blocks_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = Blueprint::new("test");
+        for i in 0..MAX_BLOCKS as i32 {
+            blueprint
+                .add(Block::new(
+                    IVec3::new(i % 100, i / 10_000, (i / 100) % 100),
+                    "cube",
+                ))
+                .unwrap();
+        }
+        let mut session = EditorSession::new("test", OWNER, blueprint, &registry).unwrap();
+
+        assert!(matches!(
+            session.apply(
+                vec![EditOperation::Place(Block::new(IVec3::NEG_ONE, "cube"))],
+                &registry
+            ),
+            Err(SessionError::NothingApplied)
+        ));
+        assert!(matches!(
+            session.check_limits(
+                &EditOperation::Place(Block::new(IVec3::NEG_ONE, "cube")),
+                &registry
+            ),
+            Err(SessionError::Limit(LimitError::Blocks))
+        ));
+        // Everything else still works on a full vehicle.
+        assert!(
+            session
+                .apply(vec![EditOperation::Remove(IVec3::ZERO)], &registry)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn late_join_test() {
+        let registry = PartRegistry::builtin();
+        let mut session = session();
+        let mut early = session.blueprint().clone();
+
+        let batches = [
+            vec![EditOperation::Place(Block::new(IVec3::X, "cube"))],
+            vec![EditOperation::Place(Block::new(IVec3::Y, "wood_cube"))],
+            vec![EditOperation::Remove(IVec3::ZERO)],
+            vec![EditOperation::Place(Block::new(IVec3::Z, "cube"))],
+        ];
+        let mut deltas = Vec::new();
+        let mut late = None;
+        for (index, batch) in batches.into_iter().enumerate() {
+            deltas.push(session.apply(batch, &registry).unwrap());
+            if index == 1 {
+                late = Some((session.sequence(), session.blueprint().clone()));
+            }
+        }
+
+        // An early member applies every delta.
+        for (_, operations) in &deltas {
+            apply_all(operations.clone(), &mut early);
+        }
+        assert_eq!(&early, session.blueprint());
+
+        // A late member applies the deltas after its snapshot.
+        let (snapshot_sequence, mut late) = late.unwrap();
+        for (sequence, operations) in &deltas {
+            if *sequence > snapshot_sequence {
+                apply_all(operations.clone(), &mut late);
+            }
+        }
+        assert_eq!(&late, session.blueprint());
+    }
+
+    #[test]
+    fn members_test() {
+        let mut session = session();
+        let client = Entity::from_raw_u32(1).unwrap();
+
+        assert!(session.join(client, OWNER));
+        assert!(!session.join(client, OWNER));
+        assert!(session.is_member(client));
+        assert_eq!(session.member(OWNER), Some(client));
+        assert!(session.leave(client));
+        assert!(!session.is_member(client));
+        assert_eq!(session.member(OWNER), None);
+    }
+
+    #[test]
+    fn name_test() {
+        let registry = PartRegistry::builtin();
+        let new = |name: &str| EditorSession::new(name, OWNER, Blueprint::new(name), &registry);
+
+        assert_eq!(new("  Boat ").unwrap().name(), "Boat");
+        assert!(matches!(new("   "), Err(SessionError::InvalidName(_))));
+        assert!(matches!(
+            new(&"a".repeat(MAX_NAME_LENGTH + 1)),
+            Err(SessionError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn sessions_test() {
+        let mut sessions = EditorSessions::default();
+        let first = sessions.create(session());
+        let second = sessions.create(session());
+        assert_ne!(first, second);
+
+        let owner = Entity::from_raw_u32(1).unwrap();
+        let other = Entity::from_raw_u32(2).unwrap();
+        sessions.join(first, owner, OWNER).unwrap();
+        sessions.join(first, other, PeerId::Netcode(2)).unwrap();
+        assert_eq!(sessions.list()[0].members.len(), 2);
+
+        // Joining another session leaves the current one.
+        sessions.join(second, other, PeerId::Netcode(2)).unwrap();
+        assert_eq!(sessions.session_of(other), Some(second));
+        assert_eq!(sessions.list()[0].members, vec![OWNER]);
+
+        // Sessions without members are closed.
+        assert!(sessions.leave(first, owner));
+        sessions.leave_all(other);
+        assert!(sessions.list().is_empty());
+        assert!(matches!(
+            sessions.join(first, owner, OWNER),
+            Err(SessionError::UnknownSession(_))
+        ));
+    }
+
+    #[test]
+    fn kick_test() {
+        let mut sessions = EditorSessions::default();
+        let id = sessions.create(session());
+        let owner = Entity::from_raw_u32(1).unwrap();
+        let other = Entity::from_raw_u32(2).unwrap();
+        let peer = PeerId::Netcode(2);
+        sessions.join(id, owner, OWNER).unwrap();
+        sessions.join(id, other, peer).unwrap();
+
+        // Members only watch until they are invited.
+        let session = sessions.get_mut(id).unwrap();
+        assert!(session.can_build(OWNER));
+        assert!(!session.can_build(peer));
+        session.invite(peer);
+        assert!(session.can_build(peer));
+        assert_eq!(sessions.list()[0].builders, vec![peer]);
+
+        // A kicked member can not join again until it is invited.
+        assert_eq!(sessions.kick(id, peer).unwrap(), other);
+        assert!(matches!(
+            sessions.kick(id, peer),
+            Err(SessionError::UnknownMember(_, _))
+        ));
+        assert!(matches!(
+            sessions.join(id, other, peer),
+            Err(SessionError::Kicked(_))
+        ));
+        let session = sessions.get_mut(id).unwrap();
+        assert!(!session.can_build(peer));
+        session.invite(peer);
+        sessions.join(id, other, peer).unwrap();
+        assert!(sessions.get(id).unwrap().can_build(peer));
+    }
+}
@@ -1,13 +1,38 @@
 use bevy::prelude::*;
+use common::{
+    vehicle::{
+        aerodynamics::AerodynamicsPlugin, joint::VehicleJointPlugin, propulsion::PropulsionPlugin,
+        signal::SignalPlugin,
+    },
+    world::water::WaterPlugin,
+};
 
-use crate::game::world::WorldPlugin;
+use crate::game::{
+    player::PlayerPlugin, save::SavePlugin, seat::SeatPlugin, vehicle::VehicleSpawnPlugin,
+    world::WorldPlugin,
+};
 
+mod player;
+pub mod save;
+mod seat;
+pub mod vehicle;
 mod world;
 
 pub struct GamePlugins;
 
 impl Plugin for GamePlugins {
     fn build(&self, app: &mut App) {
-        app.add_plugins(WorldPlugin);
+        app.add_plugins((
+            WorldPlugin,
+            PlayerPlugin,
+            VehicleSpawnPlugin,
+            SeatPlugin,
+            SignalPlugin,
+            VehicleJointPlugin,
+            PropulsionPlugin,
+            AerodynamicsPlugin,
+            WaterPlugin,
+            SavePlugin,
+        ));
     }
 }
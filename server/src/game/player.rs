@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+use common::character::{CharacterController, Stance};
+use lightyear::prelude::{
+    Connected, ControlledBy, InterpolationTarget, Lifetime, NetworkTarget, PredictionTarget,
+    RemoteId, Replicate, server::ClientOf,
+};
+use log::info;
+
+/// Where new characters appear.
+const SPAWN_POINT: Vec3 = Vec3::new(0.0, 2.0, 0.0);
+
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(spawn_character_observer);
+    }
+}
+
+/// Give every connected client an on-foot character.
+///
+/// The owner predicts it, everyone else interpolates it. It is despawned with the
+/// connection.
+fn spawn_character_observer(
+    trigger: On<Add, Connected>,
+    mut commands: Commands,
+    clients: Query<&RemoteId, With<ClientOf>>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(remote_id) = clients.get(entity) else {
+        return;
+    };
+
+    let controller = CharacterController::default();
+    commands.spawn((
+        Name::new("Character"),
+        controller.collider(Stance::Standing),
+        controller,
+        Transform::from_translation(SPAWN_POINT),
+        Replicate::to_clients(NetworkTarget::All),
+        PredictionTarget::to_clients(NetworkTarget::Single(remote_id.0)),
+        InterpolationTarget::to_clients(NetworkTarget::AllExceptSingle(remote_id.0)),
+        ControlledBy {
+            owner: entity,
+            lifetime: Lifetime::SessionBased,
+        },
+    ));
+    info!("Spawned character for {:?}", remote_id.0);
+}
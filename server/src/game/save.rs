@@ -0,0 +1,416 @@
+use std::{collections::HashMap, io, path::PathBuf, time::Duration};
+
+use avian3d::prelude::{AngularVelocity, LinearVelocity};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use common::{
+    Paths,
+    character::CharacterController,
+    save_system::{SaveSystem, SaveSystemError},
+    vehicle::{Blueprint, VehicleBlueprint, joint::VehicleSubBody, propulsion::VehicleFuel},
+    world::terrain::Terrain,
+};
+use lightyear::prelude::{
+    Connected, ControlledBy, Disconnected, PeerId, RemoteId, server::ClientOf,
+};
+use log::{error, info, warn};
+use protocol::components::SpawnedVehicle;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    game::vehicle::{VehicleIds, vehicle_bundle},
+};
+
+/// How often the world is saved while the server runs.
+const SAVE_INTERVAL: Duration = Duration::from_secs(300);
+/// The file in the save directory the world is written to.
+const WORLD_FILE: &str = "world.toml";
+
+/// Loads the world save at startup and writes it periodically and on exit.
+///
+/// Players get their character position and vehicles back when they reconnect. Until
+/// then, and after they leave, they are kept in [`OfflinePlayers`].
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SaveWorld>();
+        app.init_resource::<OfflinePlayers>();
+        app.insert_resource(SaveTimer(Timer::new(SAVE_INTERVAL, TimerMode::Repeating)));
+
+        app.add_observer(store_player_observer);
+
+        app.add_systems(PreStartup, load_world_system);
+        app.add_systems(Update, (restore_players_system, save_timer_system));
+        app.add_systems(Last, save_world_system);
+    }
+}
+
+/// Write the world save at the end of the frame.
+#[derive(Debug, Message)]
+pub struct SaveWorld;
+
+/// Everything the server persists between restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldSave {
+    pub seed: u64,
+    #[serde(default)]
+    pub players: Vec<PlayerSave>,
+}
+
+impl WorldSave {
+    /// The path of the save called `name`.
+    pub fn path(name: &str) -> PathBuf {
+        PathBuf::from(Paths::GameSave(name.to_string())).join(WORLD_FILE)
+    }
+
+    /// Save the world as `name`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the save can not be written.
+    pub fn save(&self, name: &str) -> Result<(), SaveSystemError> {
+        SaveSystem::save(Self::path(name), self)
+    }
+
+    /// Load the world saved as `name`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the save does not exist or is invalid.
+    pub fn load(name: &str) -> Result<Self, SaveSystemError> {
+        SaveSystem::load(Self::path(name))
+    }
+}
+
+/// The character and vehicles of a player.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSave {
+    #[serde(with = "common::save_system::peer_id")]
+    pub id: PeerId,
+    pub position: Vec3,
+    #[serde(default)]
+    pub vehicles: Vec<VehicleSave>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VehicleSave {
+    pub blueprint: Blueprint,
+    /// The state of every body, the first one is the vehicle entity.
+    pub bodies: Vec<BodyState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<VehicleFuel>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BodyState {
+    pub transform: Transform,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+}
+
+impl BodyState {
+    pub fn components(&self) -> (Transform, LinearVelocity, AngularVelocity) {
+        (
+            self.transform,
+            LinearVelocity(self.linear_velocity),
+            AngularVelocity(self.angular_velocity),
+        )
+    }
+}
+
+/// The saved bodies of a restored vehicle, applied when its sub-bodies are spawned.
+#[derive(Debug, Clone, Component)]
+pub struct RestoredBodies(pub Vec<BodyState>);
+
+/// The saved players that are not connected.
+#[derive(Debug, Default, Resource)]
+pub struct OfflinePlayers(HashMap<PeerId, PlayerSave>);
+
+#[derive(Debug, Resource)]
+struct SaveTimer(Timer);
+
+type VehicleData = (
+    Entity,
+    &'static SpawnedVehicle,
+    &'static VehicleBlueprint,
+    Option<&'static VehicleFuel>,
+);
+type BodyData = (
+    &'static Transform,
+    &'static LinearVelocity,
+    &'static AngularVelocity,
+);
+
+/// The players and vehicles in the world.
+#[derive(SystemParam)]
+struct WorldState<'w, 's> {
+    clients: Query<'w, 's, (Entity, &'static RemoteId), With<ClientOf>>,
+    characters:
+        Query<'w, 's, (&'static Transform, &'static ControlledBy), With<CharacterController>>,
+    vehicles: Query<'w, 's, VehicleData>,
+    bodies: Query<'w, 's, BodyData>,
+    sub_bodies: Query<'w, 's, (Entity, &'static VehicleSubBody)>,
+}
+
+impl WorldState<'_, '_> {
+    /// The save of the player `id` connected as `client`, if it has a character.
+    fn player(&self, client: Entity, id: PeerId) -> Option<PlayerSave> {
+        let (transform, _) = self
+            .characters
+            .iter()
+            .find(|(_, controlled_by)| controlled_by.owner == client)?;
+
+        let vehicles = self
+            .vehicles
+            .iter()
+            .filter(|(_, vehicle, _, _)| vehicle.owner == id)
+            .map(|(entity, _, blueprint, fuel)| {
+                let mut sub_bodies: Vec<_> = self
+                    .sub_bodies
+                    .iter()
+                    .filter(|(_, sub_body)| sub_body.vehicle == entity)
+                    .map(|(sub_entity, sub_body)| (sub_body.body, sub_entity))
+                    .collect();
+                sub_bodies.sort_by_key(|(body, _)| *body);
+
+                let bodies = std::iter::once(entity)
+                    .chain(sub_bodies.into_iter().map(|(_, sub_entity)| sub_entity))
+                    .filter_map(|body| self.bodies.get(body).ok())
+                    .map(|(transform, linear, angular)| BodyState {
+                        transform: *transform,
+                        linear_velocity: linear.0,
+                        angular_velocity: angular.0,
+                    })
+                    .collect();
+
+                VehicleSave {
+                    blueprint: blueprint.0.clone(),
+                    bodies,
+                    fuel: fuel.copied(),
+                }
+            })
+            .collect();
+
+        Some(PlayerSave {
+            id,
+            position: transform.translation,
+            vehicles,
+        })
+    }
+
+    /// The saves of every connected player.
+    fn players(&self) -> impl Iterator<Item = PlayerSave> + '_ {
+        self.clients
+            .iter()
+            .filter_map(|(client, remote_id)| self.player(client, remote_id.0))
+    }
+}
+
+fn load_world_system(
+    mut commands: Commands,
+    config: Res<Config>,
+    mut offline: ResMut<OfflinePlayers>,
+) -> Result<(), BevyError> {
+    if config.fresh {
+        info!("Starting a fresh world `{}`", config.save);
+        return Ok(());
+    }
+
+    let save = match WorldSave::load(&config.save) {
+        Ok(save) => save,
+        Err(SaveSystemError::IoError(e)) if e.kind() == io::ErrorKind::NotFound => {
+            info!(
+                "There is no save `{}` yet, starting a new world",
+                config.save
+            );
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(format!(
+                "Failed to load the save `{}`, start with --fresh to replace it: {e}",
+                config.save
+            )
+            .into());
+        }
+    };
+
+    info!(
+        "Loaded the save `{}` with {} players",
+        config.save,
+        save.players.len()
+    );
+    if config.seed.is_some_and(|seed| seed != save.seed) {
+        warn!(
+            "Ignoring the configured seed, the save `{}` uses seed {}",
+            config.save, save.seed
+        );
+    }
+    commands.insert_resource(Terrain { seed: save.seed });
+    offline.0 = save
+        .players
+        .into_iter()
+        .map(|player| (player.id, player))
+        .collect();
+
+    Ok(())
+}
+
+/// Keep the state of leaving players before their entities are despawned.
+fn store_player_observer(
+    trigger: On<Add, Disconnected>,
+    state: WorldState,
+    mut offline: ResMut<OfflinePlayers>,
+) {
+    let client = trigger.event().entity;
+    let Ok((_, remote_id)) = state.clients.get(client) else {
+        return;
+    };
+
+    if let Some(player) = state.player(client, remote_id.0) {
+        offline.0.insert(remote_id.0, player);
+    }
+}
+
+/// Move the characters of returning players to their saved position and respawn their
+/// vehicles.
+fn restore_players_system(
+    mut commands: Commands,
+    mut offline: ResMut<OfflinePlayers>,
+    mut ids: ResMut<VehicleIds>,
+    clients: Query<(Entity, &RemoteId), Added<Connected>>,
+    mut characters: Query<(&mut Transform, &ControlledBy), With<CharacterController>>,
+) {
+    for (client, remote_id) in clients.iter() {
+        let Some(player) = offline.0.remove(&remote_id.0) else {
+            continue;
+        };
+
+        for (mut transform, _) in characters
+            .iter_mut()
+            .filter(|(_, controlled_by)| controlled_by.owner == client)
+        {
+            transform.translation = player.position;
+        }
+
+        let count = player.vehicles.len();
+        for vehicle in player.vehicles {
+            let Some(state) = vehicle.bodies.first().copied() else {
+                continue;
+            };
+
+            let mut entity_commands = commands.spawn((
+                vehicle_bundle(
+                    ids.next(),
+                    remote_id.0,
+                    client,
+                    vehicle.blueprint,
+                    state.transform,
+                ),
+                LinearVelocity(state.linear_velocity),
+                AngularVelocity(state.angular_velocity),
+                RestoredBodies(vehicle.bodies),
+            ));
+            if let Some(fuel) = vehicle.fuel {
+                entity_commands.insert(fuel);
+            }
+        }
+        info!("Restored client {:?} with {} vehicles", remote_id.0, count);
+    }
+}
+
+fn save_timer_system(
+    mut timer: ResMut<SaveTimer>,
+    time: Res<Time>,
+    mut requests: MessageWriter<SaveWorld>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        requests.write(SaveWorld);
+    }
+}
+
+/// Write the world save when requested and when the server exits.
+fn save_world_system(
+    mut requests: MessageReader<SaveWorld>,
+    mut exits: MessageReader<AppExit>,
+    config: Res<Config>,
+    terrain: Option<Res<Terrain>>,
+    offline: Res<OfflinePlayers>,
+    state: WorldState,
+) {
+    let requested = requests.read().count() > 0;
+    let exiting = exits.read().count() > 0;
+    if !requested && !exiting {
+        return;
+    }
+    let Some(terrain) = terrain else {
+        return;
+    };
+
+    let save = WorldSave {
+        seed: terrain.seed,
+        players: offline.0.values().cloned().chain(state.players()).collect(),
+    };
+    match save.save(&config.save) {
+        Ok(()) => info!(
+            "Saved the world `{}` with {} players",
+            config.save,
+            save.players.len()
+        ),
+        Err(e) => error!("Failed to save the world `{}`: {}", config.save, e),
+    }
+}
+
+#[cfg(test)]
+mod save_test {
+    use std::fs;
+
+    use bevy::prelude::*;
+    use common::{
+        save_system::SaveSystem,
+        vehicle::{Block, Blueprint, propulsion::VehicleFuel},
+    };
+    use lightyear::prelude::PeerId;
+
+    use crate::game::save::{BodyState, PlayerSave, VehicleSave, WorldSave};
+
+    #[test]
+    fn save_load_test() {
+        let path = "./testworld";
+
+        let mut blueprint = Blueprint::new("boat");
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        let body = BodyState {
+            transform: Transform::from_xyz(1.0, 2.0, 3.0).with_rotation(Quat::from_rotation_y(1.0)),
+            linear_velocity: Vec3::new(0.5, 0.0, -2.0),
+            angular_velocity: Vec3::Y,
+        };
+        let save = WorldSave {
+            seed: u64::from(u32::MAX),
+            players: vec![
+                PlayerSave {
+                    // Larger than any TOML integer.
+                    id: PeerId::Netcode(u64::MAX),
+                    position: Vec3::new(4.0, 2.0, -8.0),
+                    vehicles: vec![VehicleSave {
+                        blueprint,
+                        bodies: vec![body, body],
+                        fuel: Some(VehicleFuel::full(50.0)),
+                    }],
+                },
+                PlayerSave {
+                    id: PeerId::Netcode(2),
+                    position: Vec3::ZERO,
+                    vehicles: Vec::new(),
+                },
+            ],
+        };
+
+        SaveSystem::save(path, &save).unwrap();
+        let loaded: WorldSave = SaveSystem::load(path).unwrap();
+
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(save, loaded);
+    }
+}
@@ -0,0 +1,145 @@
+use avian3d::prelude::{SpatialQuery, SpatialQueryFilter};
+use bevy::prelude::*;
+use common::{
+    character::{CharacterController, Stance},
+    part_registry::PartRegistry,
+    vehicle::{
+        VehicleBlueprint,
+        seat::{Seated, exit_position, nearest_free_seat, seat_at},
+    },
+};
+use lightyear::prelude::{ControlledBy, MessageReceiver, MessageSender, server::ClientOf};
+use log::info;
+use protocol::{
+    channels::ReliableChannel,
+    messages::{EnterSeat, ExitSeat, VehicleRejected},
+};
+
+pub struct SeatPlugin;
+
+impl Plugin for SeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (enter_seat_system, exit_seat_system).chain());
+    }
+}
+
+/// Seat the character of the client on the closest free seat.
+fn enter_seat_system(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut MessageReceiver<EnterSeat>), With<ClientOf>>,
+    mut rejected_senders: Query<&mut MessageSender<VehicleRejected>, With<ClientOf>>,
+    characters: Query<(Entity, &ControlledBy, &Transform, Has<Seated>), With<CharacterController>>,
+    seated: Query<&Seated>,
+    vehicles: Query<(Entity, &VehicleBlueprint, &Transform)>,
+    registry: Res<PartRegistry>,
+) {
+    // Seats taken this frame are not in the query yet.
+    let mut occupied: Vec<_> = seated
+        .iter()
+        .map(|seated| (seated.vehicle, seated.seat))
+        .collect();
+
+    for (client, mut receiver) in clients.iter_mut() {
+        if receiver.receive().count() == 0 {
+            continue;
+        }
+
+        let Some((character, _, transform, is_seated)) = characters
+            .iter()
+            .find(|(_, controlled_by, _, _)| controlled_by.owner == client)
+        else {
+            continue;
+        };
+        if is_seated {
+            continue;
+        }
+
+        let seat = nearest_free_seat(
+            vehicles
+                .iter()
+                .map(|(entity, blueprint, transform)| (entity, &blueprint.0, transform)),
+            &registry,
+            &occupied,
+            transform.translation,
+        );
+        let Some((vehicle, seat)) = seat else {
+            reject(
+                &mut rejected_senders,
+                client,
+                "there is no free seat in reach",
+            );
+            continue;
+        };
+
+        info!("Character {} sat down in vehicle {}", character, vehicle);
+        occupied.push((vehicle, seat));
+        commands.entity(character).insert(Seated { vehicle, seat });
+    }
+}
+
+/// Get the character of the client up next to its seat.
+fn exit_seat_system(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut MessageReceiver<ExitSeat>), With<ClientOf>>,
+    mut rejected_senders: Query<&mut MessageSender<VehicleRejected>, With<ClientOf>>,
+    mut characters: Query<(
+        Entity,
+        &ControlledBy,
+        &Seated,
+        &CharacterController,
+        &mut Transform,
+    )>,
+    vehicles: Query<(&VehicleBlueprint, &Transform), Without<Seated>>,
+    spatial_query: SpatialQuery,
+    registry: Res<PartRegistry>,
+) {
+    for (client, mut receiver) in clients.iter_mut() {
+        if receiver.receive().count() == 0 {
+            continue;
+        }
+
+        let Some((character, _, seated, controller, mut transform)) = characters
+            .iter_mut()
+            .find(|(_, controlled_by, _, _, _)| controlled_by.owner == client)
+        else {
+            continue;
+        };
+
+        let seat = vehicles
+            .get(seated.vehicle)
+            .ok()
+            .and_then(|(blueprint, vehicle)| {
+                seat_at(blueprint, seated.seat, &registry).map(|seat| vehicle.mul_transform(seat))
+            });
+        // Without a seat the character stands up where it is on its own.
+        let Some(seat) = seat else {
+            continue;
+        };
+
+        let filter = SpatialQueryFilter::from_excluded_entities([character]);
+        let collider = controller.collider(Stance::Standing);
+        let Some(position) = exit_position(&spatial_query, &seat, &collider, &filter) else {
+            reject(&mut rejected_senders, client, "there is no room to get out");
+            continue;
+        };
+
+        info!(
+            "Character {} got up from vehicle {}",
+            character, seated.vehicle
+        );
+        transform.translation = position;
+        commands.entity(character).remove::<Seated>();
+    }
+}
+
+fn reject(
+    senders: &mut Query<&mut MessageSender<VehicleRejected>, With<ClientOf>>,
+    client: Entity,
+    reason: &str,
+) {
+    if let Ok(mut sender) = senders.get_mut(client) {
+        sender.send::<ReliableChannel>(VehicleRejected {
+            reason: reason.to_string(),
+        });
+    }
+}
@@ -0,0 +1,639 @@
+use avian3d::prelude::{
+    AngularVelocity, JointCollisionDisabled, LinearVelocity, NoAutoAngularInertia,
+    NoAutoCenterOfMass, NoAutoMass, RigidBody,
+};
+use bevy::prelude::*;
+use common::{
+    part_registry::PartRegistry,
+    vehicle::{
+        BLOCK_SIZE, Blueprint, BlueprintError, VehicleBlueprint,
+        joint::{VehicleBodies, VehicleJoint, VehicleSubBody, body_physics},
+        occupancy::Occupancy,
+        seat::VehicleControls,
+        signal::validate_wire,
+    },
+};
+use lightyear::prelude::{
+    ControlledBy, InterpolationTarget, Lifetime, MessageReceiver, MessageSender, NetworkTarget,
+    PeerId, RemoteId, Replicate, server::ClientOf,
+};
+use log::{error, info};
+use protocol::{
+    channels::ReliableChannel,
+    components::SpawnedVehicle,
+    messages::{DespawnVehicle, RespawnVehicle, SpawnVehicle, VehicleId, VehicleRejected},
+};
+use thiserror::Error;
+
+use crate::{
+    config::{
+        Config,
+        role::{Permission, PermissionDenied, Role},
+    },
+    game::{
+        save::RestoredBodies,
+        vehicle::limits::{LimitError, check_blueprint},
+    },
+};
+
+pub mod limits;
+
+pub const MAX_VEHICLES_PER_PLAYER: usize = 2;
+
+/// Where vehicles appear. The lowest block of a vehicle is placed on the point.
+const SPAWN_POINTS: [Vec3; 4] = [
+    Vec3::new(10.0, 0.0, 10.0),
+    Vec3::new(-10.0, 0.0, 10.0),
+    Vec3::new(10.0, 0.0, -10.0),
+    Vec3::new(-10.0, 0.0, -10.0),
+];
+/// A spawn point is blocked while a vehicle is closer to it than this.
+const SPAWN_CLEARANCE: f32 = 5.0;
+
+pub struct VehicleSpawnPlugin;
+
+impl Plugin for VehicleSpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VehicleIds>();
+
+        app.add_systems(
+            Update,
+            (
+                spawn_vehicle_system,
+                despawn_vehicle_system,
+                respawn_vehicle_system,
+            )
+                .chain(),
+        )
+        .add_observer(spawn_sub_bodies_observer);
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SpawnError {
+    #[error("the vehicle has no blocks")]
+    Empty,
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error("invalid vehicle: {0}")]
+    Blueprint(#[from] BlueprintError),
+    #[error("you already own {MAX_VEHICLES_PER_PLAYER} vehicles")]
+    TooManyVehicles,
+    #[error("every spawn point is blocked")]
+    NoFreeSpawnPoint,
+    #[error("vehicle {0} does not exist")]
+    UnknownVehicle(VehicleId),
+    #[error("vehicle {0} belongs to somebody else")]
+    NotOwner(VehicleId),
+    #[error(transparent)]
+    PermissionDenied(#[from] PermissionDenied),
+}
+
+/// The id of the last vehicle that was spawned.
+#[derive(Debug, Default, Resource)]
+pub struct VehicleIds(VehicleId);
+
+impl VehicleIds {
+    pub fn next(&mut self) -> VehicleId {
+        self.0 += 1;
+        self.0
+    }
+}
+
+/// A dynamic vehicle owned by the `client` entity of `owner`.
+///
+/// [`common::vehicle::VehiclePlugin`] adds the collider and mass properties, clients
+/// build the mesh from the replicated blueprint. Vehicles are despawned together with
+/// the connection of their owner.
+pub fn vehicle_bundle(
+    id: VehicleId,
+    owner: PeerId,
+    client: Entity,
+    blueprint: Blueprint,
+    transform: Transform,
+) -> impl Bundle {
+    (
+        Name::new("Vehicle"),
+        SpawnedVehicle { id, owner },
+        VehicleBlueprint(blueprint),
+        VehicleControls::default(),
+        RigidBody::Dynamic,
+        transform,
+        Replicate::to_clients(NetworkTarget::All),
+        InterpolationTarget::to_clients(NetworkTarget::All),
+        ControlledBy {
+            owner: client,
+            lifetime: Lifetime::SessionBased,
+        },
+    )
+}
+
+/// Check that a blueprint sent by a client can be spawned.
+///
+/// The blueprint has to stay within the [`limits`], every part has to exist, parts must not
+/// overlap and wires must connect existing ports with at most one wire per input, the same
+/// rules the editor enforces.
+///
+/// # Errors
+///
+/// This function will return an error describing why the blueprint can not be spawned.
+pub fn validate_blueprint(
+    blueprint: &Blueprint,
+    registry: &PartRegistry,
+) -> Result<(), SpawnError> {
+    if blueprint.is_empty() {
+        return Err(SpawnError::Empty);
+    }
+    check_blueprint(blueprint, registry)?;
+
+    let mut occupancy = Occupancy::default();
+    for block in blueprint.iter() {
+        if !occupancy.can_place(block, registry)? {
+            return Err(BlueprintError::Occupied(block.position).into());
+        }
+        occupancy.add(block, registry)?;
+    }
+
+    // Loading a blueprint already drops wires to missing blocks and into connected inputs.
+    for wire in blueprint.wires() {
+        validate_wire(blueprint, registry, wire)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the first spawn point that no vehicle in `occupied` is too close to.
+pub fn free_spawn_point(
+    points: &[Vec3],
+    occupied: impl IntoIterator<Item = Vec3> + Clone,
+) -> Option<Vec3> {
+    points.iter().copied().find(|point| {
+        occupied
+            .clone()
+            .into_iter()
+            .all(|position| position.distance(*point) >= SPAWN_CLEARANCE)
+    })
+}
+
+/// The transform that centers the blueprint above `point` and puts its lowest block on it.
+pub fn spawn_transform(blueprint: &Blueprint, point: Vec3) -> Transform {
+    let Some(bounds) = blueprint.bounds() else {
+        return Transform::from_translation(point);
+    };
+
+    let center = (bounds.min + bounds.max).as_vec3() * 0.5 * BLOCK_SIZE;
+    // Block positions are cell centers.
+    let bottom = (bounds.min.y as f32 - 0.5) * BLOCK_SIZE;
+
+    Transform::from_translation(point - Vec3::new(center.x, bottom, center.z))
+}
+
+/// Spawn validated vehicles as dynamic bodies owned by the requesting client.
+fn spawn_vehicle_system(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &RemoteId, &mut MessageReceiver<SpawnVehicle>)>,
+    mut rejected_senders: Query<&mut MessageSender<VehicleRejected>, With<ClientOf>>,
+    vehicles: Query<(&SpawnedVehicle, &Transform)>,
+    registry: Res<PartRegistry>,
+    mut ids: ResMut<VehicleIds>,
+    config: Res<Config>,
+) {
+    // Vehicles spawned this frame are not in the query yet.
+    let mut spawned = Vec::new();
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        for SpawnVehicle { blueprint } in receiver.receive() {
+            let owned = vehicles
+                .iter()
+                .map(|(vehicle, _)| vehicle.owner)
+                .chain(spawned.iter().map(|(owner, _)| *owner))
+                .filter(|owner| *owner == remote_id.0)
+                .count();
+            let occupied = vehicles
+                .iter()
+                .map(|(_, transform)| transform.translation)
+                .chain(spawned.iter().map(|(_, position)| *position));
+
+            let result = config
+                .roles
+                .role(remote_id.0)
+                .check(Permission::SpawnVehicles)
+                .map_err(SpawnError::from)
+                .and_then(|_| validate_blueprint(&blueprint, &registry))
+                .and_then(|_| {
+                    if owned >= MAX_VEHICLES_PER_PLAYER {
+                        return Err(SpawnError::TooManyVehicles);
+                    }
+
+                    free_spawn_point(&SPAWN_POINTS, occupied).ok_or(SpawnError::NoFreeSpawnPoint)
+                });
+
+            let point = match result {
+                Ok(point) => point,
+                Err(e) => {
+                    reject(&mut rejected_senders, entity, e);
+                    continue;
+                }
+            };
+
+            let id = ids.next();
+            let transform = spawn_transform(&blueprint, point);
+            info!(
+                "Client {:?} spawned vehicle {} `{}` with {} blocks",
+                remote_id.0,
+                id,
+                blueprint.name(),
+                blueprint.len()
+            );
+            spawned.push((remote_id.0, transform.translation));
+            commands.spawn(vehicle_bundle(
+                id,
+                remote_id.0,
+                entity,
+                blueprint,
+                transform,
+            ));
+        }
+    }
+}
+
+/// Spawn every body of a vehicle but the first and connect them with their joints.
+///
+/// The bodies start at the transform of the vehicle, they all share the origin of the
+/// blueprint. Restored vehicles put them back where they were saved.
+fn spawn_sub_bodies_observer(
+    trigger: On<Insert, VehicleBodies>,
+    mut commands: Commands,
+    vehicles: Query<
+        (
+            &VehicleBlueprint,
+            &VehicleBodies,
+            &Transform,
+            Option<&RestoredBodies>,
+        ),
+        With<SpawnedVehicle>,
+    >,
+    registry: Res<PartRegistry>,
+) {
+    let vehicle = trigger.event().entity;
+    let Ok((blueprint, bodies, transform, restored)) = vehicles.get(vehicle) else {
+        return;
+    };
+
+    let mut entities = vec![vehicle];
+    for body in 1..bodies.len() {
+        let mut entity_commands = commands.spawn((
+            Name::new("VehicleSubBody"),
+            VehicleSubBody { vehicle, body },
+            RigidBody::Dynamic,
+            *transform,
+            Replicate::to_clients(NetworkTarget::All),
+            InterpolationTarget::to_clients(NetworkTarget::All),
+        ));
+        if let Some(state) = restored.and_then(|restored| restored.0.get(body)) {
+            entity_commands.insert(state.components());
+        }
+
+        match body_physics(&bodies.body_blueprint(blueprint, body), &registry) {
+            Ok((collider, mass)) => {
+                entity_commands.insert((NoAutoMass, NoAutoCenterOfMass, NoAutoAngularInertia));
+                if let Some(collider) = collider {
+                    entity_commands.insert(collider);
+                }
+                if let Some(mass) = mass {
+                    entity_commands.insert(mass.to_components());
+                }
+            }
+            Err(e) => error!(
+                "Failed to build body {} of vehicle `{}`: {}",
+                body,
+                blueprint.name(),
+                e
+            ),
+        }
+        entities.push(entity_commands.id());
+    }
+
+    for (index, joint) in bodies.joints().iter().enumerate() {
+        let (base, rotor) = (entities[joint.base], entities[joint.rotor]);
+        let mut entity_commands = commands.spawn((
+            Name::new("VehicleJoint"),
+            VehicleJoint {
+                vehicle,
+                index,
+                base,
+                rotor,
+            },
+            // Neighbouring bodies touch each other at the joint.
+            JointCollisionDisabled,
+        ));
+        joint.insert_joint(&mut entity_commands, base, rotor);
+    }
+}
+
+fn despawn_vehicle_system(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &RemoteId, &mut MessageReceiver<DespawnVehicle>)>,
+    mut rejected_senders: Query<&mut MessageSender<VehicleRejected>, With<ClientOf>>,
+    vehicles: Query<(Entity, &SpawnedVehicle)>,
+    config: Res<Config>,
+) {
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        let role = config.roles.role(remote_id.0);
+        for DespawnVehicle { vehicle: id } in receiver.receive() {
+            match owned_vehicle(vehicles.iter(), id, remote_id, role) {
+                Ok(vehicle) => {
+                    info!("Client {:?} despawned vehicle {}", remote_id.0, id);
+                    commands.entity(vehicle).despawn();
+                }
+                Err(e) => reject(&mut rejected_senders, entity, e),
+            }
+        }
+    }
+}
+
+fn respawn_vehicle_system(
+    mut clients: Query<(Entity, &RemoteId, &mut MessageReceiver<RespawnVehicle>)>,
+    mut rejected_senders: Query<&mut MessageSender<VehicleRejected>, With<ClientOf>>,
+    vehicles: Query<(Entity, &SpawnedVehicle, &VehicleBlueprint)>,
+    mut transforms: Query<&mut Transform, With<SpawnedVehicle>>,
+    mut velocities: Query<(&mut LinearVelocity, &mut AngularVelocity), With<SpawnedVehicle>>,
+    mut sub_bodies: Query<
+        (
+            &VehicleSubBody,
+            &mut Transform,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+        ),
+        Without<SpawnedVehicle>,
+    >,
+    config: Res<Config>,
+) {
+    for (entity, remote_id, mut receiver) in clients.iter_mut() {
+        let role = config.roles.role(remote_id.0);
+        for RespawnVehicle { vehicle: id } in receiver.receive() {
+            let owned = vehicles
+                .iter()
+                .map(|(vehicle, spawned, _)| (vehicle, spawned));
+            let result = owned_vehicle(owned, id, remote_id, role).and_then(|vehicle| {
+                // The vehicle itself does not block the spawn point it is standing on.
+                let occupied = vehicles
+                    .iter()
+                    .filter(|(other, _, _)| *other != vehicle)
+                    .filter_map(|(other, _, _)| transforms.get(other).ok())
+                    .map(|transform| transform.translation);
+                let point = free_spawn_point(&SPAWN_POINTS, occupied)
+                    .ok_or(SpawnError::NoFreeSpawnPoint)?;
+
+                Ok((vehicle, point))
+            });
+
+            let (vehicle, point) = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    reject(&mut rejected_senders, entity, e);
+                    continue;
+                }
+            };
+
+            let Ok((_, _, blueprint)) = vehicles.get(vehicle) else {
+                continue;
+            };
+            let transform = spawn_transform(blueprint, point);
+            if let Ok(mut vehicle_transform) = transforms.get_mut(vehicle) {
+                *vehicle_transform = transform;
+            }
+            if let Ok((mut linear, mut angular)) = velocities.get_mut(vehicle) {
+                linear.0 = Vec3::ZERO;
+                angular.0 = Vec3::ZERO;
+            }
+            // The joints are back in their initial pose, the bodies share one origin.
+            for (_, mut body_transform, mut linear, mut angular) in sub_bodies
+                .iter_mut()
+                .filter(|(sub_body, _, _, _)| sub_body.vehicle == vehicle)
+            {
+                *body_transform = transform;
+                linear.0 = Vec3::ZERO;
+                angular.0 = Vec3::ZERO;
+            }
+            info!("Client {:?} respawned vehicle {}", remote_id.0, id);
+        }
+    }
+}
+
+/// Find the vehicle `id` and check that the client owns it or may manage it anyway.
+fn owned_vehicle<'a>(
+    mut vehicles: impl Iterator<Item = (Entity, &'a SpawnedVehicle)>,
+    id: VehicleId,
+    remote_id: &RemoteId,
+    role: Role,
+) -> Result<Entity, SpawnError> {
+    let (entity, vehicle) = vehicles
+        .find(|(_, vehicle)| vehicle.id == id)
+        .ok_or(SpawnError::UnknownVehicle(id))?;
+    if vehicle.owner != remote_id.0 && !role.allows(Permission::ManageVehicles) {
+        return Err(SpawnError::NotOwner(id));
+    }
+
+    Ok(entity)
+}
+
+fn reject(
+    senders: &mut Query<&mut MessageSender<VehicleRejected>, With<ClientOf>>,
+    client: Entity,
+    error: SpawnError,
+) {
+    if let Ok(mut sender) = senders.get_mut(client) {
+        sender.send::<ReliableChannel>(VehicleRejected {
+            reason: error.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod vehicle_test {
+    use bevy::prelude::*;
+    use common::{
+        part_registry::PartRegistry,
+        vehicle::{
+            BLOCK_SIZE, Block, BlockRotation, Blueprint, BlueprintError, Face, MAX_EXTENT,
+            PropertyValue, signal::Wire,
+        },
+    };
+
+    use crate::game::vehicle::{
+        SpawnError, free_spawn_point,
+        limits::{LimitError, MAX_BLOCKS, MAX_NAME_LENGTH, MAX_PROPERTIES, MAX_TEXT_LENGTH},
+        spawn_transform, validate_blueprint,
+    };
+
+    #[test]
+    fn validate_test() {
+        let registry = PartRegistry::builtin();
+
+        let mut blueprint = Blueprint::new("test");
+        assert!(matches!(
+            validate_blueprint(&blueprint, &registry),
+            Err(SpawnError::Empty)
+        ));
+
+        blueprint.add(Block::new(IVec3::ZERO, "cube")).unwrap();
+        blueprint.add(Block::new(IVec3::X, "cube")).unwrap();
+        assert!(validate_blueprint(&blueprint, &registry).is_ok());
+
+        blueprint.add(Block::new(IVec3::Y, "missing")).unwrap();
+        assert!(matches!(
+            validate_blueprint(&blueprint, &registry),
+            Err(SpawnError::Blueprint(BlueprintError::UnknownPart(_)))
+        ));
+    }
+
+    #[test]
+    fn too_many_blocks_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = Blueprint::new("test");
+        for i in 0..=MAX_BLOCKS as i32 {
+            blueprint
+                .add(Block::new(
+                    IVec3::new(i % 100, i / 10_000, (i / 100) % 100),
+                    "cube",
+                ))
+                .unwrap();
+        }
+
+        assert!(matches!(
+            validate_blueprint(&blueprint, &registry),
+            Err(SpawnError::Limit(LimitError::Blocks))
+        ));
+    }
+
+    #[test]
+    fn too_large_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(Block::new(IVec3::splat(MAX_EXTENT), "cube"))
+            .unwrap();
+        assert!(validate_blueprint(&blueprint, &registry).is_ok());
+
+        blueprint
+            .add(Block::new(IVec3::new(0, -1_000_000_000, 0), "cube"))
+            .unwrap();
+        assert!(matches!(
+            validate_blueprint(&blueprint, &registry),
+            Err(SpawnError::Limit(LimitError::Extent))
+        ));
+
+        // The seat is 40 blocks wide, so it reaches past the extent from the same position.
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(Block::new(IVec3::new(MAX_EXTENT - 39, 0, 0), "seat"))
+            .unwrap();
+        assert!(validate_blueprint(&blueprint, &registry).is_ok());
+        let seat = Block::new(IVec3::new(MAX_EXTENT - 38, 0, 0), "seat");
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(seat.clone()).unwrap();
+        assert!(matches!(
+            validate_blueprint(&blueprint, &registry),
+            Err(SpawnError::Limit(LimitError::Extent))
+        ));
+        // Turned the other way it fits again.
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(seat.with_rotation(BlockRotation::new(Face::NegX, 0)))
+            .unwrap();
+        assert!(validate_blueprint(&blueprint, &registry).is_ok());
+    }
+
+    #[test]
+    fn limits_test() {
+        let registry = PartRegistry::builtin();
+        let text = |length: usize| "a".repeat(length);
+        let spawn = |name: &str, block: Block| {
+            let mut blueprint = Blueprint::new(name);
+            blueprint.add(block).unwrap();
+            validate_blueprint(&blueprint, &registry)
+        };
+        let cube = || Block::new(IVec3::ZERO, "cube");
+
+        assert!(spawn(&text(MAX_NAME_LENGTH), cube()).is_ok());
+        assert!(matches!(
+            spawn(&text(MAX_NAME_LENGTH + 1), cube()),
+            Err(SpawnError::Limit(LimitError::Length("name", _)))
+        ));
+        assert!(matches!(
+            spawn(
+                "test",
+                cube().with_property("label", PropertyValue::Text(text(MAX_TEXT_LENGTH + 1)))
+            ),
+            Err(SpawnError::Limit(LimitError::Length("property value", _)))
+        ));
+
+        let mut block = cube();
+        for index in 0..=MAX_PROPERTIES {
+            block = block.with_property(index.to_string(), PropertyValue::Bool(true));
+        }
+        assert!(matches!(
+            spawn("test", block),
+            Err(SpawnError::Limit(LimitError::Properties))
+        ));
+    }
+
+    #[test]
+    fn wire_test() {
+        let registry = PartRegistry::builtin();
+        let mut blueprint = Blueprint::new("test");
+        blueprint.add(Block::new(IVec3::ZERO, "switch")).unwrap();
+        blueprint.add(Block::new(IVec3::X, "switch")).unwrap();
+        blueprint.add(Block::new(IVec3::Y, "and_gate")).unwrap();
+        blueprint
+            .connect(Wire::new(IVec3::ZERO, "out", IVec3::Y, "a"))
+            .unwrap();
+        assert!(validate_blueprint(&blueprint, &registry).is_ok());
+
+        // Port names are not checked when a blueprint is deserialized.
+        let with_wire = |from: &str, to: &str| -> Blueprint {
+            let wire = format!(
+                "[[wires]]\nfrom = {{ block = [1, 0, 0], port = \"{from}\" }}\nto = {{ block = [0, 1, 0], port = \"{to}\" }}\n"
+            );
+            toml::from_str(&(toml::to_string(&blueprint).unwrap() + &wire)).unwrap()
+        };
+        assert!(validate_blueprint(&with_wire("out", "b"), &registry).is_ok());
+        assert!(matches!(
+            validate_blueprint(&with_wire("out", "c"), &registry),
+            Err(SpawnError::Blueprint(BlueprintError::UnknownPort(..)))
+        ));
+        assert!(matches!(
+            validate_blueprint(&with_wire("a", "b"), &registry),
+            Err(SpawnError::Blueprint(BlueprintError::UnknownPort(..)))
+        ));
+    }
+
+    #[test]
+    fn free_spawn_point_test() {
+        let points = [Vec3::ZERO, Vec3::X * 100.0];
+
+        assert_eq!(free_spawn_point(&points, []), Some(Vec3::ZERO));
+        assert_eq!(free_spawn_point(&points, [Vec3::X]), Some(Vec3::X * 100.0));
+        assert_eq!(free_spawn_point(&points, [Vec3::X, Vec3::X * 99.0]), None);
+    }
+
+    #[test]
+    fn spawn_transform_test() {
+        let mut blueprint = Blueprint::new("test");
+        blueprint
+            .add(Block::new(IVec3::new(-2, 3, 4), "cube"))
+            .unwrap();
+        blueprint
+            .add(Block::new(IVec3::new(2, 5, 4), "cube"))
+            .unwrap();
+
+        let point = Vec3::new(1.0, 2.0, 3.0);
+        let transform = spawn_transform(&blueprint, point);
+
+        // The lowest face of the lowest block touches the point.
+        let bottom = transform.translation.y + 2.5 * BLOCK_SIZE;
+        assert!((bottom - point.y).abs() < 1e-6);
+        assert!((transform.translation.x - point.x).abs() < 1e-6);
+        assert!((transform.translation.z - (point.z - 4.0 * BLOCK_SIZE)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,99 @@
+//! The limits of vehicles sent by clients. Spawned vehicles and the edits of building
+//! sessions share them, so neither can grow a vehicle the other would refuse.
+
+use bevy::prelude::*;
+use common::{
+    part_registry::PartRegistry,
+    vehicle::{Block, Blueprint, Bounds, MAX_EXTENT, PropertyValue},
+};
+use thiserror::Error;
+
+/// The most blocks a vehicle may have.
+pub const MAX_BLOCKS: usize = 100_000;
+/// The maximum length of a vehicle or session name in characters.
+pub const MAX_NAME_LENGTH: usize = 32;
+/// The most properties a single block may have.
+pub const MAX_PROPERTIES: usize = 32;
+/// The maximum length of a property key in characters.
+pub const MAX_PROPERTY_KEY_LENGTH: usize = 32;
+/// The maximum length of a text property or a paint color in characters.
+pub const MAX_TEXT_LENGTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LimitError {
+    #[error("a vehicle may have at most {MAX_BLOCKS} blocks")]
+    Blocks,
+    #[error("the vehicle reaches further than {MAX_EXTENT} blocks from its origin")]
+    Extent,
+    #[error("a block may have at most {MAX_PROPERTIES} properties")]
+    Properties,
+    #[error("the {0} is longer than {1} characters")]
+    Length(&'static str, usize),
+}
+
+/// Check the limits of every block of `blueprint` and its name.
+///
+/// # Errors
+///
+/// This function will return an error describing the first exceeded limit.
+pub fn check_blueprint(blueprint: &Blueprint, registry: &PartRegistry) -> Result<(), LimitError> {
+    if blueprint.len() > MAX_BLOCKS {
+        return Err(LimitError::Blocks);
+    }
+    check_length("name", blueprint.name(), MAX_NAME_LENGTH)?;
+    for block in blueprint.iter() {
+        check_block(block, registry)?;
+    }
+
+    Ok(())
+}
+
+/// Check the properties of `block` and that every cell of its part is within
+/// [`MAX_EXTENT`]. Unknown parts are left to the validation of the blueprint.
+///
+/// # Errors
+///
+/// This function will return an error describing the first exceeded limit.
+pub fn check_block(block: &Block, registry: &PartRegistry) -> Result<(), LimitError> {
+    let mut bounds = Bounds::from_position(block.position);
+    if let Some(part) = registry.get(&block.part) {
+        let cells = part.cell_bounds(block.rotation);
+        bounds.extend(block.position.saturating_add(cells.min));
+        bounds.extend(block.position.saturating_add(cells.max));
+    }
+    if !bounds.is_within_extent() {
+        return Err(LimitError::Extent);
+    }
+
+    if block.properties.len() > MAX_PROPERTIES {
+        return Err(LimitError::Properties);
+    }
+    for (key, value) in &block.properties {
+        check_property(key, Some(value))?;
+    }
+
+    Ok(())
+}
+
+/// # Errors
+///
+/// This function will return an error if the key or a text value is too long.
+pub fn check_property(key: &str, value: Option<&PropertyValue>) -> Result<(), LimitError> {
+    check_length("property key", key, MAX_PROPERTY_KEY_LENGTH)?;
+    if let Some(PropertyValue::Text(text)) = value {
+        check_length("property value", text, MAX_TEXT_LENGTH)?;
+    }
+
+    Ok(())
+}
+
+/// # Errors
+///
+/// This function will return an error if `text` has more than `max` characters.
+pub fn check_length(what: &'static str, text: &str, max: usize) -> Result<(), LimitError> {
+    if text.chars().count() > max {
+        return Err(LimitError::Length(what, max));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use common::{
+    character::CharacterController,
+    world::chunk::{chunk_changes, chunk_of, chunk_with_margin},
+};
+use lightyear::prelude::{
+    ControlledBy, NetworkVisibility, Replicate, Room, RoomEvent, RoomPlugin, RoomTarget,
+    server::ClientOf,
+};
+
+/// Clients receive the entities in the chunks up to this many chunks around their character.
+const LOAD_RADIUS: i32 = 2;
+/// Chunks further away than this are no longer sent to a client.
+const UNLOAD_RADIUS: i32 = 3;
+/// How far in meters an entity has to leave its chunk before it moves to the next one.
+const CHUNK_MARGIN: f32 = 8.0;
+
+/// Only replicates entities to the clients whose character is nearby.
+///
+/// Every chunk is a [`Room`]. Replicated entities are in the room of the chunk they are in
+/// and clients are in the rooms of the chunks around their character.
+pub struct InterestPlugin;
+
+impl Plugin for InterestPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<RoomPlugin>() {
+            app.add_plugins(RoomPlugin);
+        }
+
+        app.init_resource::<ChunkRooms>();
+
+        app.add_observer(insert_chunk_interest_observer);
+        app.add_observer(remove_in_chunk_observer);
+
+        app.add_systems(
+            Update,
+            (update_entity_chunks_system, update_chunk_interest_system).chain(),
+        );
+    }
+}
+
+/// The [`Room`] of every chunk that was used so far.
+#[derive(Debug, Default, Resource)]
+struct ChunkRooms(HashMap<IVec2, Entity>);
+
+impl ChunkRooms {
+    /// The room of `chunk`, spawned on first use.
+    fn room(&mut self, commands: &mut Commands, chunk: IVec2) -> Entity {
+        *self.0.entry(chunk).or_insert_with(|| {
+            commands
+                .spawn((Name::new(format!("Chunk {chunk}")), Room::default()))
+                .id()
+        })
+    }
+}
+
+/// The chunk whose room a replicated entity is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+struct InChunk(IVec2);
+
+/// The chunks whose rooms a client is in.
+#[derive(Debug, Default, Component)]
+struct ChunkInterest {
+    loaded: HashSet<IVec2>,
+}
+
+fn insert_chunk_interest_observer(trigger: On<Add, ClientOf>, mut commands: Commands) {
+    commands
+        .entity(trigger.event().entity)
+        .insert(ChunkInterest::default());
+}
+
+fn remove_in_chunk_observer(
+    trigger: On<Remove, InChunk>,
+    mut commands: Commands,
+    rooms: Res<ChunkRooms>,
+    entities: Query<&InChunk>,
+) {
+    let entity = trigger.event().entity;
+    let Ok(in_chunk) = entities.get(entity) else {
+        return;
+    };
+
+    if let Some(room) = rooms.0.get(&in_chunk.0) {
+        commands.trigger(RoomEvent {
+            room: *room,
+            target: RoomTarget::RemoveEntity(entity),
+        });
+    }
+}
+
+/// Move replicated entities into the room of the chunk they are in.
+fn update_entity_chunks_system(
+    mut commands: Commands,
+    mut rooms: ResMut<ChunkRooms>,
+    mut entities: Query<(Entity, &Transform, Option<&mut InChunk>), With<Replicate>>,
+) {
+    for (entity, transform, in_chunk) in entities.iter_mut() {
+        let Some(mut in_chunk) = in_chunk else {
+            let chunk = chunk_of(transform.translation);
+            // Without a visibility an entity is sent to everyone until a client joins its room.
+            commands
+                .entity(entity)
+                .insert((InChunk(chunk), NetworkVisibility::default()));
+            let room = rooms.room(&mut commands, chunk);
+            commands.trigger(RoomEvent {
+                room,
+                target: RoomTarget::AddEntity(entity),
+            });
+            continue;
+        };
+
+        let chunk = chunk_with_margin(in_chunk.0, transform.translation, CHUNK_MARGIN);
+        if chunk == in_chunk.0 {
+            continue;
+        }
+
+        let old_room = rooms.room(&mut commands, in_chunk.0);
+        let new_room = rooms.room(&mut commands, chunk);
+        commands.trigger(RoomEvent {
+            room: old_room,
+            target: RoomTarget::RemoveEntity(entity),
+        });
+        commands.trigger(RoomEvent {
+            room: new_room,
+            target: RoomTarget::AddEntity(entity),
+        });
+        in_chunk.0 = chunk;
+    }
+}
+
+/// Move clients into the rooms of the chunks around their character.
+fn update_chunk_interest_system(
+    mut commands: Commands,
+    mut rooms: ResMut<ChunkRooms>,
+    characters: Query<(&Transform, &ControlledBy), With<CharacterController>>,
+    mut clients: Query<&mut ChunkInterest>,
+) {
+    for (transform, controlled_by) in characters.iter() {
+        let client = controlled_by.owner;
+        let Ok(mut interest) = clients.get_mut(client) else {
+            continue;
+        };
+
+        let center = chunk_of(transform.translation);
+        let (load, unload) = chunk_changes(&interest.loaded, center, LOAD_RADIUS, UNLOAD_RADIUS);
+        for chunk in load {
+            let room = rooms.room(&mut commands, chunk);
+            commands.trigger(RoomEvent {
+                room,
+                target: RoomTarget::AddSender(client),
+            });
+            interest.loaded.insert(chunk);
+        }
+        for chunk in unload {
+            let room = rooms.room(&mut commands, chunk);
+            commands.trigger(RoomEvent {
+                room,
+                target: RoomTarget::RemoveSender(client),
+            });
+            interest.loaded.remove(&chunk);
+        }
+    }
+}
@@ -1,7 +1,62 @@
 use bevy::prelude::*;
+use common::world::{
+    chunk::chunk_center,
+    terrain::{Terrain, TerrainChunk, terrain_chunks},
+};
+use lightyear::prelude::{NetworkTarget, Replicate, server::Started};
+use log::info;
+
+use crate::{config::Config, game::world::interest::InterestPlugin};
+
+mod interest;
 
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_plugins(InterestPlugin);
+        app.add_systems(Startup, setup);
+        app.add_observer(spawn_chunks_observer);
+    }
+}
+
+/// Pick the seed of the island. A loaded save already inserted the [`Terrain`].
+fn setup(mut commands: Commands, config: Res<Config>, terrain: Option<Res<Terrain>>) {
+    let seed = match terrain {
+        Some(terrain) => terrain.seed,
+        None => {
+            // Saves are TOML, which has no integers above `i64::MAX`.
+            let seed = config
+                .seed
+                .unwrap_or_else(|| u64::from(rand::random::<u32>()));
+            commands.insert_resource(Terrain { seed });
+            seed
+        }
+    };
+    info!("Generating the terrain with seed {seed}");
+}
+
+/// Spawn the island chunks once the server can replicate them. Clients generate them from
+/// the replicated seed.
+fn spawn_chunks_observer(
+    _: On<Add, Started>,
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    chunks: Query<(), With<TerrainChunk>>,
+) {
+    if !chunks.is_empty() {
+        return;
+    }
+
+    for chunk in terrain_chunks() {
+        commands.spawn((
+            Name::new(format!("Terrain {chunk}")),
+            TerrainChunk {
+                seed: terrain.seed,
+                chunk,
+            },
+            Transform::from_translation(chunk_center(chunk)),
+            Replicate::to_clients(NetworkTarget::All),
+        ));
+    }
 }
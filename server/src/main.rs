@@ -1,23 +1,37 @@
-use std::time::Duration;
-
-use bevy::{log::LogPlugin, prelude::*, state::app::StatesPlugin};
+use avian3d::prelude::*;
+use bevy::{
+    log::LogPlugin, mesh::MeshPlugin, prelude::*, scene::ScenePlugin, state::app::StatesPlugin,
+};
 use common::CommonPlugins;
 use lightyear::prelude::server::*;
 
 use crate::{
-    config::ConfigPlugins, editor::EditorPlugins, game::GamePlugins, network::NetworkPlugins,
+    chat::ChatPlugin, command::CommandPlugin, config::ConfigPlugins, console::ConsolePlugin,
+    editor::EditorPlugins, game::GamePlugins, network::NetworkPlugins, shutdown::ShutdownPlugin,
 };
 
+mod chat;
+mod command;
 mod config;
+mod console;
 mod editor;
 mod game;
 mod network;
+mod shutdown;
 
-fn main() {
+fn main() -> AppExit {
     let mut app = App::new();
 
     // Bevy plugins and config/
     app.add_plugins((MinimalPlugins, LogPlugin::default(), StatesPlugin));
+    // Physics needs transforms, and meshes and scenes for colliders generated from them.
+    app.add_plugins((
+        TransformPlugin,
+        AssetPlugin::default(),
+        MeshPlugin,
+        ScenePlugin,
+        PhysicsPlugins::default(),
+    ));
 
     // Lightyear plugins and config.
     app.add_plugins(ServerPlugins {
@@ -32,7 +46,11 @@ fn main() {
         NetworkPlugins,
         GamePlugins,
         EditorPlugins,
+        ShutdownPlugin,
+        CommandPlugin,
+        ConsolePlugin,
+        ChatPlugin,
     ));
 
-    app.run();
+    app.run()
 }
@@ -1,17 +1,69 @@
 use bevy::prelude::*;
-use lightyear::prelude::{LocalAddr, server::*};
-use log::info;
+use common::part_registry::PartRegistry;
+use lightyear::{
+    connection::client::Disconnecting,
+    prelude::{Connected, LocalAddr, MessageReceiver, MessageSender, RemoteId, server::*},
+};
+use log::{info, warn};
+use protocol::{
+    channels::ReliableChannel,
+    messages::{Kicked, PartRegistryChecksum},
+};
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    network::access::{load_access_list_system, refuse_denied_observer},
+};
+
+pub mod access;
+
+/// How long a kicked client stays connected so the [`Kicked`] message can be delivered.
+const KICK_DELAY_SECS: f32 = 1.0;
+/// How long a client may take to send a matching [`PartRegistryChecksum`] after connecting.
+const CHECKSUM_TIMEOUT_SECS: f32 = 10.0;
 
 pub struct NetworkPlugins;
 
 impl Plugin for NetworkPlugins {
     fn build(&self, app: &mut App) {
+        app.add_systems(PreStartup, load_access_list_system);
         app.add_systems(Startup, setup);
+        app.add_systems(
+            Update,
+            (
+                verify_part_registry_system,
+                checksum_timeout_system,
+                disconnect_kicked_system,
+            )
+                .chain(),
+        );
+
+        app.add_observer(kick_client_observer)
+            .add_observer(await_checksum_observer)
+            .add_observer(refuse_full_server_observer)
+            .add_observer(refuse_denied_observer);
     }
 }
 
+/// Kick a client. The client is told the reason before it gets disconnected.
+#[derive(Debug, EntityEvent)]
+pub struct KickClient {
+    /// The [`ClientOf`] entity.
+    pub entity: Entity,
+    pub reason: String,
+}
+
+/// A connected client that has not sent a matching [`PartRegistryChecksum`] yet.
+#[derive(Debug, Component)]
+struct PendingChecksum(Timer);
+
+/// A client that is about to be disconnected.
+#[derive(Debug, Component)]
+struct Kicking {
+    reason: String,
+    timer: Timer,
+}
+
 fn setup(mut commands: Commands, config: Res<Config>) {
     info!("Starting server...");
 
@@ -30,3 +82,110 @@ fn setup(mut commands: Commands, config: Res<Config>) {
     info!("Server started on {}", config.addr);
     info!("Max players: {}", config.max_players);
 }
+
+/// Kick clients that connect while [`Config::max_players`] are already connected.
+fn refuse_full_server_observer(
+    trigger: On<Add, Connected>,
+    mut commands: Commands,
+    clients: Query<(), (With<ClientOf>, With<Connected>)>,
+    config: Res<Config>,
+) {
+    if clients.iter().count() > config.max_players as usize {
+        commands.trigger(KickClient {
+            entity: trigger.event().entity,
+            reason: "The server is full".to_string(),
+        });
+    }
+}
+
+fn await_checksum_observer(trigger: On<Add, Connected>, mut commands: Commands) {
+    commands
+        .entity(trigger.event().entity)
+        .insert(PendingChecksum(Timer::from_seconds(
+            CHECKSUM_TIMEOUT_SECS,
+            TimerMode::Once,
+        )));
+}
+
+/// Kick clients whose part definitions differ from the servers.
+fn verify_part_registry_system(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut MessageReceiver<PartRegistryChecksum>), With<ClientOf>>,
+    registry: Res<PartRegistry>,
+) {
+    for (entity, mut receiver) in clients.iter_mut() {
+        for PartRegistryChecksum(checksum) in receiver.receive() {
+            if checksum == registry.checksum() {
+                commands.entity(entity).remove::<PendingChecksum>();
+            } else {
+                commands.trigger(KickClient {
+                    entity,
+                    reason: format!(
+                        "Part definitions do not match the server (client {:016x}, server {:016x})",
+                        checksum,
+                        registry.checksum()
+                    ),
+                });
+            }
+        }
+    }
+}
+
+/// Kick clients that never sent a matching [`PartRegistryChecksum`].
+fn checksum_timeout_system(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut PendingChecksum), Without<Kicking>>,
+    time: Res<Time>,
+) {
+    for (entity, mut pending) in clients.iter_mut() {
+        if pending.0.tick(time.delta()).just_finished() {
+            commands.trigger(KickClient {
+                entity,
+                reason: "Part definitions were not verified in time".to_string(),
+            });
+        }
+    }
+}
+
+fn kick_client_observer(
+    trigger: On<KickClient>,
+    mut commands: Commands,
+    mut clients: Query<(&mut MessageSender<Kicked>, Option<&RemoteId>), Without<Kicking>>,
+) {
+    let event = trigger.event();
+    let Ok((mut sender, remote_id)) = clients.get_mut(event.entity) else {
+        return;
+    };
+
+    match remote_id {
+        Some(remote_id) => info!("Kicking {:?}: {}", remote_id.0, event.reason),
+        None => info!("Kicking {}: {}", event.entity, event.reason),
+    }
+
+    sender.send::<ReliableChannel>(Kicked {
+        reason: event.reason.clone(),
+    });
+    commands.entity(event.entity).insert(Kicking {
+        reason: event.reason.clone(),
+        timer: Timer::from_seconds(KICK_DELAY_SECS, TimerMode::Once),
+    });
+}
+
+/// Disconnect kicked clients once the [`Kicked`] message had time to arrive.
+///
+/// [`Disconnecting`] lets lightyear flush the link for a frame before it marks the client
+/// [`Disconnected`](lightyear::prelude::Disconnected) and despawns it. Lightyear does not
+/// expose the netcode disconnect packets for a single client, so a client that ignores
+/// [`Kicked`] only notices once its netcode connection times out.
+fn disconnect_kicked_system(
+    mut commands: Commands,
+    mut clients: Query<(Entity, &mut Kicking), Without<Disconnecting>>,
+    time: Res<Time>,
+) {
+    for (entity, mut kicking) in clients.iter_mut() {
+        if kicking.timer.tick(time.delta()).is_finished() {
+            warn!("Disconnecting kicked client {}: {}", entity, kicking.reason);
+            commands.entity(entity).try_insert(Disconnecting);
+        }
+    }
+}
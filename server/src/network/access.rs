@@ -0,0 +1,532 @@
+use std::{
+    fmt::Display,
+    io,
+    net::IpAddr,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use common::{
+    Paths,
+    save_system::{SaveSystem, SaveSystemError},
+};
+use lightyear::prelude::{Connected, PeerAddr, PeerId, RemoteId};
+use log::info;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::network::KickClient;
+
+/// The longest temporary ban in seconds, about a hundred years.
+pub const MAX_BAN_DURATION: u64 = 100 * 365 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AccessError {
+    #[error("`{0}` is neither a client id nor an IP address or range")]
+    InvalidTarget(String),
+    #[error(
+        "a temporary ban lasts at most {}, ban permanently instead",
+        format_duration(MAX_BAN_DURATION)
+    )]
+    TooLong,
+}
+
+/// Why a client may not join.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Refusal {
+    #[error("You are banned{}", describe_ban(.reason, .remaining))]
+    Banned {
+        reason: String,
+        /// The seconds until the ban expires, [`None`] if it is permanent.
+        remaining: Option<u64>,
+    },
+    #[error("You are not on the whitelist")]
+    NotWhitelisted,
+}
+
+/// An IP address or a CIDR range like `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpRange {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(address), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(address) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(address), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(address) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    fn max_prefix(address: IpAddr) -> u8 {
+        match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = AccessError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AccessError::InvalidTarget(s.to_string());
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max = Self::max_prefix(address);
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(invalid)?,
+            None => max,
+        };
+
+        Ok(Self { address, prefix })
+    }
+}
+
+impl TryFrom<String> for IpRange {
+    type Error = AccessError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<IpRange> for String {
+    fn from(value: IpRange) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for IpRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.prefix == Self::max_prefix(self.address) {
+            write!(f, "{}", self.address)
+        } else {
+            write!(f, "{}/{}", self.address, self.prefix)
+        }
+    }
+}
+
+/// Who an entry applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessTarget {
+    /// A client id, which stays the same between connections.
+    Client(#[serde(with = "common::save_system::u64_string")] u64),
+    Ip(IpRange),
+}
+
+impl AccessTarget {
+    pub fn matches(&self, id: PeerId, ip: Option<IpAddr>) -> bool {
+        match self {
+            Self::Client(client) => client_id(id) == Some(*client),
+            Self::Ip(range) => ip.is_some_and(|ip| range.contains(ip)),
+        }
+    }
+}
+
+impl FromStr for AccessTarget {
+    type Err = AccessError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(client) => Ok(Self::Client(client)),
+            Err(_) => Ok(Self::Ip(s.parse()?)),
+        }
+    }
+}
+
+impl Display for AccessTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Client(client) => write!(f, "client {client}"),
+            Self::Ip(range) => write!(f, "{range}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessEntry {
+    #[serde(flatten)]
+    pub target: AccessTarget,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+    /// The UNIX time in seconds the entry stops applying at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+impl AccessEntry {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+impl Display for AccessEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.target)?;
+        if let Some(expires) = self.expires {
+            write!(
+                f,
+                " for {}",
+                format_duration(expires.saturating_sub(unix_time()))
+            )?;
+        }
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The ban list and whitelist, persisted through [`SaveSystem`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Resource, Serialize, Deserialize)]
+pub struct AccessList {
+    /// Only clients on the whitelist may join.
+    #[serde(default)]
+    pub whitelist_enabled: bool,
+    #[serde(default)]
+    bans: Vec<AccessEntry>,
+    #[serde(default)]
+    whitelist: Vec<AccessEntry>,
+}
+
+impl AccessList {
+    /// Load the access list, which is empty if it was never saved.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the access list exists but is invalid.
+    pub fn load() -> Result<Self, SaveSystemError> {
+        match SaveSystem::load_data(Paths::AccessList) {
+            Err(SaveSystemError::IoError(e)) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            result => result,
+        }
+    }
+
+    /// Save the access list without the expired entries.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the access list can not be written.
+    pub fn save(&mut self) -> Result<(), SaveSystemError> {
+        self.remove_expired(unix_time());
+        SaveSystem::save_data(Paths::AccessList, self)
+    }
+
+    /// Whether the client `id` connecting from `ip` may join at the UNIX time `now`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return why the client is refused.
+    pub fn check(&self, id: PeerId, ip: Option<IpAddr>, now: u64) -> Result<(), Refusal> {
+        let active = |entry: &&AccessEntry| !entry.is_expired(now) && entry.target.matches(id, ip);
+        if let Some(ban) = self.bans.iter().find(active) {
+            return Err(Refusal::Banned {
+                reason: ban.reason.clone(),
+                remaining: ban.expires.map(|expires| expires - now),
+            });
+        }
+        if self.whitelist_enabled && !self.whitelist.iter().any(|entry| active(&entry)) {
+            return Err(Refusal::NotWhitelisted);
+        }
+
+        Ok(())
+    }
+
+    /// Ban `entry.target`, replacing an earlier ban of it.
+    pub fn ban(&mut self, entry: AccessEntry) {
+        self.unban(entry.target);
+        self.bans.push(entry);
+    }
+
+    /// Returns false if `target` was not banned.
+    pub fn unban(&mut self, target: AccessTarget) -> bool {
+        let len = self.bans.len();
+        self.bans.retain(|entry| entry.target != target);
+        self.bans.len() != len
+    }
+
+    /// Put `entry.target` on the whitelist, replacing an earlier entry of it.
+    pub fn allow(&mut self, entry: AccessEntry) {
+        self.disallow(entry.target);
+        self.whitelist.push(entry);
+    }
+
+    /// Returns false if `target` was not on the whitelist.
+    pub fn disallow(&mut self, target: AccessTarget) -> bool {
+        let len = self.whitelist.len();
+        self.whitelist.retain(|entry| entry.target != target);
+        self.whitelist.len() != len
+    }
+
+    pub fn bans(&self) -> impl Iterator<Item = &AccessEntry> {
+        self.bans.iter()
+    }
+
+    pub fn whitelist(&self) -> impl Iterator<Item = &AccessEntry> {
+        self.whitelist.iter()
+    }
+
+    pub fn remove_expired(&mut self, now: u64) {
+        self.bans.retain(|entry| !entry.is_expired(now));
+        self.whitelist.retain(|entry| !entry.is_expired(now));
+    }
+}
+
+/// The UNIX time `duration` seconds after `now`.
+///
+/// # Errors
+///
+/// This function will return an error if `duration` is longer than [`MAX_BAN_DURATION`] or
+/// the time does not fit into a TOML integer.
+pub fn expiry(now: u64, duration: u64) -> Result<u64, AccessError> {
+    if duration > MAX_BAN_DURATION {
+        return Err(AccessError::TooLong);
+    }
+
+    now.checked_add(duration)
+        .filter(|expires| i64::try_from(*expires).is_ok())
+        .ok_or(AccessError::TooLong)
+}
+
+/// The current UNIX time in seconds.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to get UNIX timestamp because UNIX_EPOCH is later than now")
+        .as_secs()
+}
+
+/// `seconds` in the largest fitting unit, like `3h`.
+pub fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// The id that identifies a client between connections.
+fn client_id(id: PeerId) -> Option<u64> {
+    match id {
+        PeerId::Netcode(id) | PeerId::Steam(id) => Some(id),
+        _ => None,
+    }
+}
+
+fn describe_ban(reason: &str, remaining: &Option<u64>) -> String {
+    let mut description = String::new();
+    if let Some(remaining) = remaining {
+        description.push_str(&format!(" for {}", format_duration(*remaining)));
+    }
+    if !reason.is_empty() {
+        description.push_str(&format!(": {reason}"));
+    }
+
+    description
+}
+
+pub(super) fn load_access_list_system(mut commands: Commands) -> Result<(), BevyError> {
+    let access = AccessList::load()
+        .map_err(|e| format!("Failed to load the ban list and whitelist: {e}"))?;
+    info!(
+        "Loaded {} bans and {} whitelist entries, the whitelist is {}",
+        access.bans.len(),
+        access.whitelist.len(),
+        if access.whitelist_enabled {
+            "on"
+        } else {
+            "off"
+        }
+    );
+    commands.insert_resource(access);
+
+    Ok(())
+}
+
+/// Kick banned clients and, with the whitelist enabled, everyone not on it.
+pub(super) fn refuse_denied_observer(
+    trigger: On<Add, Connected>,
+    mut commands: Commands,
+    clients: Query<(&RemoteId, Option<&PeerAddr>)>,
+    access: Res<AccessList>,
+) {
+    let entity = trigger.event().entity;
+    let Ok((remote_id, addr)) = clients.get(entity) else {
+        return;
+    };
+
+    let ip = addr.map(|addr| addr.0.ip());
+    if let Err(refusal) = access.check(remote_id.0, ip, unix_time()) {
+        commands.trigger(KickClient {
+            entity,
+            reason: refusal.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod access_test {
+    use std::{fs, net::IpAddr};
+
+    use common::save_system::SaveSystem;
+    use lightyear::prelude::PeerId;
+
+    use crate::network::access::{
+        AccessEntry, AccessError, AccessList, AccessTarget, IpRange, MAX_BAN_DURATION, Refusal,
+        expiry,
+    };
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn entry(target: &str, expires: Option<u64>) -> AccessEntry {
+        AccessEntry {
+            target: target.parse().unwrap(),
+            reason: String::new(),
+            expires,
+        }
+    }
+
+    #[test]
+    fn ip_range_test() {
+        let range: IpRange = "10.1.0.0/16".parse().unwrap();
+        assert!(range.contains(ip("10.1.200.3")));
+        assert!(range.contains(ip("::ffff:10.1.0.1")));
+        assert!(!range.contains(ip("10.2.0.1")));
+        assert!(!range.contains(ip("::1")));
+        assert_eq!(range.to_string(), "10.1.0.0/16");
+
+        let range: IpRange = "2001:db8::/32".parse().unwrap();
+        assert!(range.contains(ip("2001:db8:1::1")));
+        assert!(!range.contains(ip("2001:db9::1")));
+
+        assert!(
+            "0.0.0.0/0"
+                .parse::<IpRange>()
+                .unwrap()
+                .contains(ip("1.2.3.4"))
+        );
+        assert_eq!(
+            "127.0.0.1".parse::<IpRange>().unwrap().to_string(),
+            "127.0.0.1"
+        );
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("10.0.0/8".parse::<IpRange>().is_err());
+    }
+
+    #[test]
+    fn expiry_test() {
+        assert_eq!(expiry(100, 60), Ok(160));
+        assert_eq!(expiry(100, MAX_BAN_DURATION), Ok(100 + MAX_BAN_DURATION));
+        assert_eq!(expiry(100, MAX_BAN_DURATION + 1), Err(AccessError::TooLong));
+        assert_eq!(expiry(i64::MAX as u64, 60), Err(AccessError::TooLong));
+        assert_eq!(expiry(u64::MAX, 60), Err(AccessError::TooLong));
+    }
+
+    #[test]
+    fn target_test() {
+        assert_eq!("42".parse(), Ok(AccessTarget::Client(42)));
+        assert!(matches!("10.0.0.0/8".parse(), Ok(AccessTarget::Ip(_))));
+        assert!("player".parse::<AccessTarget>().is_err());
+    }
+
+    #[test]
+    fn check_test() {
+        let player = PeerId::Netcode(42);
+        let home = Some(ip("192.168.1.20"));
+        let mut access = AccessList::default();
+        assert_eq!(access.check(player, home, 100), Ok(()));
+
+        access.ban(AccessEntry {
+            reason: "griefing".to_string(),
+            ..entry("42", Some(200))
+        });
+        assert_eq!(
+            access.check(player, home, 100),
+            Err(Refusal::Banned {
+                reason: "griefing".to_string(),
+                remaining: Some(100),
+            })
+        );
+        assert_eq!(access.check(player, home, 200), Ok(()));
+        assert!(access.unban(AccessTarget::Client(42)));
+        assert!(!access.unban(AccessTarget::Client(42)));
+
+        access.ban(entry("192.168.0.0/16", None));
+        assert!(access.check(PeerId::Netcode(7), home, 100).is_err());
+        assert_eq!(access.check(player, None, 100), Ok(()));
+        access.remove_expired(u64::MAX);
+        assert_eq!(access.bans().count(), 1);
+
+        let mut access = AccessList {
+            whitelist_enabled: true,
+            ..Default::default()
+        };
+        access.allow(entry("42", None));
+        assert_eq!(access.check(player, home, 100), Ok(()));
+        assert_eq!(
+            access.check(PeerId::Netcode(7), home, 100),
+            Err(Refusal::NotWhitelisted)
+        );
+    }
+
+    #[test]
+    fn save_load_test() {
+        let path = "./testaccess";
+
+        let mut access = AccessList {
+            whitelist_enabled: true,
+            ..Default::default()
+        };
+        access.ban(AccessEntry {
+            reason: "griefing".to_string(),
+            ..entry("42", Some(1_000))
+        });
+        access.ban(entry("10.0.0.0/8", None));
+        access.allow(entry("::1", None));
+        // Larger than any TOML integer.
+        access.allow(entry(&u64::MAX.to_string(), None));
+
+        SaveSystem::save(path, &access).unwrap();
+        let loaded: AccessList = SaveSystem::load(path).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(access, loaded);
+        assert!(contents.contains("client = \"42\""), "{contents}");
+        assert!(contents.contains("ip = \"10.0.0.0/8\""), "{contents}");
+
+        // Hand-written lists may still use integers.
+        let entry: AccessEntry = toml::from_str("client = 42").unwrap();
+        assert_eq!(entry.target, AccessTarget::Client(42));
+    }
+}
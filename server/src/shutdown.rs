@@ -0,0 +1,174 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use bevy::{app::ctrlc, prelude::*};
+use lightyear::prelude::{Connected, MessageSender, server::ClientOf};
+use log::{info, warn};
+use protocol::{channels::ReliableChannel, messages::ServerShutdown};
+
+use crate::{config::Config, game::save::SaveWorld, network::KickClient};
+
+/// The reason clients are disconnected with.
+const SHUTDOWN_REASON: &str = "The server shut down";
+/// The longest the server waits for the kicked clients to disconnect.
+const DISCONNECT_TIMEOUT_SECS: f32 = 5.0;
+
+/// Shuts the server down on SIGINT or a [`Shutdown`].
+///
+/// New connections are refused, clients are warned with a countdown, the world is saved
+/// and everyone is kicked before the app exits. A second SIGINT skips the countdown.
+pub struct ShutdownPlugin;
+
+impl Plugin for ShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let handler_count = count.clone();
+        if let Err(e) = ctrlc::try_set_handler(move || {
+            handler_count.fetch_add(1, Ordering::Relaxed);
+        }) {
+            warn!("Failed to handle SIGINT, the server will exit without saving: {e}");
+        }
+        app.insert_resource(Signals { count, handled: 0 });
+
+        app.add_observer(shutdown_observer)
+            .add_observer(refuse_connection_observer);
+
+        app.add_systems(Update, (signal_system, shutdown_system).chain());
+    }
+}
+
+/// Start shutting the server down.
+#[derive(Debug, Event)]
+pub struct Shutdown {
+    /// Skip the countdown.
+    pub now: bool,
+}
+
+/// The SIGINTs received so far.
+#[derive(Debug, Resource)]
+struct Signals {
+    count: Arc<AtomicUsize>,
+    handled: usize,
+}
+
+#[derive(Debug, Resource)]
+enum ShutdownState {
+    /// Warning the clients. `announced` is the last number of seconds they were told.
+    Countdown { timer: Timer, announced: u32 },
+    /// Waiting for the kicked clients to disconnect.
+    Disconnecting { timer: Timer },
+}
+
+fn signal_system(
+    mut commands: Commands,
+    mut signals: ResMut<Signals>,
+    state: Option<Res<ShutdownState>>,
+) {
+    let count = signals.count.load(Ordering::Relaxed);
+    if count > signals.handled {
+        signals.handled = count;
+        commands.trigger(Shutdown {
+            now: state.is_some(),
+        });
+    }
+}
+
+fn shutdown_observer(
+    trigger: On<Shutdown>,
+    mut commands: Commands,
+    config: Res<Config>,
+    state: Option<Res<ShutdownState>>,
+) {
+    let seconds = if trigger.event().now {
+        0
+    } else {
+        config.shutdown_countdown
+    };
+
+    match state.as_deref() {
+        Some(ShutdownState::Disconnecting { .. }) => return,
+        Some(ShutdownState::Countdown { .. }) if seconds > 0 => return,
+        _ => {}
+    }
+
+    if seconds == 0 {
+        info!("Shutting down now");
+    } else {
+        info!("Shutting down in {} seconds", seconds);
+    }
+    commands.insert_resource(ShutdownState::Countdown {
+        timer: Timer::from_seconds(seconds as f32, TimerMode::Once),
+        announced: u32::MAX,
+    });
+}
+
+/// Kick clients that connect while the server shuts down.
+fn refuse_connection_observer(
+    trigger: On<Add, Connected>,
+    mut commands: Commands,
+    state: Option<Res<ShutdownState>>,
+) {
+    if state.is_some() {
+        commands.trigger(KickClient {
+            entity: trigger.event().entity,
+            reason: "The server is shutting down".to_string(),
+        });
+    }
+}
+
+fn shutdown_system(
+    mut commands: Commands,
+    state: Option<ResMut<ShutdownState>>,
+    mut clients: Query<(Entity, &mut MessageSender<ServerShutdown>), With<Connected>>,
+    remaining_clients: Query<(), With<ClientOf>>,
+    mut saves: MessageWriter<SaveWorld>,
+    mut exits: MessageWriter<AppExit>,
+    time: Res<Time>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+
+    match state.as_mut() {
+        ShutdownState::Countdown { timer, announced } => {
+            if timer.tick(time.delta()).is_finished() {
+                info!(
+                    "Saving the world and disconnecting {} clients",
+                    clients.iter().count()
+                );
+                saves.write(SaveWorld);
+                for (entity, _) in clients.iter() {
+                    commands.trigger(KickClient {
+                        entity,
+                        reason: SHUTDOWN_REASON.to_string(),
+                    });
+                }
+                *state = ShutdownState::Disconnecting {
+                    timer: Timer::from_seconds(DISCONNECT_TIMEOUT_SECS, TimerMode::Once),
+                };
+                return;
+            }
+
+            let seconds = timer.remaining_secs().ceil() as u32;
+            if seconds < *announced {
+                *announced = seconds;
+                for (_, mut sender) in clients.iter_mut() {
+                    sender.send::<ReliableChannel>(ServerShutdown { seconds });
+                }
+            }
+        }
+        ShutdownState::Disconnecting { timer } => {
+            let timed_out = timer.tick(time.delta()).is_finished();
+            if remaining_clients.is_empty() || timed_out {
+                if timed_out {
+                    warn!("Exiting before every client disconnected");
+                }
+                info!("Server stopped");
+                log::logger().flush();
+                exits.write(AppExit::Success);
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Runs the server binary and shuts it down with SIGINT.
+
+#![cfg(target_os = "linux")]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read},
+    net::UdpSocket,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Send the output lines of `reader` to `sender`.
+fn forward(reader: impl Read + Send + 'static, sender: Sender<String>) {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Wait for a line containing `text`, collecting every line read into `log`.
+fn wait_for(output: &Receiver<String>, log: &mut Vec<String>, text: &str) {
+    let start = Instant::now();
+    while !log.iter().any(|line| line.contains(text)) {
+        let remaining = TIMEOUT.saturating_sub(start.elapsed());
+        match output.recv_timeout(remaining) {
+            Ok(line) => log.push(line),
+            Err(e) => panic!("`{text}` was never logged ({e}):\n{}", log.join("\n")),
+        }
+    }
+}
+
+fn kill(server: &mut Child) {
+    let _ = server.kill();
+    let _ = server.wait();
+}
+
+#[test]
+fn shutdown_test() {
+    let dir = std::env::temp_dir().join(format!("avb_shutdown_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let port = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_server"))
+        .current_dir(&dir)
+        .env("XDG_DATA_HOME", &dir)
+        .env("NO_COLOR", "1")
+        .args(["--ip", "127.0.0.1", "--port", &port.to_string()])
+        .args(["--max-players", "4", "--save", "shutdown_test", "--fresh"])
+        .args(["--seed", "7", "--shutdown-countdown", "1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (sender, output) = mpsc::channel();
+    forward(server.stdout.take().unwrap(), sender.clone());
+    forward(server.stderr.take().unwrap(), sender);
+
+    let mut log = Vec::new();
+    wait_for(&output, &mut log, "Server UDP socket bound");
+
+    let status = Command::new("kill")
+        .args(["-INT", &server.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    wait_for(&output, &mut log, "Shutting down in 1 seconds");
+    wait_for(&output, &mut log, "Server stopped");
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > TIMEOUT {
+            kill(&mut server);
+            panic!("The server did not exit:\n{}", log.join("\n"));
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    log.extend(output.try_iter());
+    assert!(status.success(), "{status}:\n{}", log.join("\n"));
+
+    let save: PathBuf = [common::DIR_NAME, "shutdown_test", "world.toml"]
+        .iter()
+        .fold(dir.clone(), |path, part| path.join(part));
+    let contents = fs::read_to_string(&save).unwrap();
+    assert!(contents.contains("seed = 7"), "{contents}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}